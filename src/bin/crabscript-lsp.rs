@@ -0,0 +1,242 @@
+//NOTE A language server speaking LSP over stdio, the answers come from `analysis`.
+//
+//Documents are synced in full and analyzed again on every change. Positions of LSP count
+//UTF-16 code units in a line, they are converted to and from the byte offsets of `Span`s.
+
+use crabscript::analysis::{analyze, Analysis, CompletionKind};
+use serde_json::{json, Value};
+use std::{
+    collections::HashMap,
+    io::{self, BufRead, Write},
+    path::Path,
+};
+
+fn main() {
+    let stdin = io::stdin();
+    let mut input = stdin.lock();
+    let stdout = io::stdout();
+    let mut out = stdout.lock();
+    let mut server = Server::default();
+
+    while let Some(message) = read_message(&mut input) {
+        let message: Value = match serde_json::from_str(&message) {
+            Ok(message) => message,
+            Err(e) => {
+                eprintln!("Invalid message: {}", e);
+                continue;
+            }
+        };
+        let method = message["method"].as_str().unwrap_or_default();
+        if method == "exit" {
+            std::process::exit(if server.shut_down { 0 } else { 1 })
+        }
+
+        for reply in server.handle(method, &message["params"], message.get("id")) {
+            write_message(&mut out, &reply);
+        }
+    }
+}
+
+/// The content of the next message, `None` once the input is closed
+fn read_message(input: &mut impl BufRead) -> Option<String> {
+    let mut len = None;
+    loop {
+        let mut header = String::new();
+        if input.read_line(&mut header).ok()? == 0 {
+            return None;
+        }
+        let header = header.trim_end();
+        if header.is_empty() {
+            break;
+        }
+        if let Some((name, value)) = header.split_once(':') {
+            if name.eq_ignore_ascii_case("Content-Length") {
+                len = value.trim().parse().ok();
+            }
+        }
+    }
+
+    let mut content = vec![0; len?];
+    input.read_exact(&mut content).ok()?;
+    String::from_utf8(content).ok()
+}
+
+fn write_message(out: &mut impl Write, message: &Value) {
+    let content = message.to_string();
+    write!(out, "Content-Length: {}\r\n\r\n{}", content.len(), content)
+        .and_then(|_| out.flush())
+        .expect("the client is reading");
+}
+
+#[derive(Default)]
+struct Server {
+    /// The texts of the open documents by their uris
+    documents: HashMap<String, String>,
+    shut_down: bool,
+}
+
+impl Server {
+    /// The responses and notifications for a message, requests have an `id`
+    fn handle(&mut self, method: &str, params: &Value, id: Option<&Value>) -> Vec<Value> {
+        let uri = params["textDocument"]["uri"].as_str().unwrap_or_default();
+        let result = match method {
+            "initialize" => json!({
+                "capabilities": {
+                    "textDocumentSync": 1,
+                    "definitionProvider": true,
+                    "hoverProvider": true,
+                    "completionProvider": {},
+                },
+                "serverInfo": { "name": "crabscript-lsp" },
+            }),
+            "shutdown" => {
+                self.shut_down = true;
+                Value::Null
+            }
+            "textDocument/didOpen" => {
+                let text = params["textDocument"]["text"].as_str().unwrap_or_default();
+                self.documents.insert(uri.to_string(), text.to_string());
+                return vec![self.diagnostics(uri)];
+            }
+            "textDocument/didChange" => {
+                // Synced in full, the last change is the whole text
+                let changes = params["contentChanges"].as_array();
+                if let Some(text) = changes.and_then(|c| c.last()?["text"].as_str()) {
+                    self.documents.insert(uri.to_string(), text.to_string());
+                }
+                return vec![self.diagnostics(uri)];
+            }
+            "textDocument/didClose" => {
+                self.documents.remove(uri);
+                return vec![publish(uri, Vec::new())];
+            }
+            "textDocument/definition" => self
+                .at_position(uri, &params["position"], |analysis, text, offset| {
+                    let span = analysis.definition_at(offset)?;
+                    Some(json!({
+                        "uri": uri,
+                        "range": range(text, span.offset, span.offset + span.len),
+                    }))
+                })
+                .unwrap_or(Value::Null),
+            "textDocument/hover" => self
+                .at_position(uri, &params["position"], |analysis, _, offset| {
+                    let docs = analysis.hover_at(offset)?;
+                    Some(json!({ "contents": { "kind": "markdown", "value": docs } }))
+                })
+                .unwrap_or(Value::Null),
+            "textDocument/completion" => self
+                .at_position(uri, &params["position"], |analysis, _, offset| {
+                    let items: Vec<_> = analysis
+                        .completions_at(offset)
+                        .into_iter()
+                        .map(|c| {
+                            json!({
+                                "label": c.label,
+                                "kind": match c.kind {
+                                    CompletionKind::Variable => 6,
+                                    CompletionKind::Constant => 21,
+                                    CompletionKind::Function => 3,
+                                },
+                                "detail": c.detail,
+                            })
+                        })
+                        .collect();
+                    Some(Value::from(items))
+                })
+                .unwrap_or(Value::Null),
+            _ => match id {
+                Some(_) => {
+                    return vec![json!({
+                        "jsonrpc": "2.0",
+                        "id": id,
+                        "error": { "code": -32601, "message": format!("Unknown method {}", method) },
+                    })]
+                }
+                // Other notifications like `initialized` need no answer
+                None => return Vec::new(),
+            },
+        };
+
+        match id {
+            Some(id) => vec![json!({ "jsonrpc": "2.0", "id": id, "result": result })],
+            None => Vec::new(),
+        }
+    }
+
+    fn analyze(&self, uri: &str) -> Option<(Analysis, &str)> {
+        let text = self.documents.get(uri)?;
+        let dir = uri
+            .strip_prefix("file://")
+            .and_then(|path| Path::new(path).parent());
+        Some((analyze(text, dir), text))
+    }
+
+    fn at_position(
+        &self,
+        uri: &str,
+        position: &Value,
+        f: impl FnOnce(&Analysis, &str, usize) -> Option<Value>,
+    ) -> Option<Value> {
+        let (analysis, text) = self.analyze(uri)?;
+        let line = position["line"].as_u64()? as usize;
+        let character = position["character"].as_u64()? as usize;
+        f(&analysis, text, offset(text, line, character))
+    }
+
+    fn diagnostics(&self, uri: &str) -> Value {
+        let diagnostics = match self.analyze(uri) {
+            Some((
+                Analysis {
+                    error: Some(error), ..
+                },
+                text,
+            )) => {
+                let span = error.span();
+                vec![json!({
+                    "range": range(text, span.offset, span.offset + span.len),
+                    "severity": 1,
+                    "source": "crabscript",
+                    "message": error.kind().to_string(),
+                })]
+            }
+            _ => Vec::new(),
+        };
+        publish(uri, diagnostics)
+    }
+}
+
+fn publish(uri: &str, diagnostics: Vec<Value>) -> Value {
+    json!({
+        "jsonrpc": "2.0",
+        "method": "textDocument/publishDiagnostics",
+        "params": { "uri": uri, "diagnostics": diagnostics },
+    })
+}
+
+/// The byte offset of an LSP position, positions past the end of a line are at its end
+fn offset(text: &str, line: usize, character: usize) -> usize {
+    let start: usize = text.split_inclusive('\n').take(line).map(str::len).sum();
+    let mut units = 0;
+    for (i, c) in text[start..].char_indices() {
+        if units >= character || c == '\n' {
+            return start + i;
+        }
+        units += c.len_utf16();
+    }
+    text.len()
+}
+
+fn position(text: &str, offset: usize) -> Value {
+    let offset = offset.min(text.len());
+    let before = text.get(..offset).unwrap_or(text);
+    let line_start = before.rfind('\n').map_or(0, |i| i + 1);
+    json!({
+        "line": before.matches('\n').count(),
+        "character": before[line_start..].encode_utf16().count(),
+    })
+}
+
+fn range(text: &str, start: usize, end: usize) -> Value {
+    json!({ "start": position(text, start), "end": position(text, end) })
+}
@@ -0,0 +1,468 @@
+//NOTE Answers the questions editors ask about a script, used by the language server.
+//
+//The parser records which declaration or std function every identifier it resolves refers
+//to. The scopes come from filling the var map, which happens before parsing, so they are
+//complete even if the script fails to parse, which it mostly does while it is typed.
+
+use crate::{
+    build_pre_map,
+    dump::ScopeInfo,
+    parser::Parser,
+    parsing_error::ParsingError,
+    span::Span,
+    tokenizer::{build_lexer, TokenStream},
+    PreMap,
+};
+use std::path::Path;
+
+/// An identifier resolved by the parser
+#[derive(Debug, Clone, PartialEq)]
+pub struct Reference {
+    pub span: Span,
+    pub target: Target,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Target {
+    /// The name in the declaration of the variable
+    Variable(Span),
+    /// A std function by the name it was called by, like `add` or `math.add`
+    Std(String),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CompletionKind {
+    Variable,
+    Constant,
+    Function,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Completion {
+    pub label: String,
+    pub kind: CompletionKind,
+    /// The signature of a std function
+    pub detail: Option<&'static str>,
+}
+
+/// What the parser found out about a script
+#[derive(Debug)]
+pub struct Analysis {
+    /// The error the parser stopped at
+    pub error: Option<ParsingError>,
+    /// The identifiers resolved before parsing stopped
+    pub references: Vec<Reference>,
+    /// `None` if the brackets of the script don't match
+    pub scopes: Option<ScopeInfo>,
+}
+
+/// Parses `src`, files it imports are searched relative to `dir`
+pub fn analyze(src: &str, dir: Option<&Path>) -> Analysis {
+    let lexer = build_lexer().expect("the lexer definition is valid");
+
+    let mut parser = Parser::new(build_pre_map());
+    if let Some(dir) = dir {
+        parser.add_search_path(dir);
+    }
+    parser.record_references();
+    let error = parser
+        .parse_tokens(TokenStream::new(src, lexer.tokens(src)))
+        .err();
+
+    // A failed parse forgets the top level variables, a second parser only fills the var map
+    let mut filler = Parser::new(PreMap::default());
+    let scopes = filler
+        .fill_var_map(TokenStream::new(src, lexer.tokens(src)))
+        .ok()
+        .map(|_| filler.scopes());
+
+    Analysis {
+        error,
+        references: parser.take_references(),
+        scopes,
+    }
+}
+
+impl Analysis {
+    /// The identifier at the byte `offset`, the end of an identifier still belongs to it
+    pub fn reference_at(&self, offset: usize) -> Option<&Reference> {
+        self.references.iter().find(|r| contains(r.span, offset))
+    }
+
+    /// The name in the declaration of the variable at `offset`
+    pub fn definition_at(&self, offset: usize) -> Option<Span> {
+        match self.reference_at(offset) {
+            Some(Reference {
+                target: Target::Variable(span),
+                ..
+            }) => Some(*span),
+            Some(_) => None,
+            // A declaration is its own definition
+            None => {
+                let mut declarations = Vec::new();
+                if let Some(scope) = &self.scopes {
+                    collect_declarations(scope, &mut declarations);
+                }
+                declarations
+                    .into_iter()
+                    .find(|span| contains(*span, offset))
+            }
+        }
+    }
+
+    /// The documentation of the std function at `offset`
+    pub fn hover_at(&self, offset: usize) -> Option<String> {
+        match &self.reference_at(offset)?.target {
+            Target::Std(name) => {
+                let (qualified, signature, doc) = std_doc(name)?;
+                Some(format!("{}\n\n{} (`{}`)", signature, doc, qualified))
+            }
+            Target::Variable(_) => None,
+        }
+    }
+
+    /// The variables visible at `offset` and the std functions,
+    /// inner variables shadow outer ones with the same name
+    pub fn completions_at(&self, offset: usize) -> Vec<Completion> {
+        let mut completions: Vec<Completion> = Vec::new();
+        let mut scope = self.scopes.as_ref();
+        while let Some(current) = scope {
+            for var in &current.vars {
+                if completions.iter().all(|c| c.label != var.name) {
+                    completions.push(Completion {
+                        label: var.name.clone(),
+                        kind: match var.is_const {
+                            true => CompletionKind::Constant,
+                            false => CompletionKind::Variable,
+                        },
+                        detail: None,
+                    });
+                }
+            }
+            scope = current.children.iter().find(|child| {
+                child.span.is_some_and(|span| {
+                    span.offset < offset && offset <= span.offset + span.len.saturating_sub(1)
+                })
+            });
+        }
+
+        // Shadowed by the variables of the innermost scopes
+        completions.reverse();
+        completions.push(Completion {
+            label: "args".to_string(),
+            kind: CompletionKind::Variable,
+            detail: None,
+        });
+
+        let mut std: Vec<_> = build_pre_map().into_keys().collect();
+        std.sort_unstable();
+        for name in std {
+            if completions.iter().all(|c| c.label != name) {
+                completions.push(Completion {
+                    label: name.to_string(),
+                    kind: CompletionKind::Function,
+                    detail: std_doc(name).map(|(_, signature, _)| signature),
+                });
+            }
+        }
+
+        completions
+    }
+}
+
+fn contains(span: Span, offset: usize) -> bool {
+    span.len > 0 && span.offset <= offset && offset <= span.offset + span.len
+}
+
+fn collect_declarations(scope: &ScopeInfo, declarations: &mut Vec<Span>) {
+    declarations.extend(scope.vars.iter().map(|var| var.span));
+    for child in &scope.children {
+        collect_declarations(child, declarations);
+    }
+}
+
+/// The qualified name, signature and description of the std function `name`,
+/// which is either qualified or a name of the prelude
+pub fn std_doc(name: &str) -> Option<(&'static str, &'static str, &'static str)> {
+    STD_DOCS
+        .iter()
+        .find(|(qualified, _, _)| {
+            *qualified == name || qualified.split_once('.').map(|(_, n)| n) == Some(name)
+        })
+        .copied()
+}
+
+const STD_DOCS: &[(&str, &str, &str)] = &[
+    (
+        "math.add",
+        "add(a, b, ...)",
+        "Adds the numbers from left to right",
+    ),
+    (
+        "math.sub",
+        "sub(a, b, ...)",
+        "Subtracts the following numbers from the first one",
+    ),
+    ("math.mul", "mul(a, b, ...)", "Multiplies the numbers"),
+    (
+        "math.div",
+        "div(a, b, ...)",
+        "Divides the first number by the following ones, integer division by zero fails",
+    ),
+    (
+        "math.mod",
+        "mod(a, b, ...)",
+        "The remainder of dividing the first number by the following ones",
+    ),
+    ("math.neg", "neg(n)", "Negates a number"),
+    (
+        "iter.range",
+        "range(start, end)",
+        "An iter over the integers from start up to end, excluding end",
+    ),
+    (
+        "io.print",
+        "print(values...)",
+        "Writes the values to the output",
+    ),
+    (
+        "io.println",
+        "println(values...)",
+        "Writes every value on its own line to the output",
+    ),
+    ("io.read", "read()", "Reads a single byte from the input"),
+    (
+        "io.readln",
+        "readln()",
+        "Reads a line from the input, including the line break",
+    ),
+    (
+        "io.eprint",
+        "eprint(values...)",
+        "Writes the values to the error output",
+    ),
+    (
+        "io.eprintln",
+        "eprintln(values...)",
+        "Writes every value on its own line to the error output",
+    ),
+    (
+        "fs.cat",
+        "cat(paths...)",
+        "The contents of the files concatenated",
+    ),
+    ("fs.rm", "rm(paths...)", "Removes the files"),
+    (
+        "fs.touch",
+        "touch(paths...)",
+        "Creates the files if they don't exist",
+    ),
+    ("fs.mv", "mv(from, to)", "Moves a file"),
+    (
+        "fs.fwrite",
+        "fwrite(path, content)",
+        "Writes a string to a file, replacing its contents",
+    ),
+    (
+        "conversion.string",
+        "string(value)",
+        "Converts a value to a string",
+    ),
+    (
+        "conversion.int",
+        "int(value)",
+        "Converts a value to an integer",
+    ),
+    (
+        "conversion.float",
+        "float(value)",
+        "Converts a value to a float",
+    ),
+    (
+        "conversion.bool",
+        "bool(value)",
+        "Converts a value to a bool",
+    ),
+    (
+        "conversion.to_arr",
+        "to_arr(values...)",
+        "Collects the values into an array, arrays are flattened",
+    ),
+    ("logic.or", "or(values...)", "True if any value is truthy"),
+    (
+        "logic.xor",
+        "xor(values...)",
+        "True if an odd number of values is truthy",
+    ),
+    (
+        "logic.and",
+        "and(values...)",
+        "True if every value is truthy",
+    ),
+    (
+        "logic.not",
+        "not(values...)",
+        "True if every value is falsy",
+    ),
+    (
+        "comparison.eq",
+        "eq(a, b, ...)",
+        "True if all values are equal",
+    ),
+    (
+        "comparison.neq",
+        "neq(a, b, ...)",
+        "True if every value differs from the next one",
+    ),
+    (
+        "comparison.lt",
+        "lt(a, b, ...)",
+        "True if every value is less than the next one",
+    ),
+    (
+        "comparison.le",
+        "le(a, b, ...)",
+        "True if every value is less than or equal to the next one",
+    ),
+    (
+        "comparison.gt",
+        "gt(a, b, ...)",
+        "True if every value is greater than the next one",
+    ),
+    (
+        "comparison.ge",
+        "ge(a, b, ...)",
+        "True if every value is greater than or equal to the next one",
+    ),
+    ("array.array", "array(values...)", "An array of the values"),
+    (
+        "array.len",
+        "len(value)",
+        "The length of an array, a string or a map",
+    ),
+    (
+        "array.slice",
+        "slice(array, start, end?)",
+        "The elements from start up to end, excluding end",
+    ),
+    (
+        "array.push",
+        "push(array, values...)",
+        "A copy of the array with the values appended",
+    ),
+    (
+        "map.dict",
+        "dict(key, value, ...)",
+        "A map of the alternating keys and values",
+    ),
+    ("map.keys", "keys(map)", "The keys of a map"),
+    ("map.values", "values(map)", "The values of a map"),
+    (
+        "map.entries",
+        "entries(map)",
+        "The entries of a map as arrays of key and value",
+    ),
+    (
+        "map.has",
+        "has(map, keys...)",
+        "True if the map contains all keys",
+    ),
+    (
+        "map.remove",
+        "remove(map, keys...)",
+        "A copy of the map without the keys",
+    ),
+    (
+        "panic.panic",
+        "panic(values...)",
+        "Stops the script with the values as message",
+    ),
+    (
+        "panic.assert",
+        "assert(condition, message...)",
+        "Stops the script if the condition is falsy",
+    ),
+    (
+        "error.error",
+        "error(message)",
+        "An error value, without raising it",
+    ),
+    (
+        "error.is_error",
+        "is_error(value)",
+        "True if the value is an error",
+    ),
+    (
+        "error.raise",
+        "raise(error)",
+        "Fails with the error, other values become the message of a new error",
+    ),
+    (
+        "error.error_kind",
+        "error_kind(error)",
+        "The name of the kind of an error",
+    ),
+    (
+        "error.error_message",
+        "error_message(error)",
+        "The message of an error",
+    ),
+    (
+        "iter.map",
+        "map(iter, function, args?)",
+        "An iter calling the function on every element",
+    ),
+    (
+        "iter.iter",
+        "iter(value)",
+        "An iter over an array, a map or another iter",
+    ),
+    ("iter.reverse", "reverse(iter)", "The iter in reverse order"),
+    ("iter.rewind", "rewind(iter)", "The iter starting over"),
+    (
+        "iter.foreach",
+        "foreach(iter, function, args?)",
+        "Calls the function on every element",
+    ),
+    (
+        "iter.collect",
+        "collect(iter)",
+        "The remaining elements of an iter as array",
+    ),
+    (
+        "functional.apply",
+        "apply(function, args...)",
+        "A function calling the function with the args in front of its own",
+    ),
+    (
+        "functional.call",
+        "call(function, args)",
+        "Calls the function with the array of args",
+    ),
+    (
+        "functional.chain",
+        "chain(functions..., args)",
+        "Calls the first function with the args and every following one with the previous result",
+    ),
+    (
+        "functional.do",
+        "do(times, function, args?)",
+        "The results of calling the function the given times",
+    ),
+    (
+        "functional.repeat",
+        "repeat(times, function, args?)",
+        "The result of the last of calling the function the given times",
+    ),
+    (
+        "functional.noop",
+        "noop(...)",
+        "Does nothing and returns none",
+    ),
+    (
+        "env.argv",
+        "argv(index?)",
+        "The args the host passed, the script path followed by its args, or the one at the index",
+    ),
+    ("thread.sleep", "sleep(milliseconds)", "Pauses the script"),
+];
@@ -1,7 +1,13 @@
-use crate::node::Block;
+use crate::{
+    day_map::DayMap,
+    manager::RuntimeManager,
+    node::FunctionDef,
+    profile,
+    runtime_error::{RuntimeError, RuntimeErrorKind, RuntimeResult},
+};
 use std::{
     hash::{Hash, Hasher},
-    sync::Arc,
+    sync::{Arc, Weak},
 };
 
 // NOTE
@@ -11,8 +17,9 @@ use std::{
 
 pub type ArgVec = Vec<DayObject>;
 pub type Args<'a> = &'a [DayObject];
-pub type RustClosure = Arc<dyn Fn(Args) -> DayObject>;
-pub type RustFunction = fn(Args) -> DayObject;
+/// A host function, script values holding it can be sent to other threads
+pub type RustClosure = Arc<dyn Fn(Args) -> RuntimeResult<DayObject> + Send + Sync>;
+pub type RustFunction = fn(Args) -> RuntimeResult<DayObject>;
 pub type ThreadId = usize;
 
 /// The basic data inside a variable
@@ -34,13 +41,19 @@ pub enum DayObject {
         id: ThreadId,
         raw: bool,
     },
+    /// A runtime error as a value, either caught with `try`/`catch` or created by `error`
+    Error(Box<RuntimeError>),
+    Map(DayMap),
 }
 
 impl DayObject {
-    pub fn call(&self, args: Args) -> DayObject {
+    pub fn call(&self, args: Args) -> RuntimeResult<DayObject> {
         match self {
             DayObject::Function(f) => f.call(args),
-            _ => panic!("Tried to call non function value"),
+            other => Err(RuntimeError::new(
+                RuntimeErrorKind::NotCallable,
+                format!("Tried to call non function value {:?}", other),
+            )),
         }
     }
 }
@@ -60,6 +73,8 @@ impl PartialEq for DayObject {
             (Array(a1), Array(a2)) => a1.eq(a2),
             (Function(f1), Function(f2)) => *f1 == *f2,
             (Iter(it1), Iter(it2)) => *it1 == *it2,
+            (Error(e1), Error(e2)) => e1.kind() == e2.kind() && e1.message() == e2.message(),
+            (Map(m1), Map(m2)) => m1 == m2,
             _ => false,
         }
     }
@@ -94,6 +109,13 @@ impl PartialOrd for DayObject {
                     Option::None
                 }
             }
+            (e1 @ Error(_), e2 @ Error(_)) | (e1 @ Map(_), e2 @ Map(_)) => {
+                if e1 == e2 {
+                    Some(Ordering::Equal)
+                } else {
+                    Option::None
+                }
+            }
             _ => Option::None,
         }
     }
@@ -116,6 +138,8 @@ impl std::fmt::Debug for DayObject {
             Function(_) => write!(f, "Function"),
             Iter(_) => write!(f, "Iter"),
             Thread { id, raw: _ } => write!(f, "Thread(Id: {})", *id),
+            Error(e) => write!(f, "Error({}: {})", e.kind(), e.message()),
+            Map(m) => write!(f, "{:?}", m),
         }
     }
 }
@@ -156,9 +180,10 @@ impl Hash for DayObject {
                 //TODO Move this to impl Hash for DayFunction
                 use DayFunction::*;
                 match f {
-                    RuntimeDef(n) => state.write_usize(n.as_ref() as *const _ as usize),
+                    RuntimeDef(n, _) => state.write_usize(n.as_ref() as *const _ as usize),
                     //IMPORTANT I don't know if this really works
                     Function(c) => state.write_usize(c as *const _ as *const () as usize),
+                    Closure(c) => state.write_usize(Arc::as_ptr(c) as *const () as usize),
                     Applicator(a, args) => {
                         state.write_usize(a.as_ref() as *const _ as *const () as usize);
                         args.hash(state);
@@ -173,6 +198,16 @@ impl Hash for DayObject {
                 state.write_u8(9);
                 state.write_usize(*id)
             }
+            Error(e) => {
+                state.write_u8(10);
+                e.kind().hash(state);
+                state.write(e.message().as_bytes());
+            }
+            Map(m) => {
+                // Map equality doesn't depend on the order of the entries, the hash can't either
+                state.write_u8(11);
+                state.write_usize(m.len());
+            }
         }
     }
 }
@@ -180,8 +215,39 @@ impl Hash for DayObject {
 #[derive(Clone)]
 pub enum DayFunction {
     Function(RustFunction),
+    /// A function registered by the host, it can capture state
+    Closure(RustClosure),
     Applicator(Box<DayFunction>, ArgVec),
-    RuntimeDef(Arc<Block>),
+    /// A function defined in crabscript and the scope it was declared in
+    RuntimeDef(Arc<FunctionDef>, Env),
+}
+
+/// The scope a function defined in crabscript was declared in
+pub enum Env {
+    Strong(Arc<RuntimeManager>),
+    /// A named function stored in the scope it was declared in, a strong reference would be a
+    /// cycle that is never freed. Clones are strong, so copies outside of the scope keep it alive.
+    Weak(Weak<RuntimeManager>),
+}
+
+impl Env {
+    fn as_ptr(&self) -> *const RuntimeManager {
+        match self {
+            Env::Strong(env) => Arc::as_ptr(env),
+            Env::Weak(env) => env.as_ptr(),
+        }
+    }
+}
+
+impl Clone for Env {
+    fn clone(&self) -> Self {
+        match self {
+            Env::Strong(env) => Env::Strong(Arc::clone(env)),
+            Env::Weak(env) => env
+                .upgrade()
+                .map_or_else(|| Env::Weak(env.clone()), Env::Strong),
+        }
+    }
 }
 
 impl std::fmt::Debug for DayFunction {
@@ -194,10 +260,13 @@ impl PartialEq for DayFunction {
     fn eq(&self, other: &Self) -> bool {
         use DayFunction::*;
         match (self, other) {
-            (RuntimeDef(a), RuntimeDef(b)) => a.as_ref() as *const _ == b.as_ref() as *const _,
-            (Function(a), Function(b)) => a as *const _ == b as *const _,
+            (RuntimeDef(a, env_a), RuntimeDef(b, env_b)) => {
+                Arc::ptr_eq(a, b) && std::ptr::eq(env_a.as_ptr(), env_b.as_ptr())
+            }
+            (Function(a), Function(b)) => std::ptr::fn_addr_eq(*a, *b),
+            (Closure(a), Closure(b)) => Arc::ptr_eq(a, b),
             (Applicator(a, args1), Applicator(b, args2)) => {
-                (a.as_ref() as *const _) == (b.as_ref() as *const _) && args1 == args2
+                std::ptr::eq(a.as_ref(), b.as_ref()) && args1 == args2
             }
             _ => false,
         }
@@ -205,10 +274,21 @@ impl PartialEq for DayFunction {
 }
 
 impl DayFunction {
-    pub fn call(&self, args: Args) -> DayObject {
+    pub fn call(&self, args: Args) -> RuntimeResult<DayObject> {
         match self {
-            DayFunction::Function(f) => f(args),
-            DayFunction::RuntimeDef(block) => block.execute_args(args).value(),
+            DayFunction::Function(f) => {
+                let _profile = profile::enter_std(*f);
+                f(args)
+            }
+            DayFunction::Closure(f) => f(args),
+            DayFunction::RuntimeDef(function, Env::Strong(env)) => function.call(args, env),
+            DayFunction::RuntimeDef(function, Env::Weak(env)) => match env.upgrade() {
+                Some(env) => function.call(args, &env),
+                None => Err(RuntimeError::new(
+                    RuntimeErrorKind::NotCallable,
+                    "The scope the function was declared in doesn't exist anymore",
+                )),
+            },
             DayFunction::Applicator(f, apply_args) => {
                 let mut a = apply_args.clone();
                 let mut args = args.to_vec();
@@ -0,0 +1,197 @@
+use crate::runtime_error::{RuntimeError, RuntimeErrorKind, RuntimeResult};
+use std::{
+    cell::{Cell, RefCell},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    time::{Duration, Instant},
+};
+
+//NOTE Only loop iterations and function calls are counted as steps, every other node
+//finishes after a bounded amount of work. Counting every node would slow down all scripts.
+
+/// The clock and the cancellation are only checked every this many steps
+const CHECK_INTERVAL: u64 = 1024;
+
+/// The limits of a single run of a script, `None` is unlimited
+#[derive(Clone, Debug, Default)]
+pub struct Limits {
+    /// The number of loop iterations and function calls
+    pub max_steps: Option<u64>,
+    /// The wall time
+    pub timeout: Option<Duration>,
+    /// The number of nested function calls
+    pub max_depth: Option<usize>,
+}
+
+/// Cancels the scripts run by an engine from any thread
+#[derive(Clone, Debug, Default)]
+pub struct CancelHandle(Arc<AtomicBool>);
+
+impl CancelHandle {
+    /// Makes the running script and all following ones fail until `reset` is called
+    pub fn cancel(&self) {
+        self.0.store(true, Ordering::Relaxed)
+    }
+
+    pub fn reset(&self) {
+        self.0.store(false, Ordering::Relaxed)
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.0.load(Ordering::Relaxed)
+    }
+}
+
+struct Budget {
+    limits: Limits,
+    cancel: CancelHandle,
+    deadline: Option<Instant>,
+    /// The steps taken before the current batch
+    steps: u64,
+    /// The number of steps the countdown of the current batch started with
+    batch: u64,
+}
+
+impl Budget {
+    /// Called when the countdown of a batch ran out, starts the next batch
+    fn next_batch(&mut self) -> RuntimeResult<u64> {
+        self.steps += self.batch;
+        if let Some(max) = self.limits.max_steps {
+            if self.steps > max {
+                return Err(RuntimeError::new(
+                    RuntimeErrorKind::StepLimit,
+                    format!("The script exceeded its limit of {} steps", max),
+                ));
+            }
+        }
+        self.check()?;
+
+        // The batch ends at the first step exceeding the limit
+        self.batch = match self.limits.max_steps {
+            Some(max) => CHECK_INTERVAL.min(max.saturating_add(1) - self.steps),
+            None => CHECK_INTERVAL,
+        };
+        Ok(self.batch)
+    }
+
+    fn check(&self) -> RuntimeResult<()> {
+        if self.cancel.is_cancelled() {
+            return Err(RuntimeError::new(
+                RuntimeErrorKind::Cancelled,
+                "The script was cancelled",
+            ));
+        }
+
+        match self.deadline {
+            Some(deadline) if Instant::now() > deadline => Err(RuntimeError::new(
+                RuntimeErrorKind::Timeout,
+                format!(
+                    "The script exceeded its time limit of {:?}",
+                    self.limits.timeout.unwrap_or_default()
+                ),
+            )),
+            _ => Ok(()),
+        }
+    }
+}
+
+//NOTE The cells are checked on every step, the budget behind the refcell only once per batch
+thread_local! {
+    /// The budget of the script running on this thread
+    static BUDGET: RefCell<Option<Budget>> = const { RefCell::new(None) };
+    /// The steps left in the current batch, without a budget it never runs out
+    static COUNTDOWN: Cell<u64> = const { Cell::new(u64::MAX) };
+    static DEPTH: Cell<usize> = const { Cell::new(0) };
+    static MAX_DEPTH: Cell<usize> = const { Cell::new(usize::MAX) };
+}
+
+/// Restores the budget of the enclosing run when dropped
+pub struct BudgetGuard {
+    previous: Option<Budget>,
+    countdown: u64,
+    depth: usize,
+    max_depth: usize,
+}
+
+impl Drop for BudgetGuard {
+    fn drop(&mut self) {
+        let previous = self.previous.take();
+        BUDGET.with(|budget| *budget.borrow_mut() = previous);
+        COUNTDOWN.with(|countdown| countdown.set(self.countdown));
+        DEPTH.with(|depth| depth.set(self.depth));
+        MAX_DEPTH.with(|max_depth| max_depth.set(self.max_depth));
+    }
+}
+
+/// Limits the code running on this thread until the guard is dropped
+pub fn install(limits: &Limits, cancel: &CancelHandle) -> RuntimeResult<BudgetGuard> {
+    let mut budget = Budget {
+        deadline: limits.timeout.map(|timeout| Instant::now() + timeout),
+        limits: limits.clone(),
+        cancel: cancel.clone(),
+        steps: 0,
+        batch: 0,
+    };
+    let batch = budget.next_batch()?;
+
+    let guard = BudgetGuard {
+        previous: BUDGET.with(|current| current.borrow_mut().replace(budget)),
+        countdown: COUNTDOWN.with(|countdown| countdown.replace(batch)),
+        depth: DEPTH.with(|depth| depth.replace(0)),
+        max_depth: MAX_DEPTH
+            .with(|max_depth| max_depth.replace(limits.max_depth.unwrap_or(usize::MAX))),
+    };
+    Ok(guard)
+}
+
+/// Counts a loop iteration
+#[inline]
+pub fn step() -> RuntimeResult<()> {
+    let left = COUNTDOWN.with(|countdown| {
+        let left = countdown.get() - 1;
+        countdown.set(left);
+        left
+    });
+    if left == 0 {
+        next_batch()
+    } else {
+        Ok(())
+    }
+}
+
+#[cold]
+fn next_batch() -> RuntimeResult<()> {
+    let batch = BUDGET.with(|budget| match &mut *budget.borrow_mut() {
+        Some(budget) => budget.next_batch(),
+        None => Ok(u64::MAX),
+    })?;
+    COUNTDOWN.with(|countdown| countdown.set(batch));
+    Ok(())
+}
+
+/// Leaves the function call when dropped
+pub struct CallGuard(());
+
+impl Drop for CallGuard {
+    fn drop(&mut self) {
+        DEPTH.with(|depth| depth.set(depth.get() - 1))
+    }
+}
+
+/// Counts a function call, it lasts until the guard is dropped
+#[inline]
+pub fn enter_call() -> RuntimeResult<CallGuard> {
+    step()?;
+    let depth = DEPTH.with(|depth| depth.get());
+    if depth >= MAX_DEPTH.with(|max_depth| max_depth.get()) {
+        return Err(RuntimeError::new(
+            RuntimeErrorKind::RecursionLimit,
+            format!("The script exceeded its limit of {} nested calls", depth),
+        ));
+    }
+
+    DEPTH.with(|d| d.set(depth + 1));
+    Ok(CallGuard(()))
+}
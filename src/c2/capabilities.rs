@@ -0,0 +1,131 @@
+use crate::{
+    base::{Args, DayObject, RustClosure, RustFunction},
+    build_std_library,
+    runtime_error::{RuntimeError, RuntimeResult},
+};
+use std::{
+    collections::HashSet,
+    path::{Component, Path, PathBuf},
+    sync::Arc,
+};
+
+/// Decides which std modules the scripts of an engine can use and which files they can access.
+/// Calling a function of a denied module fails with a `PermissionDenied` error.
+#[derive(Clone, Debug)]
+pub struct Capabilities {
+    allowed: HashSet<String>,
+    fs_root: Option<PathBuf>,
+}
+
+impl Capabilities {
+    /// Every std module with unrestricted file system access
+    pub fn all() -> Self {
+        Capabilities {
+            allowed: build_std_library()
+                .keys()
+                .map(|module| module.to_string())
+                .collect(),
+            fs_root: None,
+        }
+    }
+
+    /// Only the modules that can't affect anything outside of the script,
+    /// `io`, `fs`, `env` and `thread` are denied
+    pub fn sandboxed() -> Self {
+        ["io", "fs", "env", "thread"]
+            .iter()
+            .fold(Self::all(), |capabilities, module| {
+                capabilities.deny(module)
+            })
+    }
+
+    pub fn allow(mut self, module: &str) -> Self {
+        self.allowed.insert(module.to_string());
+        self
+    }
+
+    pub fn deny(mut self, module: &str) -> Self {
+        self.allowed.remove(module);
+        self
+    }
+
+    /// Allows the `fs` module and file imports, but only inside of `root`.
+    /// Every path is resolved relative to it, even absolute ones.
+    pub fn fs_root(mut self, root: impl Into<PathBuf>) -> Self {
+        self.fs_root = Some(root.into());
+        self.allow("fs")
+    }
+
+    pub fn allows(&self, module: &str) -> bool {
+        self.allowed.contains(module)
+    }
+
+    pub fn get_fs_root(&self) -> Option<&Path> {
+        self.fs_root.as_deref()
+    }
+}
+
+impl Default for Capabilities {
+    fn default() -> Self {
+        Self::all()
+    }
+}
+
+/// A replacement for the std function `module.name` that is always denied
+pub fn denied(module: &str, name: &str) -> RustClosure {
+    let message = format!("{}.{} is not allowed", module, name);
+    Arc::new(move |_| Err(RuntimeError::permission_denied(message.clone())))
+}
+
+/// Wraps the `fs` function `name` to resolve its paths inside of `root`
+pub fn confined_fs(name: &str, f: RustFunction, root: PathBuf) -> RustClosure {
+    // Only the leading args are paths
+    let paths = match name {
+        "mv" => 2,
+        "fwrite" => 1,
+        _ => usize::MAX,
+    };
+
+    Arc::new(move |args: Args| {
+        let args = args
+            .iter()
+            .enumerate()
+            .map(|(i, arg)| match arg {
+                DayObject::Str(path) if i < paths => Ok(DayObject::Str(
+                    confine(&root, path)?.to_string_lossy().into_owned(),
+                )),
+                other => Ok(other.clone()),
+            })
+            .collect::<RuntimeResult<Vec<_>>>()?;
+        f(&args)
+    })
+}
+
+/// Resolves `path` inside of `root`, paths leaving it with `..` are denied.
+/// Symbolic links inside of the root are followed, the root must not contain
+/// links to files outside of it.
+fn confine(root: &Path, path: &str) -> RuntimeResult<PathBuf> {
+    let mut confined = root.to_path_buf();
+    let mut depth = 0;
+    for component in Path::new(path).components() {
+        match component {
+            Component::Normal(part) => {
+                confined.push(part);
+                depth += 1;
+            }
+            Component::ParentDir if depth > 0 => {
+                confined.pop();
+                depth -= 1;
+            }
+            Component::ParentDir => {
+                return Err(RuntimeError::permission_denied(format!(
+                    "{} is outside of the file system root",
+                    path
+                )))
+            }
+            Component::RootDir | Component::CurDir | Component::Prefix(_) => (),
+        }
+    }
+
+    Ok(confined)
+}
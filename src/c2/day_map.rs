@@ -0,0 +1,181 @@
+use crate::{
+    base::DayObject,
+    runtime_error::{RuntimeError, RuntimeResult},
+};
+use ahash::RandomState as AHasherBuilder;
+use std::{
+    collections::HashMap,
+    hash::{Hash, Hasher},
+};
+
+/// A map from `DayObject`s to `DayObject`s which remembers the insertion order of its keys.
+/// Only values with a reflexive equality can be keys, that excludes floats and everything
+/// containing them as well as functions, iters and threads.
+#[derive(Clone, Default)]
+pub struct DayMap {
+    entries: Vec<(DayObject, DayObject)>,
+    indices: HashMap<MapKey, usize, AHasherBuilder>,
+}
+
+/// Wrapper for keys which passed `check_key`
+#[derive(Clone, PartialEq)]
+struct MapKey(DayObject);
+
+// Keys are checked so their equality is always reflexive
+impl Eq for MapKey {}
+
+impl Hash for MapKey {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.0.hash(state)
+    }
+}
+
+fn check_key(key: &DayObject) -> RuntimeResult<()> {
+    match key {
+        DayObject::None
+        | DayObject::Bool(_)
+        | DayObject::Integer(_)
+        | DayObject::Character(_)
+        | DayObject::Str(_) => Ok(()),
+        DayObject::Array(arr) => arr.iter().try_for_each(check_key),
+        other => Err(RuntimeError::type_error(format!(
+            "{:?} can't be used as a map key",
+            other
+        ))),
+    }
+}
+
+impl DayMap {
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    pub fn with_capacity(capacity: usize) -> Self {
+        DayMap {
+            entries: Vec::with_capacity(capacity),
+            indices: HashMap::with_capacity_and_hasher(capacity, AHasherBuilder::new()),
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// Inserts `value` at `key`, if the key was already present its position is kept
+    /// and the old value returned
+    pub fn insert(&mut self, key: DayObject, value: DayObject) -> RuntimeResult<Option<DayObject>> {
+        check_key(&key)?;
+        let key = MapKey(key);
+        match self.indices.get(&key) {
+            Some(i) => Ok(Some(std::mem::replace(&mut self.entries[*i].1, value))),
+            None => {
+                self.indices.insert(key.clone(), self.entries.len());
+                self.entries.push((key.0, value));
+                Ok(None)
+            }
+        }
+    }
+
+    fn index_of(&self, key: &DayObject) -> Option<usize> {
+        check_key(key).ok()?;
+        self.indices.get(&MapKey(key.clone())).copied()
+    }
+
+    pub fn get(&self, key: &DayObject) -> Option<&DayObject> {
+        self.index_of(key).map(|i| &self.entries[i].1)
+    }
+
+    pub fn get_mut(&mut self, key: &DayObject) -> Option<&mut DayObject> {
+        self.index_of(key).map(move |i| &mut self.entries[i].1)
+    }
+
+    pub fn contains_key(&self, key: &DayObject) -> bool {
+        self.index_of(key).is_some()
+    }
+
+    /// Removes `key` keeping the order of the other entries
+    pub fn remove(&mut self, key: &DayObject) -> Option<DayObject> {
+        let index = self.index_of(key)?;
+        self.indices.remove(&MapKey(key.clone()));
+        let (_, value) = self.entries.remove(index);
+        for i in self.indices.values_mut() {
+            if *i > index {
+                *i -= 1;
+            }
+        }
+
+        Some(value)
+    }
+
+    /// Iterates over all entries in insertion order
+    pub fn iter(&self) -> impl Iterator<Item = (&DayObject, &DayObject)> {
+        self.entries.iter().map(|(k, v)| (k, v))
+    }
+
+    pub fn keys(&self) -> impl Iterator<Item = &DayObject> {
+        self.entries.iter().map(|(k, _)| k)
+    }
+
+    pub fn values(&self) -> impl Iterator<Item = &DayObject> {
+        self.entries.iter().map(|(_, v)| v)
+    }
+}
+
+/// Maps are equal if they contain the same entries, regardless of their order
+impl PartialEq for DayMap {
+    fn eq(&self, other: &Self) -> bool {
+        self.len() == other.len() && self.iter().all(|(k, v)| other.get(k) == Some(v))
+    }
+}
+
+impl std::fmt::Debug for DayMap {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_map().entries(self.iter()).finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use DayObject::*;
+
+    #[test]
+    fn insertion_order() {
+        let mut map = DayMap::new();
+        map.insert(Str("b".to_string()), Integer(1)).unwrap();
+        map.insert(Str("a".to_string()), Integer(2)).unwrap();
+        map.insert(Str("b".to_string()), Integer(3)).unwrap();
+
+        assert_eq!(
+            map.keys().cloned().collect::<Vec<_>>(),
+            vec![Str("b".to_string()), Str("a".to_string())]
+        );
+        assert_eq!(map.get(&Str("b".to_string())), Some(&Integer(3)));
+    }
+
+    #[test]
+    fn remove_keeps_order() {
+        let mut map = DayMap::new();
+        for i in 0..4 {
+            map.insert(Integer(i), Integer(i * 10)).unwrap();
+        }
+
+        assert_eq!(map.remove(&Integer(1)), Some(Integer(10)));
+        assert_eq!(map.remove(&Integer(1)), Option::None);
+        assert_eq!(
+            map.values().cloned().collect::<Vec<_>>(),
+            vec![Integer(0), Integer(20), Integer(30)]
+        );
+        assert_eq!(map.get(&Integer(3)), Some(&Integer(30)));
+    }
+
+    #[test]
+    fn invalid_key() {
+        assert!(DayMap::new().insert(Float(1.5), None).is_err());
+        assert!(!DayMap::new().contains_key(&Float(1.5)));
+    }
+}
@@ -0,0 +1,249 @@
+//NOTE Lets a front-end pause scripts before their statements to inspect and step through them.
+//
+//Without a debugger the hooks in `RootNode::execute` and `FunctionDef::call` only check a thread
+//local flag. With one every executed block records its scope and every call of a function
+//declared in crabscript its name, so the variables visible at a pause can be found by name.
+//Only the tree walker has these hooks, the engine doesn't use the vm while debugging.
+
+use crate::{base::DayObject, manager::RuntimeManager, node::BlockInfo, span::Span};
+use std::{
+    cell::{Cell, RefCell},
+    path::Path,
+    rc::Rc,
+    sync::Arc,
+};
+
+/// Decides where scripts pause and what they do afterwards
+pub trait Debugger {
+    /// Whether the statements starting on `line` pause, `file` is the imported file
+    /// they are in or `None` for the code compiled by the engine
+    fn is_breakpoint(&self, file: Option<&Path>, line: u64) -> bool;
+
+    /// Called before a statement is executed if it is at a breakpoint or reached by a step
+    fn paused(&mut self, pause: &Pause) -> Action;
+}
+
+pub type SharedDebugger = Rc<RefCell<dyn Debugger>>;
+
+/// What the script does after a pause
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Action {
+    /// Runs until the next breakpoint
+    Continue,
+    /// Pauses at the next statement, also inside of called functions
+    StepInto,
+    /// Pauses at the next statement that isn't inside of a function called meanwhile
+    StepOver,
+    /// Pauses at the next statement after the current function returned
+    StepOut,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Reason {
+    Breakpoint,
+    Step,
+}
+
+/// A scope visible at a pause, see `Pause::scopes`
+#[derive(Debug)]
+pub struct ScopeVars {
+    pub depth: usize,
+    /// The declared variables ordered by their ids
+    pub vars: Vec<(String, DayObject)>,
+}
+
+/// A script paused before a statement
+pub struct Pause<'a> {
+    /// Where the statement starts
+    pub span: Span,
+    /// The imported file the statement is in
+    pub file: Option<&'a Path>,
+    pub reason: Reason,
+    /// The names of the called functions, the innermost last
+    pub calls: &'a [String],
+    frames: &'a [Frame],
+}
+
+impl Pause<'_> {
+    /// The scopes the statement can access, the innermost first
+    pub fn scopes(&self) -> Vec<ScopeVars> {
+        let mut scopes = Vec::new();
+        let mut current = self.frames.last().map(|frame| Arc::clone(&frame.scope));
+        while let Some(scope) = current {
+            // Functions can outlive the blocks they were declared in, then the names are unknown
+            let names = self
+                .frames
+                .iter()
+                .rev()
+                .find(|frame| Arc::ptr_eq(&frame.scope, &scope))
+                .map(|frame| frame.info.names.as_slice())
+                .unwrap_or_default();
+            let depth = scope.get_depth();
+            let vars = (0..scope.len_vars())
+                .map(|id| {
+                    let name = names.get(id).cloned();
+                    (
+                        name.unwrap_or_else(|| format!("#{}", id)),
+                        scope.get_var(id, depth),
+                    )
+                })
+                .collect();
+            scopes.push(ScopeVars { depth, vars });
+            current = scope.get_predecessor();
+        }
+        scopes
+    }
+
+    /// The value of the variable `name` the statement would access
+    pub fn var(&self, name: &str) -> Option<DayObject> {
+        self.scopes().into_iter().find_map(|scope| {
+            scope
+                .vars
+                .into_iter()
+                .find(|(var, _)| var == name)
+                .map(|(_, value)| value)
+        })
+    }
+}
+
+/// A block being executed
+struct Frame {
+    info: Arc<BlockInfo>,
+    scope: Arc<RuntimeManager>,
+}
+
+struct Session {
+    debugger: SharedDebugger,
+    /// The last step and the number of calls when it was taken, `None` after continuing
+    step: Option<(Action, usize)>,
+    frames: Vec<Frame>,
+    calls: Vec<String>,
+}
+
+thread_local! {
+    /// Whether a debugger is installed, checked before every statement
+    static ACTIVE: Cell<bool> = const { Cell::new(false) };
+    static SESSION: RefCell<Option<Session>> = const { RefCell::new(None) };
+}
+
+/// Restores the debugger of the enclosing run when dropped
+pub struct DebugGuard {
+    previous: Option<Session>,
+}
+
+impl Drop for DebugGuard {
+    fn drop(&mut self) {
+        let previous = self.previous.take();
+        ACTIVE.with(|active| active.set(previous.is_some()));
+        SESSION.with(|session| *session.borrow_mut() = previous);
+    }
+}
+
+/// Makes `debugger` pause the code running on this thread until the guard is dropped,
+/// `None` runs the code without pausing
+pub fn install(debugger: Option<&SharedDebugger>) -> DebugGuard {
+    let session = debugger.map(|debugger| Session {
+        debugger: Rc::clone(debugger),
+        step: None,
+        frames: Vec::new(),
+        calls: Vec::new(),
+    });
+    ACTIVE.with(|active| active.set(session.is_some()));
+    DebugGuard {
+        previous: SESSION.with(|current| current.replace(session)),
+    }
+}
+
+/// Leaves the scope when dropped
+pub struct ScopeGuard(());
+
+impl Drop for ScopeGuard {
+    fn drop(&mut self) {
+        with_session(|session| session.frames.pop());
+    }
+}
+
+/// Records that the block of `info` is executed in `scope` until the guard is dropped
+#[inline]
+pub fn enter_scope(info: &Arc<BlockInfo>, scope: &Arc<RuntimeManager>) -> Option<ScopeGuard> {
+    if !ACTIVE.with(Cell::get) {
+        return None;
+    }
+
+    with_session(|session| {
+        session.frames.push(Frame {
+            info: Arc::clone(info),
+            scope: Arc::clone(scope),
+        })
+    })?;
+    Some(ScopeGuard(()))
+}
+
+/// Leaves the function call when dropped
+pub struct CallGuard(());
+
+impl Drop for CallGuard {
+    fn drop(&mut self) {
+        with_session(|session| session.calls.pop());
+    }
+}
+
+/// Records a call of the function `name` until the guard is dropped
+#[inline]
+pub fn enter_call(name: Option<&str>) -> Option<CallGuard> {
+    if !ACTIVE.with(Cell::get) {
+        return None;
+    }
+
+    with_session(|session| session.calls.push(name.unwrap_or("fn").to_string()))?;
+    Some(CallGuard(()))
+}
+
+/// Called before the statement starting at `span` is executed
+#[inline]
+pub fn statement(span: Span) {
+    if ACTIVE.with(Cell::get) {
+        pause(span)
+    }
+}
+
+#[cold]
+fn pause(span: Span) {
+    with_session(|session| {
+        let file = session.frames.last().and_then(|f| f.info.file.as_deref());
+        let calls = session.calls.len();
+        let debugger = Rc::clone(&session.debugger);
+
+        let reason = if debugger.borrow().is_breakpoint(file, span.line) {
+            Reason::Breakpoint
+        } else {
+            match session.step {
+                Some((Action::StepInto, _)) => Reason::Step,
+                Some((Action::StepOver, from)) if calls <= from => Reason::Step,
+                Some((Action::StepOut, from)) if calls < from => Reason::Step,
+                _ => return,
+            }
+        };
+
+        let pause = Pause {
+            span,
+            file,
+            reason,
+            calls: &session.calls,
+            frames: &session.frames,
+        };
+        // Code the debugger runs meanwhile doesn't pause
+        ACTIVE.with(|active| active.set(false));
+        let action = debugger.borrow_mut().paused(&pause);
+        ACTIVE.with(|active| active.set(true));
+
+        session.step = match action {
+            Action::Continue => None,
+            step => Some((step, calls)),
+        };
+    });
+}
+
+fn with_session<T>(f: impl FnOnce(&mut Session) -> T) -> Option<T> {
+    SESSION.with(|session| session.borrow_mut().as_mut().map(f))
+}
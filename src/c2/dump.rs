@@ -0,0 +1,408 @@
+//NOTE Renders what the lexer and the parser produced, for debugging them.
+//
+//The nodes are first converted into a generic tree of `Item`s, which is then rendered
+//either as indented text or as JSON. Every block is a scope boundary, it shows its
+//`NodePurpose` and the number of variables it declares. Identifiers show the `(id, depth)`
+//slot they were resolved to, the scopes of the parser with the names of their variables
+//are dumped next to the tree.
+
+use crate::{
+    base::RustFunction,
+    build_std_library,
+    node::{Block, BranchNode, IndexOperation, Node, Param},
+    span::Span,
+    tokenizer::{build_lexer, SpannedTokens},
+};
+use std::{fmt::Write, ptr::fn_addr_eq};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Format {
+    Text,
+    Json,
+}
+
+/// A scope of the parser, the ids of its variables are their slots in the scope
+#[derive(Debug)]
+pub struct ScopeInfo {
+    pub depth: usize,
+    /// The block of the scope, `None` for the top level
+    pub span: Option<Span>,
+    /// The variables ordered by their ids
+    pub vars: Vec<VarInfo>,
+    pub children: Vec<ScopeInfo>,
+}
+
+#[derive(Debug)]
+pub struct VarInfo {
+    pub name: String,
+    pub id: usize,
+    pub is_const: bool,
+    /// The name in the declaration
+    pub span: Span,
+}
+
+/// Renders the tokens of `src` with the positions they start at
+pub fn dump_tokens(src: &str, format: Format) -> String {
+    let lexer = build_lexer().expect("the lexer definition is valid");
+    let tokens = SpannedTokens::new(src, lexer.tokens(src));
+
+    let mut out = String::new();
+    match format {
+        Format::Text => {
+            for t in tokens {
+                let at = format!("{}:{}", t.span.line, t.span.column);
+                writeln!(out, "{:<8} {:?}", at, t.token).unwrap();
+            }
+        }
+        Format::Json => {
+            out.push('[');
+            for (i, t) in tokens.enumerate() {
+                if i > 0 {
+                    out.push(',');
+                }
+                write!(
+                    out,
+                    "{{\"line\":{},\"column\":{},\"token\":",
+                    t.span.line, t.span.column
+                )
+                .unwrap();
+                json_str(&mut out, &format!("{:?}", t.token));
+                out.push('}');
+            }
+            out.push_str("]\n");
+        }
+    }
+    out
+}
+
+/// Renders the scopes of the parser and the tree of `block`
+pub fn dump_ast(block: &Block, scopes: &ScopeInfo, format: Format) -> String {
+    let mut names: Vec<(String, RustFunction)> = build_std_library()
+        .into_iter()
+        .flat_map(|(module, functions)| {
+            functions
+                .into_iter()
+                .map(move |(name, f)| (format!("{}.{}", module, name), f))
+        })
+        .collect();
+    // Some functions are in multiple modules, the same name has to be picked every time
+    names.sort_by(|(a, _), (b, _)| a.cmp(b));
+
+    let dumper = Dumper { names };
+    let scopes = scope_item(scopes);
+    let ast = dumper.block(block);
+
+    let mut out = String::new();
+    match format {
+        Format::Text => {
+            out.push_str("scopes:\n");
+            scopes.text(&mut out, 1);
+            out.push_str("ast:\n");
+            ast.text(&mut out, 1);
+        }
+        Format::Json => {
+            out.push_str("{\"scopes\":");
+            scopes.json(&mut out);
+            out.push_str(",\"ast\":");
+            ast.json(&mut out);
+            out.push_str("}\n");
+        }
+    }
+    out
+}
+
+enum Attr {
+    Num(usize),
+    Flag(bool),
+    Text(String),
+}
+
+impl std::fmt::Display for Attr {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Attr::Num(n) => write!(f, "{}", n),
+            Attr::Flag(b) => write!(f, "{}", b),
+            Attr::Text(s) => write!(f, "{}", s),
+        }
+    }
+}
+
+/// A node of a dumped tree
+struct Item {
+    kind: String,
+    attrs: Vec<(&'static str, Attr)>,
+    children: Vec<Item>,
+}
+
+impl Item {
+    fn new(kind: &str) -> Self {
+        Item {
+            kind: kind.to_string(),
+            attrs: Vec::new(),
+            children: Vec::new(),
+        }
+    }
+
+    fn attr(mut self, name: &'static str, value: Attr) -> Self {
+        self.attrs.push((name, value));
+        self
+    }
+
+    fn at(self, span: Span) -> Self {
+        self.attr("at", Attr::Text(format!("{}:{}", span.line, span.column)))
+    }
+
+    fn child(mut self, child: Item) -> Self {
+        self.children.push(child);
+        self
+    }
+
+    fn text(&self, out: &mut String, indent: usize) {
+        write!(out, "{:width$}{}", "", self.kind, width = indent * 2).unwrap();
+        for (name, value) in &self.attrs {
+            write!(out, " {}={}", name, value).unwrap();
+        }
+        out.push('\n');
+        for c in &self.children {
+            c.text(out, indent + 1);
+        }
+    }
+
+    fn json(&self, out: &mut String) {
+        out.push_str("{\"kind\":");
+        json_str(out, &self.kind);
+        for (name, value) in &self.attrs {
+            write!(out, ",\"{}\":", name).unwrap();
+            match value {
+                Attr::Text(s) => json_str(out, s),
+                other => write!(out, "{}", other).unwrap(),
+            }
+        }
+        if !self.children.is_empty() {
+            out.push_str(",\"children\":[");
+            for (i, c) in self.children.iter().enumerate() {
+                if i > 0 {
+                    out.push(',');
+                }
+                c.json(out);
+            }
+            out.push(']');
+        }
+        out.push('}');
+    }
+}
+
+fn json_str(out: &mut String, s: &str) {
+    out.push('"');
+    for c in s.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '\t' => out.push_str("\\t"),
+            '\r' => out.push_str("\\r"),
+            c if (c as u32) < 0x20 => write!(out, "\\u{:04x}", c as u32).unwrap(),
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+}
+
+fn scope_item(scope: &ScopeInfo) -> Item {
+    let mut item = Item::new("Scope").attr("depth", Attr::Num(scope.depth));
+    for var in &scope.vars {
+        item = item.child(
+            Item::new("Var")
+                .attr("name", Attr::Text(var.name.clone()))
+                .attr("id", Attr::Num(var.id))
+                .attr("depth", Attr::Num(scope.depth))
+                .attr("const", Attr::Flag(var.is_const)),
+        );
+    }
+    for c in &scope.children {
+        item = item.child(scope_item(c));
+    }
+    item
+}
+
+struct Dumper {
+    /// The qualified names of the std functions
+    names: Vec<(String, RustFunction)>,
+}
+
+impl Dumper {
+    fn block(&self, block: &Block) -> Item {
+        let mut item = Item::new("Block")
+            .attr("purpose", Attr::Text(format!("{:?}", block.block.purpose)))
+            .attr("capacity", Attr::Num(block.capacity));
+        for node in block.block.nodes() {
+            item = item.child(self.node(node));
+        }
+        item
+    }
+
+    fn node(&self, node: &Node) -> Item {
+        match node {
+            Node::RustFunction(f) => {
+                let name = self
+                    .names
+                    .iter()
+                    .find(|(_, g)| fn_addr_eq(*g, f.0))
+                    .map_or("?", |(name, _)| name);
+                Item::new("RustFunction").attr("name", Attr::Text(name.to_string()))
+            }
+            Node::Identifier(id) => Item::new("Identifier")
+                .attr("id", Attr::Num(id.id))
+                .attr("depth", Attr::Num(id.depth)),
+            Node::Data(value) => {
+                Item::new("Data").attr("value", Attr::Text(format!("{:?}", value)))
+            }
+            Node::FunctionCall(call) => {
+                let mut item = Item::new("Call").at(call.span).child(self.node(&call.expr));
+                for a in &call.args {
+                    item = item.child(self.node(a));
+                }
+                item
+            }
+            Node::For {
+                expr,
+                block,
+                destructure,
+                span,
+            } => Item::new("For")
+                .at(*span)
+                .attr("destructure", Attr::Flag(*destructure))
+                .child(self.node(expr))
+                .child(self.block(block)),
+            Node::Assignment { assignee, value } => Item::new("Assignment")
+                .child(self.node(assignee))
+                .child(self.node(value)),
+            Node::Declaration { value, id } => Item::new("Declaration")
+                .attr("id", Attr::Num(*id))
+                .child(self.node(value)),
+            Node::ConstDeclaration { value, id } => Item::new("ConstDeclaration")
+                .attr("id", Attr::Num(*id))
+                .child(self.node(value)),
+            Node::BranchNode(branches) => {
+                let mut item = Item::new("Branches");
+                for b in branches {
+                    item = item.child(match b {
+                        BranchNode::If { block } => Item::new("If")
+                            .child(self.node(&block.condition))
+                            .child(self.block(&block.block)),
+                        BranchNode::ElseIf { block } => Item::new("ElseIf")
+                            .child(self.node(&block.condition))
+                            .child(self.block(&block.block)),
+                        BranchNode::Else { block } => Item::new("Else").child(self.block(block)),
+                    });
+                }
+                item
+            }
+            Node::While {
+                condition,
+                block,
+                span,
+            } => Item::new("While")
+                .at(*span)
+                .child(self.node(condition))
+                .child(self.block(block)),
+            Node::Block(block) => self.block(block),
+            Node::Ret(value) => {
+                let item = Item::new("Ret");
+                match value {
+                    Some(value) => item.child(self.node(value)),
+                    None => item,
+                }
+            }
+            Node::Index(index) => {
+                let mut item = Item::new("Index")
+                    .at(index.span)
+                    .child(self.node(&index.initial));
+                for op in &index.index_ops {
+                    item = item.child(match op {
+                        IndexOperation::Index(i) => Item::new("Subscript").child(self.node(i)),
+                        IndexOperation::Slice { start, end } => {
+                            let bound = |name: &str, b: &Option<Box<Node>>| match b {
+                                Some(b) => Item::new(name).child(self.node(b)),
+                                None => Item::new(name),
+                            };
+                            Item::new("Slice")
+                                .child(bound("Start", start))
+                                .child(bound("End", end))
+                        }
+                    });
+                }
+                item
+            }
+            Node::FunctionDeclaration { function, id } => {
+                let mut item = Item::new("Function");
+                if let Some(name) = &function.name {
+                    item = item.attr("name", Attr::Text(name.clone()));
+                }
+                if let Some(id) = id {
+                    item = item.attr("id", Attr::Num(*id));
+                }
+                match &function.params {
+                    Some(params) => {
+                        for (id, param) in params.iter().enumerate() {
+                            let param_item = match param {
+                                Param::Required => Item::new("Param"),
+                                Param::Optional(default) => {
+                                    Item::new("OptionalParam").child(self.node(default))
+                                }
+                                Param::Rest => Item::new("RestParam"),
+                            };
+                            item = item.child(param_item.attr("id", Attr::Num(id)));
+                        }
+                    }
+                    None => item = item.attr("params", Attr::Text("args".to_string())),
+                }
+                item.child(self.block(&function.body))
+            }
+            Node::Try {
+                block,
+                catch,
+                binds_error,
+            } => Item::new("Try")
+                .attr("binds_error", Attr::Flag(*binds_error))
+                .child(self.block(block))
+                .child(self.block(catch)),
+            Node::Break(value) => {
+                let item = Item::new("Break");
+                match value {
+                    Some(value) => item.child(self.node(value)),
+                    None => item,
+                }
+            }
+            Node::Continue => Item::new("Continue"),
+            Node::Logical { op, lhs, rhs, span } => Item::new("Logical")
+                .attr("op", Attr::Text(format!("{:?}", op)))
+                .at(*span)
+                .child(self.node(lhs))
+                .child(self.node(rhs)),
+            Node::MapLiteral { entries, span } => {
+                let mut item = Item::new("Map").at(*span);
+                for (key, value) in entries {
+                    item = item.child(
+                        Item::new("Entry")
+                            .child(self.node(key))
+                            .child(self.node(value)),
+                    );
+                }
+                item
+            }
+            Node::Args => Item::new("Args"),
+            Node::Import { module, span } => Item::new("Import")
+                .attr("path", Attr::Text(module.path().display().to_string()))
+                .at(*span),
+            Node::ModuleVar { module, id } => Item::new("ModuleVar")
+                .attr("path", Attr::Text(module.path().display().to_string()))
+                .attr("id", Attr::Num(*id)),
+            Node::BinaryOp { op, lhs, rhs, span } => Item::new("BinaryOp")
+                .attr("op", Attr::Text(format!("{:?}", op)))
+                .at(*span)
+                .child(self.node(lhs))
+                .child(self.node(rhs)),
+        }
+    }
+}
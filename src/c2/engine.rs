@@ -0,0 +1,329 @@
+#[cfg(feature = "vm")]
+use crate::vm::{self, Backend};
+use crate::{
+    base::{Args, DayObject},
+    budget::{self, CancelHandle, Limits},
+    build_pre_map, build_std_library,
+    capabilities::{confined_fs, denied, Capabilities},
+    debug::{self, Debugger, SharedDebugger},
+    dump::ScopeInfo,
+    error::CrabError,
+    hooks,
+    manager::RuntimeManager,
+    module::{FileAccess, Module},
+    node::{Block, ExpressionResult},
+    optimizer::OptLevel,
+    parser::Parser,
+    profile::{self, Profile, Profiler, SharedProfiler},
+    runtime_error::{RuntimeError, RuntimeErrorKind, RuntimeResult},
+    std_modules::env,
+    stdio::{self, Stdio},
+    tokenizer::{build_lexer, TokenStream},
+};
+use std::{
+    cell::RefCell,
+    path::PathBuf,
+    rc::Rc,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    },
+};
+
+/// The id of the next engine created
+static NEXT_ENGINE: AtomicUsize = AtomicUsize::new(0);
+
+/// Compiles and runs crabscript code inside of a host application.
+///
+/// All code compiled by one engine shares its top level, the variables and functions
+/// declared by a script stay available to the following ones and to the host.
+///
+/// An engine stays on the thread that created it, it isn't `Send` because the debugger,
+/// the profiler and the module loader are shared through `Rc`. A service running scripts
+/// on several threads creates an engine per thread.
+pub struct Engine {
+    /// Identifies the scripts compiled by this engine
+    id: usize,
+    parser: Parser,
+    /// The scope of the top level variables
+    scope: Arc<RuntimeManager>,
+    limits: Limits,
+    cancel: CancelHandle,
+    stdio: Stdio,
+    /// What `argv` returns to the scripts
+    argv: Arc<[String]>,
+    debugger: Option<SharedDebugger>,
+    profiler: Option<SharedProfiler>,
+    #[cfg(feature = "vm")]
+    backend: Backend,
+}
+
+/// Code compiled by an `Engine`, it can only be run by the engine that compiled it
+pub struct Script {
+    block: Block,
+    source: Arc<str>,
+    /// The id of the engine that compiled the script
+    engine: usize,
+}
+
+impl Script {
+    /// The code the script was compiled from, errors of the script can be rendered against it
+    pub fn source(&self) -> &str {
+        &self.source
+    }
+
+    /// The parsed code, see `dump::dump_ast`
+    pub fn block(&self) -> &Block {
+        &self.block
+    }
+}
+
+impl Engine {
+    pub fn new() -> Self {
+        Engine {
+            id: NEXT_ENGINE.fetch_add(1, Ordering::Relaxed),
+            parser: Parser::new(build_pre_map()),
+            scope: Arc::new(RuntimeManager::new()),
+            limits: Limits::default(),
+            cancel: CancelHandle::default(),
+            stdio: Stdio::inherit(),
+            argv: Arc::from([]),
+            debugger: None,
+            profiler: None,
+            #[cfg(feature = "vm")]
+            backend: Backend::default(),
+        }
+    }
+
+    /// Creates an engine whose scripts can only use what `capabilities` allow
+    pub fn with_capabilities(capabilities: &Capabilities) -> Self {
+        let mut engine = Engine::new();
+        let root = capabilities.get_fs_root();
+        engine.parser.set_file_access(match root {
+            _ if !capabilities.allows("fs") => FileAccess::None,
+            Some(root) => FileAccess::Within(root.to_path_buf()),
+            None => FileAccess::All,
+        });
+
+        for (module, functions) in build_std_library() {
+            for (name, f) in functions {
+                if !capabilities.allows(module) {
+                    engine
+                        .parser
+                        .replace_std(module, name, denied(module, name));
+                } else if let ("fs", Some(root)) = (module, root) {
+                    let confined = confined_fs(name, f, root.to_path_buf());
+                    engine.parser.replace_std(module, name, confined);
+                }
+            }
+        }
+
+        engine
+    }
+
+    /// Creates an engine for untrusted scripts, see `Capabilities::sandboxed`
+    pub fn sandboxed() -> Self {
+        Self::with_capabilities(&Capabilities::sandboxed())
+    }
+
+    /// Parses `src` as top level code without running it
+    pub fn compile(&mut self, src: &str) -> Result<Script, CrabError> {
+        let lexer = build_lexer().expect("the lexer definition is valid");
+        let tokens = TokenStream::new(src, lexer.tokens(src));
+        let block = self.parser.parse_tokens(tokens)?;
+
+        Ok(Script {
+            block,
+            source: Arc::from(src),
+            engine: self.id,
+        })
+    }
+
+    /// Runs `script` in the top level scope, it evaluates to the value of its last statement.
+    /// Scripts compiled by another engine are rejected, their variables belong to its scope.
+    pub fn run(&self, script: &Script) -> Result<DayObject, CrabError> {
+        if script.engine != self.id {
+            return Err(RuntimeError::new(
+                RuntimeErrorKind::ForeignScript,
+                "The script was compiled by another engine",
+            )
+            .into());
+        }
+        let _budget = budget::install(&self.limits, &self.cancel)?;
+        let _stdio = stdio::install(&self.stdio);
+        let _argv = env::install(&self.argv);
+        let _debug = debug::install(self.debugger.as_ref());
+        let _profile = profile::install(self.profiler.as_ref());
+        let _hooks = hooks::install(self.hooked());
+        #[cfg(feature = "vm")]
+        let _backend = vm::install(self.run_backend());
+        // Variables declared after a runtime error still have to exist in later scripts
+        self.scope.reserve_vars(script.block.capacity);
+        match script.block.execute_last(&self.scope)? {
+            // `ret` ends the script early
+            ExpressionResult::Return(value) => Ok(value),
+            res => Ok(res.value()?),
+        }
+    }
+
+    /// Compiles and runs `src`
+    pub fn eval(&mut self, src: &str) -> Result<DayObject, CrabError> {
+        let script = self.compile(src)?;
+        self.run(&script)
+    }
+
+    /// The scopes the variables of the compiled scripts were resolved in
+    pub fn scopes(&self) -> ScopeInfo {
+        self.parser.scopes()
+    }
+
+    /// The value of the top level variable `name`
+    pub fn get_var(&self, name: &str) -> Option<DayObject> {
+        let id = self.parser.top_level_var(name)?;
+        self.scope.reserve_vars(id + 1);
+        Some(self.scope.get_var(id, 0))
+    }
+
+    /// Sets the top level variable `name`, it is declared if no script declared it yet
+    pub fn set_var(&mut self, name: &str, value: DayObject) {
+        let id = match self.parser.top_level_var(name) {
+            Some(id) => id,
+            None => self.parser.declare_top_level(name),
+        };
+        self.scope.def_var(id, value);
+    }
+
+    /// Limits every following run of a script and call of a function
+    pub fn set_limits(&mut self, limits: Limits) {
+        self.limits = limits
+    }
+
+    /// Sets how much the following scripts are optimized
+    pub fn set_opt_level(&mut self, opt_level: OptLevel) {
+        self.parser.set_opt_level(opt_level)
+    }
+
+    /// Sets the streams the following scripts read from and write to
+    pub fn set_stdio(&mut self, stdio: Stdio) {
+        self.stdio = stdio
+    }
+
+    /// Sets what `argv` returns to the following scripts and calls, usually the path of the
+    /// script followed by its args. Without args `argv` returns an empty array.
+    pub fn set_argv<S: Into<String>>(&mut self, args: impl IntoIterator<Item = S>) {
+        self.argv = args.into_iter().map(Into::into).collect()
+    }
+
+    /// Makes `debugger` pause the following scripts and calls,
+    /// they are executed by the tree walker while it is set
+    pub fn set_debugger(&mut self, debugger: impl Debugger + 'static) {
+        self.debugger = Some(Rc::new(RefCell::new(debugger)))
+    }
+
+    pub fn remove_debugger(&mut self) {
+        self.debugger = None
+    }
+
+    /// Records where the following scripts and calls spend their time until `take_profile`,
+    /// they are executed by the tree walker meanwhile
+    pub fn start_profiling(&mut self) {
+        self.profiler = Some(Rc::new(RefCell::new(Profiler::new())))
+    }
+
+    /// Stops profiling, `None` if it wasn't started
+    pub fn take_profile(&mut self) -> Option<Profile> {
+        let profiler = Rc::try_unwrap(self.profiler.take()?).ok()?;
+        Some(profiler.into_inner().finish())
+    }
+
+    /// Sets the backend the following scripts and calls are executed by
+    #[cfg(feature = "vm")]
+    pub fn set_backend(&mut self, backend: Backend) {
+        self.backend = backend
+    }
+
+    /// Only the tree walker can pause and be profiled
+    #[cfg(feature = "vm")]
+    fn run_backend(&self) -> Backend {
+        match (&self.debugger, &self.profiler) {
+            (None, None) => self.backend,
+            _ => Backend::Tree,
+        }
+    }
+
+    /// Whether the scripts have to go through the hooks of the debugger, the profiler
+    /// and the depth limit, without them only the steps are counted
+    fn hooked(&self) -> bool {
+        self.debugger.is_some() || self.profiler.is_some() || self.limits.max_depth.is_some()
+    }
+
+    /// A handle to cancel the running script from another thread
+    pub fn cancel_handle(&self) -> CancelHandle {
+        self.cancel.clone()
+    }
+
+    /// Adds a directory `import "file"` searches in, after the directory of the importing
+    /// file and the ones added before. The current working directory is searched first.
+    pub fn add_search_path(&mut self, dir: impl Into<PathBuf>) {
+        self.parser.add_search_path(dir)
+    }
+
+    /// Makes `f` callable as `name` by the scripts compiled afterwards
+    pub fn register_fn(
+        &mut self,
+        name: &str,
+        f: impl Fn(Args) -> RuntimeResult<DayObject> + Send + Sync + 'static,
+    ) {
+        self.parser.add_native(name, Arc::new(f));
+    }
+
+    /// Makes the functions of `module` callable by the scripts compiled afterwards,
+    /// they are qualified with the name of the module
+    pub fn register_module(&mut self, module: impl Module) {
+        for (name, f) in module.functions() {
+            self.parser
+                .add_native(format!("{}.{}", module.name(), name), f);
+        }
+    }
+
+    /// Calls the function stored in the top level variable `name`
+    pub fn call(&self, name: &str, args: &[DayObject]) -> Result<DayObject, CrabError> {
+        match self.get_var(name) {
+            Some(function) => {
+                let _budget = budget::install(&self.limits, &self.cancel)?;
+                let _stdio = stdio::install(&self.stdio);
+                let _argv = env::install(&self.argv);
+                let _debug = debug::install(self.debugger.as_ref());
+                let _profile = profile::install(self.profiler.as_ref());
+                let _hooks = hooks::install(self.hooked());
+                let _hooks = hooks::install(self.hooked());
+                #[cfg(feature = "vm")]
+                let _backend = vm::install(self.run_backend());
+                Ok(function.call(args)?)
+            }
+            None => Err(RuntimeError::new(
+                RuntimeErrorKind::NotCallable,
+                format!("Tried to call undeclared function {}", name),
+            )
+            .into()),
+        }
+    }
+
+    /// The top level variables with their current values in the order they were declared in
+    pub fn vars(&self) -> Vec<(&str, DayObject)> {
+        self.parser
+            .top_level_vars()
+            .into_iter()
+            .map(|(name, id)| {
+                self.scope.reserve_vars(id + 1);
+                (name, self.scope.get_var(id, 0))
+            })
+            .collect()
+    }
+}
+
+impl Default for Engine {
+    fn default() -> Self {
+        Self::new()
+    }
+}
@@ -0,0 +1,60 @@
+use crate::{parsing_error::ParsingError, runtime_error::RuntimeError, span::Span};
+use std::fmt;
+
+/// Any error a script can fail with, either before or while running it
+#[derive(Debug, PartialEq)]
+pub enum CrabError {
+    Parsing(ParsingError),
+    Runtime(RuntimeError),
+}
+
+impl CrabError {
+    /// The location in the source the error occured at, if it is known
+    pub fn span(&self) -> Option<Span> {
+        match self {
+            CrabError::Parsing(e) => Some(e.span()),
+            CrabError::Runtime(e) => e.span(),
+        }
+    }
+
+    /// Renders the traceback of a runtime error and the error followed by the underlined
+    /// source line it occured at, unless it occured in an imported file.
+    /// `source` has to be the script the error originates from.
+    pub fn render(&self, source: &str) -> String {
+        let traceback = match self {
+            CrabError::Runtime(e) => e.traceback(source),
+            CrabError::Parsing(_) => String::new(),
+        };
+        let in_module = matches!(self, CrabError::Runtime(e) if e.module().is_some());
+        match self.span() {
+            Some(span) if !in_module => {
+                format!("{}{}\n{}", traceback, self, span.underline(source))
+            }
+            Some(_) => format!("{}{}", traceback, self),
+            None => format!("{}{}", traceback, self),
+        }
+    }
+}
+
+impl From<ParsingError> for CrabError {
+    fn from(e: ParsingError) -> Self {
+        CrabError::Parsing(e)
+    }
+}
+
+impl From<RuntimeError> for CrabError {
+    fn from(e: RuntimeError) -> Self {
+        CrabError::Runtime(e)
+    }
+}
+
+impl fmt::Display for CrabError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            CrabError::Parsing(e) => write!(f, "{}", e),
+            CrabError::Runtime(e) => write!(f, "{}", e),
+        }
+    }
+}
+
+impl std::error::Error for CrabError {}
@@ -0,0 +1,459 @@
+//NOTE Reprints source code in the canonical style.
+//
+//The formatter works on the tokens, the nodes neither keep comments nor names of variables
+//nor the order of hoisted functions. A statement ends at the end of its line, an operator
+//starting a line starts the next statement, so the lines of a block are kept as statements
+//while the args inside of brackets are reflowed. A group of args is printed on one line if it fits and contains no comment,
+//otherwise every arg gets its own line. Blocks always span multiple lines.
+//Comments are newline tokens whose lexeme starts with `//`.
+
+use crate::{
+    parsing_error::{ParsingError, ParsingResult},
+    span::Span,
+    tokenizer::{build_lexer, KeywordToken, OperatorToken, SpannedTokens, SymbolToken, Token},
+};
+use std::mem::take;
+
+/// Groups of args are wrapped if their line would get longer than this
+pub const MAX_WIDTH: usize = 100;
+const INDENT: usize = 4;
+
+/// Formats `src`, it only fails if the brackets of `src` don't match
+/// or a character is no token, the spacing around it would be ambiguous
+pub fn format(src: &str) -> ParsingResult<String> {
+    let lexer = build_lexer().expect("the lexer definition is valid");
+    let toks: Vec<_> = SpannedTokens::new(src, lexer.tokens(src))
+        .map(|t| Tok {
+            text: &src[t.span.offset..t.span.offset + t.span.len],
+            token: t.token,
+            span: t.span,
+        })
+        .collect();
+
+    let mut reader = Reader {
+        end: toks.last().map(|t| t.span).unwrap_or_default(),
+        toks: toks.into_iter(),
+        precedes_map: false,
+        opens_map: false,
+    };
+    let lines = reader.lines(false)?;
+
+    let mut printer = Printer { out: String::new() };
+    printer.lines(&lines, 0);
+    Ok(printer.out)
+}
+
+struct Tok<'a> {
+    token: Token<'a>,
+    /// The source of the token
+    text: &'a str,
+    span: Span,
+}
+
+impl<'a> Tok<'a> {
+    fn comment(&self) -> Option<&'a str> {
+        match self.token {
+            Token::Newline if self.text.starts_with("//") => Some(self.text.trim_end()),
+            _ => None,
+        }
+    }
+
+    fn is(&self, symbol: SymbolToken) -> bool {
+        self.token == Token::Symbol(symbol)
+    }
+}
+
+enum Piece<'a> {
+    Atom(Tok<'a>),
+    /// Args in `()`, `[]` or a map literal
+    Group {
+        open: Tok<'a>,
+        args: Vec<Arg<'a>>,
+        /// The comments after the last arg
+        trailing: Vec<&'a str>,
+    },
+    Block(Vec<Line<'a>>),
+}
+
+#[derive(Default)]
+struct Arg<'a> {
+    before: Vec<&'a str>,
+    pieces: Vec<Piece<'a>>,
+    /// The first one is printed on the line of the arg
+    after: Vec<&'a str>,
+}
+
+enum Line<'a> {
+    /// A statement with the comment at its end
+    Stmt(Vec<Piece<'a>>, Option<&'a str>),
+    Comment(&'a str),
+    Blank,
+}
+
+struct Reader<'a> {
+    toks: std::vec::IntoIter<Tok<'a>>,
+    end: Span,
+    /// Whether a `{` following the last token would open a map literal
+    precedes_map: bool,
+    /// `precedes_map` of the token before the last one
+    opens_map: bool,
+}
+
+impl<'a> Reader<'a> {
+    fn next(&mut self) -> Option<Tok<'a>> {
+        let tok = self.toks.next()?;
+        if tok.token != Token::Newline {
+            // Decided like the parser does
+            self.opens_map = self.precedes_map;
+            self.precedes_map = tok.token.precedes_map_literal();
+        }
+        Some(tok)
+    }
+
+    fn end_input(&self) -> ParsingError {
+        ParsingError::unexpected_end_of_input(self.end)
+    }
+
+    /// Reads the lines of a block until its `}`, or of the top level until the end
+    fn lines(&mut self, in_block: bool) -> ParsingResult<Vec<Line<'a>>> {
+        let mut lines = Vec::new();
+        let mut stmt = Vec::new();
+        let mut line_start = !in_block;
+
+        loop {
+            let tok = match self.next() {
+                Some(tok) => tok,
+                None if in_block => return Err(self.end_input()),
+                None => break,
+            };
+
+            match tok.token {
+                Token::Newline => {
+                    let comment = tok.comment();
+                    if !stmt.is_empty() {
+                        lines.push(Line::Stmt(take(&mut stmt), comment));
+                    } else if let Some(comment) = comment {
+                        lines.push(Line::Comment(comment));
+                    } else if line_start && !matches!(lines.last(), None | Some(Line::Blank)) {
+                        lines.push(Line::Blank);
+                    }
+                    line_start = true;
+                    continue;
+                }
+                Token::Symbol(SymbolToken::CurlyClose) if in_block => {
+                    if !stmt.is_empty() {
+                        lines.push(Line::Stmt(stmt, None));
+                    }
+                    return Ok(lines);
+                }
+                Token::Keyword(KeywordToken::Else)
+                | Token::Keyword(KeywordToken::Elif)
+                | Token::Keyword(KeywordToken::Catch)
+                    if stmt.is_empty() =>
+                {
+                    // `else` continues the line of the `}` before it
+                    while let Some(Line::Blank) = lines.last() {
+                        lines.pop();
+                    }
+                    if let Some(Line::Stmt(pieces, None)) = lines.last() {
+                        if let Some(Piece::Block(_)) = pieces.last() {
+                            if let Some(Line::Stmt(pieces, _)) = lines.pop() {
+                                stmt = pieces;
+                            }
+                        }
+                    }
+                    stmt.push(Piece::Atom(tok));
+                }
+                _ => {
+                    let piece = self.piece(tok)?;
+                    stmt.push(piece);
+                }
+            }
+            line_start = false;
+        }
+
+        if !stmt.is_empty() {
+            lines.push(Line::Stmt(stmt, None));
+        }
+        Ok(lines)
+    }
+
+    fn piece(&mut self, tok: Tok<'a>) -> ParsingResult<Piece<'a>> {
+        match tok.token {
+            Token::Symbol(SymbolToken::RoundOpen) | Token::Symbol(SymbolToken::SquareOpen) => {
+                self.group(tok)
+            }
+            Token::Symbol(SymbolToken::CurlyOpen) if self.opens_map => self.group(tok),
+            Token::Symbol(SymbolToken::CurlyOpen) => Ok(Piece::Block(self.lines(true)?)),
+            Token::Symbol(SymbolToken::RoundClose)
+            | Token::Symbol(SymbolToken::SquareClose)
+            | Token::Symbol(SymbolToken::CurlyClose)
+            | Token::Unknown(_) => Err(ParsingError::unexpected(tok.span, tok.text.to_string())),
+            _ => Ok(Piece::Atom(tok)),
+        }
+    }
+
+    fn group(&mut self, open: Tok<'a>) -> ParsingResult<Piece<'a>> {
+        let close = match open.token {
+            Token::Symbol(SymbolToken::RoundOpen) => SymbolToken::RoundClose,
+            Token::Symbol(SymbolToken::SquareOpen) => SymbolToken::SquareClose,
+            _ => SymbolToken::CurlyClose,
+        };
+        let mut args: Vec<Arg> = Vec::new();
+        let mut arg = Arg::default();
+        let mut last_line = open.span.line;
+
+        loop {
+            let tok = self.next().ok_or_else(|| self.end_input())?;
+
+            if tok.is(close) {
+                // Comments after the last arg stay in front of the closing bracket
+                let trailing = if arg.pieces.is_empty() {
+                    take(&mut arg.before)
+                } else {
+                    Vec::new()
+                };
+                if !arg.pieces.is_empty() {
+                    args.push(arg);
+                }
+                return Ok(Piece::Group {
+                    open,
+                    args,
+                    trailing,
+                });
+            }
+
+            match tok.token {
+                Token::Symbol(SymbolToken::Comma) => {
+                    args.push(take(&mut arg));
+                    last_line = tok.span.line;
+                }
+                Token::Newline => {
+                    if let Some(comment) = tok.comment() {
+                        let same_line = tok.span.line == last_line;
+                        match args.last_mut() {
+                            _ if !arg.pieces.is_empty() => arg.after.push(comment),
+                            // A comment after the comma of the arg before
+                            Some(prev) if same_line && arg.before.is_empty() => {
+                                prev.after.push(comment)
+                            }
+                            _ => arg.before.push(comment),
+                        }
+                    }
+                }
+                _ => {
+                    last_line = tok.span.line;
+                    let piece = self.piece(tok)?;
+                    arg.pieces.push(piece);
+                }
+            }
+        }
+    }
+}
+
+/// Where the pieces printed are, `:` is spaced differently in slices and maps
+#[derive(Clone, Copy, PartialEq)]
+enum Context {
+    Stmt,
+    Slice,
+    Map,
+}
+
+struct Printer {
+    out: String,
+}
+
+impl Printer {
+    fn indent(&mut self, depth: usize) {
+        self.out.extend(std::iter::repeat_n(' ', depth * INDENT));
+    }
+
+    fn column(&self) -> usize {
+        let line_start = self.out.rfind('\n').map_or(0, |i| i + 1);
+        self.out[line_start..].chars().count()
+    }
+
+    fn lines(&mut self, lines: &[Line], depth: usize) {
+        // Blank lines only separate lines
+        let start = lines.iter().position(|l| !matches!(l, Line::Blank));
+        let end = lines.iter().rposition(|l| !matches!(l, Line::Blank));
+        let lines = match (start, end) {
+            (Some(start), Some(end)) => &lines[start..=end],
+            _ => &[],
+        };
+
+        for line in lines {
+            match line {
+                Line::Blank => (),
+                Line::Comment(comment) => {
+                    self.indent(depth);
+                    self.out.push_str(comment);
+                }
+                Line::Stmt(pieces, comment) => {
+                    self.indent(depth);
+                    self.pieces(pieces, depth, Context::Stmt);
+                    if let Some(comment) = comment {
+                        self.out.push(' ');
+                        self.out.push_str(comment);
+                    }
+                }
+            }
+            self.out.push('\n');
+        }
+    }
+
+    fn pieces(&mut self, pieces: &[Piece], depth: usize, context: Context) {
+        for (i, piece) in pieces.iter().enumerate() {
+            if i > 0 && spaced(pieces, i, context) {
+                self.out.push(' ');
+            }
+            match piece {
+                Piece::Atom(tok) => self.out.push_str(tok.text),
+                Piece::Group {
+                    open,
+                    args,
+                    trailing,
+                } => self.group(open, args, trailing, depth),
+                Piece::Block(lines) if lines.iter().all(|l| matches!(l, Line::Blank)) => {
+                    self.out.push_str("{}")
+                }
+                Piece::Block(lines) => {
+                    self.out.push_str("{\n");
+                    self.lines(lines, depth + 1);
+                    self.indent(depth);
+                    self.out.push('}');
+                }
+            }
+        }
+    }
+
+    fn group(&mut self, open: &Tok, args: &[Arg], trailing: &[&str], depth: usize) {
+        let (close, context) = match open.token {
+            Token::Symbol(SymbolToken::RoundOpen) => (')', Context::Stmt),
+            Token::Symbol(SymbolToken::SquareOpen) => (']', Context::Slice),
+            _ => ('}', Context::Map),
+        };
+
+        if !has_comments(args, trailing) {
+            let start = self.out.len();
+            let column = self.column();
+            self.out.push_str(open.text);
+            for (i, arg) in args.iter().enumerate() {
+                if i > 0 {
+                    self.out.push_str(", ");
+                }
+                self.pieces(&arg.pieces, depth, context);
+            }
+            self.out.push(close);
+
+            // Blocks in the args break the line anyway, only the lines around them count
+            let printed = &self.out[start..];
+            let first = printed.lines().next().unwrap_or("").chars().count();
+            let last = printed.lines().last().unwrap_or("").chars().count();
+            let multiline = printed.contains('\n');
+            if column + first <= MAX_WIDTH && (!multiline || last <= MAX_WIDTH) {
+                return;
+            }
+            self.out.truncate(start);
+        }
+
+        self.out.push_str(open.text);
+        self.out.push('\n');
+        for (i, arg) in args.iter().enumerate() {
+            for comment in &arg.before {
+                self.indent(depth + 1);
+                self.out.push_str(comment);
+                self.out.push('\n');
+            }
+            self.indent(depth + 1);
+            self.pieces(&arg.pieces, depth + 1, context);
+            if i + 1 < args.len() {
+                self.out.push(',');
+            }
+            for (j, comment) in arg.after.iter().enumerate() {
+                if j == 0 {
+                    self.out.push(' ');
+                } else {
+                    self.out.push('\n');
+                    self.indent(depth + 1);
+                }
+                self.out.push_str(comment);
+            }
+            self.out.push('\n');
+        }
+        for comment in trailing {
+            self.indent(depth + 1);
+            self.out.push_str(comment);
+            self.out.push('\n');
+        }
+        self.indent(depth);
+        self.out.push(close);
+    }
+}
+
+/// Whether the group has to be wrapped to keep its comments (or the ones of inner groups)
+fn has_comments(args: &[Arg], trailing: &[&str]) -> bool {
+    let inner = |pieces: &[Piece]| {
+        pieces.iter().any(|p| match p {
+            Piece::Group { args, trailing, .. } => has_comments(args, trailing),
+            _ => false,
+        })
+    };
+
+    !trailing.is_empty()
+        || args
+            .iter()
+            .any(|a| !a.before.is_empty() || !a.after.is_empty() || inner(&a.pieces))
+}
+
+/// Whether a space separates the piece at `i` from the one before it
+fn spaced(pieces: &[Piece], i: usize, context: Context) -> bool {
+    let prev = match &pieces[i - 1] {
+        Piece::Atom(tok) => Some(&tok.token),
+        _ => None,
+    };
+
+    match (prev, &pieces[i]) {
+        (
+            _,
+            Piece::Atom(Tok {
+                token:
+                    Token::Symbol(SymbolToken::Comma)
+                    | Token::Symbol(SymbolToken::Colon)
+                    | Token::Symbol(SymbolToken::Semicolon),
+                ..
+            }),
+        ) => false,
+        (Some(Token::Symbol(SymbolToken::Colon)), _) => context == Context::Map,
+        (Some(Token::Symbol(SymbolToken::Ellipsis)), _) => false,
+        (Some(Token::Operator(_)), _) if is_unary(pieces, i - 1) => false,
+        // Calls and indices
+        (prev, Piece::Group { open, .. }) if !open.is(SymbolToken::CurlyOpen) => !matches!(
+            prev,
+            None | Some(Token::Identifier(_)) | Some(Token::Keyword(KeywordToken::Fn))
+        ),
+        _ => true,
+    }
+}
+
+/// Whether the operator at `i` is a prefix of the operand after it
+fn is_unary(pieces: &[Piece], i: usize) -> bool {
+    match &pieces[i] {
+        Piece::Atom(Tok {
+            token: Token::Operator(OperatorToken::Not),
+            ..
+        }) => true,
+        Piece::Atom(Tok {
+            token: Token::Operator(_),
+            ..
+        }) => match i.checked_sub(1).map(|prev| &pieces[prev]) {
+            None => true,
+            Some(Piece::Atom(Tok { token, .. })) => matches!(
+                token,
+                Token::Operator(_) | Token::Keyword(_) | Token::Symbol(_)
+            ),
+            Some(_) => false,
+        },
+        _ => false,
+    }
+}
@@ -0,0 +1,354 @@
+//NOTE Runs scripts and compares what they print with the expectations stored next to them.
+//
+//The output of `script.crab` is expected in `script.crab.out` and its error output, including the
+//rendered error it ended with, in `script.crab.err`. A missing file expects nothing. Blessing
+//writes the files from the received output instead of comparing, empty files are removed.
+//A script has to run without an error unless it contains `// expect-error: text` lines, then it
+//has to end with an error containing every text. `// max-steps: n` limits the loop iterations and
+//function calls. `// skip: reason` marks a script using something the interpreter doesn't support
+//yet, it isn't run. `// slow: reason` marks benchmarks and scripts sleeping, `cargo test` leaves
+//them out. Every script reads from an empty input.
+
+use crate::{
+    budget::Limits,
+    engine::Engine,
+    stdio::{Capture, Stdio},
+};
+use std::{
+    fmt, fs, io,
+    path::{Path, PathBuf},
+    sync::atomic::{AtomicUsize, Ordering},
+    time::Duration,
+};
+
+const EXPECT_ERROR: &str = "// expect-error:";
+const MAX_STEPS: &str = "// max-steps:";
+const SKIP: &str = "// skip:";
+const SLOW: &str = "// slow:";
+
+/// Scripts running longer fail
+const TIMEOUT: Duration = Duration::from_secs(60);
+
+/// The scripts in `dir` sorted by their names, expectation files and subdirectories are skipped.
+/// Subdirectories hold the files the scripts import.
+pub fn discover(dir: &Path) -> io::Result<Vec<PathBuf>> {
+    let mut scripts = Vec::new();
+    for entry in fs::read_dir(dir)? {
+        let path = entry?.path();
+        let expectation = matches!(
+            path.extension().and_then(|e| e.to_str()),
+            Some("out" | "err")
+        );
+        if path.is_file() && !expectation {
+            scripts.push(path);
+        }
+    }
+    scripts.sort();
+    Ok(scripts)
+}
+
+/// The file the output of `script` is expected in, `extension` is `out` or `err`
+pub fn expectation_path(script: &Path, extension: &str) -> PathBuf {
+    let mut path = script.as_os_str().to_owned();
+    path.push(".");
+    path.push(extension);
+    PathBuf::from(path)
+}
+
+/// The annotations of a script
+#[derive(Debug, Default)]
+pub struct Annotations {
+    /// The texts the error the script ends with has to contain
+    pub expected_errors: Vec<String>,
+    pub max_steps: Option<u64>,
+    /// Why the script isn't run
+    pub skip: Option<String>,
+    /// Why the script is left out of `cargo test`
+    pub slow: Option<String>,
+}
+
+impl Annotations {
+    pub fn parse(source: &str) -> Self {
+        let first = |prefix| annotations(source, prefix).next().map(str::to_string);
+        Annotations {
+            expected_errors: annotations(source, EXPECT_ERROR)
+                .map(str::to_string)
+                .collect(),
+            max_steps: first(MAX_STEPS).and_then(|steps| steps.parse().ok()),
+            skip: first(SKIP),
+            slow: first(SLOW),
+        }
+    }
+
+    /// Reads the annotations of `script`
+    pub fn read(script: &Path) -> io::Result<Self> {
+        fs::read_to_string(script).map(|source| Self::parse(&source))
+    }
+}
+
+/// What a script printed
+#[derive(Debug)]
+pub struct Run {
+    pub stdout: String,
+    /// What the script printed to its error stream followed by the error it ended with
+    pub stderr: String,
+    /// The rendered error the script ended with
+    pub error: Option<String>,
+}
+
+/// Runs `script` in a new engine with the limits of its annotations
+pub fn run_script(script: &Path) -> io::Result<Run> {
+    let source = fs::read_to_string(script)?;
+    let stdout = Capture::new();
+    let stderr = Capture::new();
+
+    let mut engine = Engine::new();
+    if let Some(dir) = script.parent() {
+        engine.add_search_path(dir);
+    }
+    engine.set_stdio(
+        Stdio::inherit()
+            .input(io::empty())
+            .output(stdout.clone())
+            .error(stderr.clone()),
+    );
+    engine.set_limits(Limits {
+        max_steps: Annotations::parse(&source).max_steps,
+        timeout: Some(TIMEOUT),
+        ..Limits::default()
+    });
+
+    let error = engine.eval(&source).err().map(|e| e.render(&source));
+    let mut stderr = stderr.contents();
+    if let Some(error) = &error {
+        stderr.push_str(error);
+        stderr.push('\n');
+    }
+    Ok(Run {
+        stdout: stdout.contents(),
+        stderr,
+        error,
+    })
+}
+
+/// The texts of the lines of `source` starting with `prefix`
+fn annotations<'a>(source: &'a str, prefix: &'a str) -> impl Iterator<Item = &'a str> {
+    source
+        .lines()
+        .filter_map(move |line| line.trim().strip_prefix(prefix))
+        .map(str::trim)
+}
+
+/// Why a script failed
+#[derive(Debug)]
+pub enum Failure {
+    /// The script or one of its expectation files couldn't be accessed
+    Io(PathBuf, io::Error),
+    /// The script ended with an error without expecting one
+    UnexpectedError(String),
+    /// The script ran without the errors it expected
+    MissingError(Vec<String>),
+    /// The error of the script doesn't contain an expected text
+    WrongError { expected: String, error: String },
+    /// The script printed something else than the expectation file contains
+    Mismatch {
+        file: PathBuf,
+        expected: String,
+        received: String,
+    },
+}
+
+impl fmt::Display for Failure {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Failure::Io(path, e) => write!(f, "Can't access {}: {}", path.display(), e),
+            Failure::UnexpectedError(error) => write!(f, "Unexpected error:\n{}", error),
+            Failure::MissingError(expected) => {
+                write!(f, "Expected an error containing {:?}", expected.join(", "))
+            }
+            Failure::WrongError { expected, error } => {
+                write!(
+                    f,
+                    "Expected an error containing {:?}, received:\n{}",
+                    expected, error
+                )
+            }
+            Failure::Mismatch {
+                file,
+                expected,
+                received,
+            } => {
+                // The first differing line, the missing one is shown as nothing
+                let (line, (expected, received)) = (1..)
+                    .zip(
+                        expected
+                            .lines()
+                            .chain([""])
+                            .zip(received.lines().chain([""])),
+                    )
+                    .find(|(_, (a, b))| a != b)
+                    .unwrap_or((1, ("", "")));
+                write!(
+                    f,
+                    "{} differs at line {}\n  expected: {:?}\n  received: {:?}",
+                    file.display(),
+                    line,
+                    expected,
+                    received
+                )
+            }
+        }
+    }
+}
+
+/// The result of checking a script
+#[derive(Debug)]
+pub struct Outcome {
+    pub script: PathBuf,
+    pub failures: Vec<Failure>,
+    /// Why the script wasn't run
+    pub skipped: Option<String>,
+}
+
+impl Outcome {
+    pub fn passed(&self) -> bool {
+        self.failures.is_empty()
+    }
+}
+
+impl fmt::Display for Outcome {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let status = match (&self.skipped, self.passed()) {
+            (Some(_), _) => "skip",
+            (None, true) => "ok",
+            (None, false) => "FAIL",
+        };
+        write!(f, "{:<4} {}", status, self.script.display())?;
+        if let Some(reason) = &self.skipped {
+            write!(f, "\n     {}", reason)?;
+        }
+        for failure in &self.failures {
+            for line in failure.to_string().lines() {
+                write!(f, "\n     {}", line)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Runs `script` and compares its output with the expectation files, `bless` writes them instead.
+/// Scripts marked with `// skip:` aren't run.
+pub fn check(script: &Path, bless: bool) -> Outcome {
+    let mut outcome = Outcome {
+        script: script.to_path_buf(),
+        failures: Vec::new(),
+        skipped: None,
+    };
+    let io_failure = |e| Failure::Io(script.to_path_buf(), e);
+    let source = match fs::read_to_string(script) {
+        Ok(source) => source,
+        Err(e) => {
+            outcome.failures.push(io_failure(e));
+            return outcome;
+        }
+    };
+    let annotations = Annotations::parse(&source);
+    if annotations.skip.is_some() {
+        outcome.skipped = annotations.skip;
+        return outcome;
+    }
+    let run = match run_script(script) {
+        Ok(run) => run,
+        Err(e) => {
+            outcome.failures.push(io_failure(e));
+            return outcome;
+        }
+    };
+
+    let expected = annotations.expected_errors;
+    match &run.error {
+        Some(error) if expected.is_empty() => {
+            outcome
+                .failures
+                .push(Failure::UnexpectedError(error.clone()));
+        }
+        Some(error) => {
+            outcome.failures.extend(
+                expected
+                    .into_iter()
+                    .filter(|text| !error.contains(text.as_str()))
+                    .map(|expected| Failure::WrongError {
+                        expected,
+                        error: error.clone(),
+                    }),
+            );
+        }
+        None if !expected.is_empty() => outcome.failures.push(Failure::MissingError(expected)),
+        None => (),
+    }
+
+    for (extension, received) in [("out", run.stdout), ("err", run.stderr)] {
+        let file = expectation_path(script, extension);
+        let res = if bless {
+            bless_file(&file, &received)
+        } else {
+            compare_file(&file, received)
+        };
+        match res {
+            Ok(None) => (),
+            Ok(Some(failure)) => outcome.failures.push(failure),
+            Err(e) => outcome.failures.push(Failure::Io(file, e)),
+        }
+    }
+    outcome
+}
+
+fn bless_file(file: &Path, received: &str) -> io::Result<Option<Failure>> {
+    if !received.is_empty() {
+        fs::write(file, received)?;
+    } else if file.exists() {
+        fs::remove_file(file)?;
+    }
+    Ok(None)
+}
+
+fn compare_file(file: &Path, received: String) -> io::Result<Option<Failure>> {
+    let expected = match fs::read_to_string(file) {
+        Ok(expected) => expected,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => String::new(),
+        Err(e) => return Err(e),
+    };
+    Ok((expected != received).then(|| Failure::Mismatch {
+        file: file.to_path_buf(),
+        expected,
+        received,
+    }))
+}
+
+/// Checks `scripts` on multiple threads, the outcomes are in the order of the scripts
+pub fn check_all(scripts: &[PathBuf], bless: bool) -> Vec<Outcome> {
+    let threads = std::thread::available_parallelism().map_or(1, |n| n.get());
+    let next = AtomicUsize::new(0);
+    let mut outcomes: Vec<_> = std::thread::scope(|scope| {
+        let workers: Vec<_> = (0..threads.min(scripts.len()))
+            .map(|_| {
+                scope.spawn(|| {
+                    let mut outcomes = Vec::new();
+                    loop {
+                        let id = next.fetch_add(1, Ordering::Relaxed);
+                        match scripts.get(id) {
+                            Some(script) => outcomes.push((id, check(script, bless))),
+                            None => return outcomes,
+                        }
+                    }
+                })
+            })
+            .collect();
+        workers
+            .into_iter()
+            .flat_map(|worker| worker.join().expect("checking a script panicked"))
+            .collect()
+    });
+    outcomes.sort_by_key(|(id, _)| *id);
+    outcomes.into_iter().map(|(_, outcome)| outcome).collect()
+}
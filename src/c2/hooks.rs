@@ -0,0 +1,34 @@
+use std::cell::Cell;
+
+//NOTE Blocks and calls check this flag once instead of asking the debugger, the profiler
+//and the depth limit one by one. Steps are always counted, a run can be cancelled anytime.
+
+thread_local! {
+    /// Whether the code running on this thread is debugged, profiled or has a depth limit
+    static ACTIVE: Cell<bool> = const { Cell::new(false) };
+}
+
+/// Restores the flag of the enclosing run when dropped
+pub struct HooksGuard {
+    previous: bool,
+}
+
+impl Drop for HooksGuard {
+    fn drop(&mut self) {
+        ACTIVE.with(|active| active.set(self.previous))
+    }
+}
+
+/// Makes blocks and calls go through the hooks of `debug`, `profile` and the depth limit
+/// of `budget` if `active` until the guard is dropped
+pub fn install(active: bool) -> HooksGuard {
+    HooksGuard {
+        previous: ACTIVE.with(|current| current.replace(active)),
+    }
+}
+
+/// Whether the hooks have to be called
+#[inline]
+pub fn active() -> bool {
+    ACTIVE.with(Cell::get)
+}
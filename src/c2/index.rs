@@ -0,0 +1,249 @@
+use crate::{
+    base::DayObject,
+    runtime_error::{RuntimeError, RuntimeErrorKind, RuntimeResult},
+};
+use std::borrow::Cow;
+
+/// An evaluated index operation, `a[i]` or `a[start:end]`
+#[derive(Debug, Clone, PartialEq)]
+pub enum Subscript {
+    Index(DayObject),
+    /// Missing bounds are the start/end of the indexed value
+    Slice(Option<i64>, Option<i64>),
+}
+
+/// Resolves a possibly negative index counting from the end, `None` if it is out of bounds
+fn resolve(index: i64, len: usize) -> Option<usize> {
+    let resolved = if index < 0 { len as i64 + index } else { index };
+
+    if resolved >= 0 && (resolved as usize) < len {
+        Some(resolved as usize)
+    } else {
+        None
+    }
+}
+
+/// Resolves the bounds of a slice, `start..end` always lies inside of `0..=len`
+fn resolve_slice(
+    start: Option<i64>,
+    end: Option<i64>,
+    len: usize,
+) -> RuntimeResult<(usize, usize)> {
+    let bound = |b: i64| if b < 0 { len as i64 + b } else { b };
+    let s = start.map_or(0, bound);
+    let e = end.map_or(len as i64, bound);
+
+    if 0 <= s && s <= e && e <= len as i64 {
+        Ok((s as usize, e as usize))
+    } else {
+        Err(RuntimeError::new(
+            RuntimeErrorKind::Index,
+            format!(
+                "the slice {}:{} is out of bounds for length {}",
+                start.map_or(String::new(), |s| s.to_string()),
+                end.map_or(String::new(), |e| e.to_string()),
+                len
+            ),
+        ))
+    }
+}
+
+fn out_of_bounds(index: i64, len: usize) -> RuntimeError {
+    RuntimeError::new(
+        RuntimeErrorKind::Index,
+        format!("the index {} is out of bounds for length {}", index, len),
+    )
+}
+
+fn key_not_found(key: &DayObject) -> RuntimeError {
+    RuntimeError::new(
+        RuntimeErrorKind::Index,
+        format!("the key {:?} is not in the map", key),
+    )
+}
+
+fn cant_index(indexed: &DayObject, index: impl std::fmt::Debug) -> RuntimeError {
+    RuntimeError::type_error(format!("Can't index into {:?} with {:?}", indexed, index))
+}
+
+/// Byte range of the chars `start..end` of `s`
+fn char_range(s: &str, start: usize, end: usize) -> std::ops::Range<usize> {
+    let byte = |i: usize| s.char_indices().nth(i).map_or(s.len(), |(b, _)| b);
+    byte(start)..byte(end)
+}
+
+impl DayObject {
+    /// Returns the element at `index` of an array, string or iter or the value at the key `index`
+    /// of a map. Elements of arrays and maps are borrowed, everything else is created.
+    pub fn index(&self, index: &DayObject) -> RuntimeResult<Cow<'_, DayObject>> {
+        match (self, index) {
+            (DayObject::Array(arr), DayObject::Integer(i)) => resolve(*i, arr.len())
+                .map(|i| Cow::Borrowed(&arr[i]))
+                .ok_or_else(|| out_of_bounds(*i, arr.len())),
+            (DayObject::Str(s), DayObject::Integer(i)) => {
+                let len = s.chars().count();
+                resolve(*i, len)
+                    .and_then(|i| s.chars().nth(i))
+                    .map(|c| Cow::Owned(DayObject::Character(c)))
+                    .ok_or_else(|| out_of_bounds(*i, len))
+            }
+            (DayObject::Iter(it), DayObject::Integer(i)) => {
+                let index = if *i < 0 {
+                    let len = it.0.pos().zip(it.0.remaining()).map(|(p, r)| p + r);
+                    let len = len.ok_or_else(|| {
+                        RuntimeError::type_error("Negative indices need an iter of known length")
+                    })?;
+                    resolve(*i, len).ok_or_else(|| out_of_bounds(*i, len))?
+                } else {
+                    *i as usize
+                };
+                it.0.get_indexed(index)?.map(Cow::Owned).ok_or_else(|| {
+                    RuntimeError::new(
+                        RuntimeErrorKind::Index,
+                        format!("the iter has no element at index {}", i),
+                    )
+                })
+            }
+            (DayObject::Map(map), key) => map
+                .get(key)
+                .map(Cow::Borrowed)
+                .ok_or_else(|| key_not_found(key)),
+            (indexed, index) => Err(cant_index(indexed, index)),
+        }
+    }
+
+    /// Like `index` but returns a mutable reference, only arrays and maps can be indexed mutably
+    pub fn index_mut(&mut self, index: &DayObject) -> RuntimeResult<&mut DayObject> {
+        match (self, index) {
+            (DayObject::Array(arr), DayObject::Integer(i)) => {
+                let len = arr.len();
+                resolve(*i, len)
+                    .map(move |i| &mut arr[i])
+                    .ok_or_else(|| out_of_bounds(*i, len))
+            }
+            (DayObject::Map(map), key) => map.get_mut(key).ok_or_else(|| key_not_found(key)),
+            (indexed, index) => Err(cant_index(indexed, index)),
+        }
+    }
+
+    /// Returns the elements `start..end` of an array or the chars of a string
+    pub fn slice(&self, start: Option<i64>, end: Option<i64>) -> RuntimeResult<DayObject> {
+        match self {
+            DayObject::Array(arr) => {
+                let (s, e) = resolve_slice(start, end, arr.len())?;
+                Ok(DayObject::Array(arr[s..e].to_vec()))
+            }
+            DayObject::Str(string) => {
+                let (s, e) = resolve_slice(start, end, string.chars().count())?;
+                Ok(DayObject::Str(string[char_range(string, s, e)].to_string()))
+            }
+            other => Err(cant_index(other, Subscript::Slice(start, end))),
+        }
+    }
+
+    pub fn subscript(&self, subscript: &Subscript) -> RuntimeResult<Cow<'_, DayObject>> {
+        match subscript {
+            Subscript::Index(i) => self.index(i),
+            Subscript::Slice(start, end) => self.slice(*start, *end).map(Cow::Owned),
+        }
+    }
+
+    /// Assigns `value` to `self[subscript]`. Missing keys are inserted into maps,
+    /// chars of strings and slices of arrays and strings can be replaced.
+    pub fn assign_subscript(
+        &mut self,
+        subscript: &Subscript,
+        value: DayObject,
+    ) -> RuntimeResult<()> {
+        match (self, subscript, value) {
+            (DayObject::Map(map), Subscript::Index(key), value) => {
+                map.insert(key.clone(), value)?;
+            }
+            (
+                DayObject::Str(s),
+                Subscript::Index(DayObject::Integer(i)),
+                DayObject::Character(c),
+            ) => {
+                let len = s.chars().count();
+                let i = resolve(*i, len).ok_or_else(|| out_of_bounds(*i, len))?;
+                let range = char_range(s, i, i + 1);
+                s.replace_range(range, c.encode_utf8(&mut [0; 4]));
+            }
+            (DayObject::Array(arr), Subscript::Slice(start, end), DayObject::Array(values)) => {
+                let (s, e) = resolve_slice(*start, *end, arr.len())?;
+                arr.splice(s..e, values);
+            }
+            (DayObject::Str(string), Subscript::Slice(start, end), DayObject::Str(value)) => {
+                let (s, e) = resolve_slice(*start, *end, string.chars().count())?;
+                let range = char_range(string, s, e);
+                string.replace_range(range, &value);
+            }
+            (this @ DayObject::Array(_), Subscript::Index(i), value) => *this.index_mut(i)? = value,
+            (this, subscript, value) => {
+                return Err(RuntimeError::type_error(format!(
+                    "Can't assign {:?} to {:?}[{:?}]",
+                    value, this, subscript
+                )))
+            }
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use DayObject::*;
+
+    #[test]
+    fn negative_index() {
+        let arr = Array(vec![Integer(1), Integer(2), Integer(3)]);
+        assert_eq!(arr.index(&Integer(-1)).unwrap().into_owned(), Integer(3));
+        assert_eq!(arr.index(&Integer(-3)).unwrap().into_owned(), Integer(1));
+        assert_eq!(
+            arr.index(&Integer(-4)).unwrap_err().kind(),
+            RuntimeErrorKind::Index
+        );
+        assert_eq!(
+            arr.index(&Integer(3)).unwrap_err().kind(),
+            RuntimeErrorKind::Index
+        );
+    }
+
+    #[test]
+    fn string_slices() {
+        let s = Str("häll".to_string());
+        assert_eq!(s.index(&Integer(1)).unwrap().into_owned(), Character('ä'));
+        assert_eq!(s.slice(Some(1), Some(3)).unwrap(), Str("äl".to_string()));
+        assert_eq!(
+            s.slice(Option::None, Some(-1)).unwrap(),
+            Str("häl".to_string())
+        );
+        assert!(s.slice(Some(3), Some(2)).is_err());
+
+        let mut s = s;
+        s.assign_subscript(&Subscript::Index(Integer(1)), Character('a'))
+            .unwrap();
+        s.assign_subscript(
+            &Subscript::Slice(Some(-2), Option::None),
+            Str("y".to_string()),
+        )
+        .unwrap();
+        assert_eq!(s, Str("hay".to_string()));
+    }
+
+    #[test]
+    fn array_slice_assignment() {
+        let mut arr = Array(vec![Integer(1), Integer(2), Integer(3)]);
+        arr.assign_subscript(
+            &Subscript::Slice(Some(1), Some(2)),
+            Array(vec![Integer(5), Integer(6)]),
+        )
+        .unwrap();
+        assert_eq!(
+            arr,
+            Array(vec![Integer(1), Integer(5), Integer(6), Integer(3)])
+        );
+    }
+}
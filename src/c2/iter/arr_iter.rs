@@ -1,6 +1,7 @@
 use crate::{
     base::DayObject,
     iter::{Iter, IterKind},
+    runtime_error::RuntimeResult,
 };
 use std::sync::Arc;
 
@@ -55,9 +56,9 @@ pub struct ArrIter {
 
 impl Iter for ArrIter {
     /// Get the next element of the iter
-    fn next(&mut self) -> Option<DayObject> {
+    fn next(&mut self) -> RuntimeResult<Option<DayObject>> {
         if self.index >= self.data.len() {
-            return None;
+            return Ok(None);
         }
 
         let data = if self.reverse {
@@ -66,23 +67,27 @@ impl Iter for ArrIter {
             self.data.get(self.index)
         };
 
-        if data != None {
+        if data.is_some() {
             self.index += 1;
         }
 
         dbg_print!(self.index);
         dbg_print!(data);
 
-        data.map(|val| val.clone())
+        Ok(data.cloned())
     }
 
-    fn get_indexed(&self, index: usize) -> Option<DayObject> {
-        if self.reverse {
+    fn get_indexed(&self, index: usize) -> RuntimeResult<Option<DayObject>> {
+        if index >= self.data.len() {
+            return Ok(None);
+        }
+
+        Ok(if self.reverse {
             self.data.get(self.data.len() - index - 1)
         } else {
             self.data.get(index)
         }
-        .map(|val| val.clone())
+        .cloned())
     }
 
     /// Get which kind of iter this is
@@ -1,19 +1,20 @@
 use crate::{
     base::{Args, DayFunction, DayObject, IterHandle},
     iter::{Iter, IterKind},
+    runtime_error::{RuntimeError, RuntimeResult},
     std_modules::conversion::single_value_to_arr,
 };
 
 use std::fmt::{Debug, Formatter, Result as FmtRes};
 
-pub fn map(args: Args) -> DayObject {
-    match (&args[0], &args[1]) {
-        (DayObject::Iter(inner), DayObject::Function(action)) => {
-            DayObject::Iter(IterHandle::new(Box::new(MapIter {
+pub fn map(args: Args) -> RuntimeResult<DayObject> {
+    match (args.first(), args.get(1)) {
+        (Some(DayObject::Iter(inner)), Some(DayObject::Function(action))) => {
+            Ok(DayObject::Iter(IterHandle::new(Box::new(MapIter {
                 action: action.clone(),
                 inner: inner.clone().0,
-                args: if args.len() > 0 {
-                    let mut given_args = single_value_to_arr(&args[2]);
+                args: if let Some(extra) = args.get(2) {
+                    let mut given_args = single_value_to_arr(extra);
                     let mut v = Vec::with_capacity(given_args.len() + 1);
                     v.push(DayObject::None);
                     v.append(&mut given_args);
@@ -21,9 +22,12 @@ pub fn map(args: Args) -> DayObject {
                 } else {
                     vec![DayObject::None]
                 },
-            })))
+            }))))
         }
-        _ => panic!("map creation error"),
+        _ => Err(RuntimeError::invalid_args(
+            "map",
+            "expected an iter and a function",
+        )),
     }
 }
 
@@ -34,24 +38,24 @@ pub struct MapIter {
 }
 
 impl Iter for MapIter {
-    fn next(&mut self) -> Option<DayObject> {
-        if let Some(data) = self.inner.next() {
+    fn next(&mut self) -> RuntimeResult<Option<DayObject>> {
+        if let Some(data) = self.inner.next()? {
             self.args[0] = data;
-            Some(self.action.call(&self.args))
+            Ok(Some(self.action.call(&self.args)?))
         } else {
-            None
+            Ok(None)
         }
     }
 
-    fn get_indexed(&self, index: usize) -> Option<DayObject> {
-        if let Some(data) = self.inner.get_indexed(index) {
+    fn get_indexed(&self, index: usize) -> RuntimeResult<Option<DayObject>> {
+        if let Some(data) = self.inner.get_indexed(index)? {
             //NOTE This solution is inefficient
             //it could be done with unsafe interior mutability
             let mut args = self.args.clone();
             args[0] = data;
-            Some(self.action.call(&self.args))
+            Ok(Some(self.action.call(&args)?))
         } else {
-            None
+            Ok(None)
         }
     }
 
@@ -1,4 +1,4 @@
-use crate::base::DayObject;
+use crate::{base::DayObject, runtime_error::RuntimeResult};
 
 pub mod arr_iter;
 pub mod map;
@@ -9,7 +9,7 @@ pub mod range;
 //are not stored in an arena. The solution would rather be iters over references
 //and moving.
 
-/// This is used inside the var manager as backing data for iterators
+// This is used inside the var manager as backing data for iterators
 /*pub trait IterData<'a> {
     fn acquire(self: Arc<Self>, data_id: usize) -> Box<dyn Iter>;
     ///Calling consume directly on data is invalid and will most likely panic
@@ -22,8 +22,8 @@ pub mod range;
 /// A CrabScript iterator
 pub trait Iter {
     /// Get the next element of the iter
-    fn next(&mut self) -> Option<DayObject>;
-    fn get_indexed(&self, index: usize) -> Option<DayObject>;
+    fn next(&mut self) -> RuntimeResult<Option<DayObject>>;
+    fn get_indexed(&self, index: usize) -> RuntimeResult<Option<DayObject>>;
     /// Get which kind of iter this is
     fn kind(&self) -> IterKind;
     //fn consume(self: Box<Self>) -> Box<dyn Iter>;
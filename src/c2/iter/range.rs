@@ -1,6 +1,7 @@
 use crate::{
     base::{Args, DayObject, IterHandle},
     iter::{Iter, IterKind},
+    runtime_error::{RuntimeError, RuntimeResult},
 };
 
 /*#[derive(Clone, Copy, Debug)]
@@ -37,12 +38,15 @@ impl IterData for RangeIterData {
 
 //TODO Range for chars
 
-pub fn range(args: Args) -> DayObject {
-    match (&args[0], &args[1]) {
-        (DayObject::Integer(a), DayObject::Integer(b)) => {
-            DayObject::Iter(IterHandle::new(Box::new(RangeIter::new(*a, *b))))
-        }
-        _ => panic!("Range creation error (wrong args)"),
+pub fn range(args: Args) -> RuntimeResult<DayObject> {
+    match args {
+        [DayObject::Integer(a), DayObject::Integer(b)] => Ok(DayObject::Iter(IterHandle::new(
+            Box::new(RangeIter::new(*a, *b)),
+        ))),
+        _ => Err(RuntimeError::invalid_args(
+            "range",
+            format!("expected 2 integers received {:?}", args),
+        )),
     }
 }
 
@@ -78,7 +82,7 @@ impl RangeIter {
 }
 
 impl Iter for RangeIter {
-    fn next(&mut self) -> Option<DayObject> {
+    fn next(&mut self) -> RuntimeResult<Option<DayObject>> {
         let i = self.index;
         self.index += 1;
         self.get_indexed(i)
@@ -151,17 +155,17 @@ impl Iter for RangeIter {
            self
        }
     */
-    fn get_indexed(&self, index: usize) -> Option<DayObject> {
+    fn get_indexed(&self, index: usize) -> RuntimeResult<Option<DayObject>> {
         use Direction::*;
 
-        if index > self.max_index {
-            return None;
+        if index >= self.max_index {
+            return Ok(None);
         }
 
-        match self.dir {
+        Ok(match self.dir {
             Positive => Some(DayObject::Integer(self.low + index as i64)),
             Negative => Some(DayObject::Integer(self.high - index as i64)),
-        }
+        })
     }
 }
 
@@ -1,4 +1,7 @@
-use crate::base::{ArgVec, DayObject};
+use crate::{
+    base::{ArgVec, DayObject},
+    runtime_error::{RuntimeError, RuntimeErrorKind, RuntimeResult},
+};
 use std::{any::Any, cell::UnsafeCell, sync::Arc};
 
 //TODO The var manager behavior should be extracted to an
@@ -62,7 +65,7 @@ impl RuntimeManager {
         self.predecessor.clone()
     }
 
-    pub fn get_nth_predecessor<'a>(self: &'a Arc<Self>, depth: usize) -> &'a Arc<Self> {
+    pub fn get_nth_predecessor(self: &Arc<Self>, depth: usize) -> &Arc<Self> {
         let mut current = self;
         for _ in 0..depth {
             //println!("!!!curd{}", current.depth);
@@ -90,9 +93,9 @@ impl RuntimeManager {
             let len = (*manager.inner_scope.get()).len();
 
             if id < len {
-                (*(*manager.inner_scope.get())[id].get()).clone()
+                (*(&*manager.inner_scope.get())[id].get()).clone()
             } else {
-                (*(*manager.inner_scope.get())[id - len].get()).clone()
+                (*(&*manager.inner_scope.get())[id - len].get()).clone()
             }
         }
     }
@@ -107,9 +110,9 @@ impl RuntimeManager {
             let len = (*manager.inner_scope.get()).len();
 
             if id < len {
-                (*manager.inner_scope.get())[id].clone()
+                (&*manager.inner_scope.get())[id].clone()
             } else {
-                (*manager.inner_scope.get())[id - len].clone()
+                (&*manager.inner_scope.get())[id - len].clone()
             }
         }
     }
@@ -128,9 +131,9 @@ impl RuntimeManager {
             let len = (*manager.inner_scope.get()).len();
 
             if id < len {
-                &mut (*(*manager.inner_scope.get())[id].get())
+                &mut (*(&*manager.inner_scope.get())[id].get())
             } else {
-                &mut (*(*manager.inner_scope.get())[id - len].get())
+                &mut (*(&*manager.inner_scope.get())[id - len].get())
             }
         }
     }
@@ -148,31 +151,48 @@ impl RuntimeManager {
             let len = (*scptr).len();
 
             if id < len {
-                *(*manager.inner_scope.get())[id].get() = value
+                *(&*manager.inner_scope.get())[id].get() = value
             } else {
-                *(*manager.inner_scope.get())[id - len].get() = value
+                *(&*manager.inner_scope.get())[id - len].get() = value
             }
         }
     }
 
     ///Changes the value of a variable in the Variable Manager
     pub fn set_var_here(self: &Arc<Self>, value: DayObject, id: usize) {
-        unsafe { *(*self.inner_scope.get()).get_unchecked(id).get() = value }
+        unsafe { *(&*self.inner_scope.get()).get_unchecked(id).get() = value }
     }
 
     ///Adds a variable to the Variable Manager
-    pub fn def_var(self: &Arc<Self>, id: usize, value: DayObject) {        
+    #[allow(clippy::arc_with_non_send_sync)]
+    pub fn def_var(self: &Arc<Self>, id: usize, value: DayObject) {
         unsafe {
             let inner = &mut *self.inner_scope.get();
 
-            if inner.len() == id {
-                inner.push(Arc::new(UnsafeCell::new(value)))
-            } else if inner.len() > id {
+            if inner.len() > id {
                 *inner[id].get() = value
             } else {
-                unreachable!()
+                // Hoisted functions are defined before the variables preceding them
+                self.reserve_vars(id);
+                inner.push(Arc::new(UnsafeCell::new(value)))
+            }
+        }
+    }
+
+    /// Makes sure the variables with ids below `len` exist, missing ones are none
+    #[allow(clippy::arc_with_non_send_sync)]
+    pub fn reserve_vars(self: &Arc<Self>, len: usize) {
+        unsafe {
+            let inner = &mut *self.inner_scope.get();
+            if inner.len() < len {
+                inner.resize_with(len, || Arc::new(UnsafeCell::new(DayObject::None)));
             }
-         }
+        }
+    }
+
+    /// The number of variables defined in this scope
+    pub fn len_vars(self: &Arc<Self>) -> usize {
+        unsafe { (*self.inner_scope.get()).len() }
     }
 
     pub fn clear(self: &Arc<Self>) {
@@ -188,29 +208,33 @@ impl RuntimeManager {
 
     //TODO Should args be mutable?
 
-    pub fn get_args(self: &Arc<Self>) -> ArgVec {
-        self.get_args_mut().clone()
+    /// The args of the innermost function call, an error outside of functions
+    pub fn get_args(self: &Arc<Self>) -> RuntimeResult<ArgVec> {
+        match self.get_args_mut() {
+            Some(args) => Ok(args.clone()),
+            None => Err(RuntimeError::new(
+                RuntimeErrorKind::ArgsOutsideFunction,
+                "args can only be used inside of a function",
+            )),
+        }
     }
 
-    pub fn get_args_mut<'a>(self: &Arc<Self>) -> &'a mut ArgVec {
+    /// `None` outside of functions
+    pub fn get_args_mut<'a>(self: &Arc<Self>) -> Option<&'a mut ArgVec> {
         let mut current = self;
         loop {
             unsafe {
                 if let Some(args) = &*current.args.get() {
                     *self.args.get() = Some(Arc::clone(args));
-                    return &mut *args.get();
+                    return Some(&mut *args.get());
                 }
             }
-            current = if let Some(pre) = &current.predecessor {
-                pre
-            } else {
-                panic!("No args found")
-            }
+            current = current.predecessor.as_ref()?;
         }
     }
 
-    pub fn get_arg(self: &Arc<Self>, id: usize) -> DayObject {
-        self.get_args_mut()[id].clone()
+    pub fn get_arg(self: &Arc<Self>, id: usize) -> Option<DayObject> {
+        self.get_args_mut()?.get(id).cloned()
     }
 
     pub fn def_args(self: &Arc<Self>, args: Arc<UnsafeCell<ArgVec>>) {
@@ -219,6 +243,7 @@ impl RuntimeManager {
         }
     }
 
+    #[allow(clippy::arc_with_non_send_sync)]
     pub fn def_args_alloc(self: &Arc<Self>, args: ArgVec) {
         self.def_args(Arc::new(UnsafeCell::new(args)))
     }
@@ -238,7 +263,7 @@ impl RuntimeManager {
     pub fn get_cache(self: &Arc<Self>, handle: CacheHandle) -> Arc<dyn Cache> {
         unsafe {
             let cptr = self.cache.get();
-            Arc::clone(&(*cptr)[handle])
+            Arc::clone(&(&*cptr)[handle])
         }
     }
 
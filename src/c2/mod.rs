@@ -1,7 +1,21 @@
+pub mod analysis;
 pub mod base;
+pub mod budget;
+pub mod capabilities;
+pub mod day_map;
+pub mod debug;
+pub mod dump;
+pub mod engine;
+pub mod error;
+pub mod format;
+pub mod golden;
+pub mod hooks;
+pub mod index;
 pub mod iter;
 pub mod manager;
+pub mod module;
 pub mod node;
+pub mod optimizer;
 pub mod std_modules;
 
 #[cfg(not(feature = "c2"))]
@@ -9,110 +23,141 @@ pub use variables::hash;
 
 pub mod parser;
 pub mod parsing_error;
+pub mod profile;
+pub mod runtime_error;
+pub mod span;
+pub mod stdio;
 pub mod tokenizer;
+#[cfg(feature = "vm")]
+pub mod vm;
 
 use ahash::RandomState as AHasherBuilder;
-use base::RustFunction;
-use parser::Parser;
+use base::{RustClosure, RustFunction};
+use engine::Engine;
+use error::CrabError;
 use std::collections::HashMap;
-use tokenizer::{build_lexer, TokenStream};
 
 pub type PreMap = HashMap<&'static str, RustFunction, AHasherBuilder>;
+pub type NativeMap = HashMap<String, RustClosure, AHasherBuilder>;
+pub type StdLibrary = HashMap<&'static str, PreMap, AHasherBuilder>;
 
 macro_rules! add_fn {
-    ($map:expr, $module_name: ident, $fnname: ident, $fname: literal) => {
-        $map.insert($fname, std_modules::$module_name::$fnname);
+    ($library:expr, $import_name: literal, $module_name: ident, $fnname: ident, $fname: literal) => {
+        $library
+            .entry($import_name)
+            .or_insert_with(|| PreMap::with_hasher(AHasherBuilder::new()))
+            .insert($fname, std_modules::$module_name::$fnname);
     };
 }
 
-/// Builds the pre_map with the standard functions
-/// this will only be used until modules land, after that
-/// this logic might be moved to the parser
-pub fn build_pre_map<'a>() -> HashMap<&'static str, RustFunction, AHasherBuilder> {
-    //NOTE currently a RandomState hasher is used if it makes sense to use a fixed one it will be used
-    let mut pre_map: PreMap = HashMap::with_capacity_and_hasher(54, AHasherBuilder::new());
-
-    add_fn!(pre_map, arithmetics, add, "add");
-    add_fn!(pre_map, arithmetics, sub, "sub");
-    add_fn!(pre_map, arithmetics, div, "div");
-    add_fn!(pre_map, arithmetics, mul, "mul");
-    add_fn!(pre_map, arithmetics, modu, "mod");
-    add_fn!(pre_map, iter, range, "range");
-
-    add_fn!(pre_map, io, print, "print");
-    add_fn!(pre_map, io, println, "println");
-    add_fn!(pre_map, io, read, "read");
-    add_fn!(pre_map, io, readln, "readln");
-
-    add_fn!(pre_map, fs, cat, "cat");
-    add_fn!(pre_map, fs, rm, "rm");
-    add_fn!(pre_map, fs, touch, "touch");
-    add_fn!(pre_map, fs, mv, "mv");
-    add_fn!(pre_map, fs, fwrite, "fwrite");
-
-    add_fn!(pre_map, conversion, to_string, "string");
-    add_fn!(pre_map, conversion, to_int, "int");
-    add_fn!(pre_map, conversion, to_float, "float");
-    add_fn!(pre_map, conversion, to_bool, "bool");
-    add_fn!(pre_map, conversion, to_arr, "to_arr");
-
-    add_fn!(pre_map, bool_ops, or, "or");
-    add_fn!(pre_map, bool_ops, xor, "xor");
-    add_fn!(pre_map, bool_ops, and, "and");
-    add_fn!(pre_map, bool_ops, not, "not");
-
-    add_fn!(pre_map, comparison, eq, "eq");
-    add_fn!(pre_map, comparison, neq, "neq");
-    add_fn!(pre_map, comparison, lt, "lt");
-    add_fn!(pre_map, comparison, le, "le");
-    add_fn!(pre_map, comparison, gt, "gt");
-    add_fn!(pre_map, comparison, ge, "ge");
-
-    add_fn!(pre_map, array, array, "array");
-    add_fn!(pre_map, array, len, "len");
-    add_fn!(pre_map, array, slice, "slice");
-    add_fn!(pre_map, array, push, "push");
-
-    add_fn!(pre_map, panic, panic, "panic");
-    add_fn!(pre_map, panic, assert, "assert");
-
-    add_fn!(pre_map, iter, map, "map");
-    add_fn!(pre_map, iter, iter, "iter");
-    add_fn!(pre_map, iter, reverse, "reverse");
-    add_fn!(pre_map, iter, rewind, "rewind");
-    add_fn!(pre_map, iter, foreach, "foreach");
-    add_fn!(pre_map, iter, collect, "collect");
-
-    add_fn!(pre_map, functional, apply, "apply");
-    add_fn!(pre_map, functional, call, "call");
-    add_fn!(pre_map, functional, chain, "chain");
-    // add_fn!(pre_map, functional, chained, "chained");
-    add_fn!(pre_map, functional, do_times, "do");
-    add_fn!(pre_map, functional, repeat, "repeat");
-
-    add_fn!(pre_map, env, argv, "argv");
-
-    add_fn!(pre_map, thread, sleep, "sleep");
+/// Builds the standard library, its modules can be imported by their names
+/// like `import math`, which makes `math.add` available
+pub fn build_std_library() -> StdLibrary {
+    let mut library = StdLibrary::with_hasher(AHasherBuilder::new());
+
+    add_fn!(library, "math", arithmetics, add, "add");
+    add_fn!(library, "math", arithmetics, sub, "sub");
+    add_fn!(library, "math", arithmetics, div, "div");
+    add_fn!(library, "math", arithmetics, mul, "mul");
+    add_fn!(library, "math", arithmetics, modu, "mod");
+    add_fn!(library, "math", arithmetics, neg, "neg");
+    add_fn!(library, "iter", iter, range, "range");
+
+    add_fn!(library, "io", io, print, "print");
+    add_fn!(library, "io", io, println, "println");
+    add_fn!(library, "io", io, read, "read");
+    add_fn!(library, "io", io, readln, "readln");
+    add_fn!(library, "io", io, eprint, "eprint");
+    add_fn!(library, "io", io, eprintln, "eprintln");
+
+    add_fn!(library, "fs", fs, cat, "cat");
+    add_fn!(library, "fs", fs, rm, "rm");
+    add_fn!(library, "fs", fs, touch, "touch");
+    add_fn!(library, "fs", fs, mv, "mv");
+    add_fn!(library, "fs", fs, fwrite, "fwrite");
+
+    add_fn!(library, "conversion", conversion, to_string, "string");
+    add_fn!(library, "conversion", conversion, to_int, "int");
+    add_fn!(library, "conversion", conversion, to_float, "float");
+    add_fn!(library, "conversion", conversion, to_bool, "bool");
+    add_fn!(library, "conversion", conversion, to_arr, "to_arr");
+
+    add_fn!(library, "logic", bool_ops, or, "or");
+    add_fn!(library, "logic", bool_ops, xor, "xor");
+    add_fn!(library, "logic", bool_ops, and, "and");
+    add_fn!(library, "logic", bool_ops, not, "not");
+
+    add_fn!(library, "comparison", comparison, eq, "eq");
+    add_fn!(library, "comparison", comparison, neq, "neq");
+    add_fn!(library, "comparison", comparison, lt, "lt");
+    add_fn!(library, "comparison", comparison, le, "le");
+    add_fn!(library, "comparison", comparison, gt, "gt");
+    add_fn!(library, "comparison", comparison, ge, "ge");
+
+    add_fn!(library, "array", array, array, "array");
+    add_fn!(library, "array", array, len, "len");
+    add_fn!(library, "array", array, slice, "slice");
+    add_fn!(library, "array", array, push, "push");
+
+    add_fn!(library, "map", map, dict, "dict");
+    add_fn!(library, "map", map, keys, "keys");
+    add_fn!(library, "map", map, values, "values");
+    add_fn!(library, "map", map, entries, "entries");
+    add_fn!(library, "map", map, has, "has");
+    add_fn!(library, "map", map, remove, "remove");
+
+    add_fn!(library, "panic", panic, panic, "panic");
+    add_fn!(library, "panic", panic, assert, "assert");
+
+    add_fn!(library, "error", error, error, "error");
+    add_fn!(library, "error", error, is_error, "is_error");
+    add_fn!(library, "error", error, raise, "raise");
+    add_fn!(library, "error", error, error_kind, "error_kind");
+    add_fn!(library, "error", error, error_message, "error_message");
+
+    add_fn!(library, "iter", iter, map, "map");
+    add_fn!(library, "iter", iter, iter, "iter");
+    add_fn!(library, "iter", iter, reverse, "reverse");
+    add_fn!(library, "iter", iter, rewind, "rewind");
+    add_fn!(library, "iter", iter, foreach, "foreach");
+    add_fn!(library, "iter", iter, collect, "collect");
+
+    add_fn!(library, "functional", functional, apply, "apply");
+    add_fn!(library, "functional", functional, call, "call");
+    add_fn!(library, "functional", functional, chain, "chain");
+    // add_fn!(library, "functional", functional, chained, "chained");
+    add_fn!(library, "functional", functional, do_times, "do");
+    add_fn!(library, "functional", functional, repeat, "repeat");
+
+    add_fn!(library, "env", env, argv, "argv");
+
+    add_fn!(library, "thread", thread, sleep, "sleep");
     /*
-    add_fn!(pre_map, thread, spawn, "spawn");
-    add_fn!(pre_map, thread, raw_spawn, "raw_spawn");
-    add_fn!(pre_map, thread, join, "join"); */
+    add_fn!(library, "thread", thread, spawn, "spawn");
+    add_fn!(library, "thread", thread, raw_spawn, "raw_spawn");
+    add_fn!(library, "thread", thread, join, "join"); */
 
-    add_fn!(pre_map, functional, noop, "noop");
+    add_fn!(library, "functional", functional, noop, "noop");
 
-    pre_map
+    library
 }
 
-pub fn run(src: &str) -> Result<(), parsing_error::ParsingError> {
-    let lexer = build_lexer().unwrap();
-
-    let tokens = lexer.tokens(src);
+/// Builds the pre_map with the standard functions, it is the prelude
+/// every script can use without importing the modules of the functions
+pub fn build_pre_map() -> PreMap {
+    //NOTE currently a RandomState hasher is used if it makes sense to use a fixed one it will be used
+    let mut pre_map: PreMap = HashMap::with_capacity_and_hasher(66, AHasherBuilder::new());
+    for module in build_std_library().into_values() {
+        pre_map.extend(module);
+    }
 
-    let pre_map = build_pre_map();
-    let mut parser = Parser::new(pre_map);
-    let block = parser.parse_tokens(TokenStream::new(tokens))?;
+    pre_map
+}
 
-    block.execute();
+/// Parses and executes `src`, any parsing or runtime error is returned
+/// instead of aborting the host. Use an `Engine` to keep the state of the script.
+pub fn run(src: &str) -> Result<(), CrabError> {
+    Engine::new().eval(src)?;
 
     Ok(())
 }
@@ -0,0 +1,229 @@
+use crate::{
+    base::{Args, DayObject, RustClosure, RustFunction},
+    build_std_library,
+    manager::RuntimeManager,
+    node::Block,
+    runtime_error::RuntimeResult,
+    StdLibrary,
+};
+use std::{
+    collections::HashMap,
+    fmt,
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+};
+
+/// A group of host functions registered under a common namespace,
+/// scripts call the function `get` of the module `http` as `http.get`
+pub trait Module {
+    /// The namespace of the module
+    fn name(&self) -> &str;
+
+    /// The functions of the module by their names inside of the namespace
+    fn functions(&self) -> Vec<(String, RustClosure)>;
+}
+
+/// A module assembled from closures by the host
+#[derive(Clone)]
+pub struct NativeModule {
+    name: String,
+    functions: Vec<(String, RustClosure)>,
+}
+
+impl NativeModule {
+    pub fn new(name: impl Into<String>) -> Self {
+        NativeModule {
+            name: name.into(),
+            functions: Vec::new(),
+        }
+    }
+
+    /// Adds the function `f` to the module under `name`
+    pub fn function(
+        mut self,
+        name: impl Into<String>,
+        f: impl Fn(Args) -> RuntimeResult<DayObject> + Send + Sync + 'static,
+    ) -> Self {
+        self.functions.push((name.into(), Arc::new(f)));
+        self
+    }
+}
+
+impl Module for NativeModule {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn functions(&self) -> Vec<(String, RustClosure)> {
+        self.functions.clone()
+    }
+}
+
+/// A script file imported with `import "path"`, its top level variables
+/// are accessed by the importing script as `<file stem>.<name>`
+pub struct FileModule {
+    path: PathBuf,
+    block: Block,
+    /// The top level scope of the module
+    scope: Arc<RuntimeManager>,
+    /// The ids of the top level variables, names starting with `_` are private
+    exports: HashMap<String, usize>,
+    executed: AtomicBool,
+}
+
+impl FileModule {
+    pub fn new(path: PathBuf, block: Block, exports: HashMap<String, usize>) -> Self {
+        let scope = Arc::new(RuntimeManager::new());
+        // The variables can be read before the module was executed by hoisted functions
+        scope.reserve_vars(block.capacity);
+        FileModule {
+            path,
+            block,
+            scope,
+            exports,
+            executed: AtomicBool::new(false),
+        }
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// The id of the exported variable `name`
+    pub fn export(&self, name: &str) -> Option<usize> {
+        self.exports.get(name).copied()
+    }
+
+    pub fn get_var(&self, id: usize) -> DayObject {
+        self.scope.get_var(id, 0)
+    }
+
+    /// Executes the module unless an import did before, a module failing
+    /// to execute is not executed again
+    pub fn execute(&self) -> RuntimeResult<()> {
+        if self.executed.swap(true, Ordering::AcqRel) {
+            return Ok(());
+        }
+
+        self.block
+            .execute_in(&self.scope)
+            .map(|_| ())
+            .map_err(|e| e.left_import(&self.path))
+    }
+}
+
+impl fmt::Debug for FileModule {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "FileModule({})", self.path.display())
+    }
+}
+
+/// The files `import "path"` can load
+#[derive(Clone, Debug)]
+pub enum FileAccess {
+    All,
+    /// Only files inside of the directory, which replaces the search path
+    Within(PathBuf),
+    None,
+}
+
+/// Finds and caches the modules imported by the scripts of an engine
+pub struct ModuleLoader {
+    std_library: StdLibrary,
+    /// The directories imported files are searched in after the directory of the importing file
+    search_path: Vec<PathBuf>,
+    file_access: FileAccess,
+    cache: HashMap<PathBuf, Arc<FileModule>>,
+    /// The files currently being parsed, the last one was imported by the one before it
+    loading: Vec<PathBuf>,
+}
+
+impl ModuleLoader {
+    /// Creates a loader searching in the current working directory
+    pub fn new() -> Self {
+        ModuleLoader {
+            std_library: build_std_library(),
+            search_path: vec![PathBuf::from(".")],
+            file_access: FileAccess::All,
+            cache: HashMap::new(),
+            loading: Vec::new(),
+        }
+    }
+
+    pub fn add_search_path(&mut self, dir: impl Into<PathBuf>) {
+        self.search_path.push(dir.into())
+    }
+
+    pub fn set_file_access(&mut self, file_access: FileAccess) {
+        self.file_access = file_access
+    }
+
+    /// Removes a function from the std library, `module` stays importable
+    pub fn remove_std_function(&mut self, module: &str, name: &str) {
+        if let Some(functions) = self.std_library.get_mut(module) {
+            functions.remove(name);
+        }
+    }
+
+    pub fn is_std_module(&self, name: &str) -> bool {
+        self.std_library.contains_key(name)
+    }
+
+    pub fn std_function(&self, module: &str, name: &str) -> Option<RustFunction> {
+        self.std_library.get(module)?.get(name).copied()
+    }
+
+    /// The canonical path of the file `path` imported by a file in `dir`
+    pub fn resolve(&self, path: &str, dir: Option<&Path>) -> Option<PathBuf> {
+        let (search_path, root) = match &self.file_access {
+            FileAccess::All => (self.search_path.as_slice(), None),
+            FileAccess::Within(root) => {
+                (std::slice::from_ref(root), Some(root.canonicalize().ok()?))
+            }
+            FileAccess::None => return None,
+        };
+
+        dir.into_iter()
+            .chain(search_path.iter().map(PathBuf::as_path))
+            .filter_map(|dir| dir.join(path).canonicalize().ok())
+            .find(|found| {
+                found.is_file() && root.as_ref().is_none_or(|root| found.starts_with(root))
+            })
+    }
+
+    pub fn cached(&self, path: &Path) -> Option<Arc<FileModule>> {
+        self.cache.get(path).cloned()
+    }
+
+    /// Marks `path` as being parsed, returns the chain of imports if it already is
+    pub fn start_loading(&mut self, path: &Path) -> Result<(), String> {
+        if let Some(start) = self.loading.iter().position(|p| p == path) {
+            let chain: Vec<_> = self.loading[start..]
+                .iter()
+                .chain(std::iter::once(&path.to_path_buf()))
+                .map(|p| p.display().to_string())
+                .collect();
+            return Err(chain.join(" -> "));
+        }
+
+        self.loading.push(path.to_path_buf());
+        Ok(())
+    }
+
+    /// Ends the parsing of the innermost file, `module` is cached if it parsed
+    pub fn finish_loading(&mut self, module: Option<Arc<FileModule>>) {
+        let path = self.loading.pop();
+        if let (Some(path), Some(module)) = (path, module) {
+            self.cache.insert(path, module);
+        }
+    }
+}
+
+impl Default for ModuleLoader {
+    fn default() -> Self {
+        Self::new()
+    }
+}
@@ -1,18 +1,35 @@
 use crate::{
-    base::{Args, DayFunction, DayObject, RustFunction},
+    base::{Args, DayFunction, DayObject, Env, RustFunction},
+    budget,
+    day_map::DayMap,
+    debug, hooks,
+    index::Subscript,
     manager::RuntimeManager,
-    std_modules::{conversion::to_bool_inner, iter::to_iter_inner},
+    module::FileModule,
+    profile,
+    runtime_error::{RuntimeError, RuntimeErrorKind, RuntimeResult},
+    span::Span,
+    std_modules::{
+        arithmetics::{add_two, sub_two},
+        conversion::to_bool_inner,
+        iter::to_iter_inner,
+    },
 };
-use std::sync::Arc;
+use std::{borrow::Cow, path::PathBuf, sync::Arc};
+
+#[cfg(feature = "vm")]
+use crate::vm::{self, Backend, Chunk};
+#[cfg(feature = "vm")]
+use std::sync::OnceLock;
 
-//TODO Closures, The rest of the nodes, Consts
+//TODO The rest of the nodes, Consts
 
-type NodeJump = unsafe fn(&Node, &Arc<RuntimeManager>) -> ExpressionResult;
+type NodeJump = unsafe fn(&Node, &Arc<RuntimeManager>) -> ExecResult;
 
 //IMPORTANT The Order of NODE_JUMPS and all other jump tables is important.
 //Check out all IMPORTANT annotations before changing anything
 
-const NODE_JUMPS: [NodeJump; 14] = [
+const NODE_JUMPS: [NodeJump; 23] = [
     //Node::RustFunction
     exec_rust_fn,
     //NODE::Identifier
@@ -41,8 +58,26 @@ const NODE_JUMPS: [NodeJump; 14] = [
     exec_ret,
     //Node::Index
     exec_index,
-    //Node::Index
+    //Node::FunctionDeclaration
     exec_function_decl,
+    //Node::Try
+    exec_try,
+    //Node::Break
+    exec_break,
+    //Node::Continue
+    exec_continue,
+    //Node::Logical
+    exec_logical,
+    //Node::MapLiteral
+    exec_map_literal,
+    //Node::Args
+    exec_args,
+    //Node::Import
+    exec_import,
+    //Node::ModuleVar
+    exec_module_var,
+    //Node::BinaryOp
+    exec_binary_op,
 ];
 
 #[repr(u8)]
@@ -52,9 +87,13 @@ pub enum Node {
     Identifier(IdentifierNode),
     Data(DayObject),
     FunctionCall(FunctionCallNode),
+    /// If `destructure` is set every element has to be an array of two elements,
+    /// which are bound to the first two variables of `block`
     For {
         expr: Box<Node>,
         block: Block,
+        destructure: bool,
+        span: Span,
     },
     Assignment {
         assignee: Box<Node>,
@@ -62,254 +101,531 @@ pub enum Node {
     },
     Declaration {
         value: Box<Node>,
-        id: usize
+        id: usize,
     },
     ConstDeclaration {
         value: Box<Node>,
-        id: usize
+        id: usize,
     },
     BranchNode(Vec<BranchNode>),
     While {
         condition: Box<Node>,
         block: Block,
+        span: Span,
     },
     Block(Block),
     Ret(Option<Arc<Node>>),
     Index(IndexNode),
+    /// Creates a function capturing the current scope, named functions are bound to `id`
     FunctionDeclaration {
-        block: Arc<Block>,
-        is_closure: bool,
+        function: Arc<FunctionDef>,
+        id: Option<usize>,
+    },
+    /// Executes `block`, if it fails the error is bound to the first
+    /// variable of `catch` (if `binds_error` is set) and `catch` is executed
+    Try {
+        block: Block,
+        catch: Block,
+        binds_error: bool,
+    },
+    /// Leaves the innermost loop, which then evaluates to the value (or none)
+    Break(Option<Box<Node>>),
+    Continue,
+    /// `&&` and `||`, the right side is only executed if it decides the result
+    Logical {
+        op: LogicalOp,
+        lhs: Box<Node>,
+        rhs: Box<Node>,
+        span: Span,
+    },
+    MapLiteral {
+        entries: Vec<(Node, Node)>,
+        span: Span,
     },
+    /// The args of the innermost function call
+    Args,
+    /// Executes the imported file module unless it already was
+    Import {
+        module: Arc<FileModule>,
+        span: Span,
+    },
+    /// A top level variable of an imported file module
+    ModuleVar {
+        module: Arc<FileModule>,
+        id: usize,
+    },
+    /// A call of a common two argument std function, created by the optimizer
+    BinaryOp {
+        op: BinaryOp,
+        lhs: Box<Node>,
+        rhs: Box<Node>,
+        span: Span,
+    },
+}
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum LogicalOp {
+    And,
+    Or,
+}
+
+/// The std functions with a dedicated node, they behave exactly like a call of the function
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum BinaryOp {
+    Add,
+    Sub,
+    Lt,
+}
+
+impl BinaryOp {
+    pub fn apply(self, lhs: &DayObject, rhs: &DayObject) -> RuntimeResult<DayObject> {
+        match self {
+            BinaryOp::Add => add_two(lhs, rhs),
+            BinaryOp::Sub => sub_two(lhs, rhs),
+            BinaryOp::Lt => Ok(DayObject::Bool(lhs < rhs)),
+        }
+    }
 }
 
 impl Node {
-    pub fn execute(&self, manager: &Arc<RuntimeManager>) -> ExpressionResult {
+    pub fn execute(&self, manager: &Arc<RuntimeManager>) -> ExecResult {
         let tag: u8 = unsafe { std::mem::transmute_copy(self) };
         unsafe { NODE_JUMPS[tag as usize](self, manager) }
     }
 
-    pub fn function_decl(block: Block, is_closure: bool) -> Self {
+    pub fn function_decl(function: FunctionDef, id: Option<usize>) -> Self {
         Self::FunctionDeclaration {
-            block: Arc::new(block),
-            is_closure,
+            function: Arc::new(function),
+            id,
         }
     }
 }
 
-unsafe fn exec_data(data: &Node, _manager: &Arc<RuntimeManager>) -> ExpressionResult {
+unsafe fn exec_data(data: &Node, _manager: &Arc<RuntimeManager>) -> ExecResult {
     dbg_print_pretty!("@data");
     let data = data as *const _ as *const (u8, DayObject);
-    ExpressionResult::Value((*data).1.clone())
+    Ok(ExpressionResult::Value((*data).1.clone()))
 }
 
-unsafe fn exec_rust_fn(rust_fn: &Node, _manager: &Arc<RuntimeManager>) -> ExpressionResult {
+unsafe fn exec_rust_fn(rust_fn: &Node, _manager: &Arc<RuntimeManager>) -> ExecResult {
     dbg_print_pretty!("@rfn");
     if let Node::RustFunction(rfn) = rust_fn {
-        return ExpressionResult::Value(DayObject::Function(DayFunction::Function(rfn.0.clone())));
+        return Ok(ExpressionResult::Value(DayObject::Function(
+            DayFunction::Function(rfn.0),
+        )));
     }
     std::hint::unreachable_unchecked();
 }
 
-type CallJump = unsafe fn(&FunctionCallNode, &Arc<RuntimeManager>) -> ExpressionResult;
+type CallJump = unsafe fn(&FunctionCallNode, &Arc<RuntimeManager>) -> ExecResult;
 
 const CALL_JUMPS: [CallJump; 3] = [call_rustfn, call_ident, call_other];
 
-unsafe fn get_args<'a>(
-    call: &'a FunctionCallNode,
+/// Evaluates the args of `call` and passes them to `f`. Every evaluation allocates its own
+/// vector, so recursive and concurrent evaluations of the same call node don't share state.
+fn with_args<T>(
+    call: &FunctionCallNode,
     manager: &Arc<RuntimeManager>,
-) -> &'a [DayObject] {
-    let cache = &mut *call.arg_cache.get();
-    if cache.len() != call.args.len() {
-        for a in &call.args {
-            cache.push(a.execute(manager).value())
-        }
-    } else {
-        for (a, n) in cache.iter_mut().zip(call.args.iter()) {
-            *a = n.execute(manager).value()
-        }
+    f: impl FnOnce(Args) -> RuntimeResult<T>,
+) -> RuntimeResult<T> {
+    let mut args = Vec::with_capacity(call.args.len());
+    for a in &call.args {
+        args.push(a.execute(manager)?.value()?)
     }
-    &*cache
+    f(&args)
 }
 
-unsafe fn call_rustfn(call: &FunctionCallNode, manager: &Arc<RuntimeManager>) -> ExpressionResult {
+unsafe fn call_rustfn(call: &FunctionCallNode, manager: &Arc<RuntimeManager>) -> ExecResult {
     dbg_print_pretty!("@crfn");
     let (_, rfn) = &*(&*call.expr as *const _ as *const (u8, ConstRustFn));
 
-    let args = get_args(call, manager);
-    return ExpressionResult::Value(rfn.0(args));
+    Ok(ExpressionResult::Value(with_args(call, manager, |args| {
+        let _profile = profile::enter_std(rfn.0);
+        rfn.0(args)
+    })?))
 }
 
-unsafe fn call_ident(call: &FunctionCallNode, manager: &Arc<RuntimeManager>) -> ExpressionResult {
+unsafe fn call_ident(call: &FunctionCallNode, manager: &Arc<RuntimeManager>) -> ExecResult {
     dbg_print_pretty!("@cid");
     if let Node::Identifier(id) = &*call.expr {
-        match id.get_mut(manager) {
-            DayObject::Function(func) => {
-                return ExpressionResult::Value(func.call(get_args(call, manager)))
-            }
-            DayObject::Iter(handle) => {
-                if let Some(obj) = handle.0.next() {
-                    return ExpressionResult::Value(obj);
-                } else {
-                    return ExpressionResult::Value(DayObject::None);
-                }
-            }
-            _ => panic!("Err: The function {:?} does not exist!", id),
-        }
+        return call_value(id.get_mut(manager), call, manager);
     }
     std::hint::unreachable_unchecked();
 }
 
-unsafe fn call_other(call: &FunctionCallNode, manager: &Arc<RuntimeManager>) -> ExpressionResult {
+unsafe fn call_other(call: &FunctionCallNode, manager: &Arc<RuntimeManager>) -> ExecResult {
     dbg_print_pretty!("@cother");
-    match call.expr.execute(manager).value() {
+    let mut callee = call.expr.execute(manager)?.value()?;
+    call_value(&mut callee, call, manager)
+}
+
+/// Calls a function value or advances an iter value
+unsafe fn call_value(
+    callee: &mut DayObject,
+    call: &FunctionCallNode,
+    manager: &Arc<RuntimeManager>,
+) -> ExecResult {
+    match callee {
         DayObject::Function(func) => {
-            return ExpressionResult::Value(func.call(get_args(call, manager)))
-        }
-        DayObject::Iter(mut handle) => {
-            if let Some(obj) = handle.0.next() {
-                return ExpressionResult::Value(obj);
-            } else {
-                return ExpressionResult::Value(DayObject::None);
-            }
+            Ok(ExpressionResult::Value(with_args(call, manager, |args| {
+                func.call(args)
+            })?))
         }
-        other => panic!("Can't call {:?}", other),
+        DayObject::Iter(handle) => Ok(ExpressionResult::Value(
+            handle.0.next()?.unwrap_or(DayObject::None),
+        )),
+        other => Err(RuntimeError::new(
+            RuntimeErrorKind::NotCallable,
+            format!("Can't call {:?}", other),
+        )),
     }
 }
 
-unsafe fn exec_call(call: &Node, manager: &Arc<RuntimeManager>) -> ExpressionResult {
+unsafe fn exec_call(call: &Node, manager: &Arc<RuntimeManager>) -> ExecResult {
     dbg_print_pretty!("@call");
     let callptr = call as *const _ as *const (u8, FunctionCallNode);
     let jmp: u8 = std::mem::transmute_copy(&*(*callptr).1.expr);
     let jmp: usize = jmp as usize;
     dbg_print!(jmp);
-    if jmp < 2 {
+    let res = if jmp < 2 {
         CALL_JUMPS[jmp](&(*callptr).1, manager)
     } else {
         CALL_JUMPS[2](&(*callptr).1, manager)
-    }
+    };
+    res.map_err(|e| e.at((*callptr).1.span))
 }
 
-unsafe fn exec_for(for_node: &Node, manager: &Arc<RuntimeManager>) -> ExpressionResult {
+unsafe fn exec_for(for_node: &Node, manager: &Arc<RuntimeManager>) -> ExecResult {
     dbg_print_pretty!("@for");
-    if let Node::For { expr, block } = for_node {
-        let mut iter = to_iter_inner(&expr.execute(manager).value());
-
-        //TODO It has to be asserted that an ident is only used after definition
-        //This could (and probably should) be done in the parser such that use before
-        //definition is a preexecution parsing error
+    if let Node::For {
+        expr,
+        block,
+        destructure,
+        span,
+    } = for_node
+    {
+        let res = run_for(expr, block, *destructure, manager);
+        return res.map_err(|e| e.at(*span));
+    }
+    std::hint::unreachable_unchecked()
+}
 
-        while let Some(i) = iter.0.next() {
-            block.scope.def_var(0, i);
-            block.execute();
+fn run_for(
+    expr: &Node,
+    block: &Block,
+    destructure: bool,
+    manager: &Arc<RuntimeManager>,
+) -> ExecResult {
+    let mut iter = to_iter_inner(&expr.execute(manager)?.value()?)?;
+
+    //TODO It has to be asserted that an ident is only used after definition
+    //This could (and probably should) be done in the parser such that use before
+    //definition is a preexecution parsing error
+
+    let mut scope = block.new_scope(manager);
+    while let Some(i) = iter.0.next()? {
+        budget::step()?;
+        // The scope is only reused if no closure captured it in the last iteration
+        if Arc::strong_count(&scope) > 1 {
+            scope = block.new_scope(manager);
+        }
+        bind_loop_vars(&scope, i, destructure)?;
+        match block.execute_in(&scope)? {
+            ret @ ExpressionResult::Return(_) => return Ok(ret),
+            ExpressionResult::Break(value) => return Ok(ExpressionResult::Value(value)),
+            _ => (),
         }
+    }
 
-        block.scope.clear();
+    Ok(ExpressionResult::Value(DayObject::None))
+}
 
+/// Binds an element of a for loop to the first variable(s) of the scope of its body
+pub fn bind_loop_vars(
+    scope: &Arc<RuntimeManager>,
+    element: DayObject,
+    destructure: bool,
+) -> RuntimeResult<()> {
+    if !destructure {
+        scope.def_var(0, element);
+        return Ok(());
+    }
 
-        return ExpressionResult::Value(DayObject::None);
+    match element {
+        DayObject::Array(mut pair) if pair.len() == 2 => {
+            let second = pair.swap_remove(1);
+            scope.def_var(0, pair.swap_remove(0));
+            scope.def_var(1, second);
+            Ok(())
+        }
+        other => Err(RuntimeError::type_error(format!(
+            "Can't destructure {:?} into two variables",
+            other
+        ))),
     }
-    std::hint::unreachable_unchecked()
 }
 
-unsafe fn exec_assignment(
-    assignment_node: &Node,
-    manager: &Arc<RuntimeManager>,
-) -> ExpressionResult {
+unsafe fn exec_assignment(assignment_node: &Node, manager: &Arc<RuntimeManager>) -> ExecResult {
     if let Node::Assignment { assignee, value: v } = assignment_node {
         match &**assignee {
             Node::Identifier(id) => {
-                let value = v.execute(manager).value();
+                let value = v.execute(manager)?.value()?;
                 id.set_var(manager, value);
-                return ExpressionResult::Value(DayObject::None);
+                return Ok(ExpressionResult::Value(DayObject::None));
             }
             Node::Index(inner) => {
-                *inner.get_mut(&manager) = v.execute(manager).value();
-                return ExpressionResult::Value(DayObject::None);
+                let value = v.execute(manager)?.value()?;
+                inner.assign(manager, value)?;
+                return Ok(ExpressionResult::Value(DayObject::None));
+            }
+            other => {
+                return Err(RuntimeError::type_error(format!(
+                    "Can't assign to {:?}",
+                    other
+                )))
             }
-            other => panic!("Can't assign to {:?}", other),
         }
     }
     std::hint::unreachable_unchecked()
 }
 
-unsafe fn exec_decl(decl_node: &Node, manager: &Arc<RuntimeManager>) -> ExpressionResult {
+unsafe fn exec_decl(decl_node: &Node, manager: &Arc<RuntimeManager>) -> ExecResult {
     dbg_print_pretty!("@decl");
-    if let Node::Declaration { value: v, id } = decl_node {
-        let value = v.execute(manager).value();
-        manager.def_var(*id, value);
-        return ExpressionResult::Value(DayObject::None);
+    match decl_node {
+        Node::Declaration { value: v, id } | Node::ConstDeclaration { value: v, id } => {
+            let value = v.execute(manager)?.value()?;
+            manager.def_var(*id, value);
+            Ok(ExpressionResult::Value(DayObject::None))
+        }
+        _ => std::hint::unreachable_unchecked(),
     }
-    std::hint::unreachable_unchecked()
 }
 
-unsafe fn exec_ident(ident_node: &Node, manager: &Arc<RuntimeManager>) -> ExpressionResult {
+unsafe fn exec_ident(ident_node: &Node, manager: &Arc<RuntimeManager>) -> ExecResult {
     dbg_print_pretty!("@id");
     let (_, id) = &*(ident_node as *const _ as *const (u8, IdentifierNode));
     let val = id.get_var(manager);
-    ExpressionResult::Value(val)
+    Ok(ExpressionResult::Value(val))
 }
 
-unsafe fn exec_branch(branch_node: &Node, manager: &Arc<RuntimeManager>) -> ExpressionResult {
+unsafe fn exec_branch(branch_node: &Node, manager: &Arc<RuntimeManager>) -> ExecResult {
     let (_, branches) = &*(branch_node as *const _ as *const (u8, Vec<BranchNode>));
     for b in branches {
         if let BranchNode::Else { block } = b {
-            return block.execute();
+            return block.execute(manager);
         } else {
             let (_, ifb) = &*(b as *const _ as *const (u8, IfBlock));
-            if let Some(res) = ifb.execute(manager) {
-                return res;
+            if let Some(res) = ifb.execute(manager)? {
+                return Ok(res);
             }
         }
     }
 
-    ExpressionResult::Value(DayObject::None)
+    Ok(ExpressionResult::Value(DayObject::None))
 }
 
-unsafe fn exec_while(while_node: &Node, manager: &Arc<RuntimeManager>) -> ExpressionResult {
-    if let Node::While{condition, block} = while_node {
-        while to_bool_inner(&condition.execute(manager).value()) {
-            block.execute();
+unsafe fn exec_while(while_node: &Node, manager: &Arc<RuntimeManager>) -> ExecResult {
+    if let Node::While {
+        condition,
+        block,
+        span,
+    } = while_node
+    {
+        let res = run_while(condition, block, manager);
+        return res.map_err(|e| e.at(*span));
+    }
+
+    std::hint::unreachable_unchecked();
+}
+
+fn run_while(condition: &Node, block: &Block, manager: &Arc<RuntimeManager>) -> ExecResult {
+    let mut scope = block.new_scope(manager);
+    while to_bool_inner(&condition.execute(manager)?.value()?)? {
+        budget::step()?;
+        if Arc::strong_count(&scope) > 1 {
+            scope = block.new_scope(manager);
+        }
+        match block.execute_in(&scope)? {
+            ret @ ExpressionResult::Return(_) => return Ok(ret),
+            ExpressionResult::Break(value) => return Ok(ExpressionResult::Value(value)),
+            _ => (),
         }
-        block.scope.clear()
     }
 
-    return ExpressionResult::Value(DayObject::None);
+    Ok(ExpressionResult::Value(DayObject::None))
 }
 
-unsafe fn exec_block(block_node: &Node, _manager: &Arc<RuntimeManager>) -> ExpressionResult {
+unsafe fn exec_block(block_node: &Node, manager: &Arc<RuntimeManager>) -> ExecResult {
     if let Node::Block(blk) = block_node {
-        return blk.execute();
+        return blk.execute(manager);
     }
 
     std::hint::unreachable_unchecked();
 }
 
-unsafe fn exec_ret(ret_node: &Node, manager: &Arc<RuntimeManager>) -> ExpressionResult {
+unsafe fn exec_ret(ret_node: &Node, manager: &Arc<RuntimeManager>) -> ExecResult {
     if let Node::Ret(blk) = ret_node {
-        if let Some(node) = blk { 
-            return ExpressionResult::Return(node.execute(manager).value())
+        if let Some(node) = blk {
+            return Ok(ExpressionResult::Return(node.execute(manager)?.value()?));
         } else {
-            return ExpressionResult::Return(DayObject::None)
+            return Ok(ExpressionResult::Return(DayObject::None));
         }
     }
 
     std::hint::unreachable_unchecked();
 }
 
-unsafe fn exec_index(index_node: &Node, manager: &Arc<RuntimeManager>) -> ExpressionResult {
+unsafe fn exec_index(index_node: &Node, manager: &Arc<RuntimeManager>) -> ExecResult {
     if let Node::Index(ind) = index_node {
-        return ExpressionResult::Value(ind.get_value(manager))
+        return Ok(ExpressionResult::Value(ind.get_value(manager)?));
     }
 
     std::hint::unreachable_unchecked();
 }
 
-unsafe fn exec_function_decl(_fdecl_node: &Node, _manager: &Arc<RuntimeManager>) -> ExpressionResult {
-    todo!()
-    /* if let Node::FunctionDeclaration { block, is_closure: _} = fdecl_node {
+unsafe fn exec_function_decl(fdecl_node: &Node, manager: &Arc<RuntimeManager>) -> ExecResult {
+    if let Node::FunctionDeclaration { function, id } = fdecl_node {
+        return Ok(ExpressionResult::Value(match id {
+            Some(id) => {
+                manager.def_var(*id, named_function(function, manager));
+                DayObject::None
+            }
+            None => DayObject::Function(DayFunction::RuntimeDef(
+                Arc::clone(function),
+                Env::Strong(Arc::clone(manager)),
+            )),
+        }));
+    }
+
+    std::hint::unreachable_unchecked();
+}
+
+/// The value of a named function declared in `scope`, which stores it
+pub fn named_function(function: &Arc<FunctionDef>, scope: &Arc<RuntimeManager>) -> DayObject {
+    DayObject::Function(DayFunction::RuntimeDef(
+        Arc::clone(function),
+        Env::Weak(Arc::downgrade(scope)),
+    ))
+}
+
+unsafe fn exec_args(_args_node: &Node, manager: &Arc<RuntimeManager>) -> ExecResult {
+    Ok(ExpressionResult::Value(DayObject::Array(
+        manager.get_args()?,
+    )))
+}
+
+unsafe fn exec_import(import_node: &Node, _manager: &Arc<RuntimeManager>) -> ExecResult {
+    if let Node::Import { module, span } = import_node {
+        module.execute().map_err(|e| e.at(*span))?;
+        return Ok(ExpressionResult::Value(DayObject::None));
+    }
+
+    std::hint::unreachable_unchecked();
+}
+
+unsafe fn exec_module_var(var_node: &Node, _manager: &Arc<RuntimeManager>) -> ExecResult {
+    if let Node::ModuleVar { module, id } = var_node {
+        return Ok(ExpressionResult::Value(module.get_var(*id)));
+    }
+
+    std::hint::unreachable_unchecked();
+}
+
+unsafe fn exec_try(try_node: &Node, manager: &Arc<RuntimeManager>) -> ExecResult {
+    if let Node::Try {
+        block,
+        catch,
+        binds_error,
+    } = try_node
+    {
+        return match block.execute(manager) {
+            Err(e) if e.kind().is_catchable() => {
+                let scope = catch.new_scope(manager);
+                if *binds_error {
+                    scope.def_var(0, DayObject::Error(Box::new(e)));
+                }
+                catch.execute_in(&scope)
+            }
+            ok => ok,
+        };
+    }
+
+    std::hint::unreachable_unchecked();
+}
+
+unsafe fn exec_break(break_node: &Node, manager: &Arc<RuntimeManager>) -> ExecResult {
+    if let Node::Break(value) = break_node {
+        return Ok(ExpressionResult::Break(match value {
+            Some(node) => node.execute(manager)?.value()?,
+            None => DayObject::None,
+        }));
     }
 
-    std::hint::unreachable_unchecked(); */
+    std::hint::unreachable_unchecked();
+}
+
+unsafe fn exec_continue(_continue_node: &Node, _manager: &Arc<RuntimeManager>) -> ExecResult {
+    Ok(ExpressionResult::Continue)
+}
+
+unsafe fn exec_map_literal(map_node: &Node, manager: &Arc<RuntimeManager>) -> ExecResult {
+    if let Node::MapLiteral { entries, span } = map_node {
+        let res = build_map(entries, manager);
+        return res.map_err(|e| e.at(*span));
+    }
+
+    std::hint::unreachable_unchecked();
+}
+
+fn build_map(entries: &[(Node, Node)], manager: &Arc<RuntimeManager>) -> ExecResult {
+    let mut map = DayMap::with_capacity(entries.len());
+    for (key, value) in entries {
+        map.insert(
+            key.execute(manager)?.value()?,
+            value.execute(manager)?.value()?,
+        )?;
+    }
+
+    Ok(ExpressionResult::Value(DayObject::Map(map)))
+}
+
+unsafe fn exec_logical(logical_node: &Node, manager: &Arc<RuntimeManager>) -> ExecResult {
+    if let Node::Logical { op, lhs, rhs, span } = logical_node {
+        let res = run_logical(*op, lhs, rhs, manager);
+        return res.map_err(|e| e.at(*span));
+    }
+
+    std::hint::unreachable_unchecked();
+}
+
+fn run_logical(op: LogicalOp, lhs: &Node, rhs: &Node, manager: &Arc<RuntimeManager>) -> ExecResult {
+    let lhs = to_bool_inner(&lhs.execute(manager)?.value()?)?;
+    let res = match op {
+        LogicalOp::And => lhs && to_bool_inner(&rhs.execute(manager)?.value()?)?,
+        LogicalOp::Or => lhs || to_bool_inner(&rhs.execute(manager)?.value()?)?,
+    };
+
+    Ok(ExpressionResult::Value(DayObject::Bool(res)))
+}
+
+unsafe fn exec_binary_op(binary_node: &Node, manager: &Arc<RuntimeManager>) -> ExecResult {
+    if let Node::BinaryOp { op, lhs, rhs, span } = binary_node {
+        let res = run_binary_op(*op, lhs, rhs, manager);
+        return res.map_err(|e| e.at(*span));
+    }
+
+    std::hint::unreachable_unchecked();
+}
+
+fn run_binary_op(
+    op: BinaryOp,
+    lhs: &Node,
+    rhs: &Node,
+    manager: &Arc<RuntimeManager>,
+) -> ExecResult {
+    let lhs = lhs.execute(manager)?.value()?;
+    let rhs = rhs.execute(manager)?.value()?;
+    Ok(ExpressionResult::Value(op.apply(&lhs, &rhs)?))
 }
 
 //------------------------------------------------------------------
@@ -333,77 +649,205 @@ pub struct IfBlock {
 }
 
 impl IfBlock {
-    fn execute(&self, manager: &Arc<RuntimeManager>) -> Option<ExpressionResult> {
-        if let DayObject::Bool(true) = self.condition.execute(manager).value() {
-            Some(self.block.execute())
+    fn execute(&self, manager: &Arc<RuntimeManager>) -> RuntimeResult<Option<ExpressionResult>> {
+        if let DayObject::Bool(true) = self.condition.execute(manager)?.value()? {
+            Ok(Some(self.block.execute(manager)?))
         } else {
-            None
+            Ok(None)
         }
     }
 }
 
+/// A block of nodes with its own scope, the scope is created anew for every execution
 pub struct Block {
     pub block: RootNode,
-    pub scope: Arc<RuntimeManager>,
+    /// The number of variables declared in this block
+    pub capacity: usize,
+    /// The bytecode of the block, compiled the first time it is executed
+    #[cfg(feature = "vm")]
+    code: OnceLock<Box<Chunk>>,
 }
 
 impl Block {
-    pub fn new(purpose: NodePurpose, scope: Arc<RuntimeManager>) -> Self {
+    pub fn new(purpose: NodePurpose, capacity: usize) -> Self {
         Self {
-            block: RootNode {
-                purpose,
-                nodes: Default::default(),
-            },
-            scope,
+            block: RootNode::new(purpose),
+            capacity,
+            #[cfg(feature = "vm")]
+            code: OnceLock::new(),
         }
     }
 
-    pub fn new_capacity(purpose: NodePurpose, inner_capacity: usize) -> Self {
-        Self {
-            block: RootNode {
-                purpose,
-                nodes: Default::default(),
-            },
-            scope: Arc::new(RuntimeManager::new_capacity(inner_capacity)),
-        }
+    pub fn push(&mut self, node: Node, span: Span) {
+        self.invalidate();
+        self.block.push(node, span)
     }
 
-    pub fn new_capacity_predecessor(
-        purpose: NodePurpose,
-        inner_capacity: usize,
-        predecessor: Option<Arc<RuntimeManager>>,
-    ) -> Self {
-        Self {
-            block: RootNode {
-                purpose,
-                nodes: Default::default(),
-            },
-            scope: Arc::new(RuntimeManager::new_capacity_predecessor(
-                inner_capacity,
-                predecessor,
-            )),
-        }
+    pub fn hoist(&mut self, node: Node, span: Span) {
+        self.invalidate();
+        self.block.hoist(node, span)
     }
 
-    pub fn push(&mut self, node: Node) {
-        self.block.push(node)
+    pub fn pop(&mut self) -> Option<(Node, Span)> {
+        self.invalidate();
+        self.block.pop()
     }
 
-    pub fn pop(&mut self) -> Option<Node> {
-        self.block.pop()
+    /// The nodes of the block, for passes rewriting them before it is executed
+    pub fn nodes_mut(&mut self) -> &mut [Node] {
+        self.invalidate();
+        self.block.nodes_mut()
     }
 
     pub fn len(&self) -> usize {
         self.block.len()
     }
 
-    pub fn execute(&self) -> ExpressionResult {
-        self.block.execute(&self.scope)
+    pub fn is_empty(&self) -> bool {
+        self.block.is_empty()
+    }
+
+    /// Creates the scope for an execution of this block nested in `predecessor`
+    pub fn new_scope(&self, predecessor: &Arc<RuntimeManager>) -> Arc<RuntimeManager> {
+        Arc::new(RuntimeManager::new_capacity_predecessor(
+            self.capacity,
+            Some(Arc::clone(predecessor)),
+        ))
+    }
+
+    /// Creates the scope of a top level block
+    pub fn root_scope(&self) -> Arc<RuntimeManager> {
+        Arc::new(RuntimeManager::new_capacity(self.capacity))
+    }
+
+    /// Executes the block in a new scope nested in `manager`
+    pub fn execute(&self, manager: &Arc<RuntimeManager>) -> ExecResult {
+        self.execute_in(&self.new_scope(manager))
+    }
+
+    pub fn execute_in(&self, scope: &Arc<RuntimeManager>) -> ExecResult {
+        #[cfg(feature = "vm")]
+        if vm::backend() == Backend::Vm {
+            return match self.run_compiled(scope)? {
+                ExpressionResult::Value(_) => Ok(ExpressionResult::Value(DayObject::None)),
+                ExpressionResult::Return(res) if self.block.purpose == NodePurpose::Function => {
+                    Ok(ExpressionResult::Value(res))
+                }
+                ctrl => Ok(ctrl),
+            };
+        }
+
+        self.block.execute(scope)
+    }
+
+    /// Executes the block in `scope`, it evaluates to the value of its last node
+    pub fn execute_last(&self, scope: &Arc<RuntimeManager>) -> ExecResult {
+        #[cfg(feature = "vm")]
+        if vm::backend() == Backend::Vm {
+            return self.run_compiled(scope);
+        }
+
+        self.block.execute_last(scope)
     }
 
-    pub fn execute_args(&self, args: Args) -> ExpressionResult {
-        self.scope.def_args_alloc(args.to_vec());
-        self.block.execute(&self.scope)
+    #[cfg(feature = "vm")]
+    fn run_compiled(&self, scope: &Arc<RuntimeManager>) -> ExecResult {
+        vm::run(self.code.get_or_init(|| Box::new(vm::compile(self))), scope)
+    }
+
+    #[cfg(feature = "vm")]
+    fn invalidate(&mut self) {
+        self.code.take();
+    }
+
+    #[cfg(not(feature = "vm"))]
+    fn invalidate(&mut self) {}
+}
+
+/// A parameter of a function, its position is the id of its variable in the body
+#[derive(Debug)]
+pub enum Param {
+    Required,
+    /// The default value is evaluated in the scope the function was declared in,
+    /// on every call the argument is missing in
+    Optional(Node),
+    /// Collects all remaining arguments into an array
+    Rest,
+}
+
+/// A function declared in a script
+#[derive(Debug)]
+pub struct FunctionDef {
+    pub name: Option<String>,
+    /// `None` if the function has no parameter list and only uses `args`
+    pub params: Option<Vec<Param>>,
+    pub body: Block,
+}
+
+impl FunctionDef {
+    /// Calls the function in a new scope nested in `env`, the scope it was declared in
+    pub fn call(&self, args: Args, env: &Arc<RuntimeManager>) -> RuntimeResult<DayObject> {
+        if !hooks::active() {
+            budget::step()?;
+            return self.call_in(args, env);
+        }
+        let _call = budget::enter_call()?;
+        let _frame = debug::enter_call(self.name.as_deref());
+        let _profile = profile::enter_call(self);
+        self.call_in(args, env)
+    }
+
+    fn call_in(&self, args: Args, env: &Arc<RuntimeManager>) -> RuntimeResult<DayObject> {
+        let scope = self.body.new_scope(env);
+        scope.def_args_alloc(args.to_vec());
+        if let Some(params) = &self.params {
+            self.bind_params(params, args, env, &scope)?;
+        }
+
+        self.body
+            .execute_in(&scope)
+            .and_then(ExpressionResult::value)
+            .map_err(|e| e.left_call(self.name.as_deref().unwrap_or("fn")))
+    }
+
+    fn bind_params(
+        &self,
+        params: &[Param],
+        args: Args,
+        env: &Arc<RuntimeManager>,
+        scope: &Arc<RuntimeManager>,
+    ) -> RuntimeResult<()> {
+        let required = params
+            .iter()
+            .filter(|p| matches!(p, Param::Required))
+            .count();
+        let has_rest = matches!(params.last(), Some(Param::Rest));
+
+        if args.len() < required || (!has_rest && args.len() > params.len()) {
+            let expected = if has_rest {
+                format!("at least {}", required)
+            } else if required == params.len() {
+                required.to_string()
+            } else {
+                format!("{} to {}", required, params.len())
+            };
+            return Err(RuntimeError::invalid_args(
+                self.name.as_deref().unwrap_or("fn"),
+                format!("expected {} args, received {}", expected, args.len()),
+            ));
+        }
+
+        for (id, param) in params.iter().enumerate() {
+            let value = match (param, args.get(id)) {
+                (Param::Rest, _) => DayObject::Array(args.get(id..).unwrap_or_default().to_vec()),
+                (_, Some(arg)) => arg.clone(),
+                (Param::Optional(default), None) => default.execute(env)?.value()?,
+                (Param::Required, None) => unreachable!("the arity was checked"),
+            };
+            scope.def_var(id, value);
+        }
+
+        Ok(())
     }
 }
 
@@ -414,54 +858,133 @@ impl Debug for Block {
     }
 }
 
+/// What debuggers need to know about a block besides its nodes
+#[derive(Debug, Default)]
+pub struct BlockInfo {
+    /// The names of the variables of the scope ordered by their ids
+    pub names: Vec<String>,
+    /// The imported file the block is in, `None` for the code compiled by the engine
+    pub file: Option<PathBuf>,
+}
+
 #[derive(Debug)]
 pub struct RootNode {
     nodes: Vec<Node>,
+    /// The spans of the first tokens of the nodes
+    spans: Vec<Span>,
     pub purpose: NodePurpose,
+    pub info: Arc<BlockInfo>,
 }
 
-impl<'a: 'v, 'v, 's> RootNode {
+impl RootNode {
     pub fn new(purpose: NodePurpose) -> Self {
         Self {
             nodes: Default::default(),
+            spans: Default::default(),
             purpose,
+            info: Default::default(),
         }
     }
 
-    pub fn push(&mut self, node: Node) {
-        self.nodes.push(node)
+    /// Appends the statement `node` starting at `span`
+    pub fn push(&mut self, node: Node, span: Span) {
+        self.nodes.push(node);
+        self.spans.push(span)
+    }
+
+    pub fn pop(&mut self) -> Option<(Node, Span)> {
+        Some((self.nodes.pop()?, self.spans.pop()?))
     }
 
-    pub fn pop(&mut self) -> Option<Node> {
-        self.nodes.pop()
+    /// Inserts `node` behind the named function declarations at the start of the block
+    pub fn hoist(&mut self, node: Node, span: Span) {
+        let pos = self
+            .nodes
+            .iter()
+            .take_while(|n| matches!(n, Node::FunctionDeclaration { id: Some(_), .. }))
+            .count();
+        self.nodes.insert(pos, node);
+        self.spans.insert(pos, span);
     }
 
     pub fn len(&self) -> usize {
         self.nodes.len()
     }
 
-    pub fn execute(&'s self, manager: &Arc<RuntimeManager>) -> ExpressionResult {
-        for n in self.nodes.iter() {
-            match n.execute(manager) {
+    pub fn is_empty(&self) -> bool {
+        self.nodes.is_empty()
+    }
+
+    pub fn nodes(&self) -> &[Node] {
+        &self.nodes
+    }
+
+    pub fn nodes_mut(&mut self) -> &mut [Node] {
+        &mut self.nodes
+    }
+
+    /// The spans the nodes start at
+    pub fn spans(&self) -> &[Span] {
+        &self.spans
+    }
+
+    pub fn execute(&self, manager: &Arc<RuntimeManager>) -> ExecResult {
+        let hooked = hooks::active();
+        let _scope = if hooked {
+            debug::enter_scope(&self.info, manager)
+        } else {
+            None
+        };
+        for (n, span) in self.nodes.iter().zip(&self.spans) {
+            if hooked {
+                debug::statement(*span);
+                profile::statement(&self.info, *span);
+            }
+            match n.execute(manager)? {
                 ExpressionResult::Return(res) => {
-                    return if self.purpose == NodePurpose::Function {
+                    return Ok(if self.purpose == NodePurpose::Function {
                         ExpressionResult::Value(res)
                     } else {
                         ExpressionResult::Return(res)
-                    }
+                    });
                 }
-                v => v,
-            };
+                // Loop control flow is handled by the innermost loop
+                ctrl @ ExpressionResult::Break(_) | ctrl @ ExpressionResult::Continue => {
+                    return Ok(ctrl)
+                }
+                _ => (),
+            }
         }
 
-        ExpressionResult::Value(DayObject::None)
+        Ok(ExpressionResult::Value(DayObject::None))
+    }
+
+    /// Like `execute` but evaluates to the value of the last node instead of none,
+    /// so the interactive shell can print it
+    pub fn execute_last(&self, manager: &Arc<RuntimeManager>) -> ExecResult {
+        let _scope = debug::enter_scope(&self.info, manager);
+        let last = match self.nodes.len().checked_sub(1) {
+            Some(last) => last,
+            None => return Ok(ExpressionResult::Value(DayObject::None)),
+        };
+
+        for (n, span) in self.nodes[..last].iter().zip(&self.spans) {
+            debug::statement(*span);
+            profile::statement(&self.info, *span);
+            match n.execute(manager)? {
+                ExpressionResult::Value(_) => (),
+                other => return Ok(other),
+            }
+        }
+
+        debug::statement(self.spans[last]);
+        profile::statement(&self.info, self.spans[last]);
+        self.nodes[last].execute(manager)
     }
 }
 
 //Prolly args should be an extra Node
 
-use std::cell::UnsafeCell;
-
 #[repr(C)]
 #[derive(Debug)]
 pub struct IdentifierNode {
@@ -497,7 +1020,7 @@ pub enum NodePurpose {
     For,
 }
 
-impl<'a> IntoIterator for RootNode {
+impl IntoIterator for RootNode {
     type Item = Node;
     type IntoIter = std::vec::IntoIter<Self::Item>;
     fn into_iter(self) -> Self::IntoIter {
@@ -505,9 +1028,42 @@ impl<'a> IntoIterator for RootNode {
     }
 }
 
+/// `[index]` or `[start:end]`, the bounds of a slice are optional
 #[derive(Debug)]
-pub struct IndexOperation {
-    pub index: Box<Node>,
+pub enum IndexOperation {
+    Index(Box<Node>),
+    Slice {
+        start: Option<Box<Node>>,
+        end: Option<Box<Node>>,
+    },
+}
+
+impl IndexOperation {
+    fn evaluate(&self, manager: &Arc<RuntimeManager>) -> RuntimeResult<Subscript> {
+        let bound = |b: &Option<Box<Node>>| -> RuntimeResult<Option<i64>> {
+            match b {
+                Some(node) => slice_bound(node.execute(manager)?.value()?).map(Some),
+                None => Ok(None),
+            }
+        };
+
+        match self {
+            IndexOperation::Index(index) => Ok(Subscript::Index(index.execute(manager)?.value()?)),
+            IndexOperation::Slice { start, end } => {
+                Ok(Subscript::Slice(bound(start)?, bound(end)?))
+            }
+        }
+    }
+}
+
+pub fn slice_bound(bound: DayObject) -> RuntimeResult<i64> {
+    match bound {
+        DayObject::Integer(i) => Ok(i),
+        other => Err(RuntimeError::type_error(format!(
+            "Slice bounds have to be integers, received {:?}",
+            other
+        ))),
+    }
 }
 
 #[derive(Debug, Clone)]
@@ -515,14 +1071,21 @@ pub enum ExpressionResult {
     Return(DayObject),
     Value(DayObject),
     Yielded(DayObject),
+    Break(DayObject),
+    Continue,
 }
 
+pub type ExecResult = RuntimeResult<ExpressionResult>;
+
 impl ExpressionResult {
     ///Retrievs the value if this is the value variant
-    pub fn value(self) -> DayObject {
+    pub fn value(self) -> RuntimeResult<DayObject> {
         match self {
-            Self::Value(d) => d,
-            er => panic!("Expected value received {:?}", er),
+            Self::Value(d) => Ok(d),
+            er => Err(RuntimeError::new(
+                RuntimeErrorKind::UnexpectedControlFlow,
+                format!("Expected value received {:?}", er),
+            )),
         }
     }
 }
@@ -531,21 +1094,90 @@ impl ExpressionResult {
 pub struct IndexNode {
     pub initial: Box<Node>,
     pub index_ops: Vec<IndexOperation>,
+    pub span: Span,
 }
 
 impl IndexNode {
-    pub fn get_value(&self, manager: &Arc<RuntimeManager>) -> DayObject {
-        self.get_mut(manager).clone()
+    pub fn get_value(&self, manager: &Arc<RuntimeManager>) -> RuntimeResult<DayObject> {
+        self.get_value_inner(manager).map_err(|e| e.at(self.span))
     }
 
-    pub fn get_mut(&self, manager: &Arc<RuntimeManager>) -> &mut DayObject {
-        match &*self.initial {
-            Node::Identifier(IdentifierNode { id, depth }) => {
-                manager.get_var_mut(*id, *depth)
-            },
-            _ => todo!()
+    fn get_value_inner(&self, manager: &Arc<RuntimeManager>) -> RuntimeResult<DayObject> {
+        let subscripts = self.subscripts(manager)?;
+        // Variables are indexed in place so they don't have to be cloned as a whole
+        let initial = match &*self.initial {
+            Node::Identifier(id) => return subscript_all(id.get_mut(manager), &subscripts),
+            other => other.execute(manager)?.value()?,
+        };
+
+        subscript_all(&initial, &subscripts)
+    }
+
+    /// Assigns `value` to the place this index expression refers to
+    pub fn assign(&self, manager: &Arc<RuntimeManager>, value: DayObject) -> RuntimeResult<()> {
+        self.assign_inner(manager, value)
+            .map_err(|e| e.at(self.span))
+    }
+
+    fn assign_inner(&self, manager: &Arc<RuntimeManager>, value: DayObject) -> RuntimeResult<()> {
+        let subscripts = self.subscripts(manager)?;
+        let place = match &*self.initial {
+            Node::Identifier(IdentifierNode { id, depth }) => manager.get_var_mut(*id, *depth),
+            other => {
+                return Err(RuntimeError::type_error(format!(
+                    "Can't assign to {:?}",
+                    other
+                )))
+            }
+        };
+
+        assign_subscripts(place, &subscripts, value)
+    }
+
+    fn subscripts(&self, manager: &Arc<RuntimeManager>) -> RuntimeResult<Vec<Subscript>> {
+        self.index_ops
+            .iter()
+            .map(|op| op.evaluate(manager))
+            .collect()
+    }
+}
+
+/// Assigns `value` to the element of `place` the subscripts lead to
+pub fn assign_subscripts(
+    mut place: &mut DayObject,
+    subscripts: &[Subscript],
+    value: DayObject,
+) -> RuntimeResult<()> {
+    let (last, subscripts) = match subscripts.split_last() {
+        Some(split) => split,
+        None => {
+            *place = value;
+            return Ok(());
         }
+    };
+
+    for s in subscripts {
+        place = match s {
+            Subscript::Index(i) => place.index_mut(i)?,
+            Subscript::Slice(..) => {
+                return Err(RuntimeError::type_error("Can't assign into a slice"))
+            }
+        };
+    }
+
+    place.assign_subscript(last, value)
+}
+
+pub fn subscript_all(value: &DayObject, subscripts: &[Subscript]) -> RuntimeResult<DayObject> {
+    let mut value = Cow::Borrowed(value);
+    for s in subscripts {
+        value = match value {
+            Cow::Borrowed(v) => v.subscript(s)?,
+            Cow::Owned(v) => Cow::Owned(v.subscript(s)?.into_owned()),
+        };
     }
+
+    Ok(value.into_owned())
 }
 
 #[repr(C)]
@@ -553,7 +1185,7 @@ impl IndexNode {
 pub struct FunctionCallNode {
     pub expr: Box<Node>,
     pub args: Vec<Node>,
-    pub arg_cache: UnsafeCell<Vec<DayObject>>,
+    pub span: Span,
 }
 
 use std::fmt::{Debug, Formatter, Result as FmtResult};
@@ -0,0 +1,324 @@
+use crate::{
+    base::{DayObject, RustFunction},
+    node::{BinaryOp, Block, BranchNode, FunctionCallNode, IndexOperation, Node, Param},
+    std_modules::{arithmetics, bool_ops, comparison, conversion},
+};
+use std::{ptr::fn_addr_eq, sync::Arc};
+
+/// How much the optimizer rewrites parsed code, the code behaves the same on every level
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, PartialOrd, Ord)]
+pub enum OptLevel {
+    /// The code is executed as parsed
+    None,
+    /// Calls of pure std functions with constant args are folded, consts with literal values
+    /// are inlined and branches with constant conditions are removed
+    Basic,
+    /// Also replaces calls of common std functions by dedicated nodes
+    #[default]
+    Full,
+}
+
+/// The std functions without side effects
+const PURE: &[RustFunction] = &[
+    arithmetics::add,
+    arithmetics::sub,
+    arithmetics::mul,
+    arithmetics::div,
+    arithmetics::modu,
+    arithmetics::neg,
+    comparison::eq,
+    comparison::neq,
+    comparison::lt,
+    comparison::le,
+    comparison::gt,
+    comparison::ge,
+    conversion::to_string,
+    conversion::to_int,
+    conversion::to_float,
+    conversion::to_bool,
+    bool_ops::and,
+    bool_ops::or,
+    bool_ops::xor,
+    bool_ops::not,
+];
+
+/// The std functions whose calls with two args have a dedicated node
+const SPECIALIZED: &[(RustFunction, BinaryOp)] = &[
+    (arithmetics::add, BinaryOp::Add),
+    (arithmetics::sub, BinaryOp::Sub),
+    (comparison::lt, BinaryOp::Lt),
+];
+
+/// Optimizes the top level block of a script or file
+pub fn optimize(block: &mut Block, level: OptLevel) {
+    if level == OptLevel::None {
+        return;
+    }
+
+    let mut optimizer = Optimizer {
+        level,
+        consts: Vec::new(),
+    };
+    // The top level is executed in the root scope
+    optimizer.nodes(block, 0);
+}
+
+struct Optimizer {
+    level: OptLevel,
+    /// The slots of the consts with literal values declared before the current node
+    consts: Vec<((usize, usize), DayObject)>,
+}
+
+impl Optimizer {
+    /// Optimizes a block executed in a new scope nested in a scope of `depth`
+    fn block(&mut self, block: &mut Block, depth: usize) {
+        self.nodes(block, depth + 1)
+    }
+
+    /// Optimizes the nodes of a block executed in a scope of `depth`
+    fn nodes(&mut self, block: &mut Block, depth: usize) {
+        // The consts of the block go out of scope with it
+        let outer = self.consts.len();
+        for node in block.nodes_mut() {
+            self.node(node, depth);
+        }
+        self.consts.truncate(outer);
+    }
+
+    fn node(&mut self, node: &mut Node, depth: usize) {
+        if let Some(rewritten) = self.rewrite(node, depth) {
+            *node = rewritten;
+        }
+    }
+
+    /// Optimizes the children of `node` and returns the node replacing it, if any
+    fn rewrite(&mut self, node: &mut Node, depth: usize) -> Option<Node> {
+        match node {
+            Node::Identifier(id) => self
+                .consts
+                .iter()
+                .rev()
+                .find(|(slot, _)| *slot == (id.id, id.depth))
+                .map(|(_, value)| Node::Data(value.clone())),
+            Node::FunctionCall(call) => {
+                self.node(&mut call.expr, depth);
+                for a in call.args.iter_mut() {
+                    self.node(a, depth);
+                }
+                fold(call).or_else(|| match self.level {
+                    OptLevel::Full => specialize(call),
+                    _ => None,
+                })
+            }
+            Node::Declaration { value, .. } => {
+                self.node(value, depth);
+                None
+            }
+            Node::ConstDeclaration { value, id } => {
+                self.node(value, depth);
+                if let Node::Data(value) = &**value {
+                    if is_literal(value) {
+                        self.consts.push(((*id, depth), value.clone()));
+                    }
+                }
+                None
+            }
+            Node::Assignment { assignee, value } => {
+                self.node(value, depth);
+                // The assigned variable itself is never replaced
+                if let Node::Index(index) = &mut **assignee {
+                    self.index_ops(&mut index.index_ops, depth);
+                }
+                None
+            }
+            Node::Index(index) => {
+                self.node(&mut index.initial, depth);
+                self.index_ops(&mut index.index_ops, depth);
+                None
+            }
+            Node::BranchNode(branches) => {
+                for b in branches.iter_mut() {
+                    match b {
+                        BranchNode::If { block } | BranchNode::ElseIf { block } => {
+                            self.node(&mut block.condition, depth);
+                            self.block(&mut block.block, depth);
+                        }
+                        BranchNode::Else { block } => self.block(block, depth),
+                    }
+                }
+                prune(branches)
+            }
+            Node::For { expr, block, .. } => {
+                self.node(expr, depth);
+                self.block(block, depth);
+                None
+            }
+            Node::While {
+                condition, block, ..
+            } => {
+                self.node(condition, depth);
+                self.block(block, depth);
+                None
+            }
+            Node::Block(block) => {
+                self.block(block, depth);
+                None
+            }
+            Node::Try { block, catch, .. } => {
+                self.block(block, depth);
+                self.block(catch, depth);
+                None
+            }
+            Node::FunctionDeclaration { function, .. } => {
+                // Functions shared with already executed code are left alone
+                if let Some(function) = Arc::get_mut(function) {
+                    for param in function.params.iter_mut().flatten() {
+                        if let Param::Optional(default) = param {
+                            self.node(default, depth + 1);
+                        }
+                    }
+                    self.block(&mut function.body, depth);
+                }
+                None
+            }
+            Node::Ret(Some(value)) => {
+                if let Some(value) = Arc::get_mut(value) {
+                    self.node(value, depth);
+                }
+                None
+            }
+            Node::Break(Some(value)) => {
+                self.node(value, depth);
+                None
+            }
+            Node::Logical { lhs, rhs, .. } | Node::BinaryOp { lhs, rhs, .. } => {
+                self.node(lhs, depth);
+                self.node(rhs, depth);
+                None
+            }
+            Node::MapLiteral { entries, .. } => {
+                for (key, value) in entries.iter_mut() {
+                    self.node(key, depth);
+                    self.node(value, depth);
+                }
+                None
+            }
+            Node::RustFunction(_)
+            | Node::Data(_)
+            | Node::Ret(None)
+            | Node::Break(None)
+            | Node::Continue
+            | Node::Args
+            | Node::Import { .. }
+            | Node::ModuleVar { .. } => None,
+        }
+    }
+
+    fn index_ops(&mut self, ops: &mut [IndexOperation], depth: usize) {
+        for op in ops {
+            match op {
+                IndexOperation::Index(index) => self.node(index, depth),
+                IndexOperation::Slice { start, end } => {
+                    for bound in start.iter_mut().chain(end.iter_mut()) {
+                        self.node(bound, depth);
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// Values that can't be modified in place, so consts of them can be copied into every use
+fn is_literal(value: &DayObject) -> bool {
+    matches!(
+        value,
+        DayObject::None
+            | DayObject::Float(_)
+            | DayObject::Bool(_)
+            | DayObject::Integer(_)
+            | DayObject::Character(_)
+            | DayObject::Str(_)
+    )
+}
+
+/// Evaluates a call of a pure std function with literal args while parsing, so the functions
+/// must not panic, the arithmetic ones are checked. Calls failing (like an overflowing add)
+/// are kept so they fail when (and if) they are executed.
+fn fold(call: &FunctionCallNode) -> Option<Node> {
+    let f = match &*call.expr {
+        Node::RustFunction(f) if PURE.iter().any(|p| fn_addr_eq(*p, f.0)) => f.0,
+        _ => return None,
+    };
+    let args = call
+        .args
+        .iter()
+        .map(|a| match a {
+            Node::Data(value) if is_literal(value) => Some(value.clone()),
+            _ => None,
+        })
+        .collect::<Option<Vec<_>>>()?;
+
+    f(&args).ok().map(Node::Data)
+}
+
+fn specialize(call: &mut FunctionCallNode) -> Option<Node> {
+    let op = match &*call.expr {
+        Node::RustFunction(f) if call.args.len() == 2 => SPECIALIZED
+            .iter()
+            .find(|(special, _)| fn_addr_eq(*special, f.0))
+            .map(|(_, op)| *op)?,
+        _ => return None,
+    };
+
+    let rhs = call.args.pop()?;
+    let lhs = call.args.pop()?;
+    Some(Node::BinaryOp {
+        op,
+        lhs: Box::new(lhs),
+        rhs: Box::new(rhs),
+        span: call.span,
+    })
+}
+
+/// Removes the branches whose conditions are constants, a branch with a condition
+/// other than true is never taken and one with true is taken instead of the following ones
+fn prune(branches: &mut Vec<BranchNode>) -> Option<Node> {
+    let constant = |b: &BranchNode| match b {
+        BranchNode::If { block } | BranchNode::ElseIf { block } => {
+            matches!(*block.condition, Node::Data(_))
+        }
+        BranchNode::Else { .. } => false,
+    };
+    if !branches.iter().any(constant) {
+        return None;
+    }
+
+    let mut kept = Vec::with_capacity(branches.len());
+    for b in branches.drain(..) {
+        match b {
+            BranchNode::If { block } | BranchNode::ElseIf { block } => match *block.condition {
+                Node::Data(DayObject::Bool(true)) => {
+                    kept.push(BranchNode::Else { block: block.block });
+                    break;
+                }
+                Node::Data(_) => (),
+                _ if kept.is_empty() => kept.push(BranchNode::If { block }),
+                _ => kept.push(BranchNode::ElseIf { block }),
+            },
+            BranchNode::Else { block } => {
+                kept.push(BranchNode::Else { block });
+                break;
+            }
+        }
+    }
+
+    match kept.pop() {
+        None => Some(Node::Data(DayObject::None)),
+        Some(BranchNode::Else { block }) if kept.is_empty() => Some(Node::Block(block)),
+        Some(last) => {
+            kept.push(last);
+            *branches = kept;
+            None
+        }
+    }
+}
@@ -1,17 +1,24 @@
 use super::parsing_error::{ParsingError, ParsingErrorKind, ParsingResult};
-use super::PreMap;
+use super::{NativeMap, PreMap};
 use crate::{
-    base::DayObject,
-    manager::RuntimeManager,
+    analysis::{Reference, Target},
+    base::{DayFunction, DayObject, RustClosure, RustFunction},
+    dump::{ScopeInfo, VarInfo},
+    module::{FileAccess, FileModule, ModuleLoader},
     node::*,
-    tokenizer::{DataToken, KeywordToken, SymbolToken, Token, TokenStream},
+    optimizer::{optimize, OptLevel},
+    span::Span,
+    std_modules::{arithmetics, bool_ops, comparison},
+    tokenizer::{
+        build_lexer, DataToken, KeywordToken, OperatorToken, SpannedToken, SymbolToken, Token,
+        TokenStream,
+    },
 };
-use std::sync::Arc;
+use std::{cell::RefCell, path::PathBuf, rc::Rc, sync::Arc};
 
 //IMPORTANT
 //NOTE For now the var map will be preallocated instead of resolving at runtime
 //NOTE For now the var map will be preallocated instead of resolving at runtime
-//NOTE At first this will do the line keeping logic twice but that could be easily optimizable
 //NOTE A scope stack will also be used inside the parser. The main complicated thing by now
 //is how to solve inner and outer scopes that will be done later
 //NOTE is implemented with the indextree map so it could be done multithreaded it's not implemented with
@@ -20,94 +27,285 @@ use std::sync::Arc;
 
 //TODO Make constants matter
 
-pub struct Parser<'tokens> {
-    curr_line: u64,
+pub struct Parser {
+    /// The span of the token parsed last
+    span: Span,
+    /// Whether the block currently parsed is (inside of) the body of a loop
+    in_loop: bool,
     pre_map: PreMap,
-    var_tree: VarTree<'tokens>,
+    /// The functions registered by the host by their qualified names
+    natives: NativeMap,
+    /// Shared with the parsers of the imported files
+    loader: Rc<RefCell<ModuleLoader>>,
+    /// The directory of the file parsed, if the code comes from a file
+    dir: Option<PathBuf>,
+    /// The file parsed if it was imported
+    file: Option<PathBuf>,
+    /// The imported modules by their namespaces
+    imports: HashMap<String, Import>,
+    opt_level: OptLevel,
+    var_tree: VarTree,
+    /// The resolved identifiers, only recorded if requested
+    references: Option<Vec<Reference>>,
 }
 
-impl<'tokens> Parser<'tokens> {
+/// A module made accessible by an import statement
+enum Import {
+    Std,
+    File(Arc<FileModule>),
+}
+
+impl Parser {
     pub fn new(pre_map: PreMap) -> Self {
+        Self::with_loader(pre_map, Rc::new(RefCell::new(ModuleLoader::new())), None)
+    }
+
+    /// Creates a parser for a file in `dir` or for code without a file
+    fn with_loader(
+        pre_map: PreMap,
+        loader: Rc<RefCell<ModuleLoader>>,
+        dir: Option<PathBuf>,
+    ) -> Self {
         Parser {
-            curr_line: 1,
+            span: Span::default(),
+            in_loop: false,
             pre_map,
+            natives: NativeMap::default(),
+            loader,
+            dir,
+            file: None,
+            imports: HashMap::new(),
+            opt_level: OptLevel::default(),
             var_tree: VarTree::new(),
+            references: None,
         }
     }
 
-    pub fn parse_tokens<'node, 'text>(
+    /// Adds a directory files are imported from
+    pub fn add_search_path(&mut self, dir: impl Into<PathBuf>) {
+        self.loader.borrow_mut().add_search_path(dir)
+    }
+
+    /// Parses `tokens` as top level code. Calling this again continues the same
+    /// top level, the variables declared by earlier calls stay visible. If parsing fails
+    /// the top level variables declared by `tokens` are forgotten again.
+    pub fn parse_tokens<'node, 'text, 'tokens>(
         &mut self,
-        mut tokens: TokenStream<'node, 'text, 'tokens>,
+        tokens: TokenStream<'node, 'text, 'tokens>,
     ) -> ParsingResult<Block> {
-        tokens = TokenStream::from(self.fill_var_map(tokens)?);
-        dbg_print!(format!("{:#?}", &self.var_tree));
-        let blk = self.parse(tokens, NodePurpose::TopLevel, None);
+        self.var_tree.rewind();
+        let top_level = self.var_tree.get_current().clone();
+
+        let blk = self.fill_var_map(tokens).and_then(|tokens| {
+            dbg_print!(format!("{:#?}", &self.var_tree));
+            self.parse(TokenStream::from(tokens), NodePurpose::TopLevel)
+        });
         dbg_print_pretty!(blk);
-        blk.map(|b| b.0)
+
+        match blk {
+            Ok((mut block, _)) => {
+                optimize(&mut block, self.opt_level);
+                Ok(block)
+            }
+            Err(e) => {
+                self.var_tree.rewind();
+                *self.var_tree.get_current_mut() = top_level;
+                Err(e)
+            }
+        }
+    }
+
+    /// The names and ids of the top level variables ordered by their id
+    pub fn top_level_vars(&self) -> Vec<(&str, usize)> {
+        let root = self.var_tree.arena.get(self.var_tree.root).unwrap().get();
+        let mut vars: Vec<_> = root
+            .vars
+            .iter()
+            .map(|(name, var)| (&**name, var.id))
+            .collect();
+        vars.sort_by_key(|(_, id)| *id);
+        vars
+    }
+
+    /// The scopes the variables were resolved in, starting at the top level
+    pub fn scopes(&self) -> ScopeInfo {
+        self.var_tree.scope_info(self.var_tree.root, 0)
+    }
+
+    /// Makes the parser record which declaration or std function every identifier refers to
+    pub fn record_references(&mut self) {
+        self.references.get_or_insert_with(Vec::new);
     }
 
-    pub fn fill_var_map<'node, 'text>(
+    /// The references recorded since the last call, also of code that failed to parse
+    pub fn take_references(&mut self) -> Vec<Reference> {
+        self.references
+            .as_mut()
+            .map(std::mem::take)
+            .unwrap_or_default()
+    }
+
+    /// Sets how much the following code and the files it imports are optimized
+    pub fn set_opt_level(&mut self, opt_level: OptLevel) {
+        self.opt_level = opt_level
+    }
+
+    pub fn set_file_access(&mut self, file_access: FileAccess) {
+        self.loader.borrow_mut().set_file_access(file_access)
+    }
+
+    /// Replaces the std function `name` of `module` by `f`, for both `name` and `module.name`
+    pub fn replace_std(&mut self, module: &str, name: &str, f: RustClosure) {
+        self.pre_map.remove(name);
+        self.loader.borrow_mut().remove_std_function(module, name);
+        self.add_native(name, Arc::clone(&f));
+        self.add_native(format!("{}.{}", module, name), f);
+    }
+
+    /// Makes the host function `f` callable as `name` by the code parsed afterwards
+    pub fn add_native(&mut self, name: impl Into<String>, f: RustClosure) {
+        self.natives.insert(name.into(), f);
+    }
+
+    /// The id of the top level variable `name` if it was declared
+    pub fn top_level_var(&self, name: &str) -> Option<usize> {
+        let root = self.var_tree.arena.get(self.var_tree.root).unwrap().get();
+        root.vars.get(name).map(|var| var.id)
+    }
+
+    /// Declares the top level variable `name` if it doesn't exist yet and returns its id
+    pub fn declare_top_level(&mut self, name: &str) -> usize {
+        let root = self
+            .var_tree
+            .arena
+            .get_mut(self.var_tree.root)
+            .unwrap()
+            .get_mut();
+        match root.vars.get(name) {
+            Some(var) => var.id,
+            None => root.declare(name, 0, false, Span::default()),
+        }
+    }
+
+    pub fn fill_var_map<'node, 'text, 'tokens>(
         &mut self,
         mut tokens: TokenStream<'node, 'text, 'tokens>,
-    ) -> ParsingResult<Vec<Token<'tokens>>> {
-        let mut v = Vec::with_capacity(tokens.size_hint());
+    ) -> ParsingResult<Vec<SpannedToken<'tokens>>> {
+        let mut v: Vec<SpannedToken<'tokens>> = Vec::with_capacity(tokens.size_hint());
+        // For every open brace whether it belongs to a map literal instead of a block
+        let mut braces: Vec<bool> = vec![];
+        // Variables of a for loop, a catch or the parameters of a function,
+        // they are defined in the next block opened at this depth
+        let mut block_vars: Vec<(usize, Vec<(&'tokens str, Span)>)> = vec![];
+        // Parameter lists of functions, a default value could contain another function
+        let mut param_lists: Vec<ParamList<'tokens>> = vec![];
+
+        while let Some(t) = tokens.next_spanned() {
+            self.span = t.span;
+            if let Some(list) = param_lists.last_mut() {
+                if list.update(&t) {
+                    let names = param_lists.pop().map(|l| l.names).unwrap_or_default();
+                    for (i, (name, span)) in names.iter().enumerate() {
+                        if names[..i].iter().any(|(other, _)| other == name) {
+                            return Err(ParsingError::new(
+                                ParsingErrorKind::DuplicateParameter(name.to_string()),
+                                *span,
+                            ));
+                        }
+                    }
+                    block_vars.push((braces.len(), names));
+                }
+            }
 
-        while let Some(t) = tokens.next() {
-            match &t {
+            match &t.token {
                 Token::Keyword(KeywordToken::Let)
                 | Token::Keyword(KeywordToken::Const)
                 | Token::Keyword(KeywordToken::Fn) => {
+                    let is_const = t.token == Token::Keyword(KeywordToken::Const);
+                    let t_is_fn = t.token == Token::Keyword(KeywordToken::Fn);
                     v.push(t);
-                    let idt = self.next_token(&mut tokens)?;
-                    if let Token::Identifier(ident) = idt {
+                    let idt = self.next_spanned_token(&mut tokens)?;
+                    if let Token::Identifier(ident) = idt.token {
+                        let span = idt.span;
                         v.push(idt);
                         let depth = self.var_tree.depth();
-                        let vars = self.var_tree.get_current_mut();
                         dbg_print_pretty!(v);
-                        vars.insert(
-                            ident,
-                            Variable {
-                                depth,
-                                id: vars.len(),
-                            },
-                        );
-                    } else if Token::Symbol(SymbolToken::CurlyOpen) == idt {
-                        self.var_tree.to_new_successor();
-                        self.var_tree.pre_order.push(self.var_tree.current);
-                        v.push(idt);
+                        self.var_tree
+                            .get_current_mut()
+                            .declare(ident, depth, is_const, span);
+                    } else {
+                        tokens.reinsert(idt.token);
+                    }
+
+                    if t_is_fn {
+                        let next = self.next_spanned_token(&mut tokens)?;
+                        if next.token == Token::Symbol(SymbolToken::RoundOpen) {
+                            param_lists.push(ParamList::default());
+                        }
+                        tokens.reinsert(next.token);
                     }
                 }
                 Token::Keyword(KeywordToken::For) => {
                     v.push(t);
-                    let idt = self.next_token(&mut tokens)?;
-                    if let Token::Identifier(ident) = idt {
-                        v.push(idt);
-                        loop {
-                            let tok = self.next_token(&mut tokens)?;
-                            if tok == Token::Symbol(SymbolToken::CurlyOpen) {
-                                v.push(tok);
+                    let mut idents = vec![];
+                    loop {
+                        let idt = self.next_spanned_token(&mut tokens)?;
+                        match idt.token {
+                            Token::Identifier(ident) => idents.push((ident, idt.span)),
+                            Token::Symbol(SymbolToken::Comma) => (),
+                            other => {
+                                tokens.reinsert(other);
                                 break;
                             }
-                            v.push(tok);
                         }
-                        self.var_tree.to_new_successor();
-                        self.var_tree.pre_order.push(self.var_tree.current);
-                        let depth = self.var_tree.depth();
-                        let vars = self.var_tree.get_current_mut();
-                        vars.insert(ident, Variable { depth, id: 0 });
+                        v.push(idt);
+                    }
+                    block_vars.push((braces.len(), idents));
+                }
+                Token::Keyword(KeywordToken::Catch) => {
+                    v.push(t);
+                    let idt = self.next_spanned_token(&mut tokens)?;
+                    if let Token::Identifier(ident) = idt.token {
+                        // The caught error is always the first variable of the catch block
+                        block_vars.push((braces.len(), vec![(ident, idt.span)]));
+                        v.push(idt);
+                    } else {
+                        tokens.reinsert(idt.token);
                     }
                 }
                 Token::Symbol(SymbolToken::CurlyOpen) => {
-                    self.var_tree.to_new_successor();
-                    self.var_tree.pre_order.push(self.var_tree.current);
+                    let is_map = v
+                        .iter()
+                        .rev()
+                        .find(|t| t.token != Token::Newline)
+                        .is_some_and(|t| t.token.precedes_map_literal());
+                    if !is_map {
+                        self.var_tree.move_to_new_successor();
+                        self.var_tree.pre_order.push(self.var_tree.current);
+                        self.var_tree.spans.insert(self.var_tree.current, t.span);
+                        if block_vars
+                            .last()
+                            .is_some_and(|(depth, _)| *depth == braces.len())
+                        {
+                            let (_, idents) = block_vars.pop().unwrap_or_default();
+                            let depth = self.var_tree.depth();
+                            let vars = self.var_tree.get_current_mut();
+                            for (ident, span) in idents {
+                                vars.declare(ident, depth, false, span);
+                            }
+                        }
+                    }
+                    braces.push(is_map);
                     v.push(t)
                 }
                 Token::Symbol(SymbolToken::CurlyClose) => {
-                    self.var_tree.to_predecessor();
-                    v.push(t)
-                }
-                Token::Newline => {
-                    self.curr_line += 1;
+                    if !braces.pop().unwrap_or(false) {
+                        let current = self.var_tree.current;
+                        if let Some(span) = self.var_tree.spans.get_mut(&current) {
+                            *span = span.to(t.span);
+                        }
+                        self.var_tree.move_to_predecessor();
+                    }
                     v.push(t)
                 }
                 _ => v.push(t),
@@ -116,120 +314,168 @@ impl<'tokens> Parser<'tokens> {
 
         dbg_print_pretty!(v);
 
-        self.curr_line = 0;
-
         Ok(v)
     }
 
-    fn parse<'node, 'text>(
+    fn parse<'node, 'text, 'tokens>(
         &mut self,
         mut tokens: TokenStream<'node, 'text, 'tokens>,
         purpose: NodePurpose,
-        predecessor: Option<Arc<RuntimeManager>>,
     ) -> ParsingResult<(Block, TokenStream<'node, 'text, 'tokens>)> {
-        let mut block =
-            Block::new_capacity_predecessor(purpose, self.var_tree.len_vars(), predecessor);
-
-        self.var_tree.to_next_preorder();
+        self.var_tree.move_to_next_preorder();
         let current = self.var_tree.current;
+        let mut block = Block::new(purpose, self.var_tree.len_vars());
+        block.block.info = Arc::new(BlockInfo {
+            names: self.var_tree.names(),
+            file: self.file.clone(),
+        });
+
+        // Functions can't break out of the loops they are declared in
+        let outer_in_loop = self.in_loop;
+        self.in_loop = match block.block.purpose {
+            NodePurpose::While | NodePurpose::For => true,
+            NodePurpose::Function => false,
+            _ => outer_in_loop,
+        };
 
         while let Ok(token) = self.next_token(&mut tokens) {
             dbg_print!(&token);
             self.var_tree.current = current;
+            let start = self.span;
             match token {
                 Token::Keyword(k) => {
-                    let (node, ts) = self.parse_keyword(k, tokens, Arc::clone(&block.scope))?;
+                    let (node, ts) = self.parse_keyword(k, tokens)?;
                     tokens = ts;
-                    block.push(node)
+                    // Named functions can be called before they are declared
+                    if let Node::FunctionDeclaration { id: Some(_), .. } = node {
+                        block.hoist(node, start)
+                    } else {
+                        block.push(node, start)
+                    }
                 }
-                Token::Data(val) => block.push(self.parse_data(val)),
-
-                Token::Identifier(id) => {
-                    let (node, ts) = self.parse_ident(id, tokens, Arc::clone(&block.scope))?;
+                t @ Token::Data(_) | t @ Token::Identifier(_) | t @ Token::Operator(_) => {
+                    let (node, ts) = self.parse_expression(t, tokens)?;
                     tokens = ts;
-                    block.push(node)
+                    block.push(node, start)
                 }
 
                 Token::Symbol(sym) => match sym {
                     SymbolToken::SquareOpen => {
-                        let (node, ts) = self.parse_index(
-                            match block.pop() {
-                                Some(n) => n,
-                                None => {
-                                    return Err(ParsingError::new(
-                                        ParsingErrorKind::UnexpectedEndOfInput,
-                                        self.curr_line,
-                                    ))
-                                }
-                            },
-                            tokens,
-                            Arc::clone(&block.scope),
-                        )?;
-
-                        block.push(node);
+                        let (initial, start) = match block.pop() {
+                            Some(n) => n,
+                            None => {
+                                return Err(ParsingError::new(
+                                    ParsingErrorKind::UnexpectedEndOfInput,
+                                    self.span,
+                                ))
+                            }
+                        };
+                        let (node, ts) = self.parse_index(initial, tokens)?;
+
+                        block.push(node, start);
                         tokens = ts
                     }
                     SymbolToken::CurlyOpen => {
-                        let (node, ts) =
-                            self.parse(tokens, NodePurpose::Block, Some(Arc::clone(&block.scope)))?;
+                        let (node, ts) = self.parse(tokens, NodePurpose::Block)?;
                         tokens = ts;
-                        block.push(Node::Block(node))
+                        block.push(Node::Block(node), start)
                     }
                     SymbolToken::CurlyClose => {
                         if let NodePurpose::TopLevel = block.block.purpose {
-                            return Err(ParsingError::unexpected(self.curr_line, "}".to_string()));
+                            return Err(ParsingError::unexpected(self.span, "}".to_string()));
                         } else {
+                            self.in_loop = outer_in_loop;
                             return Ok((block, tokens));
                         }
                     }
                     SymbolToken::RoundOpen => {
-                        let expr = block.pop().ok_or(ParsingError::new(
+                        let (expr, start) = block.pop().ok_or(ParsingError::new(
                             ParsingErrorKind::ExpectedNotFound("Preceeding function".to_string()),
-                            self.curr_line,
+                            self.span,
                         ))?;
-                        let (node, ts) = self.parse_call(expr, tokens, Arc::clone(&block.scope))?;
+                        let (node, ts) = self.parse_call(expr, self.span, tokens)?;
                         tokens = ts;
-                        block.push(node);
+                        block.push(node, start);
                     }
-                    t => return Err(ParsingError::unexpected(self.curr_line, format!("{:?}", t))),
+                    t => return Err(ParsingError::unexpected(self.span, format!("{:?}", t))),
                 },
-                Token::Newline => {
-                    dbg_print!(self.curr_line);
-                    self.curr_line += 1
+                // Lines are tracked by the spans of the tokens
+                Token::Newline => {}
+                Token::Unknown(t) => {
+                    return Err(ParsingError::unexpected(self.span, t.to_string()))
                 }
             }
             //dbg_print!(&block);
         }
         dbg_print_pretty!(block);
+        self.in_loop = outer_in_loop;
         Ok((block, tokens))
     }
 
-    pub fn parse_index<'node, 'text>(
+    /// Parses the expression before or after the `:` of a slice, the bound is omitted
+    /// if the slice continues right away
+    fn parse_index_bound<'node, 'text, 'tokens>(
+        &mut self,
+        mut tokens: TokenStream<'node, 'text, 'tokens>,
+    ) -> Result<(Option<Node>, TokenStream<'node, 'text, 'tokens>), ParsingError> {
+        match self.next_token(&mut tokens)? {
+            t @ Token::Symbol(SymbolToken::Colon) | t @ Token::Symbol(SymbolToken::SquareClose) => {
+                tokens.reinsert(t);
+                Ok((None, tokens))
+            }
+            t => {
+                let (node, tokens) = self.parse_expression(t, tokens)?;
+                Ok((Some(node), tokens))
+            }
+        }
+    }
+
+    pub fn parse_index<'node, 'text, 'tokens>(
         &mut self,
         initial: Node,
         mut tokens: TokenStream<'node, 'text, 'tokens>,
-        predecessor: Arc<RuntimeManager>,
     ) -> Result<(Node, TokenStream<'node, 'text, 'tokens>), ParsingError> {
+        let start = self.span;
         let mut index_ops = Vec::new();
+        let mut end;
 
         loop {
-            let next_token = self.next_token(&mut tokens)?;
+            let (start_node, ts) = self.parse_index_bound(tokens)?;
+            tokens = ts;
 
-            let (node, ts) = self.parse_expression(next_token, tokens, Arc::clone(&predecessor))?;
-            index_ops.push(IndexOperation {
-                index: Box::new(node),
-            });
+            let op = match self.next_token(&mut tokens)? {
+                Token::Symbol(SymbolToken::SquareClose) => match start_node {
+                    Some(node) => IndexOperation::Index(Box::new(node)),
+                    None => return Err(ParsingError::unexpected(self.span, "]".to_string())),
+                },
+                Token::Symbol(SymbolToken::Colon) => {
+                    let (end_node, ts) = self.parse_index_bound(tokens)?;
+                    tokens = ts;
 
-            tokens = ts;
+                    let tok = self.next_token(&mut tokens);
+                    if Ok(Token::Symbol(SymbolToken::SquareClose)) != tok {
+                        return Err(ParsingError::unexpected_expected(
+                            self.span,
+                            format!("{:?}", tok),
+                            "]".to_string(),
+                        ));
+                    }
 
-            let tok = self.next_token(&mut tokens);
-            if Ok(Token::Symbol(SymbolToken::SquareClose)) != tok {
-                return Err(ParsingError::unexpected_expected(
-                    self.curr_line,
-                    format!("{:?}", tok),
-                    "]".to_string(),
-                ));
-            }
+                    IndexOperation::Slice {
+                        start: start_node.map(Box::new),
+                        end: end_node.map(Box::new),
+                    }
+                }
+                t => {
+                    return Err(ParsingError::unexpected_expected(
+                        self.span,
+                        format!("{:?}", t),
+                        "]".to_string(),
+                    ))
+                }
+            };
+            index_ops.push(op);
+            end = self.span;
 
             let tok = self.next_token(&mut tokens);
             if Ok(Token::Symbol(SymbolToken::SquareOpen)) == tok {
@@ -249,9 +495,9 @@ impl<'tokens> Parser<'tokens> {
                 Node::Index(IndexNode {
                     initial: Box::new(initial),
                     index_ops,
+                    span: start.to(end),
                 }),
                 tokens,
-                predecessor,
             )
         } else {
             if let Ok(tok) = next {
@@ -260,6 +506,7 @@ impl<'tokens> Parser<'tokens> {
             let node = Node::Index(IndexNode {
                 initial: Box::new(initial),
                 index_ops,
+                span: start.to(end),
             });
 
             Ok((node, tokens))
@@ -269,51 +516,99 @@ impl<'tokens> Parser<'tokens> {
     //TODO Implement the get_ident method returning either an RustFunction or an Variable Position
     //TODO Change the current approach to one with Unresolved Nodes to be more friendly with the interactive shell
 
-    fn get_var<'a>(&'a mut self, identifier: &str) -> &Variable {
+    fn get_var(&self, identifier: &str) -> ParsingResult<&Variable> {
         for i in self.var_tree.current.ancestors(&self.var_tree.arena) {
             if let Some(arena) = self.var_tree.arena.get(i) {
-                if let Some(v) = arena.get().get(identifier) {
-                    return v;
+                if let Some(v) = arena.get().vars.get(identifier) {
+                    return Ok(v);
                 }
             }
         }
 
-        panic!("can't find {}", identifier)
+        Err(ParsingError::new(
+            ParsingErrorKind::UndefinedVariable(identifier.to_string()),
+            self.span,
+        ))
+    }
+
+    fn get_ident(&mut self, identifier: &str) -> ParsingResult<Node> {
+        let node = self.resolve_ident(identifier)?;
+        if self.references.is_some() {
+            let target = match &node {
+                Node::RustFunction(_) => Some(Target::Std(identifier.to_string())),
+                Node::Identifier(_) => Some(Target::Variable(self.get_var(identifier)?.span)),
+                _ => None,
+            };
+            if let (Some(references), Some(target)) = (&mut self.references, target) {
+                references.push(Reference {
+                    span: self.span,
+                    target,
+                });
+            }
+        }
+
+        Ok(node)
     }
 
-    fn get_ident<'node>(&mut self, identifier: &'node str) -> Option<Node> {
-        /* if identifier == "args" {
-            return Some(Node::Identifier(IdentifierNode::Args));
-        } */
+    fn resolve_ident(&self, identifier: &str) -> ParsingResult<Node> {
+        if identifier == "args" {
+            return Ok(Node::Args);
+        }
 
         if let Some(pref) = self.pre_map.get(identifier) {
-            return Some(Node::RustFunction(ConstRustFn(pref.clone())));
+            return Ok(Node::RustFunction(ConstRustFn(*pref)));
         }
 
-        let var = self.get_var(identifier);
+        if let Some((namespace, name)) = identifier.split_once('.') {
+            match self.imports.get(namespace) {
+                Some(Import::Std) => {
+                    if let Some(f) = self.loader.borrow().std_function(namespace, name) {
+                        return Ok(Node::RustFunction(ConstRustFn(f)));
+                    }
+                }
+                Some(Import::File(module)) => {
+                    if let Some(id) = module.export(name) {
+                        return Ok(Node::ModuleVar {
+                            module: Arc::clone(module),
+                            id,
+                        });
+                    }
+                }
+                None => (),
+            }
+        }
+
+        if let Some(native) = self.natives.get(identifier) {
+            return Ok(Node::Data(DayObject::Function(DayFunction::Closure(
+                Arc::clone(native),
+            ))));
+        }
 
-        Some(Node::Identifier(IdentifierNode::new(var.id, var.depth)))
+        let var = self.get_var(identifier)?;
+
+        Ok(Node::Identifier(IdentifierNode::new(var.id, var.depth)))
     }
 
     ///parses anything starting with an ident(ifier)
-    pub fn parse_ident<'node, 'text>(
+    pub fn parse_ident<'node, 'text, 'tokens>(
         &mut self,
         identifier: &'node str,
         mut tokens: TokenStream<'node, 'text, 'tokens>,
-        predecessor: Arc<RuntimeManager>,
     ) -> ParsingResult<(Node, TokenStream<'node, 'text, 'tokens>)> {
+        let start = self.span;
+        let ident = self.get_ident(identifier)?;
         let next = self.next_token(&mut tokens);
-        //TODO better error message
-        let ident = self
-            .get_ident(identifier)
-            .unwrap_or_else(|| panic!("Access to undefined ident {}", identifier));
         match next {
             Err(_) => Ok((ident, tokens)),
-            Ok(Token::Symbol(SymbolToken::RoundOpen)) => {
-                self.parse_call(ident, tokens, predecessor)
-            }
+            Ok(Token::Symbol(SymbolToken::RoundOpen)) => self.parse_call(ident, start, tokens),
             Ok(Token::Symbol(SymbolToken::Equals)) => {
-                self.parse_assignment(ident, tokens, predecessor)
+                if self.get_var(identifier).map_or(true, |v| v.is_const) {
+                    return Err(ParsingError::new(
+                        ParsingErrorKind::ConstAssignment(identifier.to_string()),
+                        start,
+                    ));
+                }
+                self.parse_assignment(ident, tokens)
             }
             Ok(token) => {
                 tokens.reinsert(token);
@@ -322,14 +617,13 @@ impl<'tokens> Parser<'tokens> {
         }
     }
 
-    fn parse_assignment<'node, 'text>(
+    fn parse_assignment<'node, 'text, 'tokens>(
         &mut self,
         assignee: Node,
         mut tokens: TokenStream<'node, 'text, 'tokens>,
-        predecessor: Arc<RuntimeManager>,
     ) -> ParsingResult<(Node, TokenStream<'node, 'text, 'tokens>)> {
         let next_token = self.next_token(&mut tokens)?;
-        let (node, ts) = self.parse_expression(next_token, tokens, predecessor)?;
+        let (node, ts) = self.parse_expression(next_token, tokens)?;
 
         Ok((
             Node::Assignment {
@@ -340,17 +634,18 @@ impl<'tokens> Parser<'tokens> {
         ))
     }
 
-    fn parse_call<'node, 'text>(
+    /// Parses the arguments of a call to `expr`, `start` is the span the callee starts at
+    fn parse_call<'node, 'text, 'tokens>(
         &mut self,
         expr: Node,
+        start: Span,
         mut tokens: TokenStream<'node, 'text, 'tokens>,
-        predecessor: Arc<RuntimeManager>,
     ) -> ParsingResult<(Node, TokenStream<'node, 'text, 'tokens>)> {
         let mut args: Vec<Node> = vec![];
         loop {
             let next_token = self.next_token(&mut tokens)?;
 
-            let (arg, ts) = self.parse_arg(next_token, tokens, Arc::clone(&predecessor))?;
+            let (arg, ts) = self.parse_arg(next_token, tokens)?;
             tokens = ts;
             dbg_print!(&arg);
             if let Some(n) = arg {
@@ -363,7 +658,7 @@ impl<'tokens> Parser<'tokens> {
                     }
                     t => {
                         return Err(ParsingError::unexpected_expected(
-                            self.curr_line,
+                            self.span,
                             format!("{:?}", t),
                             ", or )".to_string(),
                         ));
@@ -374,14 +669,15 @@ impl<'tokens> Parser<'tokens> {
             }
         }
 
+        let span = start.to(self.span);
         let fcall = Node::FunctionCall(FunctionCallNode {
             expr: Box::new(expr),
             args,
-            arg_cache: Default::default(),
+            span,
         });
         if let Ok(next) = self.next_token(&mut tokens) {
             if Token::Symbol(SymbolToken::RoundOpen) == next {
-                self.parse_call(fcall, tokens, predecessor)
+                self.parse_call(fcall, start, tokens)
             } else {
                 tokens.reinsert(next);
                 Ok((fcall, tokens))
@@ -391,90 +687,294 @@ impl<'tokens> Parser<'tokens> {
         }
     }
 
-    pub fn parse_arg<'node, 'text>(
+    pub fn parse_arg<'node, 'text, 'tokens>(
         &mut self,
         token: Token<'tokens>,
         tokens: TokenStream<'node, 'text, 'tokens>,
-        predecessor: Arc<RuntimeManager>,
     ) -> ParsingResult<(Option<Node>, TokenStream<'node, 'text, 'tokens>)> {
         match token {
             Token::Symbol(SymbolToken::RoundClose) => Ok((None, tokens)),
             t => {
-                let (node, ts) = self.parse_expression(t, tokens, predecessor)?;
+                let (node, ts) = self.parse_expression(t, tokens)?;
                 Ok((Some(node), ts))
             }
         }
     }
 
-    pub fn parse_expression<'node, 'text>(
+    /// Parses an expression starting with `token`,
+    /// binary operators are parsed by precedence climbing
+    pub fn parse_expression<'node, 'text, 'tokens>(
+        &mut self,
+        token: Token<'tokens>,
+        tokens: TokenStream<'node, 'text, 'tokens>,
+    ) -> ParsingResult<(Node, TokenStream<'node, 'text, 'tokens>)> {
+        self.parse_binary(token, tokens, 0)
+    }
+
+    /// Parses an expression only containing binary operators binding stronger than `min_precedence`
+    fn parse_binary<'node, 'text, 'tokens>(
+        &mut self,
+        token: Token<'tokens>,
+        tokens: TokenStream<'node, 'text, 'tokens>,
+        min_precedence: u8,
+    ) -> ParsingResult<(Node, TokenStream<'node, 'text, 'tokens>)> {
+        let (mut lhs, mut tokens) = self.parse_unary(token, tokens)?;
+
+        loop {
+            match self.next_token(&mut tokens) {
+                // Like `ret` and `break` an expression ends at the end of its line,
+                // an operator starting the next line starts the next statement
+                Ok(Token::Operator(op))
+                    if !tokens.after_newline()
+                        && op.precedence().is_some_and(|p| p > min_precedence) =>
+                {
+                    let span = self.span;
+                    let next_token = self.next_token(&mut tokens)?;
+                    // All binary operators are left associative
+                    let (rhs, ts) =
+                        self.parse_binary(next_token, tokens, op.precedence().unwrap_or_default())?;
+                    tokens = ts;
+                    lhs = Self::binary_node(op, lhs, rhs, span);
+                }
+                Ok(t) => {
+                    tokens.reinsert(t);
+                    break;
+                }
+                // The expression is the last thing in the input
+                Err(_) => break,
+            }
+        }
+
+        Ok((lhs, tokens))
+    }
+
+    fn parse_unary<'node, 'text, 'tokens>(
+        &mut self,
+        token: Token<'tokens>,
+        mut tokens: TokenStream<'node, 'text, 'tokens>,
+    ) -> ParsingResult<(Node, TokenStream<'node, 'text, 'tokens>)> {
+        let op = match token {
+            Token::Operator(op @ OperatorToken::Minus)
+            | Token::Operator(op @ OperatorToken::Not) => op,
+            t => return self.parse_primary(t, tokens),
+        };
+
+        let span = self.span;
+        let next_token = self.next_token(&mut tokens)?;
+        // Negative integer literals are folded right away, before their range is checked
+        if let (OperatorToken::Minus, Token::Data(DataToken::Integer(i))) = (&op, &next_token) {
+            let int = 0i64
+                .checked_sub_unsigned(*i)
+                .ok_or_else(|| ParsingError::new(ParsingErrorKind::IntegerOutOfRange, self.span))?;
+            return self.parse_postfix(Node::Data(DayObject::Integer(int)), tokens);
+        }
+        let (operand, tokens) = self.parse_unary(next_token, tokens)?;
+
+        let node = match (op, operand) {
+            // Negative literals are folded right away
+            (OperatorToken::Minus, Node::Data(DayObject::Float(f))) => {
+                Node::Data(DayObject::Float(-f))
+            }
+            (OperatorToken::Minus, operand) => {
+                Self::operator_call(arithmetics::neg, operand, None, span)
+            }
+            (_, operand) => Self::operator_call(bool_ops::not, operand, None, span),
+        };
+
+        Ok((node, tokens))
+    }
+
+    fn parse_primary<'node, 'text, 'tokens>(
         &mut self,
         token: Token<'tokens>,
         mut tokens: TokenStream<'node, 'text, 'tokens>,
-        predecessor: Arc<RuntimeManager>,
     ) -> ParsingResult<(Node, TokenStream<'node, 'text, 'tokens>)> {
         let (node, ts) = match token {
-            Token::Data(data) => Ok((self.parse_data(data), tokens)),
-            Token::Identifier(id) => self.parse_ident(id, tokens, Arc::clone(&predecessor)),
-            Token::Keyword(key) => self.parse_keyword(key, tokens, Arc::clone(&predecessor)),
-            t => todo!("error handling {:?}", t),
+            Token::Data(data) => Ok((self.parse_data(data)?, tokens)),
+            Token::Identifier(id) => self.parse_ident(id, tokens),
+            Token::Keyword(key) => self.parse_keyword(key, tokens),
+            Token::Symbol(SymbolToken::CurlyOpen) => self.parse_map_literal(tokens),
+            Token::Symbol(SymbolToken::RoundOpen) => {
+                let next_token = self.next_token(&mut tokens)?;
+                let (node, mut tokens) = self.parse_expression(next_token, tokens)?;
+                match self.next_token(&mut tokens)? {
+                    Token::Symbol(SymbolToken::RoundClose) => Ok((node, tokens)),
+                    t => Err(ParsingError::unexpected_expected(
+                        self.span,
+                        format!("{:?}", t),
+                        ")".to_string(),
+                    )),
+                }
+            }
+            t => Err(ParsingError::unexpected_expected(
+                self.span,
+                format!("{:?}", t),
+                "expression".to_string(),
+            )),
         }?;
 
-        tokens = ts;
+        self.parse_postfix(node, ts)
+    }
 
-        let next = self.next_token(&mut tokens)?;
-        match next {
-            Token::Symbol(SymbolToken::SquareOpen) => self.parse_index(node, tokens, predecessor),
-            t => {
+    /// Parses the indexing following `node`
+    fn parse_postfix<'node, 'text, 'tokens>(
+        &mut self,
+        node: Node,
+        mut tokens: TokenStream<'node, 'text, 'tokens>,
+    ) -> ParsingResult<(Node, TokenStream<'node, 'text, 'tokens>)> {
+        match self.next_token(&mut tokens) {
+            Ok(Token::Symbol(SymbolToken::SquareOpen)) => self.parse_index(node, tokens),
+            Ok(t) => {
                 dbg_print_pretty!(t);
                 tokens.reinsert(t);
                 dbg_print_pretty!(tokens);
 
                 Ok((node, tokens))
             }
+            // The expression is the last thing in the input
+            Err(_) => Ok((node, tokens)),
         }
     }
 
+    /// Parses `{ key: value, ... }` after the `{`, a trailing comma is allowed
+    fn parse_map_literal<'node, 'text, 'tokens>(
+        &mut self,
+        mut tokens: TokenStream<'node, 'text, 'tokens>,
+    ) -> ParsingResult<(Node, TokenStream<'node, 'text, 'tokens>)> {
+        let start = self.span;
+        let mut entries = vec![];
+
+        loop {
+            let next_token = self.next_token(&mut tokens)?;
+            if next_token == Token::Symbol(SymbolToken::CurlyClose) {
+                break;
+            }
+
+            let (key, mut ts) = self.parse_expression(next_token, tokens)?;
+            match self.next_token(&mut ts)? {
+                Token::Symbol(SymbolToken::Colon) => (),
+                t => {
+                    return Err(ParsingError::unexpected_expected(
+                        self.span,
+                        format!("{:?}", t),
+                        ":".to_string(),
+                    ))
+                }
+            }
+            let next_token = self.next_token(&mut ts)?;
+            let (value, ts) = self.parse_expression(next_token, ts)?;
+            tokens = ts;
+            entries.push((key, value));
+
+            match self.next_token(&mut tokens)? {
+                Token::Symbol(SymbolToken::Comma) => (),
+                Token::Symbol(SymbolToken::CurlyClose) => break,
+                t => {
+                    return Err(ParsingError::unexpected_expected(
+                        self.span,
+                        format!("{:?}", t),
+                        ", or }".to_string(),
+                    ))
+                }
+            }
+        }
+
+        Ok((
+            Node::MapLiteral {
+                entries,
+                span: start.to(self.span),
+            },
+            tokens,
+        ))
+    }
+
+    /// Lowers a binary operator to the node executing it
+    fn binary_node(op: OperatorToken, lhs: Node, rhs: Node, span: Span) -> Node {
+        let f = match op {
+            OperatorToken::And | OperatorToken::Or => {
+                return Node::Logical {
+                    op: if op == OperatorToken::And {
+                        LogicalOp::And
+                    } else {
+                        LogicalOp::Or
+                    },
+                    lhs: Box::new(lhs),
+                    rhs: Box::new(rhs),
+                    span,
+                }
+            }
+            OperatorToken::Plus => arithmetics::add,
+            OperatorToken::Minus => arithmetics::sub,
+            OperatorToken::Asterisk => arithmetics::mul,
+            OperatorToken::Slash => arithmetics::div,
+            OperatorToken::Percent => arithmetics::modu,
+            OperatorToken::EqualsEquals => comparison::eq,
+            OperatorToken::NotEquals => comparison::neq,
+            OperatorToken::Less => comparison::lt,
+            OperatorToken::LessEquals => comparison::le,
+            OperatorToken::Greater => comparison::gt,
+            OperatorToken::GreaterEquals => comparison::ge,
+            OperatorToken::Not => bool_ops::not,
+        };
+
+        Self::operator_call(f, lhs, Some(rhs), span)
+    }
+
+    /// Operators are calls to the std functions implementing them
+    fn operator_call(f: RustFunction, lhs: Node, rhs: Option<Node>, span: Span) -> Node {
+        let mut args = vec![lhs];
+        args.extend(rhs);
+
+        Node::FunctionCall(FunctionCallNode {
+            expr: Box::new(Node::RustFunction(ConstRustFn(f))),
+            args,
+            span,
+        })
+    }
+
     /// Parses Data out of DataTolkens into DayObjects
-    pub fn parse_data(&mut self, data: DataToken) -> Node {
-        Node::Data(match data {
-            DataToken::Integer(i) => DayObject::Integer(i),
+    pub fn parse_data(&mut self, data: DataToken) -> ParsingResult<Node> {
+        Ok(Node::Data(match data {
+            DataToken::Integer(i) => {
+                DayObject::Integer(0i64.checked_add_unsigned(i).ok_or_else(|| {
+                    ParsingError::new(ParsingErrorKind::IntegerOutOfRange, self.span)
+                })?)
+            }
             DataToken::Float(f) => DayObject::Float(f),
             DataToken::Bool(b) => DayObject::Bool(b),
             DataToken::Character(c) => DayObject::Character(c),
             DataToken::Str(s) => DayObject::Str(s),
             DataToken::None => DayObject::None,
-        })
+        }))
     }
 
-    fn parse_keyword<'node, 'text>(
+    fn parse_keyword<'node, 'text, 'tokens>(
         &mut self,
         keyword: KeywordToken,
         mut tokens: TokenStream<'node, 'text, 'tokens>,
-        predecessor: Arc<RuntimeManager>,
     ) -> ParsingResult<(Node, TokenStream<'node, 'text, 'tokens>)> {
         dbg_print!(&keyword);
         match keyword {
             KeywordToken::Ret => {
-                let (expr, ts) = self.parse_ret(tokens, predecessor)?;
+                let (expr, ts) = self.parse_ret(tokens)?;
                 match expr {
                     Some(expr) => Ok((Node::Ret(Some(Arc::new(expr))), ts)),
                     None => Ok((Node::Ret(None), ts)),
                 }
             }
-            KeywordToken::Let => self.parse_declaration(tokens, predecessor),
-            KeywordToken::Const => self.parse_const_declaration(tokens, predecessor),
+            KeywordToken::Let => self.parse_declaration(tokens),
+            KeywordToken::Const => self.parse_const_declaration(tokens),
             KeywordToken::If => {
                 tokens.reinsert(KeywordToken::If.into());
                 let mut branches = vec![];
                 while let Ok(next_token) = self.next_token(&mut tokens) {
                     match next_token {
-                        Token::Keyword(KeywordToken::If) if branches.len() != 0 => {
+                        Token::Keyword(KeywordToken::If) if !branches.is_empty() => {
                             tokens.reinsert(Token::Keyword(KeywordToken::If));
                             break;
                         }
                         next => {
-                            let (b, ts) =
-                                self.parse_branch_inner(next, tokens, Arc::clone(&predecessor))?;
+                            let (b, ts) = self.parse_branch_inner(next, tokens)?;
                             tokens = ts;
                             if let Some(b) = b {
                                 branches.push(b)
@@ -488,93 +988,349 @@ impl<'tokens> Parser<'tokens> {
                 Ok((Node::BranchNode(branches), tokens))
             }
             KeywordToken::While => {
+                let span = self.span;
                 let next_token = self.next_token(&mut tokens)?;
-                let (condition, mut tokens) =
-                    self.parse_expression(next_token, tokens, Arc::clone(&predecessor))?;
+                let (condition, mut tokens) = self.parse_expression(next_token, tokens)?;
                 dbg_print!(&condition);
                 if Token::Symbol(SymbolToken::CurlyOpen) != self.next_token(&mut tokens)? {
                     return Err(ParsingError::new(
                         ParsingErrorKind::ExpectedNotFound("{".to_string()),
-                        self.curr_line,
+                        self.span,
                     ));
                 }
-                let (block, tokens) = self.parse(tokens, NodePurpose::While, Some(predecessor))?;
+                let (block, tokens) = self.parse(tokens, NodePurpose::While)?;
                 dbg_print!(&block);
 
                 Ok((
                     Node::While {
                         condition: Box::new(condition),
                         block,
+                        span,
                     },
                     tokens,
                 ))
             }
             KeywordToken::Fn => {
                 let next = self.next_token(&mut tokens)?;
-                let id = if let Token::Identifier(s) = next {
-                    Some(s)
+                let (name, id) = if let Token::Identifier(s) = next {
+                    (Some(s.to_string()), Some(self.get_var(s)?.id))
                 } else {
                     tokens.reinsert(next);
+                    (None, None)
+                };
+
+                let mut next = self.next_token(&mut tokens);
+                let params = if Ok(Token::Symbol(SymbolToken::RoundOpen)) == next {
+                    let (params, ts) = self.parse_params(tokens)?;
+                    tokens = ts;
+                    next = self.next_token(&mut tokens);
+                    Some(params)
+                } else {
                     None
                 };
 
-                if Ok(Token::Symbol(SymbolToken::CurlyOpen)) != self.next_token(&mut tokens) {
+                if Ok(Token::Symbol(SymbolToken::CurlyOpen)) != next {
                     return Err(ParsingError::new(
                         ParsingErrorKind::ExpectedNotFound("{".to_string()),
-                        self.curr_line,
+                        self.span,
                     ));
                 }
-                let (block, tokens) =
-                    self.parse(tokens, NodePurpose::Function, Some(predecessor))?;
+                let (body, tokens) = self.parse(tokens, NodePurpose::Function)?;
 
-                Ok((Node::function_decl(block, id == None), tokens))
+                Ok((
+                    Node::function_decl(FunctionDef { name, params, body }, id),
+                    tokens,
+                ))
             }
             KeywordToken::For => {
+                let span = self.span;
                 self.get_identifier(&mut tokens)?;
-                if Some(Token::Keyword(KeywordToken::In)) != tokens.next() {
+                // `for k, v in` destructures every element into two variables
+                let mut next = self.next_token(&mut tokens)?;
+                let destructure = next == Token::Symbol(SymbolToken::Comma);
+                if destructure {
+                    self.get_identifier(&mut tokens)?;
+                    next = self.next_token(&mut tokens)?;
+                }
+                if Token::Keyword(KeywordToken::In) != next {
                     return Err(ParsingError::new(
                         ParsingErrorKind::ExpectedNotFound("in".to_string()),
-                        self.curr_line,
+                        self.span,
                     ));
                 }
                 let next_token = self.next_token(&mut tokens)?;
-                let (iter, mut tokens) =
-                    self.parse_expression(next_token, tokens, Arc::clone(&predecessor))?;
+                let (iter, mut tokens) = self.parse_expression(next_token, tokens)?;
                 dbg_print!(&iter);
                 if Some(Token::Symbol(SymbolToken::CurlyOpen)) != tokens.next() {
                     return Err(ParsingError::new(
                         ParsingErrorKind::ExpectedNotFound("{".to_string()),
-                        self.curr_line,
+                        self.span,
                     ));
                 }
-                let (block, tokens) = self.parse(tokens, NodePurpose::For, Some(predecessor))?;
+                let (block, tokens) = self.parse(tokens, NodePurpose::For)?;
                 dbg_print!(&block);
                 Ok((
                     Node::For {
                         expr: Box::new(iter),
                         block,
+                        destructure,
+                        span,
                     },
                     tokens,
                 ))
             }
-            _ => todo!(),
+            KeywordToken::Try => self.parse_try(tokens),
+            KeywordToken::Import => self.parse_import(tokens),
+            KeywordToken::Break | KeywordToken::Continue if !self.in_loop => {
+                Err(ParsingError::new(
+                    ParsingErrorKind::OutsideOfLoop(format!("{:?}", keyword).to_lowercase()),
+                    self.span,
+                ))
+            }
+            KeywordToken::Break => {
+                let (value, ts) = self.parse_break(tokens)?;
+                Ok((Node::Break(value.map(Box::new)), ts))
+            }
+            KeywordToken::Continue => Ok((Node::Continue, tokens)),
+            k => Err(ParsingError::unexpected(self.span, format!("{:?}", k))),
         }
     }
 
-    fn parse_ret<'node, 'text>(
+    /// Parses the parameters of a function after the opening parenthesis,
+    /// `a`, `a = default` and a last `...rest`.
+    /// The names were already bound to the first variables of the body by `fill_var_map`
+    fn parse_params<'node, 'text, 'tokens>(
+        &mut self,
+        mut tokens: TokenStream<'node, 'text, 'tokens>,
+    ) -> ParsingResult<(Vec<Param>, TokenStream<'node, 'text, 'tokens>)> {
+        let mut params = vec![];
+        loop {
+            let param = match self.next_token(&mut tokens)? {
+                Token::Symbol(SymbolToken::RoundClose) => break,
+                Token::Symbol(SymbolToken::Ellipsis) => {
+                    self.get_identifier(&mut tokens)?;
+                    Param::Rest
+                }
+                Token::Identifier(_) => {
+                    let next = self.next_token(&mut tokens)?;
+                    if next == Token::Symbol(SymbolToken::Equals) {
+                        let next_token = self.next_token(&mut tokens)?;
+                        let (default, ts) = self.parse_expression(next_token, tokens)?;
+                        tokens = ts;
+                        Param::Optional(default)
+                    } else {
+                        tokens.reinsert(next);
+                        if params.iter().any(|p| !matches!(p, Param::Required)) {
+                            return Err(ParsingError::new(
+                                ParsingErrorKind::ExpectedNotFound("default value".to_string()),
+                                self.span,
+                            ));
+                        }
+                        Param::Required
+                    }
+                }
+                t => {
+                    return Err(ParsingError::unexpected_expected(
+                        self.span,
+                        format!("{:?}", t),
+                        "parameter".to_string(),
+                    ))
+                }
+            };
+
+            let is_rest = matches!(param, Param::Rest);
+            params.push(param);
+            match self.next_token(&mut tokens)? {
+                Token::Symbol(SymbolToken::Comma) if !is_rest => (),
+                Token::Symbol(SymbolToken::RoundClose) => break,
+                t => {
+                    return Err(ParsingError::unexpected_expected(
+                        self.span,
+                        format!("{:?}", t),
+                        if is_rest { ")" } else { ", or )" }.to_string(),
+                    ))
+                }
+            }
+        }
+
+        Ok((params, tokens))
+    }
+
+    /// Parses `import "path/to/file.crab"` and `import module` of the std library
+    fn parse_import<'node, 'text, 'tokens>(
+        &mut self,
+        mut tokens: TokenStream<'node, 'text, 'tokens>,
+    ) -> ParsingResult<(Node, TokenStream<'node, 'text, 'tokens>)> {
+        let span = self.span;
+        match self.next_token(&mut tokens)? {
+            Token::Data(DataToken::Str(path)) => {
+                let module = self.import_file(&path, span)?;
+                let namespace = module
+                    .path()
+                    .file_stem()
+                    .map(|stem| stem.to_string_lossy().into_owned())
+                    .unwrap_or(path);
+                self.imports
+                    .insert(namespace, Import::File(Arc::clone(&module)));
+                Ok((Node::Import { module, span }, tokens))
+            }
+            Token::Identifier(name) => {
+                if self.loader.borrow().is_std_module(name) {
+                    self.imports.insert(name.to_string(), Import::Std);
+                } else {
+                    // Modules registered by the host are always available
+                    let prefix = format!("{}.", name);
+                    if !self.natives.keys().any(|k| k.starts_with(&prefix)) {
+                        return Err(ParsingError::new(
+                            ParsingErrorKind::ModuleNotFound(name.to_string()),
+                            self.span,
+                        ));
+                    }
+                }
+                Ok((Node::Data(DayObject::None), tokens))
+            }
+            t => Err(ParsingError::unexpected_expected(
+                self.span,
+                format!("{:?}", t),
+                "module name or path".to_string(),
+            )),
+        }
+    }
+
+    /// Parses the file `path` into a module or takes it from the cache
+    fn import_file(&mut self, path: &str, span: Span) -> ParsingResult<Arc<FileModule>> {
+        let not_found =
+            || ParsingError::new(ParsingErrorKind::ModuleNotFound(path.to_string()), span);
+        let resolved = self
+            .loader
+            .borrow()
+            .resolve(path, self.dir.as_deref())
+            .ok_or_else(not_found)?;
+        if let Some(module) = self.loader.borrow().cached(&resolved) {
+            return Ok(module);
+        }
+        let src = std::fs::read_to_string(&resolved).map_err(|_| not_found())?;
+
+        self.loader
+            .borrow_mut()
+            .start_loading(&resolved)
+            .map_err(|chain| ParsingError::new(ParsingErrorKind::ImportCycle(chain), span))?;
+
+        let mut parser = Parser::with_loader(
+            self.pre_map.clone(),
+            Rc::clone(&self.loader),
+            resolved.parent().map(PathBuf::from),
+        );
+        parser.natives = self.natives.clone();
+        parser.opt_level = self.opt_level;
+        parser.file = Some(resolved.clone());
+        let lexer = build_lexer().expect("the lexer definition is valid");
+        let module = parser
+            .parse_tokens(TokenStream::new(&src, lexer.tokens(&src)))
+            .map(|block| {
+                let exports = parser
+                    .top_level_vars()
+                    .into_iter()
+                    .filter(|(name, _)| !name.starts_with('_'))
+                    .map(|(name, id)| (name.to_string(), id))
+                    .collect();
+                Arc::new(FileModule::new(resolved.clone(), block, exports))
+            });
+        self.loader
+            .borrow_mut()
+            .finish_loading(module.as_ref().ok().cloned());
+
+        module.map_err(|error| {
+            ParsingError::new(
+                ParsingErrorKind::InModule {
+                    path: resolved.display().to_string(),
+                    error: Box::new(error),
+                },
+                span,
+            )
+        })
+    }
+
+    /// Parses `try { ... } catch e { ... }`, the name of the error is optional
+    fn parse_try<'node, 'text, 'tokens>(
+        &mut self,
+        mut tokens: TokenStream<'node, 'text, 'tokens>,
+    ) -> ParsingResult<(Node, TokenStream<'node, 'text, 'tokens>)> {
+        if Ok(Token::Symbol(SymbolToken::CurlyOpen)) != self.next_token(&mut tokens) {
+            return Err(ParsingError::new(
+                ParsingErrorKind::ExpectedNotFound("{".to_string()),
+                self.span,
+            ));
+        }
+        let (block, mut tokens) = self.parse(tokens, NodePurpose::Block)?;
+
+        if Ok(Token::Keyword(KeywordToken::Catch)) != self.next_token(&mut tokens) {
+            return Err(ParsingError::new(
+                ParsingErrorKind::ExpectedNotFound("catch".to_string()),
+                self.span,
+            ));
+        }
+
+        let next = self.next_token(&mut tokens)?;
+        let binds_error = matches!(next, Token::Identifier(_));
+        let next = if binds_error {
+            self.next_token(&mut tokens)?
+        } else {
+            next
+        };
+        if Token::Symbol(SymbolToken::CurlyOpen) != next {
+            return Err(ParsingError::new(
+                ParsingErrorKind::ExpectedNotFound("{".to_string()),
+                self.span,
+            ));
+        }
+        let (catch, tokens) = self.parse(tokens, NodePurpose::Block)?;
+
+        Ok((
+            Node::Try {
+                block,
+                catch,
+                binds_error,
+            },
+            tokens,
+        ))
+    }
+
+    fn parse_ret<'node, 'text, 'tokens>(
         &mut self,
         mut tokens: TokenStream<'node, 'text, 'tokens>,
-        predecessor: Arc<RuntimeManager>,
     ) -> ParsingResult<(Option<Node>, TokenStream<'node, 'text, 'tokens>)> {
         match self.next_token(&mut tokens) {
             Err(_) => Ok((None, tokens)),
             Ok(t) => {
-                let expr = self.parse_expression(t, tokens, predecessor)?;
+                let expr = self.parse_expression(t, tokens)?;
                 Ok((Some(expr.0), expr.1))
             }
         }
     }
 
+    /// Parses the optional value of a `break`, it has to be on the same line
+    fn parse_break<'node, 'text, 'tokens>(
+        &mut self,
+        mut tokens: TokenStream<'node, 'text, 'tokens>,
+    ) -> ParsingResult<(Option<Node>, TokenStream<'node, 'text, 'tokens>)> {
+        let line = self.span.line;
+        match tokens.next_spanned() {
+            Some(t)
+                if t.span.line == line && !matches!(t.token, Token::Symbol(_) | Token::Newline) =>
+            {
+                self.span = t.span;
+                let (expr, ts) = self.parse_expression(t.token, tokens)?;
+                Ok((Some(expr), ts))
+            }
+            Some(t) => {
+                tokens.reinsert(t.token);
+                Ok((None, tokens))
+            }
+            None => Ok((None, tokens)),
+        }
+    }
+
     /// Parses the branch belonging to the token `tok`
     ///
     /// ### Returns:
@@ -583,38 +1339,33 @@ impl<'tokens> Parser<'tokens> {
     ///     - *Some* if branch could be passed
     ///     - *None* if no branch could belong to `tok`
     /// 2. TokenStream
-    fn parse_branch_inner<'node, 'text>(
+    fn parse_branch_inner<'node, 'text, 'tokens>(
         &mut self,
         tok: Token<'tokens>,
         mut tokens: TokenStream<'node, 'text, 'tokens>,
-        predecessor: Arc<RuntimeManager>,
     ) -> ParsingResult<(Option<BranchNode>, TokenStream<'node, 'text, 'tokens>)> {
         if let Token::Keyword(ktok) = tok {
             match ktok {
                 KeywordToken::If => {
-                    let (condition, block, tokens) = self.parse_if_inner(tokens, predecessor)?;
+                    let (condition, block, tokens) = self.parse_if_inner(tokens)?;
                     let block = IfBlock { condition, block };
                     Ok((Some(BranchNode::ElseIf { block }), tokens))
                 }
                 KeywordToken::Else => match self.next_token(&mut tokens)? {
                     Token::Keyword(KeywordToken::If) => {
-                        let (condition, block, tokens) =
-                            self.parse_if_inner(tokens, predecessor)?;
+                        let (condition, block, tokens) = self.parse_if_inner(tokens)?;
                         let block = IfBlock { condition, block };
                         Ok((Some(BranchNode::ElseIf { block }), tokens))
                     }
                     Token::Symbol(SymbolToken::CurlyOpen) => {
-                        let (block, tokens) =
-                            self.parse(tokens, NodePurpose::Conditional, Some(predecessor))?;
+                        let (block, tokens) = self.parse(tokens, NodePurpose::Conditional)?;
                         Ok((Some(BranchNode::Else { block }), tokens))
                     }
-                    t => {
-                        return Err(ParsingError::unexpected_expected(
-                            self.curr_line,
-                            format!("{:?}", t),
-                            "if or {".to_string(),
-                        ));
-                    }
+                    t => Err(ParsingError::unexpected_expected(
+                        self.span,
+                        format!("{:?}", t),
+                        "if or {".to_string(),
+                    )),
                 },
                 _ => {
                     // No branch to parse
@@ -623,100 +1374,114 @@ impl<'tokens> Parser<'tokens> {
                 }
             }
         } else {
-            tokens.reinsert(tok.into());
+            tokens.reinsert(tok);
 
             Ok((None, tokens))
         }
     }
 
-    fn parse_if_inner<'node, 'text>(
+    fn parse_if_inner<'node, 'text, 'tokens>(
         &mut self,
         mut tokens: TokenStream<'node, 'text, 'tokens>,
-        predecessor: Arc<RuntimeManager>,
     ) -> ParsingResult<(Box<Node>, Block, TokenStream<'node, 'text, 'tokens>)> {
         let next_token = self.next_token(&mut tokens)?;
 
-        let (condition, mut tokens) =
-            self.parse_expression(next_token, tokens, Arc::clone(&predecessor))?;
+        let (condition, mut tokens) = self.parse_expression(next_token, tokens)?;
 
         if Ok(Token::Symbol(SymbolToken::CurlyOpen)) != self.next_token(&mut tokens) {
             return Err(ParsingError::new(
                 ParsingErrorKind::ExpectedNotFound("{".to_string()),
-                self.curr_line,
+                self.span,
             ));
         }
 
-        let (block, tokens) = self.parse(tokens, NodePurpose::Conditional, Some(predecessor))?;
+        let (block, tokens) = self.parse(tokens, NodePurpose::Conditional)?;
 
         Ok((Box::new(condition), block, tokens))
     }
 
-    fn parse_declaration<'node, 'text>(
+    fn parse_declaration<'node, 'text, 'tokens>(
         &mut self,
         tokens: TokenStream<'node, 'text, 'tokens>,
-        predecessor: Arc<RuntimeManager>,
     ) -> Result<(Node, TokenStream<'node, 'text, 'tokens>), ParsingError> {
-        let decl = self.decl_inner(tokens, predecessor)?;
-        Ok((Node::Declaration { value: decl.1, id: self.get_var(decl.0).id }, decl.2))
+        let decl = self.decl_inner(tokens)?;
+        Ok((
+            Node::Declaration {
+                value: decl.1,
+                id: self.get_var(decl.0)?.id,
+            },
+            decl.2,
+        ))
     }
 
-    fn parse_const_declaration<'node, 'text>(
+    fn parse_const_declaration<'node, 'text, 'tokens>(
         &mut self,
         tokens: TokenStream<'node, 'text, 'tokens>,
-        predecessor: Arc<RuntimeManager>,
     ) -> Result<(Node, TokenStream<'node, 'text, 'tokens>), ParsingError> {
-        let decl = self.decl_inner(tokens, predecessor)?;
-        Ok((Node::ConstDeclaration { value: decl.1, id: self.get_var(decl.0).id }, decl.2))
+        let decl = self.decl_inner(tokens)?;
+        Ok((
+            Node::ConstDeclaration {
+                value: decl.1,
+                id: self.get_var(decl.0)?.id,
+            },
+            decl.2,
+        ))
     }
 
-    fn decl_inner<'node, 'text>(
+    fn decl_inner<'node, 'text, 'tokens>(
         &mut self,
         mut tokens: TokenStream<'node, 'text, 'tokens>,
-        predecessor: Arc<RuntimeManager>,
     ) -> Result<(&'node str, Box<Node>, TokenStream<'node, 'text, 'tokens>), ParsingError> {
         //NOTE currently testing this little cool macro
-        let id =
-            expect!(self.next_token(&mut tokens)? => Token::Identifier | "Expected identifier");
+        let id = expect!(self.next_token(&mut tokens)? => Token::Identifier | return ParsingError::new(
+            ParsingErrorKind::ExpectedNotFound("identifier".to_string()),
+            self.span,
+        ));
         if Ok(Token::Symbol(SymbolToken::Equals)) != self.next_token(&mut tokens) {
             return Err(ParsingError::new(
                 ParsingErrorKind::ExpectedNotFound("=".to_string()),
-                self.curr_line,
+                self.span,
             ));
         }
         let next_token = self.next_token(&mut tokens)?;
-        let (node, ts) = self.parse_expression(next_token, tokens, predecessor)?;
+        let (node, ts) = self.parse_expression(next_token, tokens)?;
 
         Ok((id, Box::new(node), ts))
     }
 
     /// Retruns the next non-meta token
     /// And handles the meta-tokens,
-    /// by e.g. incrementing line numbers.
+    /// by e.g. skipping newlines and remembering the span.
     /// ### Errors
     /// `UnexpectedEnd` when no more tokens are in the token stream (`tokens.next()` returns `None`)
-    fn next_token<'node, 'text>(
+    fn next_token<'node, 'text, 'tokens>(
         &mut self,
         tokens: &mut TokenStream<'node, 'text, 'tokens>,
     ) -> ParsingResult<Token<'tokens>> {
+        self.next_spanned_token(tokens).map(|t| t.token)
+    }
+
+    /// Like `next_token` but keeps the span of the token
+    fn next_spanned_token<'node, 'text, 'tokens>(
+        &mut self,
+        tokens: &mut TokenStream<'node, 'text, 'tokens>,
+    ) -> ParsingResult<SpannedToken<'tokens>> {
         loop {
-            match tokens.next() {
-                Some(Token::Newline) => {
-                    self.curr_line += 1;
-                }
+            match tokens.next_spanned() {
+                Some(SpannedToken {
+                    token: Token::Newline,
+                    ..
+                }) => {}
                 Some(other_token) => {
+                    self.span = other_token.span;
                     return Ok(other_token);
                 }
-                None => {
-                    return Err(ParsingError::new(
-                        ParsingErrorKind::UnexpectedEndOfInput,
-                        self.curr_line,
-                    ))
-                }
+                None => return Err(ParsingError::unexpected_end_of_input(self.span)),
             }
         }
     }
 
-    fn get_identifier<'node, 'text>(
+    fn get_identifier<'node, 'text, 'tokens>(
         &mut self,
         tokens: &mut TokenStream<'node, 'text, 'tokens>,
     ) -> ParsingResult<&'text str> {
@@ -725,7 +1490,7 @@ impl<'tokens> Parser<'tokens> {
         } else {
             Err(ParsingError::new(
                 ParsingErrorKind::ExpectedNotFound("identifier".to_string()),
-                self.curr_line,
+                self.span,
             ))
         }
     }
@@ -733,37 +1498,75 @@ impl<'tokens> Parser<'tokens> {
 
 use indextree::{Arena, NodeId};
 use std::collections::HashMap;
-type Scope<'a> = HashMap<&'a str, Variable>;
+
+/// The variables declared in a scope
+#[derive(Debug, Default, Clone)]
+struct Scope {
+    vars: HashMap<Arc<str>, Variable>,
+    /// The number of slots taken, redeclaring a name takes a new slot
+    /// so the ids of the scope only ever increase
+    slots: usize,
+}
+
+impl Scope {
+    /// Declares `name` in the next free slot and returns its id
+    fn declare(&mut self, name: &str, depth: usize, is_const: bool, span: Span) -> usize {
+        let id = self.slots;
+        self.slots += 1;
+        self.vars.insert(
+            name.into(),
+            Variable {
+                id,
+                depth,
+                is_const,
+                span,
+            },
+        );
+        id
+    }
+}
 
 //TODO Save all NodeIds in an Preorder ordering and traverse it by that
 
 #[derive(Debug)]
-struct VarTree<'a> {
-    arena: Arena<Scope<'a>>,
+struct VarTree {
+    arena: Arena<Scope>,
+    /// The top level scope
+    root: NodeId,
     current: NodeId,
     pre_order: Vec<NodeId>,
+    /// The spans of the blocks of the scopes, from `{` to `}`
+    spans: HashMap<NodeId, Span>,
 }
 
-impl<'a> VarTree<'a> {
+impl VarTree {
     fn new() -> Self {
         let mut arena = Arena::default();
-        let current = arena.new_node(Default::default());
+        let root = arena.new_node(Default::default());
         VarTree {
             arena,
-            current,
-            pre_order: vec![current],
+            root,
+            current: root,
+            pre_order: vec![root],
+            spans: HashMap::new(),
         }
     }
 
-    fn to_predecessor(&mut self) {
+    /// Moves back to the top level scope to fill and parse the next piece of top level code
+    fn rewind(&mut self) {
+        self.current = self.root;
+        self.pre_order = vec![self.root];
+    }
+
+    fn move_to_predecessor(&mut self) {
         self.current = self.predecessor().unwrap_or(self.current);
     }
 
     fn predecessor(&self) -> Option<NodeId> {
-        self.current.ancestors(&self.arena).skip(1).next()
+        self.current.ancestors(&self.arena).nth(1)
     }
 
-    fn to_new_successor(&mut self) {
+    fn move_to_new_successor(&mut self) {
         self.current = self.new_successor();
     }
 
@@ -773,11 +1576,11 @@ impl<'a> VarTree<'a> {
         new
     }
 
-    fn get_current(&self) -> &Scope<'a> {
+    fn get_current(&self) -> &Scope {
         self.arena.get(self.current).unwrap().get()
     }
 
-    fn get_current_mut(&mut self) -> &mut Scope<'a> {
+    fn get_current_mut(&mut self) -> &mut Scope {
         self.arena.get_mut(self.current).unwrap().get_mut()
     }
 
@@ -786,10 +1589,49 @@ impl<'a> VarTree<'a> {
     }
 
     fn len_vars(&self) -> usize {
-        self.get_current().len()
+        self.get_current().slots
+    }
+
+    /// The names of the variables of the current scope indexed by their ids,
+    /// the slots of redeclared names are named by their ids
+    fn names(&self) -> Vec<String> {
+        let scope = self.get_current();
+        let mut names: Vec<_> = (0..scope.slots).map(|id| format!("#{}", id)).collect();
+        for (name, var) in &scope.vars {
+            names[var.id] = name.to_string();
+        }
+        names
     }
 
-    fn to_next_preorder(&mut self) {
+    fn scope_info(&self, scope: NodeId, depth: usize) -> ScopeInfo {
+        let mut vars: Vec<_> = self
+            .arena
+            .get(scope)
+            .unwrap()
+            .get()
+            .vars
+            .iter()
+            .map(|(name, var)| VarInfo {
+                name: name.to_string(),
+                id: var.id,
+                is_const: var.is_const,
+                span: var.span,
+            })
+            .collect();
+        vars.sort_by_key(|var| var.id);
+
+        ScopeInfo {
+            depth,
+            span: self.spans.get(&scope).copied(),
+            vars,
+            children: scope
+                .children(&self.arena)
+                .map(|child| self.scope_info(child, depth + 1))
+                .collect(),
+        }
+    }
+
+    fn move_to_next_preorder(&mut self) {
         self.current = self.get_next_preorder();
         //println!("l{} | c{} | v{:?}", self.pre_order.len(), self.current, self.get_current());
     }
@@ -799,8 +1641,45 @@ impl<'a> VarTree<'a> {
     }
 }
 
+/// Tracks a parameter list of a function while filling the var map
+#[derive(Default)]
+struct ParamList<'a> {
+    parens: usize,
+    names: Vec<(&'a str, Span)>,
+    /// Set at the start of the list and after commas, unset inside of default values
+    expects_name: bool,
+}
+
+impl<'a> ParamList<'a> {
+    /// Records the names of the parameters, returns true when the list is closed
+    fn update(&mut self, token: &SpannedToken<'a>) -> bool {
+        match token.token {
+            Token::Symbol(SymbolToken::RoundOpen) => {
+                self.parens += 1;
+                self.expects_name = self.parens == 1;
+            }
+            Token::Symbol(SymbolToken::RoundClose) => {
+                self.parens -= 1;
+                return self.parens == 0;
+            }
+            Token::Symbol(SymbolToken::Comma) if self.parens == 1 => self.expects_name = true,
+            Token::Symbol(SymbolToken::Ellipsis) | Token::Newline => (),
+            Token::Identifier(name) if self.expects_name => {
+                self.names.push((name, token.span));
+                self.expects_name = false;
+            }
+            _ => self.expects_name = false,
+        }
+
+        false
+    }
+}
+
 #[derive(Debug, Clone)]
 struct Variable {
     id: usize,
     depth: usize,
+    is_const: bool,
+    /// The name in the declaration
+    span: Span,
 }
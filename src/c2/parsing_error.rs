@@ -1,3 +1,4 @@
+use crate::span::Span;
 use std::fmt;
 
 //NOTE it might be possible to implement this with &'a str instead of string, but that would be hard
@@ -7,7 +8,7 @@ pub type ParsingResult<T> = Result<T, ParsingError>;
 #[derive(Debug, PartialEq)]
 pub struct ParsingError {
     error_type: ParsingErrorKind,
-    line: u64,
+    span: Span,
 }
 
 /// Specifies the type of `Parsing Error`
@@ -24,62 +25,120 @@ pub enum ParsingErrorKind {
     UnexpectedEndOfInput,
     /// An undefined was tried to be accessed
     UndefinedVariable(String),
+    /// A value was assigned to a constant after its declaration
+    ConstAssignment(String),
+    /// A loop control keyword like `break` was used outside of a loop
+    OutsideOfLoop(String),
+    /// A function has two parameters with the same name
+    DuplicateParameter(String),
+    /// An imported file doesn't exist in any directory of the search path,
+    /// or a std module with the imported name doesn't exist
+    ModuleNotFound(String),
+    /// A file imports itself through the listed chain of files
+    ImportCycle(String),
+    /// An integer literal doesn't fit into an integer
+    IntegerOutOfRange,
+    /// An imported file failed to parse
+    InModule {
+        path: String,
+        error: Box<ParsingError>,
+    },
 }
 
-impl<'a> ParsingError {
-    pub fn new(error_type: ParsingErrorKind, line: u64) -> Self {
-        ParsingError { error_type, line }
+impl ParsingError {
+    pub fn new(error_type: ParsingErrorKind, span: Span) -> Self {
+        ParsingError { error_type, span }
     }
 
-    pub fn unexpected_end_of_input(line: u64) -> Self {
-        Self::new(ParsingErrorKind::UnexpectedEndOfInput, line)
+    pub fn unexpected_end_of_input(span: Span) -> Self {
+        Self::new(ParsingErrorKind::UnexpectedEndOfInput, span)
     }
 
-    pub fn unexpected(line: u64, unexpected: String) -> Self {
+    pub fn unexpected(span: Span, unexpected: String) -> Self {
         Self::new(
             ParsingErrorKind::Unexpected {
                 unexpected,
                 expected: None,
             },
-            line,
+            span,
         )
     }
 
-    pub fn unexpected_expected(line: u64, unexpected: String, expected: String) -> Self {
+    pub fn unexpected_expected(span: Span, unexpected: String, expected: String) -> Self {
         Self::new(
             ParsingErrorKind::Unexpected {
                 unexpected,
                 expected: Some(expected),
             },
-            line,
+            span,
         )
     }
+
+    pub fn kind(&self) -> &ParsingErrorKind {
+        &self.error_type
+    }
+
+    /// The location of the token the parser failed at
+    pub fn span(&self) -> Span {
+        self.span
+    }
 }
 
 impl fmt::Display for ParsingError {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        write!(
-            f,
-            "ERROR [l. {}]:\t{}",
-            self.line,
-            match &self.error_type {
-                ParsingErrorKind::ExpectedNotFound(s) =>
-                    format!("Expected {} but could not find it.", s),
-                ParsingErrorKind::Unexpected {
-                    unexpected,
-                    expected,
-                } =>
-                    if let Some(ex) = expected {
-                        format!("Expected an {} found {}.", ex, unexpected)
-                    } else {
-                        format!("An unexpected {} was found.", unexpected)
-                    },
-                ParsingErrorKind::UnexpectedEndOfInput =>
-                //TODO This should not allocate a string
-                    "The file/command ended unexpectedly. Are you missing something?".to_string(),
-                ParsingErrorKind::UndefinedVariable(id) =>
-                    format!("The variable {} was not defined", id),
+        write!(f, "ERROR [{}]:\t{}", self.span, self.error_type)
+    }
+}
+
+impl fmt::Display for ParsingErrorKind {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ParsingErrorKind::ExpectedNotFound(s) => {
+                write!(f, "Expected {} but could not find it.", s)
             }
-        )
+            ParsingErrorKind::Unexpected {
+                unexpected,
+                expected,
+            } => {
+                if let Some(ex) = expected {
+                    write!(f, "Expected an {} found {}.", ex, unexpected)
+                } else {
+                    write!(f, "An unexpected {} was found.", unexpected)
+                }
+            }
+            ParsingErrorKind::UnexpectedEndOfInput => write!(
+                f,
+                "The file/command ended unexpectedly. Are you missing something?"
+            ),
+            ParsingErrorKind::UndefinedVariable(id) => {
+                write!(f, "The variable {} was not defined", id)
+            }
+            ParsingErrorKind::ConstAssignment(id) => {
+                write!(f, "The constant {} can't be assigned to", id)
+            }
+            ParsingErrorKind::OutsideOfLoop(keyword) => {
+                write!(f, "{} can only be used inside of a loop", keyword)
+            }
+            ParsingErrorKind::DuplicateParameter(name) => {
+                write!(f, "The parameter {} is declared twice", name)
+            }
+            ParsingErrorKind::ModuleNotFound(module) => {
+                write!(f, "The module {} could not be found", module)
+            }
+            ParsingErrorKind::ImportCycle(chain) => {
+                write!(f, "The modules import each other: {}", chain)
+            }
+            ParsingErrorKind::IntegerOutOfRange => write!(
+                f,
+                "The integer literal is out of range, integers go from {} to {}",
+                i64::MIN,
+                i64::MAX
+            ),
+            ParsingErrorKind::InModule { path, error } => {
+                write!(f, "{} [{} {}]", error.kind(), path, error.span())
+            }
+        }
     }
 }
+
+impl std::error::Error for ParsingError {}
@@ -0,0 +1,449 @@
+//NOTE Measures where scripts spend their time, per function and per line.
+//
+//Like the debugger this hooks into `RootNode::execute` and the calls of functions, without a
+//profiler the hooks only check a thread local flag. The time between two hooks is charged to the
+//line that was executed last in the innermost call and to the stack of calls, so the time of a
+//line excludes the functions it called. Only the tree walker has these hooks, the engine doesn't
+//use the vm while profiling.
+
+use crate::{
+    base::RustFunction,
+    build_std_library,
+    node::{BlockInfo, FunctionDef},
+    span::Span,
+};
+use ahash::RandomState as AHasherBuilder;
+use std::{
+    cell::{Cell, RefCell},
+    collections::HashMap,
+    fmt::Write,
+    path::{Path, PathBuf},
+    ptr::fn_addr_eq,
+    rc::Rc,
+    sync::Arc,
+    time::{Duration, Instant},
+};
+
+/// The outermost entry of the folded stacks
+const TOP_LEVEL: &str = "top level";
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum FunctionKind {
+    /// Declared in crabscript
+    Script,
+    /// A function of the standard library
+    Std,
+}
+
+#[derive(Clone, Debug)]
+pub struct FunctionStats {
+    pub name: String,
+    pub kind: FunctionKind,
+    pub calls: u64,
+    /// The time until the calls returned, recursive calls are only counted once
+    pub inclusive: Duration,
+    /// The time spent in the function itself and not in the functions it called
+    pub exclusive: Duration,
+}
+
+#[derive(Clone, Debug)]
+pub struct LineStats {
+    /// The imported file the line is in, `None` for the code compiled by the engine
+    pub file: Option<PathBuf>,
+    pub line: u64,
+    /// How often statements starting on the line were executed
+    pub hits: u64,
+    /// The time spent executing the line, without the functions it called
+    pub time: Duration,
+}
+
+/// What the profiled scripts spent their time on
+#[derive(Debug, Default)]
+pub struct Profile {
+    functions: Vec<FunctionStats>,
+    lines: Vec<LineStats>,
+    /// The exclusive time of every stack of functions, the outermost first
+    stacks: HashMap<Vec<usize>, Duration, AHasherBuilder>,
+    total: Duration,
+}
+
+impl Profile {
+    /// The time spent running the scripts and calls
+    pub fn total(&self) -> Duration {
+        self.total
+    }
+
+    /// The called functions, the one with the most exclusive time first
+    pub fn functions(&self) -> Vec<&FunctionStats> {
+        let mut functions: Vec<_> = self.functions.iter().collect();
+        functions.sort_by(|a, b| {
+            b.exclusive
+                .cmp(&a.exclusive)
+                .then(b.calls.cmp(&a.calls))
+                .then(a.name.cmp(&b.name))
+        });
+        functions
+    }
+
+    pub fn function(&self, name: &str) -> Option<&FunctionStats> {
+        self.functions.iter().find(|f| f.name == name)
+    }
+
+    /// The executed lines, the one with the most time first
+    pub fn lines(&self) -> Vec<&LineStats> {
+        let mut lines: Vec<_> = self.lines.iter().collect();
+        lines.sort_by(|a, b| {
+            b.time
+                .cmp(&a.time)
+                .then(b.hits.cmp(&a.hits))
+                .then(a.file.cmp(&b.file))
+                .then(a.line.cmp(&b.line))
+        });
+        lines
+    }
+
+    pub fn line(&self, file: Option<&Path>, line: u64) -> Option<&LineStats> {
+        self.lines
+            .iter()
+            .find(|l| l.file.as_deref() == file && l.line == line)
+    }
+
+    /// A table of the functions and one of the lines, `script` names the code compiled by the engine
+    pub fn report(&self, script: &str) -> String {
+        let mut out = format!("Total time {}\n\n", millis(self.total));
+
+        let _ = writeln!(
+            out,
+            "{:>10} {:>12} {:>12}  function",
+            "calls", "inclusive", "exclusive"
+        );
+        for f in self.functions() {
+            let kind = match f.kind {
+                FunctionKind::Script => "",
+                FunctionKind::Std => " (std)",
+            };
+            let _ = writeln!(
+                out,
+                "{:>10} {:>12} {:>12}  {}{}",
+                f.calls,
+                millis(f.inclusive),
+                millis(f.exclusive),
+                f.name,
+                kind
+            );
+        }
+
+        let _ = writeln!(out, "\n{:>10} {:>12}  line", "hits", "time");
+        for l in self.lines() {
+            let file = l
+                .file
+                .as_ref()
+                .map_or_else(|| script.to_string(), |f| f.display().to_string());
+            let _ = writeln!(
+                out,
+                "{:>10} {:>12}  {}:{}",
+                l.hits,
+                millis(l.time),
+                file,
+                l.line
+            );
+        }
+        out
+    }
+
+    /// The stacks of calls with their exclusive time in microseconds, one per line
+    /// like `top level;f;g 120`, as read by flamegraph tools
+    pub fn folded(&self) -> String {
+        let mut stacks: Vec<_> = self
+            .stacks
+            .iter()
+            .filter(|(_, time)| time.as_micros() > 0)
+            .map(|(stack, time)| {
+                let mut names = vec![TOP_LEVEL];
+                names.extend(stack.iter().map(|&f| self.functions[f].name.as_str()));
+                format!("{} {}", names.join(";"), time.as_micros())
+            })
+            .collect();
+        stacks.sort();
+
+        let mut out = String::new();
+        for stack in stacks {
+            out.push_str(&stack);
+            out.push('\n');
+        }
+        out
+    }
+}
+
+fn millis(time: Duration) -> String {
+    format!("{:.3}ms", time.as_secs_f64() * 1000.0)
+}
+
+/// A call that didn't return yet
+struct Call {
+    function: usize,
+    start: Instant,
+    /// The line executed last in the call
+    line: Option<usize>,
+}
+
+/// Collects a `Profile` while scripts run
+pub struct Profiler {
+    profile: Profile,
+    /// The functions by their addresses
+    ids: HashMap<usize, usize, AHasherBuilder>,
+    /// How many calls of every function didn't return yet
+    running: Vec<u32>,
+    files: Vec<Option<PathBuf>>,
+    /// The lines by the indices of their files and their numbers
+    lines: HashMap<(usize, u64), usize, AHasherBuilder>,
+    std_names: Vec<(String, RustFunction)>,
+    calls: Vec<Call>,
+    /// The line executed last at the top level
+    line: Option<usize>,
+    /// The time everything before was charged at
+    mark: Instant,
+    /// The time since the stack of calls last changed
+    pending: Duration,
+}
+
+impl Profiler {
+    pub fn new() -> Self {
+        let mut std_names: Vec<(String, RustFunction)> = build_std_library()
+            .into_iter()
+            .flat_map(|(module, functions)| {
+                functions
+                    .into_iter()
+                    .map(move |(name, f)| (format!("{}.{}", module, name), f))
+            })
+            .collect();
+        // Some functions are in multiple modules, the same name has to be picked every time
+        std_names.sort_by(|(a, _), (b, _)| a.cmp(b));
+
+        Profiler {
+            profile: Profile::default(),
+            ids: HashMap::default(),
+            running: Vec::new(),
+            files: Vec::new(),
+            lines: HashMap::default(),
+            std_names,
+            calls: Vec::new(),
+            line: None,
+            mark: Instant::now(),
+            pending: Duration::ZERO,
+        }
+    }
+
+    /// The profile of everything run so far
+    pub fn finish(self) -> Profile {
+        self.profile
+    }
+
+    /// Charges the time since the last mark to the current line and stack
+    fn charge(&mut self, now: Instant) {
+        let elapsed = now.saturating_duration_since(self.mark);
+        self.mark = now;
+        let line = match self.calls.last() {
+            Some(call) => call.line,
+            None => self.line,
+        };
+        if let Some(line) = line {
+            self.profile.lines[line].time += elapsed;
+        }
+        self.pending += elapsed;
+    }
+
+    /// Charges the pending time to the current stack before it changes
+    fn flush(&mut self) {
+        let pending = std::mem::take(&mut self.pending);
+        if let Some(call) = self.calls.last() {
+            self.profile.functions[call.function].exclusive += pending;
+        }
+
+        let stack: Vec<usize> = self.calls.iter().map(|call| call.function).collect();
+        *self.profile.stacks.entry(stack).or_default() += pending;
+    }
+
+    fn statement(&mut self, info: &BlockInfo, span: Span) {
+        self.charge(Instant::now());
+        let file = match self.files.iter().position(|file| *file == info.file) {
+            Some(file) => file,
+            None => {
+                self.files.push(info.file.clone());
+                self.files.len() - 1
+            }
+        };
+        let next = self.lines.len();
+        let id = *self.lines.entry((file, span.line)).or_insert(next);
+        if id == next {
+            self.profile.lines.push(LineStats {
+                file: info.file.clone(),
+                line: span.line,
+                hits: 0,
+                time: Duration::ZERO,
+            });
+        }
+        self.profile.lines[id].hits += 1;
+
+        match self.calls.last_mut() {
+            Some(call) => call.line = Some(id),
+            None => self.line = Some(id),
+        }
+    }
+
+    fn enter(&mut self, address: usize, name: impl FnOnce(&Self) -> (String, FunctionKind)) {
+        let now = Instant::now();
+        self.charge(now);
+        self.flush();
+
+        let next = self.profile.functions.len();
+        let function = *self.ids.entry(address).or_insert(next);
+        if function == next {
+            let (name, kind) = name(self);
+            self.profile.functions.push(FunctionStats {
+                name,
+                kind,
+                calls: 0,
+                inclusive: Duration::ZERO,
+                exclusive: Duration::ZERO,
+            });
+            self.running.push(0);
+        }
+        self.profile.functions[function].calls += 1;
+        self.running[function] += 1;
+        self.calls.push(Call {
+            function,
+            start: now,
+            line: None,
+        });
+    }
+
+    fn leave(&mut self) {
+        let now = Instant::now();
+        self.charge(now);
+        self.flush();
+
+        if let Some(call) = self.calls.pop() {
+            self.running[call.function] -= 1;
+            if self.running[call.function] == 0 {
+                self.profile.functions[call.function].inclusive += now - call.start;
+            }
+        }
+    }
+}
+
+impl Default for Profiler {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+pub type SharedProfiler = Rc<RefCell<Profiler>>;
+
+thread_local! {
+    /// Whether a profiler is installed, checked before every statement
+    static ACTIVE: Cell<bool> = const { Cell::new(false) };
+    static PROFILER: RefCell<Option<SharedProfiler>> = const { RefCell::new(None) };
+}
+
+/// Stops the profiling when dropped and restores the profiler of the enclosing run
+pub struct ProfileGuard {
+    previous: Option<SharedProfiler>,
+    start: Instant,
+}
+
+impl Drop for ProfileGuard {
+    fn drop(&mut self) {
+        let previous = self.previous.take();
+        ACTIVE.with(|active| active.set(previous.is_some()));
+        let current = PROFILER.with(|current| current.replace(previous));
+        if let Some(profiler) = current {
+            let mut profiler = profiler.borrow_mut();
+            let now = Instant::now();
+            profiler.charge(now);
+            profiler.flush();
+            profiler.line = None;
+            profiler.profile.total += now - self.start;
+        }
+    }
+}
+
+/// Makes `profiler` record the code running on this thread until the guard is dropped,
+/// `None` runs the code without recording it
+pub fn install(profiler: Option<&SharedProfiler>) -> ProfileGuard {
+    let start = Instant::now();
+    if let Some(profiler) = profiler {
+        profiler.borrow_mut().mark = start;
+    }
+    ACTIVE.with(|active| active.set(profiler.is_some()));
+    ProfileGuard {
+        previous: PROFILER.with(|current| current.replace(profiler.map(Rc::clone))),
+        start,
+    }
+}
+
+/// Leaves the function call when dropped
+pub struct CallGuard(());
+
+impl Drop for CallGuard {
+    fn drop(&mut self) {
+        with_profiler(Profiler::leave);
+    }
+}
+
+/// Records a call of the script function `function` until the guard is dropped
+#[inline]
+pub fn enter_call(function: &FunctionDef) -> Option<CallGuard> {
+    if !ACTIVE.with(Cell::get) {
+        return None;
+    }
+
+    with_profiler(|profiler| {
+        profiler.enter(function as *const FunctionDef as usize, |_| {
+            let name = match (&function.name, function.body.block.spans().first()) {
+                (Some(name), _) => name.clone(),
+                (None, Some(span)) => format!("fn:{}", span.line),
+                (None, None) => "fn".to_string(),
+            };
+            (name, FunctionKind::Script)
+        })
+    })?;
+    Some(CallGuard(()))
+}
+
+/// Records a call of the std function `function` until the guard is dropped
+#[inline]
+pub fn enter_std(function: RustFunction) -> Option<CallGuard> {
+    if !ACTIVE.with(Cell::get) {
+        return None;
+    }
+
+    with_profiler(|profiler| {
+        profiler.enter(function as usize, |profiler| {
+            let name = profiler
+                .std_names
+                .iter()
+                .find(|(_, f)| fn_addr_eq(*f, function))
+                .map_or("native", |(name, _)| name);
+            (name.to_string(), FunctionKind::Std)
+        })
+    })?;
+    Some(CallGuard(()))
+}
+
+/// Called before the statement starting at `span` of a block of `info` is executed
+#[inline]
+pub fn statement(info: &Arc<BlockInfo>, span: Span) {
+    if ACTIVE.with(Cell::get) {
+        record(info, span)
+    }
+}
+
+#[cold]
+fn record(info: &BlockInfo, span: Span) {
+    with_profiler(|profiler| profiler.statement(info, span));
+}
+
+fn with_profiler<T>(f: impl FnOnce(&mut Profiler) -> T) -> Option<T> {
+    PROFILER.with(|profiler| profiler.borrow().as_ref().map(|p| f(&mut p.borrow_mut())))
+}
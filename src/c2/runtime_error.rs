@@ -0,0 +1,289 @@
+use crate::span::Span;
+use std::{
+    fmt,
+    path::{Path, PathBuf},
+};
+
+pub type RuntimeResult<T> = Result<T, RuntimeError>;
+
+/// How often the same entry of a traceback is shown in a row
+const MAX_REPEATED: usize = 3;
+
+/// An error that occured while executing a script
+#[derive(Debug, PartialEq, Clone)]
+pub struct RuntimeError {
+    kind: RuntimeErrorKind,
+    message: String,
+    span: Option<Span>,
+    /// The calls of functions declared in crabscript the error left, the innermost first
+    trace: Vec<TraceFrame>,
+}
+
+/// A call of a function declared in crabscript
+#[derive(Debug, PartialEq, Clone)]
+pub struct TraceFrame {
+    /// The name of the function, `fn` if it is anonymous
+    pub function: String,
+    /// Where the function was called, `None` until the error reached the call
+    pub call_site: Option<Span>,
+    /// The imported file if the frame is the top level of a module instead of a call
+    pub module: Option<PathBuf>,
+}
+
+/// Specifies the type of `Runtime Error`
+#[derive(Debug, PartialEq, Eq, Hash, Clone, Copy)]
+pub enum RuntimeErrorKind {
+    /// A value had a type that can't be used in this operation
+    TypeError,
+    /// A function received the wrong number or kind of arguments
+    InvalidArguments,
+    /// A value that is not a function was called
+    NotCallable,
+    /// A value could not be converted to another type
+    Conversion,
+    /// An arithmetic operation is not defined for its operands, e.g. division by zero
+    Arithmetic,
+    /// An index was out of bounds or a key was not in a map
+    Index,
+    /// Reading from or writing to a file or stream failed
+    Io,
+    /// A script assertion did not hold
+    AssertionFailed,
+    /// The script called `panic`
+    Panic,
+    /// A control flow statement like `ret` was used where a value was expected
+    UnexpectedControlFlow,
+    /// `args` was used outside of a function
+    ArgsOutsideFunction,
+    /// An error created by the script itself with `error` or `raise`
+    Custom,
+    /// The capabilities of the engine don't allow the operation
+    PermissionDenied,
+    /// The script executed more loop iterations and calls than allowed
+    StepLimit,
+    /// The script ran longer than allowed
+    Timeout,
+    /// The script nested more function calls than allowed
+    RecursionLimit,
+    /// The host cancelled the script
+    Cancelled,
+    /// The host ran a script with an engine that didn't compile it
+    ForeignScript,
+}
+
+impl RuntimeErrorKind {
+    /// Errors ending the script because of a limit set by the host can't be caught by `try`
+    pub fn is_catchable(self) -> bool {
+        !matches!(
+            self,
+            RuntimeErrorKind::StepLimit
+                | RuntimeErrorKind::Timeout
+                | RuntimeErrorKind::RecursionLimit
+                | RuntimeErrorKind::Cancelled
+        )
+    }
+}
+
+impl RuntimeError {
+    pub fn new(kind: RuntimeErrorKind, message: impl Into<String>) -> Self {
+        RuntimeError {
+            kind,
+            message: message.into(),
+            span: None,
+            trace: Vec::new(),
+        }
+    }
+
+    pub fn type_error(message: impl Into<String>) -> Self {
+        Self::new(RuntimeErrorKind::TypeError, message)
+    }
+
+    /// Creates an `InvalidArguments` error for the function `fname`
+    pub fn invalid_args(fname: &str, message: impl fmt::Display) -> Self {
+        Self::new(
+            RuntimeErrorKind::InvalidArguments,
+            format!("{}: {}", fname, message),
+        )
+    }
+
+    pub fn io(message: impl Into<String>) -> Self {
+        Self::new(RuntimeErrorKind::Io, message)
+    }
+
+    pub fn permission_denied(message: impl Into<String>) -> Self {
+        Self::new(RuntimeErrorKind::PermissionDenied, message)
+    }
+
+    pub fn kind(&self) -> RuntimeErrorKind {
+        self.kind
+    }
+
+    pub fn message(&self) -> &str {
+        &self.message
+    }
+
+    pub fn line(&self) -> Option<u64> {
+        self.span.map(|s| s.line)
+    }
+
+    pub fn span(&self) -> Option<Span> {
+        self.span
+    }
+
+    pub fn trace(&self) -> &[TraceFrame] {
+        &self.trace
+    }
+
+    /// Attaches the source location to this error if it doesn't know it yet.
+    /// Errors are created without a span deep inside of std functions,
+    /// the innermost node knowing its span claims them. After the error left
+    /// a function the node calling it claims the call site instead.
+    pub fn at(mut self, span: Span) -> Self {
+        if self.span.is_none() {
+            self.span = Some(span);
+        } else if let Some(
+            frame @ TraceFrame {
+                call_site: None, ..
+            },
+        ) = self.trace.last_mut()
+        {
+            frame.call_site = Some(span);
+        }
+        self
+    }
+
+    /// Records that the error left a call of `function`. Errors without a span were raised
+    /// before the body of the function started, they belong to the call site.
+    pub fn left_call(mut self, function: &str) -> Self {
+        if self.span.is_some() {
+            self.trace.push(TraceFrame {
+                function: function.to_string(),
+                call_site: None,
+                module: None,
+            });
+        }
+        self
+    }
+
+    /// Records that the error left the top level of the module imported from `path`,
+    /// the span and the frames inside of it belong to that file
+    pub fn left_import(mut self, path: &Path) -> Self {
+        if self.span.is_some() {
+            self.trace.push(TraceFrame {
+                function: "top level".to_string(),
+                call_site: None,
+                module: Some(path.to_path_buf()),
+            });
+        }
+        self
+    }
+
+    /// The imported file the span of the error belongs to, `None` for the script itself
+    pub fn module(&self) -> Option<&Path> {
+        self.trace.iter().find_map(|frame| frame.module.as_deref())
+    }
+
+    /// Renders the calls the error left with their lines of `source` the way python does,
+    /// the most recent call last. Empty if the error didn't leave a call.
+    pub fn traceback(&self, source: &str) -> String {
+        if self.trace.is_empty() {
+            return String::new();
+        }
+
+        // Every function was at the call site of the next inner one, the innermost at the error
+        let functions = std::iter::once("top level")
+            .chain(self.trace.iter().rev().map(|frame| frame.function.as_str()));
+        let lines = self
+            .trace
+            .iter()
+            .rev()
+            .map(|frame| frame.call_site)
+            .chain(std::iter::once(self.span))
+            .map(|span| span.map(|span| span.line));
+        // Everything inside of an import is in the imported file
+        let files =
+            std::iter::once(None).chain(self.trace.iter().rev().scan(None, |file, frame| {
+                if let Some(module) = &frame.module {
+                    *file = Some(module.as_path());
+                }
+                Some(*file)
+            }));
+        let entries: Vec<_> = functions
+            .zip(lines)
+            .zip(files)
+            .map(|((function, line), file)| (function, line, file))
+            .collect();
+
+        let mut out = String::from("Traceback (most recent call last):\n");
+        let mut i = 0;
+        while i < entries.len() {
+            // Recursion repeats the same entry, only the first few are shown
+            let repeated = entries[i..]
+                .iter()
+                .take_while(|entry| **entry == entries[i])
+                .count();
+            for &(function, line, file) in entries[i..].iter().take(repeated.min(MAX_REPEATED)) {
+                match (line, file) {
+                    // Only the source of the script is known, not the one of the module
+                    (Some(line), Some(file)) => out.push_str(&format!(
+                        "  {}, line {}, in {}\n",
+                        file.display(),
+                        line,
+                        function
+                    )),
+                    (Some(line), None) => {
+                        let text = source.lines().nth(line.saturating_sub(1) as usize);
+                        out.push_str(&format!("  line {}, in {}\n", line, function));
+                        out.push_str(&format!("    {}\n", text.unwrap_or_default().trim()));
+                    }
+                    (None, _) => out.push_str(&format!("  in {}\n", function)),
+                }
+            }
+            if repeated > MAX_REPEATED {
+                out.push_str(&format!(
+                    "  [Previous line repeated {} more times]\n",
+                    repeated - MAX_REPEATED
+                ));
+            }
+            i += repeated;
+        }
+        out
+    }
+}
+
+impl fmt::Display for RuntimeErrorKind {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let name = match self {
+            RuntimeErrorKind::TypeError => "TypeError",
+            RuntimeErrorKind::InvalidArguments => "InvalidArguments",
+            RuntimeErrorKind::NotCallable => "NotCallable",
+            RuntimeErrorKind::Conversion => "ConversionError",
+            RuntimeErrorKind::Arithmetic => "ArithmeticError",
+            RuntimeErrorKind::Index => "IndexError",
+            RuntimeErrorKind::Io => "IoError",
+            RuntimeErrorKind::AssertionFailed => "AssertionFailed",
+            RuntimeErrorKind::Panic => "Panic",
+            RuntimeErrorKind::UnexpectedControlFlow => "UnexpectedControlFlow",
+            RuntimeErrorKind::ArgsOutsideFunction => "ArgsOutsideFunction",
+            RuntimeErrorKind::Custom => "Error",
+            RuntimeErrorKind::PermissionDenied => "PermissionDenied",
+            RuntimeErrorKind::StepLimit => "StepLimit",
+            RuntimeErrorKind::Timeout => "Timeout",
+            RuntimeErrorKind::RecursionLimit => "RecursionLimit",
+            RuntimeErrorKind::Cancelled => "Cancelled",
+            RuntimeErrorKind::ForeignScript => "ForeignScript",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+impl fmt::Display for RuntimeError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.span {
+            Some(span) => write!(f, "ERROR [{}]:\t{}: {}", span, self.kind, self.message),
+            None => write!(f, "ERROR:\t{}: {}", self.kind, self.message),
+        }
+    }
+}
+
+impl std::error::Error for RuntimeError {}
@@ -0,0 +1,111 @@
+use std::fmt;
+
+/// The location of a piece of source code, lines and columns start at 1
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub struct Span {
+    /// Byte offset of the first character
+    pub offset: usize,
+    /// Length in bytes
+    pub len: usize,
+    pub line: u64,
+    /// Column of the first character, counted in chars not bytes
+    pub column: u64,
+}
+
+impl Default for Span {
+    fn default() -> Self {
+        Span {
+            offset: 0,
+            len: 0,
+            line: 1,
+            column: 1,
+        }
+    }
+}
+
+impl Span {
+    /// Creates a span reaching from the start of `self` to the end of `end`
+    pub fn to(self, end: Span) -> Span {
+        Span {
+            len: (end.offset + end.len).saturating_sub(self.offset),
+            ..self
+        }
+    }
+
+    /// Renders the line of `source` this span starts on and underlines the span with carets.
+    /// Spans reaching over multiple lines are only underlined until the end of the first line.
+    pub fn underline(&self, source: &str) -> String {
+        let src_line = source
+            .lines()
+            .nth(self.line.saturating_sub(1) as usize)
+            .unwrap_or("");
+        let gutter = self.line.to_string().len();
+
+        // Tabs are kept so the carets line up with the rendered source line
+        let padding: String = src_line
+            .chars()
+            .take(self.column.saturating_sub(1) as usize)
+            .map(|c| if c == '\t' { '\t' } else { ' ' })
+            .collect();
+        let start = source.len().min(self.offset);
+        let end = source.len().min(self.offset + self.len);
+        // Spans of imported files don't have to fit into `source`
+        let underlined = source
+            .get(start..end)
+            .unwrap_or("")
+            .chars()
+            .take_while(|c| *c != '\n')
+            .count()
+            .max(1);
+
+        format!(
+            "{:>gutter$} | {}\n{:>gutter$} | {}{}",
+            self.line,
+            src_line,
+            "",
+            padding,
+            "^".repeat(underlined),
+            gutter = gutter
+        )
+    }
+}
+
+impl fmt::Display for Span {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "l. {}, c. {}", self.line, self.column)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn underline() {
+        let source = "let x = 10\nlet y = add(x, \"ten\")\n";
+        let span = Span {
+            offset: 19,
+            len: 13,
+            line: 2,
+            column: 9,
+        };
+
+        assert_eq!(
+            span.underline(source),
+            "2 | let y = add(x, \"ten\")\n  |         ^^^^^^^^^^^^^"
+        );
+    }
+
+    #[test]
+    fn underline_multiline() {
+        let source = "\tfoo(\n1)";
+        let span = Span {
+            offset: 1,
+            len: 7,
+            line: 1,
+            column: 2,
+        };
+
+        assert_eq!(span.underline(source), "1 | \tfoo(\n  | \t^^^^");
+    }
+}
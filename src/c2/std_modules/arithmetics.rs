@@ -1,40 +1,116 @@
-use crate::base::{
-    Args,
-    DayObject::{self, *},
+use crate::{
+    base::{
+        Args,
+        DayObject::{self, *},
+    },
+    runtime_error::{RuntimeError, RuntimeErrorKind, RuntimeResult},
 };
 
+/// Integer overflow would abort the interpreter or wrap silently, so it is an error
 macro_rules! def_op {
-    ($name: ident, $othername: ident, $op: tt) => {
-        pub fn $othername(a: &DayObject, b: &DayObject) -> DayObject {
+    ($name: ident, $othername: ident, $op: tt, $checked: ident) => {
+        pub fn $othername(a: &DayObject, b: &DayObject) -> RuntimeResult<DayObject> {
             match (a,b) {
-                (Integer(a),Integer(b)) => Integer(a $op b),
-                (Float(a),Float(b)) => Float(a $op b),
-                (Float(a),Integer(b)) => Float(a $op *b as f64),
-                (Integer(a),Float(b)) => Float(*a as f64 $op b),
-                _ => panic!("can only add float and int")
+                (Integer(a),Integer(b)) => a.$checked(*b).map(Integer).ok_or_else(|| {
+                    RuntimeError::new(
+                        RuntimeErrorKind::Arithmetic,
+                        format!("{} of {} and {} overflows", stringify!($name), a, b),
+                    )
+                }),
+                (Float(a),Float(b)) => Ok(Float(a $op b)),
+                (Float(a),Integer(b)) => Ok(Float(a $op *b as f64)),
+                (Integer(a),Float(b)) => Ok(Float(*a as f64 $op b)),
+                _ => Err(RuntimeError::type_error(format!(
+                    "{} can only be used with float and int, received {:?} and {:?}",
+                    stringify!($name), a, b
+                ))),
             }
         }
 
-        pub fn $name(args: Args) -> DayObject {
+        pub fn $name(args: Args) -> RuntimeResult<DayObject> {
             if args.len() == 2 {
                 return $othername(&args[0], &args[1])
             }
 
-            let mut result = args[0].clone();
+            let mut result = args
+                .first()
+                .ok_or_else(|| RuntimeError::invalid_args(stringify!($name), "no args"))?
+                .clone();
             for a in args.iter().skip(1) {
-                result = $othername(&result, a);
+                result = $othername(&result, a)?;
             }
 
-            result
+            Ok(result)
         }
     };
 }
 
-def_op!(add, add_two, +);
-def_op!(sub, sub_two, -);
-def_op!(mul, mul_two, *);
-def_op!(div, div_two, /);
-def_op!(modu, modu_two, %);
+/// Integer division and modulo by zero would abort the interpreter,
+/// so they are checked before the operation
+macro_rules! def_checked_op {
+    ($name: ident, $othername: ident, $op: tt, $checked: ident) => {
+        pub fn $othername(a: &DayObject, b: &DayObject) -> RuntimeResult<DayObject> {
+            match (a,b) {
+                (Integer(a),Integer(b)) => a.$checked(*b).map(Integer).ok_or_else(|| {
+                    RuntimeError::new(
+                        RuntimeErrorKind::Arithmetic,
+                        format!("{} of {} by {} is not defined", stringify!($name), a, b),
+                    )
+                }),
+                (Float(a),Float(b)) => Ok(Float(a $op b)),
+                (Float(a),Integer(b)) => Ok(Float(a $op *b as f64)),
+                (Integer(a),Float(b)) => Ok(Float(*a as f64 $op b)),
+                _ => Err(RuntimeError::type_error(format!(
+                    "{} can only be used with float and int, received {:?} and {:?}",
+                    stringify!($name), a, b
+                ))),
+            }
+        }
+
+        pub fn $name(args: Args) -> RuntimeResult<DayObject> {
+            if args.len() == 2 {
+                return $othername(&args[0], &args[1])
+            }
+
+            let mut result = args
+                .first()
+                .ok_or_else(|| RuntimeError::invalid_args(stringify!($name), "no args"))?
+                .clone();
+            for a in args.iter().skip(1) {
+                result = $othername(&result, a)?;
+            }
+
+            Ok(result)
+        }
+    };
+}
+
+def_op!(add, add_two, +, checked_add);
+def_op!(sub, sub_two, -, checked_sub);
+def_op!(mul, mul_two, *, checked_mul);
+def_checked_op!(div, div_two, /, checked_div);
+def_checked_op!(modu, modu_two, %, checked_rem);
+
+/// Negates a single number, used for the unary minus
+pub fn neg(args: Args) -> RuntimeResult<DayObject> {
+    match args {
+        [Integer(i)] => i.checked_neg().map(Integer).ok_or_else(|| {
+            RuntimeError::new(
+                RuntimeErrorKind::Arithmetic,
+                format!("neg of {} overflows", i),
+            )
+        }),
+        [Float(f)] => Ok(Float(-f)),
+        [other] => Err(RuntimeError::type_error(format!(
+            "neg can only be used with float and int, received {:?}",
+            other
+        ))),
+        _ => Err(RuntimeError::invalid_args(
+            "neg",
+            "expected exactly one arg",
+        )),
+    }
+}
 
 /*
 FIXME
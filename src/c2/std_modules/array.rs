@@ -1,52 +1,76 @@
-use crate::base::{Args, DayObject};
+use crate::{
+    base::{Args, DayObject},
+    runtime_error::{RuntimeError, RuntimeResult},
+};
 
-pub fn array(args: Args) -> DayObject {
-    DayObject::Array(args.to_vec())
+pub fn array(args: Args) -> RuntimeResult<DayObject> {
+    Ok(DayObject::Array(args.to_vec()))
 }
 
-pub fn len(args: Args) -> DayObject {
+pub fn len(args: Args) -> RuntimeResult<DayObject> {
     //NOTE later on things like this will be implemented with more variadic idioms
-    if args.len() == 0 {
-        panic!("Error invalid args no args")
-    }
-
-    if let DayObject::Array(arr) = &args[0] {
-        DayObject::Integer(arr.len() as i64)
-    } else {
-        panic!("Error invalid args non array args")
+    match args.first() {
+        Some(DayObject::Array(arr)) => Ok(DayObject::Integer(arr.len() as i64)),
+        Some(DayObject::Str(s)) => Ok(DayObject::Integer(s.chars().count() as i64)),
+        Some(DayObject::Map(map)) => Ok(DayObject::Integer(map.len() as i64)),
+        Some(other) => Err(RuntimeError::invalid_args(
+            "len",
+            format!("can't get the length of {:?}", other),
+        )),
+        None => Err(RuntimeError::invalid_args("len", "no args")),
     }
 }
 
 /// Slice into an array by args[0] = arr args[1] = lowerbound
 /// args[2] = upperbound
-pub fn slice(args: Args) -> DayObject {
-    if args.len() == 0 {
-        return DayObject::Array(vec![]);
+pub fn slice(args: Args) -> RuntimeResult<DayObject> {
+    if args.is_empty() {
+        return Ok(DayObject::Array(vec![]));
     }
 
-    match (args.get(0), args.get(1), args.get(2)) {
-        (Some(DayObject::Array(arr)), Some(DayObject::Integer(lower)), None) => {
-            DayObject::Array(arr[(*lower as usize)..].to_vec())
+    let (arr, lower, upper) = match args {
+        [DayObject::Array(arr), DayObject::Integer(lower)] => (arr, *lower, arr.len() as i64),
+        [DayObject::Array(arr), DayObject::Integer(lower), DayObject::Integer(upper)] => {
+            (arr, *lower, *upper)
         }
-        (
-            Some(DayObject::Array(arr)),
-            Some(DayObject::Integer(lower)),
-            Some(DayObject::Integer(upper)),
-        ) => DayObject::Array(arr[(*lower as usize)..(*upper as usize)].to_vec()),
-        _ => panic!("Arg error"),
+        _ => {
+            return Err(RuntimeError::invalid_args(
+                "slice",
+                "expected (array, lower: int, upper: int)",
+            ))
+        }
+    };
+
+    if lower < 0 || upper < lower || upper as usize > arr.len() {
+        return Err(RuntimeError::invalid_args(
+            "slice",
+            format!(
+                "{}..{} is out of bounds for an array of length {}",
+                lower,
+                upper,
+                arr.len()
+            ),
+        ));
     }
+
+    Ok(DayObject::Array(
+        arr[(lower as usize)..(upper as usize)].to_vec(),
+    ))
 }
 
 //Push needs ref for it to really make sense/to really mutate the content
-pub fn push(args: Args) -> DayObject {
-    if let DayObject::Array(arr) = &args[0] {
+pub fn push(args: Args) -> RuntimeResult<DayObject> {
+    if let Some(DayObject::Array(arr)) = args.first() {
         let mut arr = arr.clone();
         for e in args.iter().skip(1) {
             arr.push(e.clone())
         }
 
-        DayObject::Array(arr)
+        Ok(DayObject::Array(arr))
     } else {
-        panic!("Errorius")
+        Err(RuntimeError::invalid_args(
+            "push",
+            "expected an array as first arg",
+        ))
     }
 }
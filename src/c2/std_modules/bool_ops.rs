@@ -1,52 +1,52 @@
 use super::conversion::to_bool_inner;
-use crate::base::{Args, DayObject};
+use crate::{
+    base::{Args, DayObject},
+    runtime_error::RuntimeResult,
+};
 
-pub fn and(args: Args) -> DayObject {
+pub fn and(args: Args) -> RuntimeResult<DayObject> {
     let mut b = true;
 
     for a in args {
-        b = b & to_bool_inner(a);
+        b &= to_bool_inner(a)?;
         if !b {
             break;
         }
     }
 
-    DayObject::Bool(b)
+    Ok(DayObject::Bool(b))
 }
 
-pub fn or(args: Args) -> DayObject {
+pub fn or(args: Args) -> RuntimeResult<DayObject> {
     let mut b = false;
 
     for a in args {
-        b = b | to_bool_inner(a);
+        b |= to_bool_inner(a)?;
         if b {
             break;
         }
     }
 
-    DayObject::Bool(b)
+    Ok(DayObject::Bool(b))
 }
 
 ///Returns true if all args are falsy
-pub fn not(args: Args) -> DayObject {
-    let mut b = true;
-
+pub fn not(args: Args) -> RuntimeResult<DayObject> {
     for a in args {
-        b = b & to_bool_inner(a);
-        if b {
-            break;
+        if to_bool_inner(a)? {
+            return Ok(DayObject::Bool(false));
         }
     }
 
-    DayObject::Bool(b)
+    Ok(DayObject::Bool(true))
 }
 
-pub fn xor(args: Args) -> DayObject {
+pub fn xor(args: Args) -> RuntimeResult<DayObject> {
     let mut b = false;
 
     for a in args {
-        b = b ^ to_bool_inner(a);
+        b ^= to_bool_inner(a)?;
     }
 
-    DayObject::Bool(b)
+    Ok(DayObject::Bool(b))
 }
@@ -1,8 +1,11 @@
-use crate::base::{Args, DayObject};
+use crate::{
+    base::{Args, DayObject},
+    runtime_error::RuntimeResult,
+};
 
 macro_rules! cmp_fn {
     ($name: ident, $op: tt) => {
-        pub fn $name(args: Args) -> DayObject {
+        pub fn $name(args: Args) -> RuntimeResult<DayObject> {
             let mut b = true;
 
             for a in args.windows(2) {
@@ -12,7 +15,7 @@ macro_rules! cmp_fn {
                 }
             }
 
-            DayObject::Bool(b)
+            Ok(DayObject::Bool(b))
         }
     };
 }
@@ -36,7 +39,7 @@ mod cmp_tests {
     fn cmp_eq() {
         assert_eq!(
             eq(&[DayObject::Integer(10), DayObject::Float(10.0)]),
-            DayObject::Bool(true)
+            Ok(DayObject::Bool(true))
         )
     }
 
@@ -48,7 +51,7 @@ mod cmp_tests {
                 DayObject::Float(10.0),
                 DayObject::Integer(10)
             ]),
-            DayObject::Bool(true)
+            Ok(DayObject::Bool(true))
         )
     }
 
@@ -56,7 +59,7 @@ mod cmp_tests {
     fn cmp_neq() {
         assert_eq!(
             neq(&[DayObject::Integer(10), DayObject::Str("10".to_string())]),
-            DayObject::Bool(true)
+            Ok(DayObject::Bool(true))
         )
     }
 
@@ -67,7 +70,7 @@ mod cmp_tests {
                 DayObject::Str("B".to_string()),
                 DayObject::Str("A".to_string())
             ]),
-            DayObject::Bool(true)
+            Ok(DayObject::Bool(true))
         )
     }
 
@@ -75,7 +78,7 @@ mod cmp_tests {
     fn cmp_gt2() {
         assert_eq!(
             gt(&[DayObject::Integer(10), DayObject::Integer(10)]),
-            DayObject::Bool(false)
+            Ok(DayObject::Bool(false))
         )
     }
 
@@ -88,7 +91,7 @@ mod cmp_tests {
                 DayObject::Integer(9),
                 DayObject::Integer(9)
             ]),
-            DayObject::Bool(true)
+            Ok(DayObject::Bool(true))
         )
     }
 }
@@ -1,7 +1,11 @@
-use crate::base::{
-    Args,
-    DayObject::{self, *},
+use crate::{
+    base::{
+        Args,
+        DayObject::{self, *},
+    },
+    runtime_error::{RuntimeError, RuntimeErrorKind, RuntimeResult},
 };
+use std::convert::{TryFrom, TryInto};
 
 impl From<String> for DayObject {
     fn from(s: String) -> Self {
@@ -21,48 +25,55 @@ impl From<f64> for DayObject {
     }
 }
 
-impl Into<String> for &DayObject {
-    fn into(self) -> String {
-        to_string_inner(&self)
+impl From<&DayObject> for String {
+    fn from(obj: &DayObject) -> Self {
+        to_string_inner(obj)
     }
 }
 
-impl Into<i64> for &DayObject {
-    fn into(self) -> i64 {
-        match &self {
-            Integer(i) => *i,
-            Float(f) => *f as i64,
-            Str(s) => s
-                .trim()
-                .parse()
-                .unwrap_or_else(|_| panic!("Can't convert {:?} to int", self)),
-            _ => panic!("Can't convert {:?} to int", self),
+fn conversion_error(obj: &DayObject, target: &str) -> RuntimeError {
+    RuntimeError::new(
+        RuntimeErrorKind::Conversion,
+        format!("Can't convert {:?} to {}", obj, target),
+    )
+}
+
+impl TryFrom<&DayObject> for i64 {
+    type Error = RuntimeError;
+
+    fn try_from(obj: &DayObject) -> RuntimeResult<i64> {
+        match obj {
+            Integer(i) => Ok(*i),
+            Float(f) => Ok(*f as i64),
+            Str(s) => s.trim().parse().map_err(|_| conversion_error(obj, "int")),
+            _ => Err(conversion_error(obj, "int")),
         }
     }
 }
 
-impl Into<f64> for &DayObject {
-    fn into(self) -> f64 {
-        match &self {
-            Integer(i) => *i as f64,
-            Float(f) => *f,
-            Str(s) => s
-                .trim()
-                .parse()
-                .unwrap_or_else(|_| panic!("Can't convert {:?} to float", self)),
-            _ => panic!("Can't convert {:?} to float", self),
+impl TryFrom<&DayObject> for f64 {
+    type Error = RuntimeError;
+
+    fn try_from(obj: &DayObject) -> RuntimeResult<f64> {
+        match obj {
+            Integer(i) => Ok(*i as f64),
+            Float(f) => Ok(*f),
+            Str(s) => s.trim().parse().map_err(|_| conversion_error(obj, "float")),
+            _ => Err(conversion_error(obj, "float")),
         }
     }
 }
 
-impl Into<bool> for &DayObject {
-    fn into(self) -> bool {
-        match &self {
-            Integer(i) => *i != 0,
-            Float(f) => *f != 0.0,
-            Str(s) => !s.is_empty(),
-            Bool(b) => *b,
-            _ => panic!("Can't convert {:?} to bool", self),
+impl TryFrom<&DayObject> for bool {
+    type Error = RuntimeError;
+
+    fn try_from(obj: &DayObject) -> RuntimeResult<bool> {
+        match obj {
+            Integer(i) => Ok(*i != 0),
+            Float(f) => Ok(*f != 0.0),
+            Str(s) => Ok(!s.is_empty()),
+            Bool(b) => Ok(*b),
+            _ => Err(conversion_error(obj, "bool")),
         }
     }
 }
@@ -76,49 +87,44 @@ pub(crate) fn to_string_inner(obj: &DayObject) -> String {
         DayObject::None => "none".to_string(),
         DayObject::Float(f) => f.to_string(),
         DayObject::Array(arr) => format!("{:?}", arr),
+        DayObject::Error(e) => format!("{}: {}", e.kind(), e.message()),
         val => format!("{:?}", val),
     }
 }
 
-pub fn to_string(args: Args) -> DayObject {
-    if args.len() != 1 {
-        eprintln!("to_string expected 1 argument received: {}", args.len());
-        std::process::exit(1);
+/// Checks that the conversion function `fname` received exactly one argument
+fn single_arg<'a>(fname: &str, args: Args<'a>) -> RuntimeResult<&'a DayObject> {
+    match args {
+        [arg] => Ok(arg),
+        _ => Err(RuntimeError::invalid_args(
+            fname,
+            format!("expected 1 argument received: {}", args.len()),
+        )),
     }
-
-    DayObject::Str(to_string_inner(&args[0]))
 }
 
-pub fn to_int_inner(arg: &DayObject) -> i64 {
-    arg.into()
+pub fn to_string(args: Args) -> RuntimeResult<DayObject> {
+    Ok(DayObject::Str(to_string_inner(single_arg("string", args)?)))
 }
 
-pub fn to_int(args: Args) -> DayObject {
-    if args.len() != 1 {
-        panic!("to_int expects exactly one argument")
-    }
-
-    DayObject::Integer(to_int_inner(&args[0]))
+pub fn to_int_inner(arg: &DayObject) -> RuntimeResult<i64> {
+    arg.try_into()
 }
 
-pub fn to_float(args: Args) -> DayObject {
-    if args.len() != 1 {
-        panic!("to_float expects exactly one argument")
-    }
-
-    DayObject::Float((&args[0]).into())
+pub fn to_int(args: Args) -> RuntimeResult<DayObject> {
+    Ok(DayObject::Integer(to_int_inner(single_arg("int", args)?)?))
 }
 
-pub fn to_bool(args: Args) -> DayObject {
-    if args.len() != 1 {
-        panic!("to_bool expects exactly one argument")
-    }
+pub fn to_float(args: Args) -> RuntimeResult<DayObject> {
+    Ok(DayObject::Float(single_arg("float", args)?.try_into()?))
+}
 
-    DayObject::Bool(to_bool_inner(&args[0]))
+pub fn to_bool(args: Args) -> RuntimeResult<DayObject> {
+    Ok(DayObject::Bool(to_bool_inner(single_arg("bool", args)?)?))
 }
 
-pub fn to_arr(args: Args) -> DayObject {
-    DayObject::Array(to_arr_inner(args))
+pub fn to_arr(args: Args) -> RuntimeResult<DayObject> {
+    Ok(DayObject::Array(to_arr_inner(args)))
 }
 
 pub fn single_value_to_arr(val: &DayObject) -> Vec<DayObject> {
@@ -137,8 +143,8 @@ pub fn to_arr_inner(args: Args) -> Vec<DayObject> {
     }
 }
 
-pub fn to_bool_inner(arg: &DayObject) -> bool {
-    arg.into()
+pub fn to_bool_inner(arg: &DayObject) -> RuntimeResult<bool> {
+    arg.try_into()
 }
 
 #[cfg(test)]
@@ -147,14 +153,14 @@ mod test {
 
     #[test]
     fn conversion_itos() {
-        assert_eq!(to_string(&[Integer(10)]), Str("10".to_string()))
+        assert_eq!(to_string(&[Integer(10)]), Ok(Str("10".to_string())))
     }
 
     #[test]
     fn conversion_ftos() {
         assert_eq!(
             to_string(&[Float(10.3333456)]),
-            Str("10.3333456".to_string())
+            Ok(Str("10.3333456".to_string()))
         )
     }
 }
@@ -1,17 +1,49 @@
-use crate::base::{Args, DayObject};
-use std::env::args as argsv;
+use crate::{
+    base::{Args, DayObject},
+    runtime_error::{RuntimeError, RuntimeResult},
+};
+use std::{cell::RefCell, convert::TryFrom, sync::Arc};
 
-pub fn argv(args: Args) -> DayObject {
-    //All those args.remove should be better wrapped with something more safe (or i we use VecDeque)
-    if args.len() == 0 {
-        return DayObject::Array(argsv().map(DayObject::Str).collect());
-    } else if let DayObject::Integer(i) = args[0] {
-        DayObject::Str(
-            argsv()
-                .nth(i as usize)
-                .expect("There should be an error here"),
-        )
+thread_local! {
+    /// The args of the script running on this thread
+    static ARGV: RefCell<Arc<[String]>> = RefCell::new(Arc::from([]));
+}
+
+/// Restores the args of the enclosing run when dropped
+pub struct ArgvGuard {
+    previous: Arc<[String]>,
+}
+
+impl Drop for ArgvGuard {
+    fn drop(&mut self) {
+        let previous = std::mem::replace(&mut self.previous, Arc::from([]));
+        ARGV.with(|argv| *argv.borrow_mut() = previous);
+    }
+}
+
+/// Makes `argv` of the code running on this thread return `argv` until the guard is dropped
+pub fn install(argv: &Arc<[String]>) -> ArgvGuard {
+    ArgvGuard {
+        previous: ARGV.with(|current| current.replace(Arc::clone(argv))),
+    }
+}
+
+/// The args the host passed to the script, the command line interpreter passes the path
+/// of the script followed by its args. Unlike the args of the process they don't start
+/// with the interpreter, so the script path is at index 0 and its first arg at index 1.
+pub fn argv(args: Args) -> RuntimeResult<DayObject> {
+    let argv = ARGV.with(|argv| Arc::clone(&argv.borrow()));
+    if let Some(DayObject::Integer(i)) = args.first() {
+        usize::try_from(*i)
+            .ok()
+            .and_then(|i| argv.get(i))
+            .map(|arg| DayObject::Str(arg.clone()))
+            .ok_or_else(|| {
+                RuntimeError::invalid_args("argv", format!("there is no argument {}", i))
+            })
     } else {
-        return DayObject::Array(argsv().map(DayObject::Str).collect());
+        Ok(DayObject::Array(
+            argv.iter().cloned().map(DayObject::Str).collect(),
+        ))
     }
 }
@@ -0,0 +1,66 @@
+use super::conversion::to_string_inner;
+use crate::{
+    base::{Args, DayObject},
+    runtime_error::{RuntimeError, RuntimeErrorKind, RuntimeResult},
+};
+
+/// Creates an error value with args[0] as message without raising it
+pub fn error(args: Args) -> RuntimeResult<DayObject> {
+    match args {
+        [msg] => Ok(DayObject::Error(Box::new(RuntimeError::new(
+            RuntimeErrorKind::Custom,
+            to_string_inner(msg),
+        )))),
+        _ => Err(RuntimeError::invalid_args(
+            "error",
+            format!("expected 1 argument (message) received: {}", args.len()),
+        )),
+    }
+}
+
+pub fn is_error(args: Args) -> RuntimeResult<DayObject> {
+    match args {
+        [arg] => Ok(DayObject::Bool(matches!(arg, DayObject::Error(_)))),
+        _ => Err(RuntimeError::invalid_args(
+            "is_error",
+            format!("expected 1 argument received: {}", args.len()),
+        )),
+    }
+}
+
+/// Fails with args[0] if it is an error value, any other value
+/// is raised as the message of a new error
+pub fn raise(args: Args) -> RuntimeResult<DayObject> {
+    match args {
+        [DayObject::Error(e)] => Err((**e).clone()),
+        [msg] => Err(RuntimeError::new(
+            RuntimeErrorKind::Custom,
+            to_string_inner(msg),
+        )),
+        _ => Err(RuntimeError::invalid_args(
+            "raise",
+            format!("expected 1 argument received: {}", args.len()),
+        )),
+    }
+}
+
+/// Returns the name of the kind of an error value as string
+pub fn error_kind(args: Args) -> RuntimeResult<DayObject> {
+    match args {
+        [DayObject::Error(e)] => Ok(DayObject::Str(e.kind().to_string())),
+        _ => Err(RuntimeError::invalid_args(
+            "error_kind",
+            "expected an error value",
+        )),
+    }
+}
+
+pub fn error_message(args: Args) -> RuntimeResult<DayObject> {
+    match args {
+        [DayObject::Error(e)] => Ok(DayObject::Str(e.message().to_string())),
+        _ => Err(RuntimeError::invalid_args(
+            "error_message",
+            "expected an error value",
+        )),
+    }
+}
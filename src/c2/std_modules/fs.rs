@@ -1,67 +1,101 @@
-use crate::base::{Args, DayObject};
+use crate::{
+    base::{Args, DayObject},
+    runtime_error::{RuntimeError, RuntimeResult},
+};
 use std::fs as fio;
 use DayObject::*;
 
-pub fn cat(args: Args) -> DayObject {
+pub fn cat(args: Args) -> RuntimeResult<DayObject> {
     let mut cated = String::new();
     for p in args {
         match p {
             Str(s) => {
-                cated.push_str(&fio::read_to_string(s).expect("error reading file"));
+                let content = fio::read_to_string(s)
+                    .map_err(|e| RuntimeError::io(format!("cat: can't read {}: {}", s, e)))?;
+                cated.push_str(&content);
+            }
+            _ => {
+                return Err(RuntimeError::invalid_args(
+                    "cat",
+                    "expects only strings as file path",
+                ))
             }
-            _ => panic!("cat expects only strings as file path"),
         }
     }
 
-    Str(cated)
+    Ok(Str(cated))
 }
 
-pub fn touch(args: Args) -> DayObject {
+pub fn touch(args: Args) -> RuntimeResult<DayObject> {
     for p in args {
         match p {
             Str(s) => {
-                fio::File::create(s).expect("error reading file");
+                fio::File::create(s)
+                    .map_err(|e| RuntimeError::io(format!("touch: can't create {}: {}", s, e)))?;
+            }
+            _ => {
+                return Err(RuntimeError::invalid_args(
+                    "touch",
+                    "expects only strings as file path",
+                ))
             }
-            _ => panic!("touch expects only strings as file path"),
         }
     }
 
-    DayObject::None
+    Ok(DayObject::None)
 }
 
-pub fn rm(args: Args) -> DayObject {
+pub fn rm(args: Args) -> RuntimeResult<DayObject> {
     for p in args {
         match p {
             Str(s) => {
-                fio::remove_file(s).expect("error reading file");
+                fio::remove_file(s)
+                    .map_err(|e| RuntimeError::io(format!("rm: can't remove {}: {}", s, e)))?;
+            }
+            _ => {
+                return Err(RuntimeError::invalid_args(
+                    "rm",
+                    "expects only strings as file path",
+                ))
             }
-            _ => panic!("rm expects only strings as file path"),
         }
     }
 
-    DayObject::None
+    Ok(DayObject::None)
 }
 
-pub fn mv(args: Args) -> DayObject {
-    match &args[..] {
-        [Str(from), Str(to)] => fio::rename(from, to).expect("Can't rename file"),
-        _ => panic!(
-            "mv expects 2 arguments (from: string, to: string) received {:?}",
-            args
-        ),
+pub fn mv(args: Args) -> RuntimeResult<DayObject> {
+    match args {
+        [Str(from), Str(to)] => fio::rename(from, to)
+            .map_err(|e| RuntimeError::io(format!("mv: can't move {} to {}: {}", from, to, e)))?,
+        _ => {
+            return Err(RuntimeError::invalid_args(
+                "mv",
+                format!(
+                    "expects 2 arguments (from: string, to: string) received {:?}",
+                    args
+                ),
+            ))
+        }
     }
 
-    DayObject::None
+    Ok(DayObject::None)
 }
 
-pub fn fwrite(args: Args) -> DayObject {
-    match &args[..] {
-        [Str(path), Str(content)] => fio::write(path, content).expect("Can't rename file"),
-        _ => panic!(
-            "fwrite expects 2 arguments (path: string, content: string) received {:?}",
-            args
-        ),
+pub fn fwrite(args: Args) -> RuntimeResult<DayObject> {
+    match args {
+        [Str(path), Str(content)] => fio::write(path, content)
+            .map_err(|e| RuntimeError::io(format!("fwrite: can't write {}: {}", path, e)))?,
+        _ => {
+            return Err(RuntimeError::invalid_args(
+                "fwrite",
+                format!(
+                    "expects 2 arguments (path: string, content: string) received {:?}",
+                    args
+                ),
+            ))
+        }
     }
 
-    DayObject::None
+    Ok(DayObject::None)
 }
@@ -1,52 +1,56 @@
-use crate::base::{Args, DayFunction, DayObject};
+use crate::{
+    base::{Args, DayFunction, DayObject},
+    runtime_error::{RuntimeError, RuntimeResult},
+};
 
-pub fn noop(_args: Args) -> DayObject {
-    DayObject::None
+pub fn noop(_args: Args) -> RuntimeResult<DayObject> {
+    Ok(DayObject::None)
 }
 
-pub fn call(args: Args) -> DayObject {
-    match (&args[0], &args[0]) {
-        (DayObject::Function(fun), DayObject::Array(arr)) => fun.call(arr),
-        _ => panic!("call error"),
+pub fn call(args: Args) -> RuntimeResult<DayObject> {
+    match args {
+        [DayObject::Function(fun), DayObject::Array(arr)] => fun.call(arr),
+        _ => Err(RuntimeError::invalid_args(
+            "call",
+            "expected a function and an array of args",
+        )),
     }
 }
 
-pub fn apply(args: Args) -> DayObject {
-    DayObject::Function(if let DayObject::Function(fun) = args[0].clone() {
-        if args.len() == 1 {
-            let arg = &args[0];
-            if let DayObject::Array(arr) = arg {
-                DayFunction::Applicator(Box::new(fun), arr.to_vec())
-            } else {
-                DayFunction::Applicator(Box::new(fun), vec![arg.clone()])
-            }
-        } else {
-            DayFunction::Applicator(Box::new(fun), args.to_vec())
-        }
-    } else {
-        panic!("apply error")
-    })
+pub fn apply(args: Args) -> RuntimeResult<DayObject> {
+    match args {
+        [DayObject::Function(fun), DayObject::Array(arr)] => Ok(DayObject::Function(
+            DayFunction::Applicator(Box::new(fun.clone()), arr.to_vec()),
+        )),
+        [DayObject::Function(fun), rest @ ..] => Ok(DayObject::Function(DayFunction::Applicator(
+            Box::new(fun.clone()),
+            rest.to_vec(),
+        ))),
+        _ => Err(RuntimeError::invalid_args(
+            "apply",
+            "expected a function as first arg",
+        )),
+    }
 }
 
-pub fn chain(_args: Args) -> DayObject {
-    todo!("Reimplement this with the chain call function")
-    /*     if let Some(DayObject::Array(initial_args)) = args.last() {
-        //TODO rethink this len of a at the end is always 1
-        //NOTE i don't know if new_scope is correct
-        if args.len() == 0 {
-            panic!("Return error here")
-        } else {
-            let mut a = args[0].call(initial_args);
-            for f in args {
-                a = f.call(&[a]);
+/// Calls the first function with the args in the array that is the last arg
+/// and every following function with the result of the previous one
+pub fn chain(args: Args) -> RuntimeResult<DayObject> {
+    //NOTE Maybe the order of the args in this fn should change
+    match args {
+        [first, funs @ .., DayObject::Array(initial_args)] => {
+            let mut a = first.call(initial_args)?;
+            for f in funs {
+                a = f.call(&[a])?;
             }
 
-            a
+            Ok(a)
         }
-    } else {
-        //NOTE Maybe the order of the args in this fn should change
-        panic!("Expected array as last arg to chain")
-    } */
+        _ => Err(RuntimeError::invalid_args(
+            "chain",
+            "expected functions followed by an array of initial args",
+        )),
+    }
 }
 
 /* pub fn chained(args: Args) -> DayObject {
@@ -62,42 +66,49 @@ pub fn chain(_args: Args) -> DayObject {
     })))
 }*/
 
-pub fn do_times(args: Args) -> DayObject {
-    let times = expect!(args[0] => DayObject::Integer | "Expected int as first arg in do");
-    let fun = expect!(&args[1] => DayObject::Function | "Expected function as second arg in do");
+/// Returns the times and the function do and repeat were called with
+fn times_and_fun<'a>(fname: &str, args: Args<'a>) -> RuntimeResult<(i64, &'a DayFunction)> {
+    match args {
+        [DayObject::Integer(times), DayObject::Function(fun), ..] => Ok((*times, fun)),
+        _ => Err(RuntimeError::invalid_args(
+            fname,
+            "expected int as first and function as second arg",
+        )),
+    }
+}
 
-    let mut results = Vec::with_capacity(times as usize);
+pub fn do_times(args: Args) -> RuntimeResult<DayObject> {
+    let (times, fun) = times_and_fun("do", args)?;
+
+    let mut results = Vec::with_capacity(times.max(0) as usize);
 
     if let Some(DayObject::Array(fun_args)) = args.get(2) {
         for _ in 0..times {
-            results.push(fun.call(fun_args));
+            results.push(fun.call(fun_args)?);
         }
     } else {
         for _ in 0..times {
-            results.push(fun.call(&[]));
+            results.push(fun.call(&[])?);
         }
     }
 
-    DayObject::Array(results)
+    Ok(DayObject::Array(results))
 }
 
-pub fn repeat(args: Args) -> DayObject {
-    let times = expect!(args[0] => DayObject::Integer | "Expected int as first arg in do");
-    let fun = expect!(&args[1] => DayObject::Function | "Expected function as second arg in do");
+pub fn repeat(args: Args) -> RuntimeResult<DayObject> {
+    let (times, fun) = times_and_fun("repeat", args)?;
 
-    if let Some(DayObject::Array(fun_args)) = args.get(2) {
-        for _ in 0..(times - 1) {
-            fun.call(fun_args);
-        }
+    let fun_args = match args.get(2) {
+        Some(DayObject::Array(fun_args)) => &fun_args[..],
+        _ => &[],
+    };
 
-        fun.call(fun_args)
-    } else {
-        for _ in 0..(times - 1) {
-            fun.call(&[]);
-        }
-
-        fun.call(&[])
+    let mut result = DayObject::None;
+    for _ in 0..times {
+        result = fun.call(fun_args)?;
     }
+
+    Ok(result)
 }
 
 #[cfg(test)]
@@ -1,52 +1,73 @@
 use super::conversion::to_string_inner;
-use crate::base::{Args, DayObject};
-use std::io::Read;
+use crate::{
+    base::{Args, DayObject},
+    runtime_error::{RuntimeError, RuntimeResult},
+    stdio::{read_input, write_error, write_output},
+};
+
+/// Writes every arg, if `lines` is set each on its own line
+fn write_args(
+    args: Args,
+    lines: bool,
+    write: fn(&str) -> RuntimeResult<()>,
+) -> RuntimeResult<DayObject> {
+    if args.is_empty() && lines {
+        write("\n")?;
+    }
 
-pub fn print(args: Args) -> DayObject {
     for a in args {
-        //dbg_print!(&a);
-        print!("{}", to_string_inner(&a))
+        let mut text = to_string_inner(a);
+        if lines {
+            text.push('\n');
+        }
+        write(&text)?;
     }
 
-    DayObject::None
+    Ok(DayObject::None)
 }
 
-pub fn println(args: Args) -> DayObject {
-    if args.len() == 0 {
-        println!("");
-        return DayObject::None;
-    }
+pub fn print(args: Args) -> RuntimeResult<DayObject> {
+    write_args(args, false, write_output)
+}
 
-    for a in args {
-        println!("{}", to_string_inner(&a))
-    }
+pub fn println(args: Args) -> RuntimeResult<DayObject> {
+    write_args(args, true, write_output)
+}
 
-    DayObject::None
+pub fn eprint(args: Args) -> RuntimeResult<DayObject> {
+    write_args(args, false, write_error)
 }
 
-pub fn readln(args: Args) -> DayObject {
+pub fn eprintln(args: Args) -> RuntimeResult<DayObject> {
+    write_args(args, true, write_error)
+}
+
+pub fn readln(args: Args) -> RuntimeResult<DayObject> {
     if !args.is_empty() {
-        eprintln!("read expected 0 argument(s) received: {}", args.len());
+        return Err(RuntimeError::invalid_args(
+            "readln",
+            format!("expected 0 argument(s) received: {}", args.len()),
+        ));
     }
 
     let mut s = String::new();
-    std::io::stdin()
-        .read_line(&mut s)
-        .expect("Unable to read from stding");
+    read_input(|input| input.read_line(&mut s))
+        .map_err(|e| RuntimeError::io(format!("Unable to read from stdin: {}", e)))?;
 
-    DayObject::Str(s)
+    Ok(DayObject::Str(s))
 }
 
-pub fn read(args: Args) -> DayObject {
+pub fn read(args: Args) -> RuntimeResult<DayObject> {
     if !args.is_empty() {
-        eprintln!("read expected 0 argument(s) received: {}", args.len());
+        return Err(RuntimeError::invalid_args(
+            "read",
+            format!("expected 0 argument(s) received: {}", args.len()),
+        ));
     }
 
-    DayObject::Character(
-        std::io::stdin()
-            .bytes()
-            .next()
-            .expect("Can't read from stdin")
-            .expect("Can't read a byte from stdin") as char,
-    )
+    let mut byte = [0u8];
+    read_input(|input| input.read_exact(&mut byte))
+        .map_err(|e| RuntimeError::io(format!("Can't read a byte from stdin: {}", e)))?;
+
+    Ok(DayObject::Character(byte[0] as char))
 }
@@ -1,84 +1,96 @@
 use crate::{
     base::{Args, DayFunction, DayObject, IterHandle},
-    std_modules::conversion::{single_value_to_arr, to_arr_inner},
+    runtime_error::{RuntimeError, RuntimeResult},
+    std_modules::{
+        conversion::{single_value_to_arr, to_arr_inner},
+        map::entries_inner,
+    },
 };
 
 pub use crate::iter::arr_iter::arr_iter;
 pub use crate::iter::map::map;
 pub use crate::iter::range::range;
 
-pub fn to_iter_inner(arg: &DayObject) -> IterHandle {
+pub fn to_iter_inner(arg: &DayObject) -> RuntimeResult<IterHandle> {
     match arg {
-        DayObject::Array(arr) => IterHandle::new(Box::new(arr_iter(arr.to_vec()))),
-        DayObject::Iter(it) => it.clone(),
-        v => panic!("can't convert {:?} to iter", v),
+        DayObject::Array(arr) => Ok(IterHandle::new(Box::new(arr_iter(arr.to_vec())))),
+        DayObject::Iter(it) => Ok(it.clone()),
+        // Maps are iterated as arrays of key and value
+        DayObject::Map(map) => Ok(IterHandle::new(Box::new(arr_iter(entries_inner(map))))),
+        v => Err(RuntimeError::type_error(format!(
+            "can't convert {:?} to iter",
+            v
+        ))),
     }
 }
 
-pub fn foreach_inner(mut iter: IterHandle, fun: &DayFunction, args: Args) -> DayObject {
+pub fn foreach_inner(
+    mut iter: IterHandle,
+    fun: &DayFunction,
+    args: Args,
+) -> RuntimeResult<DayObject> {
     let mut arr = to_arr_inner(args);
     arr.insert(0, DayObject::None);
-    while let Some(n) = iter.0.next() {
+    while let Some(n) = iter.0.next()? {
         arr[0] = n;
-        fun.call(&arr);
+        fun.call(&arr)?;
     }
 
-    DayObject::None
+    Ok(DayObject::None)
 }
 
-pub fn foreach(args: Args) -> DayObject {
-    match (&args[0], &args[1]) {
-        (DayObject::Iter(iter), DayObject::Function(fun)) => {
-            foreach_inner(iter.clone(), fun, &single_value_to_arr(&args[2]))
+pub fn foreach(args: Args) -> RuntimeResult<DayObject> {
+    let extra = args.get(2).map(single_value_to_arr).unwrap_or_default();
+    match (args.first(), args.get(1)) {
+        (Some(DayObject::Function(fun)), Some(iterable))
+        | (Some(iterable), Some(DayObject::Function(fun))) => {
+            foreach_inner(to_iter_inner(iterable)?, fun, &extra)
         }
-        (DayObject::Function(fun), DayObject::Iter(iter)) => {
-            foreach_inner(iter.clone(), fun, &single_value_to_arr(&args[2]))
-        }
-        _ => panic!("Invalid argument for foreach"),
+        _ => Err(RuntimeError::invalid_args(
+            "foreach",
+            "expected an iter and a function",
+        )),
     }
 }
 
-pub fn iter(args: Args) -> DayObject {
-    DayObject::Iter(to_iter_inner(&args[0]))
+/// Returns the first arg or an `InvalidArguments` error for `fname`
+fn first_arg<'a>(fname: &str, args: Args<'a>) -> RuntimeResult<&'a DayObject> {
+    args.first()
+        .ok_or_else(|| RuntimeError::invalid_args(fname, "no args"))
 }
 
-pub fn rewind(args: Args) -> DayObject {
-    match &args[0] {
-        DayObject::Iter(it) => {
-            let it = IterHandle(it.0.rewound().expect("Error"));
-            DayObject::Iter(it)
-        }
-        v => panic!("can't rewind {:?}", v),
-    }
+pub fn iter(args: Args) -> RuntimeResult<DayObject> {
+    Ok(DayObject::Iter(to_iter_inner(first_arg("iter", args)?)?))
 }
 
-pub fn reverse(args: Args) -> DayObject {
-    match &args[0] {
-        DayObject::Iter(it) => {
-            let it = IterHandle(it.0.reversed().expect("Error"));
-            DayObject::Iter(it)
-        }
-        v => panic!("can't reverse {:?}", v),
-    }
+pub fn rewind(args: Args) -> RuntimeResult<DayObject> {
+    let it = to_iter_inner(first_arg("rewind", args)?)?;
+    it.0.rewound()
+        .map(|it| DayObject::Iter(IterHandle(it)))
+        .ok_or_else(|| RuntimeError::type_error("this iter can't be rewound"))
 }
 
-pub fn collect(args: Args) -> DayObject {
-    match &args[0] {
-        DayObject::Iter(it) => collect_inner(it.clone()),
-        _ => panic!("collect needs iter"),
-    }
+pub fn reverse(args: Args) -> RuntimeResult<DayObject> {
+    let it = to_iter_inner(first_arg("reverse", args)?)?;
+    it.0.reversed()
+        .map(|it| DayObject::Iter(IterHandle(it)))
+        .ok_or_else(|| RuntimeError::type_error("this iter can't be reversed"))
+}
+
+pub fn collect(args: Args) -> RuntimeResult<DayObject> {
+    collect_inner(to_iter_inner(first_arg("collect", args)?)?)
 }
 
-pub fn collect_inner(mut iter: IterHandle) -> DayObject {
+pub fn collect_inner(mut iter: IterHandle) -> RuntimeResult<DayObject> {
     let mut arr = if let Some(len) = iter.0.remaining() {
         Vec::with_capacity(len)
     } else {
         vec![]
     };
 
-    while let Some(data) = iter.0.next() {
+    while let Some(data) = iter.0.next()? {
         arr.push(data);
     }
 
-    DayObject::Array(arr)
+    Ok(DayObject::Array(arr))
 }
@@ -1,11 +1,75 @@
 use crate::{
     base::{Args, DayObject},
+    day_map::DayMap,
+    runtime_error::{RuntimeError, RuntimeResult},
 };
 
-pub fn map(args: Args) -> DayObject {
-    
+/// Creates a map out of alternating keys and values: `dict(k1, v1, k2, v2)`
+pub fn dict(args: Args) -> RuntimeResult<DayObject> {
+    if !args.len().is_multiple_of(2) {
+        return Err(RuntimeError::invalid_args(
+            "dict",
+            "expected pairs of keys and values",
+        ));
+    }
+
+    let mut map = DayMap::with_capacity(args.len() / 2);
+    for pair in args.chunks(2) {
+        map.insert(pair[0].clone(), pair[1].clone())?;
+    }
+
+    Ok(DayObject::Map(map))
+}
+
+/// Returns the map of the first arg or an `InvalidArguments` error for `fname`
+fn map_arg<'a>(fname: &str, args: Args<'a>) -> RuntimeResult<&'a DayMap> {
+    match args.first() {
+        Some(DayObject::Map(map)) => Ok(map),
+        _ => Err(RuntimeError::invalid_args(
+            fname,
+            "expected a map as first arg",
+        )),
+    }
+}
+
+pub fn keys(args: Args) -> RuntimeResult<DayObject> {
+    Ok(DayObject::Array(
+        map_arg("keys", args)?.keys().cloned().collect(),
+    ))
+}
+
+pub fn values(args: Args) -> RuntimeResult<DayObject> {
+    Ok(DayObject::Array(
+        map_arg("values", args)?.values().cloned().collect(),
+    ))
 }
 
-pub fn map_insert(args: Args) -> DayObject {
-    
+/// Returns all entries as arrays of key and value
+pub fn entries(args: Args) -> RuntimeResult<DayObject> {
+    Ok(DayObject::Array(entries_inner(map_arg("entries", args)?)))
+}
+
+pub fn entries_inner(map: &DayMap) -> Vec<DayObject> {
+    map.iter()
+        .map(|(k, v)| DayObject::Array(vec![k.clone(), v.clone()]))
+        .collect()
+}
+
+/// Returns true if the map contains all of the given keys
+pub fn has(args: Args) -> RuntimeResult<DayObject> {
+    let map = map_arg("has", args)?;
+    Ok(DayObject::Bool(
+        args.iter().skip(1).all(|k| map.contains_key(k)),
+    ))
+}
+
+//Like push this returns the changed map, as there are no references yet
+/// Returns the map without the given keys
+pub fn remove(args: Args) -> RuntimeResult<DayObject> {
+    let mut map = map_arg("remove", args)?.clone();
+    for k in args.iter().skip(1) {
+        map.remove(k);
+    }
+
+    Ok(DayObject::Map(map))
 }
@@ -4,9 +4,11 @@ pub mod bool_ops;
 pub mod comparison;
 pub mod conversion;
 pub mod env;
+pub mod error;
 pub mod fs;
 pub mod functional;
 pub mod io;
 pub mod iter;
+pub mod map;
 pub mod panic;
 pub mod thread;
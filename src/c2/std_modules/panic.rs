@@ -1,30 +1,40 @@
 use super::conversion::to_bool_inner;
-use crate::base::{Args, DayObject};
+use crate::{
+    base::{Args, DayObject},
+    runtime_error::{RuntimeError, RuntimeErrorKind, RuntimeResult},
+};
 
-/// This is just used for easy errors that propagated through the rust system
-/// mainly used for debug purposes
-pub fn panic(args: Args) -> DayObject {
-    if args.len() == 0 {
-        panic!("")
-    } else if args.len() != 1 {
-        panic!("{:?}", args)
-    } else {
-        panic!("{:?}", args[0])
-    }
+/// Stops the script with a `Panic` error containing the args
+pub fn panic(args: Args) -> RuntimeResult<DayObject> {
+    let message = match args {
+        [] => String::new(),
+        [arg] => format!("{:?}", arg),
+        args => format!("{:?}", args),
+    };
+
+    Err(RuntimeError::new(RuntimeErrorKind::Panic, message))
 }
 
 //TODO Improve output of those boys
 
-/// This is just used for easy errors that propagated through the rust system
-/// mainly used for debug purposes
-pub fn assert(args: Args) -> DayObject {
-    if args.len() > 2 {
-        assert!(to_bool_inner(&args[0]), format!("{:?}", args));
-    } else if args.len() == 2 {
-        assert!(to_bool_inner(&args[0]), format!("{:?}", &args[2]));
-    } else {
-        assert!(to_bool_inner(&args[0]));
+/// Stops the script with an `AssertionFailed` error if args[0] is falsy,
+/// the remaining args are used as message
+pub fn assert(args: Args) -> RuntimeResult<DayObject> {
+    let (cond, rest) = args
+        .split_first()
+        .ok_or_else(|| RuntimeError::invalid_args("assert", "no args"))?;
+
+    if !to_bool_inner(cond)? {
+        let message = match rest {
+            [] => "assertion failed".to_string(),
+            [msg] => format!("{:?}", msg),
+            rest => format!("{:?}", rest),
+        };
+        return Err(RuntimeError::new(
+            RuntimeErrorKind::AssertionFailed,
+            message,
+        ));
     }
 
-    DayObject::None
+    Ok(DayObject::None)
 }
@@ -1,4 +1,7 @@
-use crate::base::{Args, DayObject};
+use crate::{
+    base::{Args, DayObject},
+    runtime_error::{RuntimeError, RuntimeResult},
+};
 use std::{
     //sync::Arc,
     thread,
@@ -6,11 +9,18 @@ use std::{
 };
 
 //NOTE This is only a prototype the inner workings will change
-pub fn sleep(args: Args) -> DayObject {
-    if let DayObject::Integer(i) = args[0] {
-        thread::sleep(Duration::from_millis(i as u64));
+pub fn sleep(args: Args) -> RuntimeResult<DayObject> {
+    match args.first() {
+        Some(DayObject::Integer(i)) if *i >= 0 => thread::sleep(Duration::from_millis(*i as u64)),
+        _ => {
+            return Err(RuntimeError::invalid_args(
+                "sleep",
+                "expects a positive number of milliseconds",
+            ))
+        }
     }
-    DayObject::None
+
+    Ok(DayObject::None)
 }
 
 /* pub fn spawn(mut args: Args) -> DayObject {
@@ -0,0 +1,150 @@
+use crate::runtime_error::{RuntimeError, RuntimeResult};
+use std::{
+    cell::RefCell,
+    io::{self, BufRead, Write},
+    rc::Rc,
+};
+
+type SharedInput = Rc<RefCell<dyn BufRead>>;
+type SharedOutput = Rc<RefCell<dyn Write>>;
+
+/// The streams the io functions of scripts read from and write to,
+/// every stream that isn't set is the one of the process
+#[derive(Clone, Default)]
+pub struct Stdio {
+    input: Option<SharedInput>,
+    output: Option<SharedOutput>,
+    error: Option<SharedOutput>,
+}
+
+impl Stdio {
+    /// The stdio of the process
+    pub fn inherit() -> Self {
+        Self::default()
+    }
+
+    /// Sets the stream of `read` and `readln`
+    pub fn input(mut self, input: impl BufRead + 'static) -> Self {
+        self.input = Some(Rc::new(RefCell::new(input)));
+        self
+    }
+
+    /// Sets the stream of `print` and `println`
+    pub fn output(mut self, output: impl Write + 'static) -> Self {
+        self.output = Some(Rc::new(RefCell::new(output)));
+        self
+    }
+
+    /// Sets the stream of `eprint` and `eprintln`
+    pub fn error(mut self, error: impl Write + 'static) -> Self {
+        self.error = Some(Rc::new(RefCell::new(error)));
+        self
+    }
+}
+
+/// A buffer collecting everything written to it, clones share the buffer
+#[derive(Clone, Default)]
+pub struct Capture(Rc<RefCell<Vec<u8>>>);
+
+impl Capture {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Everything written so far, invalid utf-8 is replaced
+    pub fn contents(&self) -> String {
+        String::from_utf8_lossy(&self.0.borrow()).into_owned()
+    }
+
+    pub fn clear(&self) {
+        self.0.borrow_mut().clear()
+    }
+}
+
+impl Write for Capture {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.0.borrow_mut().extend_from_slice(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+/// Writes everything to two streams, e.g. to the terminal and a log file
+pub struct Tee<A: Write, B: Write>(pub A, pub B);
+
+impl<A: Write, B: Write> Write for Tee<A, B> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.0.write_all(buf)?;
+        self.1.write_all(buf)?;
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.0.flush()?;
+        self.1.flush()
+    }
+}
+
+thread_local! {
+    /// The streams of the script running on this thread
+    static STDIO: RefCell<Stdio> = RefCell::new(Stdio::default());
+}
+
+/// Restores the streams of the enclosing run when dropped
+pub struct StdioGuard {
+    previous: Stdio,
+}
+
+impl Drop for StdioGuard {
+    fn drop(&mut self) {
+        let previous = std::mem::take(&mut self.previous);
+        STDIO.with(|stdio| *stdio.borrow_mut() = previous);
+    }
+}
+
+/// Makes the code running on this thread use `stdio` until the guard is dropped
+pub fn install(stdio: &Stdio) -> StdioGuard {
+    StdioGuard {
+        previous: STDIO.with(|current| current.replace(stdio.clone())),
+    }
+}
+
+/// Writes to the output stream
+pub fn write_output(text: &str) -> RuntimeResult<()> {
+    let output = STDIO.with(|stdio| stdio.borrow().output.clone());
+    let res = match output {
+        Some(output) => output.borrow_mut().write_all(text.as_bytes()),
+        None => io::stdout().write_all(text.as_bytes()),
+    };
+    res.map_err(|e| RuntimeError::io(format!("Unable to write to stdout: {}", e)))
+}
+
+/// Writes to the error stream
+pub fn write_error(text: &str) -> RuntimeResult<()> {
+    let error = STDIO.with(|stdio| stdio.borrow().error.clone());
+    let res = match error {
+        Some(error) => error.borrow_mut().write_all(text.as_bytes()),
+        None => io::stderr().write_all(text.as_bytes()),
+    };
+    res.map_err(|e| RuntimeError::io(format!("Unable to write to stderr: {}", e)))
+}
+
+/// Reads from the input stream, the output is flushed first so prompts are visible
+pub fn read_input<T>(f: impl FnOnce(&mut dyn BufRead) -> io::Result<T>) -> io::Result<T> {
+    let (input, output) = STDIO.with(|stdio| {
+        let stdio = stdio.borrow();
+        (stdio.input.clone(), stdio.output.clone())
+    });
+    match output {
+        Some(output) => output.borrow_mut().flush()?,
+        None => io::stdout().flush()?,
+    }
+
+    match input {
+        Some(input) => f(&mut *input.borrow_mut()),
+        None => f(&mut io::stdin().lock()),
+    }
+}
@@ -1,14 +1,35 @@
+use crate::span::Span;
 use regex_lexer::{Lexer, LexerBuilder, Tokens};
 
 #[derive(Debug, PartialEq, Eq)]
 pub enum Token<'a> {
     Data(DataToken),
-    //Operator(OperatorToken),
+    Operator(OperatorToken),
     Keyword(KeywordToken),
     Identifier(&'a str),
     //Null,
     Symbol(SymbolToken),
     Newline,
+    /// A character no other token matches, the parser reports it as unexpected
+    Unknown(&'a str),
+}
+
+impl Token<'_> {
+    /// Whether a `{` following this token opens a map literal instead of a block,
+    /// this is the case wherever only an expression can follow
+    pub fn precedes_map_literal(&self) -> bool {
+        matches!(
+            self,
+            Token::Symbol(SymbolToken::Equals)
+                | Token::Symbol(SymbolToken::RoundOpen)
+                | Token::Symbol(SymbolToken::SquareOpen)
+                | Token::Symbol(SymbolToken::Comma)
+                | Token::Symbol(SymbolToken::Colon)
+                | Token::Operator(_)
+                | Token::Keyword(KeywordToken::Ret)
+                | Token::Keyword(KeywordToken::In)
+        )
+    }
 }
 
 impl From<DataToken> for Token<'_> {
@@ -23,6 +44,12 @@ impl From<SymbolToken> for Token<'_> {
     }
 }
 
+impl From<OperatorToken> for Token<'_> {
+    fn from(op: OperatorToken) -> Self {
+        Token::Operator(op)
+    }
+}
+
 impl From<KeywordToken> for Token<'_> {
     fn from(key: KeywordToken) -> Self {
         Token::Keyword(key)
@@ -32,7 +59,9 @@ impl From<KeywordToken> for Token<'_> {
 #[derive(Debug, PartialEq)]
 pub enum DataToken {
     Bool(bool),
-    Integer(i64),
+    /// The magnitude of the literal, the parser checks its range because
+    /// `-9223372036854775808` only fits as a negative literal
+    Integer(u64),
     Float(f64),
     Character(char),
     Str(String),
@@ -41,7 +70,7 @@ pub enum DataToken {
 
 impl std::cmp::Eq for DataToken {}
 
-#[derive(Debug, PartialEq, Eq)]
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
 pub enum SymbolToken {
     RoundOpen,
     RoundClose,
@@ -52,17 +81,44 @@ pub enum SymbolToken {
     SquareClose,
     Comma,
     Semicolon,
+    Colon,
+    /// `...` in front of the rest parameter of a function
+    Ellipsis,
 }
 
-/*
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
 pub enum OperatorToken {
-    Puls,
+    Plus,
     Minus,
-    Astrist,
+    Asterisk,
     Slash,
     Percent,
+    EqualsEquals,
+    NotEquals,
+    Less,
+    LessEquals,
+    Greater,
+    GreaterEquals,
+    And,
+    Or,
+    Not,
+}
+
+impl OperatorToken {
+    /// The binding power of the operator used as binary operator,
+    /// higher binds stronger. `None` if it is unary only.
+    pub fn precedence(self) -> Option<u8> {
+        use OperatorToken::*;
+        match self {
+            Or => Some(1),
+            And => Some(2),
+            EqualsEquals | NotEquals | Less | LessEquals | Greater | GreaterEquals => Some(3),
+            Plus | Minus => Some(4),
+            Asterisk | Slash | Percent => Some(5),
+            Not => None,
+        }
+    }
 }
-*/
 
 #[derive(PartialEq, Eq, Debug, Clone)]
 pub enum KeywordToken {
@@ -76,116 +132,292 @@ pub enum KeywordToken {
     Ret,
     For,
     In,
+    Try,
+    Catch,
+    Break,
+    Continue,
+    Import,
 }
 
-pub fn build_lexer<'t>() -> Result<Lexer<'t, Token<'t>>, regex::Error> {
+/// A token together with the slice of the source it was lexed from
+pub type Lexeme<'t> = (Token<'t>, &'t str);
+
+pub fn build_lexer<'t>() -> Result<Lexer<'t, Lexeme<'t>>, regex::Error> {
     LexerBuilder::new()
-        .token("=", |_| Some(SymbolToken::Equals.into()))
-        .token(r"-?[0-9]+", |tok| {
-            Some(DataToken::Integer(tok.parse().unwrap()).into())
+        // Every other token wins against this one, because later tokens win ties
+        .token(r".", |tok| Some((Token::Unknown(tok), tok)))
+        .token("=", |tok| Some((SymbolToken::Equals.into(), tok)))
+        // Signs are unary operators, otherwise `a -1` would be lexed as `a` and `-1`
+        .token(r"[0-9]+", |tok| {
+            // Literals too large for a u64 are out of range for the parser as well
+            let magnitude = tok.parse().unwrap_or(u64::MAX);
+            Some((DataToken::Integer(magnitude).into(), tok))
         })
-        .token(r"-?[0-9]+\.[0-9]+", |tok| {
-            Some(DataToken::Float(tok.parse().unwrap()).into())
+        .token(r"[0-9]+\.[0-9]+", |tok| {
+            Some((DataToken::Float(tok.parse().unwrap()).into(), tok))
         })
         .token(r"'.'", |tok| {
-            Some(DataToken::Character(tok[1..tok.len() - 1].parse().unwrap()).into())
+            Some((
+                DataToken::Character(tok[1..tok.len() - 1].parse().unwrap()).into(),
+                tok,
+            ))
         })
         .token("\".*?\"", |tok| {
-            let s = Some(DataToken::Str(tok[1..tok.len() - 1].replace("\\n", "\n")).into());
-            s
-        })
-        .token(r"\(", |_| Some(SymbolToken::RoundOpen.into()))
-        .token(r"\)", |_| Some(SymbolToken::RoundClose.into()))
-        .token(r"\{", |_| Some(SymbolToken::CurlyOpen.into()))
-        .token(r"\}", |_| Some(SymbolToken::CurlyClose.into()))
-        .token(r"\[", |_| Some(SymbolToken::SquareOpen.into()))
-        .token(r"\]", |_| Some(SymbolToken::SquareClose.into()))
-        .token(r",", |_| Some(SymbolToken::Comma.into()))
-        .token(r";", |_| Some(SymbolToken::Semicolon.into()))
-        .token(r"(_|[a-zA-Z])[a-zA-Z_0-9]*", |tok| {
-            Some(Token::Identifier(tok))
+            Some((
+                DataToken::Str(tok[1..tok.len() - 1].replace("\\n", "\n")).into(),
+                tok,
+            ))
         })
-        .token(r"//.*?\n", |_| Some(Token::Newline))
+        .token(r"\(", |tok| Some((SymbolToken::RoundOpen.into(), tok)))
+        .token(r"\)", |tok| Some((SymbolToken::RoundClose.into(), tok)))
+        .token(r"\{", |tok| Some((SymbolToken::CurlyOpen.into(), tok)))
+        .token(r"\}", |tok| Some((SymbolToken::CurlyClose.into(), tok)))
+        .token(r"\[", |tok| Some((SymbolToken::SquareOpen.into(), tok)))
+        .token(r"\]", |tok| Some((SymbolToken::SquareClose.into(), tok)))
+        .token(r",", |tok| Some((SymbolToken::Comma.into(), tok)))
+        .token(r";", |tok| Some((SymbolToken::Semicolon.into(), tok)))
+        .token(r":", |tok| Some((SymbolToken::Colon.into(), tok)))
+        .token(r"\.\.\.", |tok| Some((SymbolToken::Ellipsis.into(), tok)))
+        // Names of module members are qualified with the module name, like `http.get`
+        .token(
+            r"(_|[a-zA-Z])[a-zA-Z_0-9]*(\.(_|[a-zA-Z])[a-zA-Z_0-9]*)*",
+            |tok| Some((Token::Identifier(tok), tok)),
+        )
+        .token(r"//.*?\n", |tok| Some((Token::Newline, tok)))
         .token(r"(true|false)", |tok| {
-            Some(DataToken::Bool(tok.parse().unwrap()).into())
+            Some((DataToken::Bool(tok.parse().unwrap()).into(), tok))
         })
-        .token("ret", |_| Some(KeywordToken::Ret.into()))
-        .token("while", |_| Some(KeywordToken::While.into()))
-        .token("if", |_| Some(KeywordToken::If.into()))
-        .token("else", |_| Some(KeywordToken::Else.into()))
-        .token("elif", |_| Some(KeywordToken::Elif.into()))
-        .token("const", |_| Some(KeywordToken::Const.into()))
-        .token("for", |_| Some(KeywordToken::For.into()))
-        .token("in", |_| Some(KeywordToken::In.into()))
+        .token("ret", |tok| Some((KeywordToken::Ret.into(), tok)))
+        .token("while", |tok| Some((KeywordToken::While.into(), tok)))
+        .token("if", |tok| Some((KeywordToken::If.into(), tok)))
+        .token("else", |tok| Some((KeywordToken::Else.into(), tok)))
+        .token("elif", |tok| Some((KeywordToken::Elif.into(), tok)))
+        .token("const", |tok| Some((KeywordToken::Const.into(), tok)))
+        .token("for", |tok| Some((KeywordToken::For.into(), tok)))
+        .token("in", |tok| Some((KeywordToken::In.into(), tok)))
         //Change to data
-        .token("none", |_| Some(DataToken::None.into()))
-        .token("let", |_| Some(KeywordToken::Let.into()))
-        .token("fn", |_| Some(KeywordToken::Fn.into()))
-        /*
-        .token(r"\+", |tok| Some(Token::Operator::Puls(tok.parse().unwrap()))
-        .token(r"-", |tok| Some(Token::Operator::Minus(tok.parse().unwrap()))
-        .token(r"\*", |tok| Some(Token::Operator::Atrist(tok.parse().unwrap()))
-        .token(r"/", |tok| Some(Token::Operator::Slash(tok.parse().unwrap()))
-        .token(r"%" |tok| Some(Token::Operator::(tok.parse().unwrap()))
-        */
+        .token("none", |tok| Some((DataToken::None.into(), tok)))
+        .token("let", |tok| Some((KeywordToken::Let.into(), tok)))
+        .token("fn", |tok| Some((KeywordToken::Fn.into(), tok)))
+        .token("try", |tok| Some((KeywordToken::Try.into(), tok)))
+        .token("catch", |tok| Some((KeywordToken::Catch.into(), tok)))
+        .token("break", |tok| Some((KeywordToken::Break.into(), tok)))
+        .token("continue", |tok| Some((KeywordToken::Continue.into(), tok)))
+        .token("import", |tok| Some((KeywordToken::Import.into(), tok)))
+        .token(r"\+", |tok| Some((OperatorToken::Plus.into(), tok)))
+        .token(r"-", |tok| Some((OperatorToken::Minus.into(), tok)))
+        .token(r"\*", |tok| Some((OperatorToken::Asterisk.into(), tok)))
+        .token(r"/", |tok| Some((OperatorToken::Slash.into(), tok)))
+        .token(r"%", |tok| Some((OperatorToken::Percent.into(), tok)))
+        .token(r"==", |tok| Some((OperatorToken::EqualsEquals.into(), tok)))
+        .token(r"!=", |tok| Some((OperatorToken::NotEquals.into(), tok)))
+        .token(r"<", |tok| Some((OperatorToken::Less.into(), tok)))
+        .token(r"<=", |tok| Some((OperatorToken::LessEquals.into(), tok)))
+        .token(r">", |tok| Some((OperatorToken::Greater.into(), tok)))
+        .token(r">=", |tok| {
+            Some((OperatorToken::GreaterEquals.into(), tok))
+        })
+        .token(r"&&", |tok| Some((OperatorToken::And.into(), tok)))
+        .token(r"\|\|", |tok| Some((OperatorToken::Or.into(), tok)))
+        .token(r"!", |tok| Some((OperatorToken::Not.into(), tok)))
         .token(r"\s", |_| None)
-        .token(r"//.*", |_| Some(Token::Newline))
-        .token("\n", |_| Some(Token::Newline))
+        .token(r"//.*", |tok| Some((Token::Newline, tok)))
+        .token("\n", |tok| Some((Token::Newline, tok)))
         .build()
 }
 
+/// Whether `src` has unclosed brackets and more input is needed to parse it
+pub fn is_incomplete(src: &str) -> bool {
+    let lexer = build_lexer().expect("the lexer definition is valid");
+    let mut open = 0i64;
+    for (token, _) in lexer.tokens(src) {
+        match token {
+            Token::Symbol(SymbolToken::RoundOpen)
+            | Token::Symbol(SymbolToken::CurlyOpen)
+            | Token::Symbol(SymbolToken::SquareOpen) => open += 1,
+            Token::Symbol(SymbolToken::RoundClose)
+            | Token::Symbol(SymbolToken::CurlyClose)
+            | Token::Symbol(SymbolToken::SquareClose) => open -= 1,
+            _ => (),
+        }
+    }
+
+    open > 0
+}
+
+#[derive(Debug, PartialEq)]
+pub struct SpannedToken<'a> {
+    pub token: Token<'a>,
+    pub span: Span,
+}
+
+/// Attaches a `Span` to every token produced by the lexer
+#[derive(Debug)]
+pub struct SpannedTokens<'node, 'text, 'tokens> {
+    tokens: Tokens<'node, 'text, Lexeme<'tokens>>,
+    source: &'text str,
+    line: u64,
+    line_start: usize,
+    scanned: usize,
+}
+
+impl<'node, 'text, 'tokens> SpannedTokens<'node, 'text, 'tokens> {
+    /// `tokens` has to be lexed from `source`
+    pub fn new(source: &'text str, tokens: Tokens<'node, 'text, Lexeme<'tokens>>) -> Self {
+        Self {
+            tokens,
+            source,
+            line: 1,
+            line_start: 0,
+            scanned: 0,
+        }
+    }
+}
+
+impl<'tokens> Iterator for SpannedTokens<'_, '_, 'tokens> {
+    type Item = SpannedToken<'tokens>;
+
+    fn next(&mut self) -> Option<SpannedToken<'tokens>> {
+        let (token, text) = self.tokens.next()?;
+        // Every lexeme is a slice of the source, so its offset is the distance between both
+        let offset = text.as_ptr() as usize - self.source.as_ptr() as usize;
+
+        for (i, b) in self.source.as_bytes()[self.scanned..offset]
+            .iter()
+            .enumerate()
+        {
+            if *b == b'\n' {
+                self.line += 1;
+                self.line_start = self.scanned + i + 1;
+            }
+        }
+        self.scanned = offset;
+
+        let column = self.source[self.line_start..offset].chars().count() as u64 + 1;
+
+        Some(SpannedToken {
+            token,
+            span: Span {
+                offset,
+                len: text.len(),
+                line: self.line,
+                column,
+            },
+        })
+    }
+}
+
 #[derive(Debug)]
 pub struct TokenStream<'node, 'text, 'tokens> {
-    untouched_tokens: Option<Tokens<'node, 'text, Token<'tokens>>>,
-    peeked_tokens: Vec<Token<'tokens>>,
+    untouched_tokens: Option<SpannedTokens<'node, 'text, 'tokens>>,
+    peeked_tokens: Vec<SpannedToken<'tokens>>,
+    last_span: Span,
+    /// Whether a newline was returned since the last other token
+    newline_pending: bool,
+    /// Whether a newline preceded the token returned last
+    after_newline: bool,
 }
 
 impl<'node, 'text, 'tokens> TokenStream<'node, 'text, 'tokens> {
-    pub fn new(tokens: Tokens<'node, 'text, Token<'tokens>>) -> Self {
+    pub fn new(source: &'text str, tokens: Tokens<'node, 'text, Lexeme<'tokens>>) -> Self {
         Self {
-            untouched_tokens: Some(tokens),
+            untouched_tokens: Some(SpannedTokens::new(source, tokens)),
             peeked_tokens: vec![],
+            last_span: Span::default(),
+            newline_pending: false,
+            after_newline: false,
         }
     }
 
-    pub fn next(&mut self) -> Option<Token<'tokens>> {
-        if self.peeked_tokens.is_empty() {
-            if let Some(untouched) = &mut self.untouched_tokens {
-                untouched.next()
-            } else {
-                None
-            }
-        } else {
-            Some(self.peeked_tokens.remove(0))
+    /// Puts `token` back in front of the stream, it gets the span of the token returned last.
+    /// The newline preceding that token is put back as well, so the line break isn't lost.
+    pub fn reinsert(&mut self, token: Token<'tokens>) {
+        self.peeked_tokens.insert(
+            0,
+            SpannedToken {
+                token,
+                span: self.last_span,
+            },
+        );
+        if std::mem::take(&mut self.after_newline) {
+            self.peeked_tokens.insert(
+                0,
+                SpannedToken {
+                    token: Token::Newline,
+                    span: self.last_span,
+                },
+            );
         }
     }
 
-    pub fn reinsert(&mut self, token: Token<'tokens>) {
-        self.peeked_tokens.insert(0, token)
+    /// Whether a newline preceded the token returned last
+    pub fn after_newline(&self) -> bool {
+        self.after_newline
+    }
+
+    /// The span of the token returned last
+    pub fn span(&self) -> Span {
+        self.last_span
+    }
+
+    pub fn next_spanned(&mut self) -> Option<SpannedToken<'tokens>> {
+        let next = if self.peeked_tokens.is_empty() {
+            self.untouched_tokens.as_mut()?.next()
+        } else {
+            Some(self.peeked_tokens.remove(0))
+        };
+
+        if let Some(t) = &next {
+            self.last_span = t.span;
+            if let Token::Newline = t.token {
+                self.newline_pending = true;
+            } else {
+                self.after_newline = std::mem::take(&mut self.newline_pending);
+            }
+        }
+        next
     }
 
     pub fn size_hint(&self) -> usize {
         if let Some(untouched) = &self.untouched_tokens {
-            untouched.size_hint().1.unwrap_or(0) + self.peeked_tokens.len()
+            untouched.tokens.size_hint().1.unwrap_or(0) + self.peeked_tokens.len()
         } else {
             self.peeked_tokens.len()
         }
     }
 
-    pub fn into_iter(self) -> Box<dyn Iterator<Item = Token<'tokens>> + 'node> {
+    pub fn into_boxed_iter(self) -> Box<dyn Iterator<Item = Token<'tokens>> + 'node>
+    where
+        'text: 'node,
+        'tokens: 'node,
+    {
+        let peeked = self.peeked_tokens.into_iter();
         if let Some(untouched) = self.untouched_tokens {
-            Box::new(untouched.chain(self.peeked_tokens.into_iter()))
+            Box::new(peeked.chain(untouched).map(|t| t.token))
         } else {
-            Box::new(self.peeked_tokens.into_iter())
+            Box::new(peeked.map(|t| t.token))
         }
     }
 }
 
-impl<'node, 'text, 'tokens> From<Vec<Token<'tokens>>> for TokenStream<'node, 'text, 'tokens> {
-    fn from(v: Vec<Token<'tokens>>) -> Self {
+impl<'tokens> Iterator for TokenStream<'_, '_, 'tokens> {
+    type Item = Token<'tokens>;
+
+    fn next(&mut self) -> Option<Token<'tokens>> {
+        self.next_spanned().map(|t| t.token)
+    }
+}
+
+impl<'node, 'text, 'tokens> From<Vec<SpannedToken<'tokens>>>
+    for TokenStream<'node, 'text, 'tokens>
+{
+    fn from(v: Vec<SpannedToken<'tokens>>) -> Self {
         Self {
             untouched_tokens: None,
             peeked_tokens: v,
+            last_span: Span::default(),
+            newline_pending: false,
+            after_newline: false,
         }
     }
 }
@@ -198,7 +430,11 @@ mod tests {
     fn hello_world() {
         let source = "print(\"Hello, World!\") ";
         assert_eq!(
-            build_lexer().unwrap().tokens(source).collect::<Vec<_>>(),
+            build_lexer()
+                .unwrap()
+                .tokens(source)
+                .map(|(t, _)| t)
+                .collect::<Vec<_>>(),
             vec![
                 Token::Identifier("print"),
                 SymbolToken::RoundOpen.into(),
@@ -207,4 +443,28 @@ mod tests {
             ],
         )
     }
+
+    #[test]
+    fn spans() {
+        let source = "let a = 1\n\tprint(a)";
+        let lexer = build_lexer().unwrap();
+        let spans = SpannedTokens::new(source, lexer.tokens(source))
+            .map(|t| (t.span.offset, t.span.len, t.span.line, t.span.column))
+            .collect::<Vec<_>>();
+
+        assert_eq!(
+            spans,
+            vec![
+                (0, 3, 1, 1),
+                (4, 1, 1, 5),
+                (6, 1, 1, 7),
+                (8, 1, 1, 9),
+                (9, 1, 1, 10),
+                (11, 5, 2, 2),
+                (16, 1, 2, 7),
+                (17, 1, 2, 8),
+                (18, 1, 2, 9),
+            ]
+        )
+    }
 }
@@ -0,0 +1,550 @@
+use super::{Chunk, Op, Subscript};
+use crate::{
+    base::{DayFunction, DayObject},
+    node::{
+        Block, BranchNode, FunctionCallNode, IndexNode, IndexOperation, LogicalOp, Node, RootNode,
+    },
+    runtime_error::RuntimeError,
+    span::Span,
+};
+
+/// Compiles `block` to be executed in its own scope, the chunk evaluates to the value
+/// of the last node of the block
+pub fn compile(block: &Block) -> Chunk {
+    let mut compiler = Compiler {
+        chunk: Chunk::default(),
+        loops: Vec::new(),
+        scopes: 0,
+        handlers: 0,
+        iters: 0,
+    };
+    compiler.root(&block.block);
+    compiler.chunk
+}
+
+/// A loop the compiled code is currently in
+struct Loop {
+    /// The op `continue` jumps to
+    start: u32,
+    /// The number of scopes, handlers and iters outside of the loop
+    scopes: usize,
+    handlers: usize,
+    iters: usize,
+    /// The jumps of `break` to the end of the loop, patched once it is known
+    breaks: Vec<usize>,
+    /// Whether the loop is used as a value or a statement
+    keep: bool,
+}
+
+struct Compiler {
+    chunk: Chunk,
+    loops: Vec<Loop>,
+    /// The number of scopes, handlers and iters entered by the compiled code at this point
+    scopes: usize,
+    handlers: usize,
+    iters: usize,
+}
+
+impl Compiler {
+    fn emit(&mut self, op: Op) -> usize {
+        self.chunk.ops.push(op);
+        self.chunk.ops.len() - 1
+    }
+
+    fn pc(&self) -> u32 {
+        self.chunk.ops.len() as u32
+    }
+
+    /// Makes the jump at `at` target the next op
+    fn patch(&mut self, at: usize) {
+        let target = self.pc();
+        match &mut self.chunk.ops[at] {
+            Op::Jump(t)
+            | Op::JumpIfFalse(t)
+            | Op::JumpUnlessTrue(t)
+            | Op::And(t)
+            | Op::Or(t)
+            | Op::PushHandler(t)
+            | Op::Next { exit: t, .. } => *t = target,
+            other => unreachable!("{:?} is not a jump", other),
+        }
+    }
+
+    fn constant(&mut self, value: DayObject) -> Op {
+        self.chunk.consts.push(value);
+        Op::Const(self.chunk.consts.len() as u32 - 1)
+    }
+
+    fn error(&mut self, error: RuntimeError) -> Op {
+        self.chunk.errors.push(error);
+        Op::Raise(self.chunk.errors.len() as u32 - 1)
+    }
+
+    /// Compiles `f`, errors of the ops it emitted are located at `span`
+    fn spanned(&mut self, span: Span, f: impl FnOnce(&mut Self)) {
+        let start = self.pc();
+        f(self);
+        let end = self.pc();
+        self.chunk.spans.push((start..end, span));
+    }
+
+    fn root(&mut self, root: &RootNode) {
+        match root.nodes().split_last() {
+            Some((last, nodes)) => {
+                for n in nodes {
+                    self.node(n, false);
+                }
+                self.node(last, true);
+            }
+            None => {
+                self.emit(Op::PushNone);
+            }
+        }
+        self.emit(Op::End);
+    }
+
+    /// Compiles `block` in a new scope as a statement
+    fn block(&mut self, block: &Block) {
+        self.emit(Op::EnterScope(block.capacity as u32));
+        self.scopes += 1;
+        self.body(block);
+        self.emit(Op::LeaveScope);
+        self.scopes -= 1;
+    }
+
+    /// Compiles the nodes of `block` as statements in the current scope
+    fn body(&mut self, block: &Block) {
+        for n in block.block.nodes() {
+            self.node(n, false);
+        }
+    }
+
+    /// Compiles `node`, if `keep` is set its value is left on the stack
+    fn node(&mut self, node: &Node, keep: bool) {
+        match node {
+            Node::RustFunction(rfn) => {
+                let op = self.constant(DayObject::Function(DayFunction::Function(rfn.0)));
+                self.emit(op);
+            }
+            Node::Identifier(id) => {
+                self.emit(Op::LoadVar {
+                    id: id.id as u32,
+                    depth: id.depth as u32,
+                });
+            }
+            Node::Data(value) => {
+                let op = self.constant(value.clone());
+                self.emit(op);
+            }
+            Node::FunctionCall(call) => self.call(call),
+            Node::Args => {
+                self.emit(Op::LoadArgs);
+            }
+            Node::Index(index) => self.index(index),
+            Node::Logical { op, lhs, rhs, span } => self.spanned(*span, |c| {
+                c.node(lhs, true);
+                let jump = c.emit(match op {
+                    LogicalOp::And => Op::And(0),
+                    LogicalOp::Or => Op::Or(0),
+                });
+                c.node(rhs, true);
+                c.emit(Op::ToBool);
+                c.patch(jump);
+            }),
+            Node::BinaryOp { op, lhs, rhs, span } => self.spanned(*span, |c| {
+                c.node(lhs, true);
+                c.node(rhs, true);
+                c.emit(Op::Binary(*op));
+            }),
+            Node::MapLiteral { entries, span } => self.spanned(*span, |c| {
+                for (key, value) in entries {
+                    c.node(key, true);
+                    c.node(value, true);
+                }
+                c.emit(Op::MakeMap(entries.len() as u32));
+            }),
+            Node::ModuleVar { module, id } => {
+                self.chunk.modules.push(module.clone());
+                self.emit(Op::LoadModuleVar {
+                    module: self.chunk.modules.len() as u32 - 1,
+                    id: *id as u32,
+                });
+            }
+            Node::FunctionDeclaration { function, id } => {
+                self.chunk.functions.push(function.clone());
+                let function = self.chunk.functions.len() as u32 - 1;
+                match id {
+                    Some(id) => {
+                        self.emit(Op::DefFunction {
+                            function,
+                            id: *id as u32,
+                        });
+                        return self.none(keep);
+                    }
+                    None => {
+                        self.emit(Op::Closure(function));
+                    }
+                }
+            }
+            Node::Assignment { assignee, value } => {
+                self.assignment(assignee, value);
+                return self.none(keep);
+            }
+            Node::Declaration { value, id } | Node::ConstDeclaration { value, id } => {
+                self.node(value, true);
+                self.emit(Op::DefVar(*id as u32));
+                return self.none(keep);
+            }
+            Node::Import { module, span } => {
+                self.chunk.modules.push(module.clone());
+                let op = Op::Import(self.chunk.modules.len() as u32 - 1);
+                self.spanned(*span, |c| {
+                    c.emit(op);
+                });
+                return self.none(keep);
+            }
+            Node::Block(block) => {
+                self.block(block);
+                return self.none(keep);
+            }
+            Node::BranchNode(branches) => {
+                self.branches(branches);
+                return self.none(keep);
+            }
+            Node::Try {
+                block,
+                catch,
+                binds_error,
+            } => {
+                self.try_catch(block, catch, *binds_error);
+                return self.none(keep);
+            }
+            Node::While {
+                condition,
+                block,
+                span,
+            } => return self.spanned(*span, |c| c.while_loop(condition, block, keep)),
+            Node::For {
+                expr,
+                block,
+                destructure,
+                span,
+            } => return self.spanned(*span, |c| c.for_loop(expr, block, *destructure, keep)),
+            Node::Ret(value) => {
+                match value {
+                    Some(value) => self.node(value, true),
+                    None => {
+                        self.emit(Op::PushNone);
+                    }
+                }
+                self.emit(Op::Return);
+                return self.none(keep);
+            }
+            Node::Break(value) => {
+                self.break_loop(value.as_deref());
+                return self.none(keep);
+            }
+            Node::Continue => {
+                self.continue_loop();
+                return self.none(keep);
+            }
+        }
+
+        self.keep(keep)
+    }
+
+    /// Pops the value of an expression if it isn't kept
+    fn keep(&mut self, keep: bool) {
+        if !keep {
+            self.emit(Op::Pop);
+        }
+    }
+
+    /// Pushes the none value of a statement if it is kept
+    fn none(&mut self, keep: bool) {
+        if keep {
+            self.emit(Op::PushNone);
+        }
+    }
+
+    fn call(&mut self, call: &FunctionCallNode) {
+        self.spanned(call.span, |c| {
+            let argc = call.args.len() as u32;
+            match &*call.expr {
+                Node::RustFunction(rfn) => {
+                    c.args(&call.args);
+                    c.emit(Op::CallRust {
+                        function: rfn.0,
+                        argc,
+                    });
+                }
+                Node::Identifier(id) => {
+                    c.args(&call.args);
+                    c.emit(Op::CallVar {
+                        id: id.id as u32,
+                        depth: id.depth as u32,
+                        argc,
+                    });
+                }
+                other => {
+                    c.node(other, true);
+                    c.args(&call.args);
+                    c.emit(Op::Call { argc });
+                }
+            }
+        })
+    }
+
+    fn args(&mut self, args: &[Node]) {
+        for a in args {
+            self.node(a, true);
+        }
+    }
+
+    /// Pushes the values of the subscripts of `ops` and returns their shape
+    fn subscripts(&mut self, ops: &[IndexOperation]) -> u32 {
+        let mut shape = Vec::with_capacity(ops.len());
+        for op in ops {
+            match op {
+                IndexOperation::Index(index) => {
+                    self.node(index, true);
+                    shape.push(Subscript::Index);
+                }
+                IndexOperation::Slice { start, end } => {
+                    for bound in [start, end].iter().copied().flatten() {
+                        self.node(bound, true);
+                    }
+                    shape.push(Subscript::Slice {
+                        start: start.is_some(),
+                        end: end.is_some(),
+                    });
+                }
+            }
+        }
+
+        self.chunk.shapes.push(shape);
+        self.chunk.shapes.len() as u32 - 1
+    }
+
+    fn index(&mut self, index: &IndexNode) {
+        self.spanned(index.span, |c| {
+            let shape = c.subscripts(&index.index_ops);
+            match &*index.initial {
+                Node::Identifier(id) => {
+                    c.emit(Op::IndexVar {
+                        id: id.id as u32,
+                        depth: id.depth as u32,
+                        shape,
+                    });
+                }
+                other => {
+                    c.node(other, true);
+                    c.emit(Op::Index { shape });
+                }
+            }
+        })
+    }
+
+    fn assignment(&mut self, assignee: &Node, value: &Node) {
+        match assignee {
+            Node::Identifier(id) => {
+                self.node(value, true);
+                self.emit(Op::StoreVar {
+                    id: id.id as u32,
+                    depth: id.depth as u32,
+                });
+            }
+            Node::Index(index) => {
+                self.node(value, true);
+                self.spanned(index.span, |c| {
+                    let shape = c.subscripts(&index.index_ops);
+                    let op = match &*index.initial {
+                        Node::Identifier(id) => Op::AssignIndex {
+                            id: id.id as u32,
+                            depth: id.depth as u32,
+                            shape,
+                        },
+                        other => c.error(RuntimeError::type_error(format!(
+                            "Can't assign to {:?}",
+                            other
+                        ))),
+                    };
+                    c.emit(op);
+                });
+            }
+            other => {
+                let op = self.error(RuntimeError::type_error(format!(
+                    "Can't assign to {:?}",
+                    other
+                )));
+                self.emit(op);
+            }
+        }
+    }
+
+    fn branches(&mut self, branches: &[BranchNode]) {
+        let mut ends = Vec::with_capacity(branches.len());
+        for b in branches {
+            match b {
+                BranchNode::If { block: ifb } | BranchNode::ElseIf { block: ifb } => {
+                    self.node(&ifb.condition, true);
+                    let next = self.emit(Op::JumpUnlessTrue(0));
+                    self.block(&ifb.block);
+                    ends.push(self.emit(Op::Jump(0)));
+                    self.patch(next);
+                }
+                BranchNode::Else { block } => {
+                    self.block(block);
+                    break;
+                }
+            }
+        }
+
+        for end in ends {
+            self.patch(end);
+        }
+    }
+
+    fn try_catch(&mut self, block: &Block, catch: &Block, binds_error: bool) {
+        let handler = self.emit(Op::PushHandler(0));
+        self.handlers += 1;
+        self.block(block);
+        self.emit(Op::PopHandler);
+        self.handlers -= 1;
+        let end = self.emit(Op::Jump(0));
+
+        // The machine jumps here with the error on the stack
+        self.patch(handler);
+        self.emit(Op::EnterScope(catch.capacity as u32));
+        self.scopes += 1;
+        self.emit(if binds_error { Op::DefVar(0) } else { Op::Pop });
+        self.body(catch);
+        self.emit(Op::LeaveScope);
+        self.scopes -= 1;
+        self.patch(end);
+    }
+
+    fn enter_loop(&mut self, start: u32, outside: (usize, usize), keep: bool) {
+        let (scopes, iters) = outside;
+        self.loops.push(Loop {
+            start,
+            scopes,
+            handlers: self.handlers,
+            iters,
+            breaks: Vec::new(),
+            keep,
+        });
+    }
+
+    /// Leaves the scope of the loop body and patches the breaks,
+    /// they skip the ops that are only executed if the loop ends without one
+    fn exit_loop(&mut self, keep: bool) {
+        let lp = self.loops.pop().expect("a loop was entered");
+        self.emit(Op::LeaveScope);
+        self.scopes -= 1;
+        for _ in lp.iters..self.iters {
+            self.emit(Op::PopIter);
+        }
+        self.iters = lp.iters;
+        self.none(keep);
+
+        for b in lp.breaks {
+            self.patch(b);
+        }
+    }
+
+    fn while_loop(&mut self, condition: &Node, block: &Block, keep: bool) {
+        let outside = (self.scopes, self.iters);
+        let capacity = block.capacity as u32;
+        self.emit(Op::EnterScope(capacity));
+        self.scopes += 1;
+
+        let start = self.pc();
+        self.emit(Op::SwapScope);
+        self.node(condition, true);
+        self.emit(Op::SwapScope);
+        let exit = self.emit(Op::JumpIfFalse(0));
+        self.emit(Op::NextIteration(capacity));
+
+        self.enter_loop(start, outside, keep);
+        self.body(block);
+        self.emit(Op::Jump(start));
+        self.patch(exit);
+        self.exit_loop(keep);
+    }
+
+    fn for_loop(&mut self, expr: &Node, block: &Block, destructure: bool, keep: bool) {
+        let outside = (self.scopes, self.iters);
+        self.node(expr, true);
+        self.emit(Op::Iter);
+        self.iters += 1;
+        self.emit(Op::EnterScope(block.capacity as u32));
+        self.scopes += 1;
+
+        let start = self.pc();
+        let next = self.emit(Op::Next {
+            exit: 0,
+            capacity: block.capacity as u32,
+            destructure,
+        });
+
+        self.enter_loop(start, outside, keep);
+        self.body(block);
+        self.emit(Op::Jump(start));
+        self.patch(next);
+        self.exit_loop(keep);
+    }
+
+    /// Pops the handlers and leaves the scopes entered inside of the body of the innermost loop,
+    /// including the scope of the body itself when `leave_body` is set
+    fn unwind(&mut self, leave_body: bool) -> Option<&Loop> {
+        let lp = self.loops.last()?;
+        let scopes = self.scopes - lp.scopes - if leave_body { 0 } else { 1 };
+        let handlers = self.handlers - lp.handlers;
+
+        for _ in 0..handlers {
+            self.chunk.ops.push(Op::PopHandler);
+        }
+        for _ in 0..scopes {
+            self.chunk.ops.push(Op::LeaveScope);
+        }
+        self.loops.last()
+    }
+
+    fn break_loop(&mut self, value: Option<&Node>) {
+        match value {
+            Some(value) => self.node(value, true),
+            None => {
+                self.emit(Op::PushNone);
+            }
+        }
+
+        let (iters, keep) = match self.unwind(true) {
+            Some(lp) => (lp.iters, lp.keep),
+            None => {
+                // Outside of a loop the break ends the chunk like it ends a block
+                self.emit(Op::Break);
+                return;
+            }
+        };
+        for _ in iters..self.iters {
+            self.emit(Op::PopIter);
+        }
+        if !keep {
+            self.emit(Op::Pop);
+        }
+
+        let jump = self.emit(Op::Jump(0));
+        if let Some(lp) = self.loops.last_mut() {
+            lp.breaks.push(jump);
+        }
+    }
+
+    fn continue_loop(&mut self) {
+        let op = match self.unwind(false) {
+            Some(lp) => Op::Jump(lp.start),
+            None => Op::Continue,
+        };
+        self.emit(op);
+    }
+}
@@ -0,0 +1,331 @@
+use super::{Chunk, Op, Subscript as Shape};
+use crate::{
+    base::{Args, DayFunction, DayObject, Env, IterHandle},
+    budget,
+    day_map::DayMap,
+    index::Subscript,
+    manager::RuntimeManager,
+    node::{
+        assign_subscripts, bind_loop_vars, named_function, slice_bound, subscript_all, ExecResult,
+        ExpressionResult,
+    },
+    runtime_error::{RuntimeError, RuntimeErrorKind, RuntimeResult},
+    std_modules::{conversion::to_bool_inner, iter::to_iter_inner},
+};
+use std::sync::Arc;
+
+/// The state at the start of a try block, restored when one of its errors is caught
+struct Handler {
+    /// The first op of the catch block
+    catch: usize,
+    /// The op that pushed the handler
+    start: usize,
+    stack: usize,
+    scope: Arc<RuntimeManager>,
+    scopes: usize,
+    iters: usize,
+}
+
+struct Machine<'a> {
+    chunk: &'a Chunk,
+    pc: usize,
+    stack: Vec<DayObject>,
+    scope: Arc<RuntimeManager>,
+    /// The scopes the current one is nested in, the innermost one last
+    scopes: Vec<Arc<RuntimeManager>>,
+    /// The iters of the for loops, the innermost one last
+    iters: Vec<IterHandle>,
+    handlers: Vec<Handler>,
+}
+
+/// Executes `chunk` in `scope`, a chunk ending normally evaluates to the value of its last node
+pub fn run(chunk: &Chunk, scope: &Arc<RuntimeManager>) -> ExecResult {
+    let mut machine = Machine {
+        chunk,
+        pc: 0,
+        stack: Vec::new(),
+        scope: Arc::clone(scope),
+        scopes: Vec::new(),
+        iters: Vec::new(),
+        handlers: Vec::new(),
+    };
+
+    loop {
+        match machine.execute() {
+            Ok(res) => return Ok(res),
+            Err(e) => machine.catch(e)?,
+        }
+    }
+}
+
+impl Machine<'_> {
+    fn execute(&mut self) -> ExecResult {
+        let chunk = self.chunk;
+        loop {
+            // SAFETY Every chunk ends with `End` and every jump targets an op of its chunk
+            let op = unsafe { *chunk.ops.get_unchecked(self.pc) };
+            self.pc += 1;
+
+            match op {
+                Op::Const(i) => self.stack.push(chunk.consts[i as usize].clone()),
+                Op::PushNone => self.stack.push(DayObject::None),
+                Op::Pop => {
+                    self.pop();
+                }
+                Op::LoadVar { id, depth } => {
+                    let value = self.scope.get_var(id as usize, depth as usize);
+                    self.stack.push(value)
+                }
+                Op::StoreVar { id, depth } => {
+                    let value = self.pop();
+                    self.scope.set_var(value, id as usize, depth as usize)
+                }
+                Op::DefVar(id) => {
+                    let value = self.pop();
+                    self.scope.def_var(id as usize, value)
+                }
+                Op::LoadArgs => self.stack.push(DayObject::Array(self.scope.get_args()?)),
+                Op::LoadModuleVar { module, id } => self
+                    .stack
+                    .push(chunk.modules[module as usize].get_var(id as usize)),
+                Op::Import(module) => chunk.modules[module as usize].execute()?,
+                Op::Closure(function) => {
+                    self.stack.push(DayObject::Function(DayFunction::RuntimeDef(
+                        Arc::clone(&chunk.functions[function as usize]),
+                        Env::Strong(Arc::clone(&self.scope)),
+                    )))
+                }
+                Op::DefFunction { function, id } => self.scope.def_var(
+                    id as usize,
+                    named_function(&chunk.functions[function as usize], &self.scope),
+                ),
+                Op::CallRust { function, argc } => {
+                    let start = self.stack.len() - argc as usize;
+                    let res = function(&self.stack[start..])?;
+                    self.stack.truncate(start);
+                    self.stack.push(res);
+                }
+                Op::CallVar { id, depth, argc } => {
+                    let start = self.stack.len() - argc as usize;
+                    let callee = self.scope.get_var_mut(id as usize, depth as usize);
+                    let res = call_value(callee, &self.stack[start..])?;
+                    self.stack.truncate(start);
+                    self.stack.push(res);
+                }
+                Op::Call { argc } => {
+                    let start = self.stack.len() - argc as usize;
+                    let mut callee = std::mem::replace(&mut self.stack[start - 1], DayObject::None);
+                    let res = call_value(&mut callee, &self.stack[start..])?;
+                    self.stack.truncate(start - 1);
+                    self.stack.push(res);
+                }
+                Op::Index { shape } => {
+                    let value = self.pop();
+                    let subscripts = self.subscripts(shape)?;
+                    self.stack.push(subscript_all(&value, &subscripts)?);
+                }
+                Op::IndexVar { id, depth, shape } => {
+                    let subscripts = self.subscripts(shape)?;
+                    // Variables are indexed in place so they don't have to be cloned as a whole
+                    let value = self.scope.get_var_mut(id as usize, depth as usize);
+                    self.stack.push(subscript_all(value, &subscripts)?);
+                }
+                Op::AssignIndex { id, depth, shape } => {
+                    let subscripts = self.subscripts(shape)?;
+                    let value = self.pop();
+                    let place = self.scope.get_var_mut(id as usize, depth as usize);
+                    assign_subscripts(place, &subscripts, value)?;
+                }
+                Op::MakeMap(len) => {
+                    let start = self.stack.len() - 2 * len as usize;
+                    let mut map = DayMap::with_capacity(len as usize);
+                    let mut entries = self.stack.drain(start..);
+                    while let (Some(key), Some(value)) = (entries.next(), entries.next()) {
+                        map.insert(key, value)?;
+                    }
+                    drop(entries);
+                    self.stack.push(DayObject::Map(map));
+                }
+                Op::Binary(op) => {
+                    let rhs = self.pop();
+                    let lhs = self.pop();
+                    self.stack.push(op.apply(&lhs, &rhs)?);
+                }
+                Op::ToBool => {
+                    let value = self.pop();
+                    self.stack.push(DayObject::Bool(to_bool_inner(&value)?));
+                }
+                Op::And(end) => {
+                    if !to_bool_inner(&self.pop())? {
+                        self.stack.push(DayObject::Bool(false));
+                        self.pc = end as usize;
+                    }
+                }
+                Op::Or(end) => {
+                    if to_bool_inner(&self.pop())? {
+                        self.stack.push(DayObject::Bool(true));
+                        self.pc = end as usize;
+                    }
+                }
+                Op::Jump(target) => self.pc = target as usize,
+                Op::JumpIfFalse(target) => {
+                    if !to_bool_inner(&self.pop())? {
+                        self.pc = target as usize;
+                    }
+                }
+                Op::JumpUnlessTrue(target) => {
+                    if !matches!(self.pop(), DayObject::Bool(true)) {
+                        self.pc = target as usize;
+                    }
+                }
+                Op::EnterScope(capacity) => {
+                    let scope = Arc::new(RuntimeManager::new_capacity_predecessor(
+                        capacity as usize,
+                        Some(Arc::clone(&self.scope)),
+                    ));
+                    let outer = std::mem::replace(&mut self.scope, scope);
+                    self.scopes.push(outer);
+                }
+                Op::LeaveScope => {
+                    self.scope = self.scopes.pop().expect("the left scope was entered");
+                }
+                Op::SwapScope => {
+                    let outer = self.scopes.last_mut().expect("the loop scope was entered");
+                    std::mem::swap(outer, &mut self.scope);
+                }
+                Op::NextIteration(capacity) => {
+                    budget::step()?;
+                    self.renew_scope(capacity);
+                }
+                Op::Iter => {
+                    let value = self.pop();
+                    self.iters.push(to_iter_inner(&value)?);
+                }
+                Op::Next {
+                    exit,
+                    capacity,
+                    destructure,
+                } => {
+                    let iter = self.iters.last_mut().expect("the for loop pushed its iter");
+                    match iter.0.next()? {
+                        Some(element) => {
+                            budget::step()?;
+                            self.renew_scope(capacity);
+                            bind_loop_vars(&self.scope, element, destructure)?;
+                        }
+                        None => self.pc = exit as usize,
+                    }
+                }
+                Op::PopIter => {
+                    self.iters.pop();
+                }
+                Op::PushHandler(catch) => self.handlers.push(Handler {
+                    catch: catch as usize,
+                    start: self.pc - 1,
+                    stack: self.stack.len(),
+                    scope: Arc::clone(&self.scope),
+                    scopes: self.scopes.len(),
+                    iters: self.iters.len(),
+                }),
+                Op::PopHandler => {
+                    self.handlers.pop();
+                }
+                Op::Raise(error) => return Err(chunk.errors[error as usize].clone()),
+                Op::Return => return Ok(ExpressionResult::Return(self.pop())),
+                Op::Break => return Ok(ExpressionResult::Break(self.pop())),
+                Op::Continue => return Ok(ExpressionResult::Continue),
+                Op::End => return Ok(ExpressionResult::Value(self.pop())),
+            }
+        }
+    }
+
+    #[inline]
+    fn pop(&mut self) -> DayObject {
+        self.stack.pop().expect("the compiler balances the stack")
+    }
+
+    /// The scope of a loop body is only reused if no closure captured it in the last iteration
+    #[inline]
+    fn renew_scope(&mut self, capacity: u32) {
+        if Arc::strong_count(&self.scope) > 1 {
+            let outer = self.scopes.last().expect("the loop scope was entered");
+            self.scope = Arc::new(RuntimeManager::new_capacity_predecessor(
+                capacity as usize,
+                Some(Arc::clone(outer)),
+            ));
+        }
+    }
+
+    /// Pops the values of the subscripts of `shape`
+    fn subscripts(&mut self, shape: u32) -> RuntimeResult<Vec<Subscript>> {
+        let shape = &self.chunk.shapes[shape as usize];
+        let count: usize = shape
+            .iter()
+            .map(|s| match s {
+                Shape::Index => 1,
+                Shape::Slice { start, end } => *start as usize + *end as usize,
+            })
+            .sum();
+
+        let mut values = self.stack.drain(self.stack.len() - count..);
+        let mut subscripts = Vec::with_capacity(shape.len());
+        for s in shape {
+            subscripts.push(match *s {
+                Shape::Index => Subscript::Index(values.next().expect("the index was pushed")),
+                Shape::Slice { start, end } => {
+                    Subscript::Slice(bound(&mut values, start)?, bound(&mut values, end)?)
+                }
+            });
+        }
+
+        Ok(subscripts)
+    }
+
+    /// Recovers from `error` if it was raised in a try block, otherwise it ends the chunk
+    fn catch(&mut self, error: RuntimeError) -> RuntimeResult<()> {
+        let failed = self.pc - 1;
+        let handler = match self.handlers.pop() {
+            Some(handler) if error.kind().is_catchable() => handler,
+            _ => return Err(self.locate(error, failed, 0)),
+        };
+
+        // Only the nodes inside of the try block locate the caught error
+        let error = self.locate(error, failed, handler.start);
+        self.stack.truncate(handler.stack);
+        self.stack.push(DayObject::Error(Box::new(error)));
+        self.scope = handler.scope;
+        self.scopes.truncate(handler.scopes);
+        self.iters.truncate(handler.iters);
+        self.pc = handler.catch;
+        Ok(())
+    }
+
+    fn locate(&self, error: RuntimeError, pc: usize, from: usize) -> RuntimeError {
+        match self.chunk.span_at(pc, from) {
+            Some(span) => error.at(span),
+            None => error,
+        }
+    }
+}
+
+/// Calls a function value or advances an iter value
+fn call_value(callee: &mut DayObject, args: Args) -> RuntimeResult<DayObject> {
+    match callee {
+        DayObject::Function(func) => func.call(args),
+        DayObject::Iter(handle) => Ok(handle.0.next()?.unwrap_or(DayObject::None)),
+        other => Err(RuntimeError::new(
+            RuntimeErrorKind::NotCallable,
+            format!("Can't call {:?}", other),
+        )),
+    }
+}
+
+fn bound(
+    values: &mut impl Iterator<Item = DayObject>,
+    present: bool,
+) -> RuntimeResult<Option<i64>> {
+    match present {
+        true => slice_bound(values.next().expect("the bound was pushed")).map(Some),
+        false => Ok(None),
+    }
+}
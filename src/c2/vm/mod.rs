@@ -0,0 +1,209 @@
+//NOTE The bytecode backend, enabled by the `vm` feature.
+//
+//Blocks are compiled into a `Chunk` of ops for a stack machine the first time they are
+//executed. Nested blocks like loop bodies and branches are compiled into the chunk of the
+//enclosing block, functions get their own chunk which is compiled on their first call.
+//Variables keep living in the `RuntimeManager` scopes, the ops address them by the
+//`(id, depth)` slots the parser resolved, so compiled code and the tree walker can call
+//each other. Default values of parameters are still evaluated by the tree walker.
+
+mod compiler;
+mod machine;
+
+pub use compiler::compile;
+pub use machine::run;
+
+use crate::{
+    base::{DayObject, RustFunction},
+    module::FileModule,
+    node::{BinaryOp, FunctionDef},
+    runtime_error::RuntimeError,
+    span::Span,
+};
+use std::{cell::Cell, ops::Range, sync::Arc};
+
+/// An instruction of the machine. Jump targets are indices into the ops of the chunk,
+/// the other operands index the pools of the chunk or are variable slots.
+#[derive(Clone, Copy, Debug)]
+pub enum Op {
+    /// Pushes a value of the constant pool
+    Const(u32),
+    PushNone,
+    Pop,
+    LoadVar {
+        id: u32,
+        depth: u32,
+    },
+    /// Pops the value of an existing variable
+    StoreVar {
+        id: u32,
+        depth: u32,
+    },
+    /// Pops the value of a variable declared in the current scope
+    DefVar(u32),
+    /// Pushes the args of the innermost function call
+    LoadArgs,
+    LoadModuleVar {
+        module: u32,
+        id: u32,
+    },
+    /// Executes an imported file module unless it already was
+    Import(u32),
+    /// Pushes a function of the function pool capturing the current scope
+    Closure(u32),
+    /// Declares a named function of the function pool in the variable of the current scope
+    DefFunction {
+        function: u32,
+        id: u32,
+    },
+    /// Pops `argc` args and pushes the result of the call
+    CallRust {
+        function: RustFunction,
+        argc: u32,
+    },
+    /// Like `CallRust` but calls the value of a variable (or advances the iter in it)
+    CallVar {
+        id: u32,
+        depth: u32,
+        argc: u32,
+    },
+    /// Like `CallVar` but the callee is pushed before the args
+    Call {
+        argc: u32,
+    },
+    /// Pops the values of the subscripts of a shape and the indexed value on top of them
+    Index {
+        shape: u32,
+    },
+    /// Like `Index` but indexes a variable in place
+    IndexVar {
+        id: u32,
+        depth: u32,
+        shape: u32,
+    },
+    /// Pops the values of the subscripts of a shape and the assigned value below them
+    AssignIndex {
+        id: u32,
+        depth: u32,
+        shape: u32,
+    },
+    /// Pops the given number of key value pairs into a map
+    MakeMap(u32),
+    /// Pops both sides of a binary op and pushes its result
+    Binary(BinaryOp),
+    /// Converts the top of the stack to a bool
+    ToBool,
+    /// Pops the left side of `&&`, if it is false pushes false and jumps
+    And(u32),
+    /// Pops the left side of `||`, if it is true pushes true and jumps
+    Or(u32),
+    Jump(u32),
+    /// Pops a condition of a loop and jumps if it is false
+    JumpIfFalse(u32),
+    /// Pops a condition of a branch and jumps unless it is exactly true
+    JumpUnlessTrue(u32),
+    /// Enters a new scope with the given capacity nested in the current one
+    EnterScope(u32),
+    LeaveScope,
+    /// Swaps the current scope with the one it is nested in,
+    /// the condition of a while loop is evaluated outside of the body
+    SwapScope,
+    /// Counts a loop iteration and replaces the scope of the body (with the given capacity)
+    /// if a closure captured it in the last iteration
+    NextIteration(u32),
+    /// Pops a value and makes it the iter of the innermost for loop
+    Iter,
+    /// Advances the iter of the innermost for loop and binds the element like `NextIteration`,
+    /// jumps to `exit` once it is exhausted
+    Next {
+        exit: u32,
+        capacity: u32,
+        destructure: bool,
+    },
+    PopIter,
+    /// Errors after this op jump to the given catch block until the handler is popped
+    PushHandler(u32),
+    PopHandler,
+    /// Fails with an error of the error pool
+    Raise(u32),
+    /// Pops the value the chunk returns
+    Return,
+    /// Pops the value of a `break` outside of any loop of the chunk
+    Break,
+    Continue,
+    /// Pops the value of the last node, this is always the last op of a chunk
+    End,
+}
+
+/// A subscript of an index expression, the bounds of a slice are only on the stack if present
+#[derive(Clone, Copy, Debug)]
+pub enum Subscript {
+    Index,
+    Slice { start: bool, end: bool },
+}
+
+/// The compiled code of a block
+#[derive(Debug, Default)]
+pub struct Chunk {
+    ops: Vec<Op>,
+    consts: Vec<DayObject>,
+    functions: Vec<Arc<FunctionDef>>,
+    modules: Vec<Arc<FileModule>>,
+    shapes: Vec<Vec<Subscript>>,
+    errors: Vec<RuntimeError>,
+    /// The ops compiled from nodes with a span, inner nodes come before the outer ones
+    spans: Vec<(Range<u32>, Span)>,
+}
+
+impl Chunk {
+    pub fn ops(&self) -> &[Op] {
+        &self.ops
+    }
+
+    /// The span of the innermost node containing the op at `pc`,
+    /// only nodes starting at `from` or later are considered
+    fn span_at(&self, pc: usize, from: usize) -> Option<Span> {
+        let pc = pc as u32;
+        self.spans
+            .iter()
+            .find(|(range, _)| range.start >= from as u32 && range.contains(&pc))
+            .map(|(_, span)| *span)
+    }
+}
+
+/// The backends blocks can be executed by
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Backend {
+    #[default]
+    Vm,
+    /// The tree walker of `node`
+    Tree,
+}
+
+thread_local! {
+    /// The backend of the script running on this thread
+    static BACKEND: Cell<Backend> = const { Cell::new(Backend::Vm) };
+}
+
+/// Restores the backend of the enclosing run when dropped
+pub struct BackendGuard {
+    previous: Backend,
+}
+
+impl Drop for BackendGuard {
+    fn drop(&mut self) {
+        BACKEND.with(|backend| backend.set(self.previous))
+    }
+}
+
+/// Makes the code running on this thread use `backend` until the guard is dropped
+pub fn install(backend: Backend) -> BackendGuard {
+    BackendGuard {
+        previous: BACKEND.with(|current| current.replace(backend)),
+    }
+}
+
+#[inline]
+pub fn backend() -> Backend {
+    BACKEND.with(|backend| backend.get())
+}
@@ -0,0 +1,180 @@
+use crabscript::{
+    debug::{Action, Debugger, Pause, Reason},
+    engine::Engine,
+};
+use std::{
+    collections::BTreeSet,
+    io::{self, BufRead, Write},
+    path::Path,
+};
+
+const HELP: &str = "s, step         execute the statement, pausing in called functions
+n, next         execute the statement and the functions it calls
+o, out          run until the current function returned
+c, continue     run until the next breakpoint
+b, break [line] add a breakpoint or list the breakpoints
+d, delete line  remove a breakpoint
+p, print name   show the value of a variable
+v, vars         show the variables of all visible scopes
+bt, calls       show the called functions
+l, list         show the code around the statement
+q, quit         stop the script";
+
+/// Runs the file in `args` under the debugger, `-b line` sets a breakpoint before it starts.
+/// Without breakpoints it pauses at the first statement. Returns the exit code.
+pub fn run(args: &[String]) -> i32 {
+    let mut breakpoints = BTreeSet::new();
+    let mut path = None;
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "-b" => match args.next().and_then(|line| line.parse().ok()) {
+                Some(line) => {
+                    breakpoints.insert(line);
+                }
+                None => path = None,
+            },
+            _ => path = Some(arg),
+        }
+    }
+    let path = match path {
+        Some(path) => path,
+        None => {
+            eprintln!("Usage: crabscript debug [-b line]... <file>");
+            return 2;
+        }
+    };
+
+    let source = match std::fs::read_to_string(path) {
+        Ok(source) => source,
+        Err(e) => {
+            eprintln!("Can't read {}: {}", path, e);
+            return 2;
+        }
+    };
+
+    let mut engine = Engine::new();
+    engine.set_argv([path.clone()]);
+    if let Some(dir) = Path::new(path).parent() {
+        engine.add_search_path(dir);
+    }
+    println!("Debugging {}, enter h for help", path);
+    engine.set_debugger(Cli {
+        path: path.clone(),
+        source: source.clone(),
+        starting: breakpoints.is_empty(),
+        breakpoints,
+    });
+
+    match engine.eval(&source) {
+        Ok(_) => 0,
+        Err(e) => {
+            eprintln!("{}", e.render(&source));
+            1
+        }
+    }
+}
+
+struct Cli {
+    path: String,
+    source: String,
+    /// The lines of the debugged file
+    breakpoints: BTreeSet<u64>,
+    /// Set until the first pause, which is at the first statement
+    starting: bool,
+}
+
+impl Cli {
+    fn show_location(&self, pause: &Pause, context: u64) {
+        let (path, source) = match pause.file {
+            Some(file) => (
+                file.display().to_string(),
+                std::fs::read_to_string(file).unwrap_or_default(),
+            ),
+            None => (self.path.clone(), self.source.clone()),
+        };
+        println!("{}:{}", path, pause.span.line);
+
+        let first = pause.span.line.saturating_sub(context).max(1);
+        for (line, text) in (1..).zip(source.lines()) {
+            if line >= first && line <= pause.span.line + context {
+                let marker = if line == pause.span.line { "->" } else { "  " };
+                println!("{} {:>4} {}", marker, line, text);
+            }
+        }
+    }
+}
+
+impl Debugger for Cli {
+    fn is_breakpoint(&self, file: Option<&Path>, line: u64) -> bool {
+        self.starting || (file.is_none() && self.breakpoints.contains(&line))
+    }
+
+    fn paused(&mut self, pause: &Pause) -> Action {
+        if pause.reason == Reason::Breakpoint && !std::mem::take(&mut self.starting) {
+            print!("Breakpoint at ");
+        }
+        self.show_location(pause, 0);
+
+        loop {
+            print!("(debug) ");
+            let _ = io::stdout().flush();
+            let mut input = String::new();
+            if io::stdin().lock().read_line(&mut input).unwrap_or(0) == 0 {
+                // Without input the script runs to its end
+                self.breakpoints.clear();
+                return Action::Continue;
+            }
+
+            let mut words = input.split_whitespace();
+            let command = words.next().unwrap_or("s");
+            let arg = words.next();
+            match (command, arg) {
+                ("s" | "step", _) => return Action::StepInto,
+                ("n" | "next", _) => return Action::StepOver,
+                ("o" | "out", _) => return Action::StepOut,
+                ("c" | "continue", _) => return Action::Continue,
+                ("b" | "break", None) => {
+                    let lines: Vec<_> = self.breakpoints.iter().map(u64::to_string).collect();
+                    println!("Breakpoints: {}", lines.join(", "));
+                }
+                ("b" | "break", Some(line)) => match line.parse() {
+                    Ok(line) => {
+                        self.breakpoints.insert(line);
+                    }
+                    Err(_) => println!("{} is no line", line),
+                },
+                ("d" | "delete", Some(line)) => {
+                    if !line
+                        .parse()
+                        .is_ok_and(|line| self.breakpoints.remove(&line))
+                    {
+                        println!("There is no breakpoint at {}", line);
+                    }
+                }
+                ("p" | "print", Some(name)) => match pause.var(name) {
+                    Some(value) => println!("{} = {:?}", name, value),
+                    None => println!("{} is not declared here", name),
+                },
+                ("v" | "vars", _) => {
+                    for scope in pause.scopes() {
+                        println!("depth {}:", scope.depth);
+                        for (name, value) in scope.vars {
+                            println!("  {} = {:?}", name, value);
+                        }
+                    }
+                }
+                ("bt" | "calls", _) => {
+                    for (depth, name) in pause.calls.iter().enumerate().rev() {
+                        println!("{:>4} {}", depth, name);
+                    }
+                    println!("   - top level");
+                }
+                ("l" | "list", _) => self.show_location(pause, 5),
+                ("q" | "quit", _) => std::process::exit(0),
+                ("h" | "help", _) => println!("{}", HELP),
+                _ => println!("Unknown command {}, enter h for help", input.trim()),
+            }
+        }
+    }
+}
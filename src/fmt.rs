@@ -0,0 +1,46 @@
+use crabscript::{error::CrabError, format::format};
+
+/// Formats the files in `args`, with `--check` they are only checked.
+/// Returns the exit code, files with unmatched brackets or that aren't formatted make it non-zero.
+pub fn run(args: &[String]) -> i32 {
+    let check = args.iter().any(|a| a == "--check");
+    let paths: Vec<_> = args.iter().filter(|a| *a != "--check").collect();
+    if paths.is_empty() {
+        eprintln!("Usage: crabscript fmt [--check] <files>");
+        return 2;
+    }
+
+    let mut code = 0;
+    for path in paths {
+        let src = match std::fs::read_to_string(path) {
+            Ok(src) => src,
+            Err(e) => {
+                eprintln!("Can't read {}: {}", path, e);
+                code = 2;
+                continue;
+            }
+        };
+
+        let formatted = match format(&src) {
+            Ok(formatted) => formatted,
+            Err(e) => {
+                eprintln!("{}: {}", path, CrabError::from(e).render(&src));
+                code = code.max(1);
+                continue;
+            }
+        };
+        if formatted == src {
+            continue;
+        }
+
+        if check {
+            println!("{} is not formatted", path);
+            code = code.max(1);
+        } else if let Err(e) = std::fs::write(path, formatted) {
+            eprintln!("Can't write {}: {}", path, e);
+            code = 2;
+        }
+    }
+
+    code
+}
@@ -25,6 +25,13 @@ mod dbg_print {
                 panic!($msg)
             }
         }};
+        ($expr:expr => $enum:path | return $err:expr) => {{
+            if let $enum(item) = $expr {
+                item
+            } else {
+                return Err($err);
+            }
+        }};
     }
 }
 
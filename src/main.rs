@@ -1,9 +1,129 @@
-use crabscript::run;
+use crabscript::{
+    dump::{dump_ast, dump_tokens, Format},
+    engine::Engine,
+    optimizer::OptLevel,
+    profile::Profile,
+};
+use std::path::Path;
+
+mod debug;
+mod fmt;
+mod repl;
+mod test;
+
+/// What is done with the file
+#[derive(PartialEq)]
+enum Mode {
+    Run,
+    DumpAst,
+    DumpTokens,
+    /// Runs the file and reports where it spent its time
+    Profile,
+}
+
+const USAGE: &str = "Usage: crabscript [-O0|-O1|-O2] [--dump-ast|--dump-tokens [--json]|--profile] [<file> [args]...]
+       crabscript fmt|debug|test ...";
 
 fn main() {
-    let file_content = std::fs::read_to_string(std::env::args().nth(1).unwrap()).unwrap();
-    if let Err(e) = run(&file_content) {
-        eprintln!("{}", e);
+    let mut opt_level = OptLevel::default();
+    let mut mode = Mode::Run;
+    let mut format = Format::Text;
+    let mut path = None;
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    match args.first().map(String::as_str) {
+        Some("fmt") => std::process::exit(fmt::run(&args[1..])),
+        Some("debug") => std::process::exit(debug::run(&args[1..])),
+        Some("test") => std::process::exit(test::run(&args[1..])),
+        _ => (),
+    }
+
+    // The args after the path are passed to the script, after the path itself
+    let mut args = args.into_iter();
+    for arg in args.by_ref() {
+        match arg.as_str() {
+            "-O0" => opt_level = OptLevel::None,
+            "-O1" => opt_level = OptLevel::Basic,
+            "-O2" => opt_level = OptLevel::Full,
+            "--dump-ast" => mode = Mode::DumpAst,
+            "--dump-tokens" => mode = Mode::DumpTokens,
+            "--profile" => mode = Mode::Profile,
+            "--json" => format = Format::Json,
+            "-i" => (),
+            flag if flag.starts_with('-') => {
+                eprintln!("Unknown flag {}\n{}", flag, USAGE);
+                std::process::exit(2)
+            }
+            _ => {
+                path = Some(arg);
+                break;
+            }
+        }
+    }
+
+    let path = match path {
+        Some(path) => path,
+        None if mode == Mode::Profile => {
+            eprintln!("Profiling needs a file");
+            std::process::exit(2)
+        }
+        None if mode != Mode::Run => {
+            eprintln!("Dumping needs a file");
+            std::process::exit(2)
+        }
+        None => {
+            if let Err(e) = repl::run(opt_level) {
+                eprintln!("{}", e);
+                std::process::exit(1)
+            }
+            return;
+        }
+    };
+
+    let file_content = match std::fs::read_to_string(&path) {
+        Ok(content) => content,
+        Err(e) => {
+            eprintln!("Can't read {}: {}", path, e);
+            std::process::exit(2)
+        }
+    };
+
+    if mode == Mode::DumpTokens {
+        print!("{}", dump_tokens(&file_content, format));
+        return;
+    }
+
+    let mut engine = Engine::new();
+    engine.set_argv(std::iter::once(path.clone()).chain(args));
+    engine.set_opt_level(opt_level);
+    if let Some(dir) = Path::new(&path).parent() {
+        engine.add_search_path(dir);
+    }
+    if mode == Mode::Profile {
+        engine.start_profiling();
+    }
+    let res = engine.compile(&file_content).and_then(|script| match mode {
+        Mode::DumpAst => {
+            print!("{}", dump_ast(script.block(), &engine.scopes(), format));
+            Ok(())
+        }
+        _ => engine.run(&script).map(drop),
+    });
+    if let Some(profile) = engine.take_profile() {
+        write_profile(&profile, &path);
+    }
+    if let Err(e) = res {
+        eprintln!("{}", e.render(&file_content));
         std::process::exit(1)
     }
 }
+
+/// Prints the report to stderr, so it doesn't mix with the output of the script,
+/// and writes the folded stacks next to the file
+fn write_profile(profile: &Profile, path: &str) {
+    eprint!("{}", profile.report(path));
+    let folded = format!("{}.folded", path);
+    match std::fs::write(&folded, profile.folded()) {
+        Ok(()) => eprintln!("\nFolded stacks written to {}", folded),
+        Err(e) => eprintln!("\nCan't write {}: {}", folded, e),
+    }
+}
@@ -0,0 +1,97 @@
+use crabscript::{base::DayObject, engine::Engine, optimizer::OptLevel, tokenizer::is_incomplete};
+use rustyline::{error::ReadlineError, DefaultEditor};
+use std::path::PathBuf;
+
+const HELP: &str = "Enter statements to execute them, the value of the last one is printed.
+Input continues on the next line while brackets are unclosed, Ctrl-C discards it.
+
+:help          show this message
+:vars          list the top level variables and their values
+:load <file>   execute a file in this shell
+:quit          leave the shell (or Ctrl-D)";
+
+/// Runs the interactive shell until the input ends
+pub fn run(opt_level: OptLevel) -> rustyline::Result<()> {
+    let mut editor = DefaultEditor::new()?;
+    let history = history_path();
+    if let Some(path) = &history {
+        // There is no history on the first start
+        let _ = editor.load_history(path);
+    }
+
+    let mut engine = Engine::new();
+    engine.set_opt_level(opt_level);
+    let mut input = String::new();
+    loop {
+        let prompt = if input.is_empty() { ">> " } else { ".. " };
+        match editor.readline(prompt) {
+            Ok(line) => {
+                if input.is_empty() {
+                    if let Some(command) = line.trim().strip_prefix(':') {
+                        editor.add_history_entry(line.as_str())?;
+                        if !meta_command(command, &mut engine) {
+                            break;
+                        }
+                        continue;
+                    }
+                }
+
+                input.push_str(&line);
+                input.push('\n');
+                if is_incomplete(&input) {
+                    continue;
+                }
+
+                editor.add_history_entry(input.trim_end())?;
+                eval(&mut engine, &input);
+                input.clear();
+            }
+            Err(ReadlineError::Interrupted) => input.clear(),
+            Err(ReadlineError::Eof) => break,
+            Err(e) => return Err(e),
+        }
+    }
+
+    if let Some(path) = &history {
+        editor.save_history(path)?;
+    }
+    Ok(())
+}
+
+fn eval(engine: &mut Engine, src: &str) {
+    match engine.eval(src) {
+        Ok(DayObject::None) => (),
+        Ok(value) => println!("{:?}", value),
+        Err(e) => eprintln!("{}", e.render(src)),
+    }
+}
+
+/// Executes a command starting with `:`, returns false if the shell should be left
+fn meta_command(command: &str, engine: &mut Engine) -> bool {
+    let (name, arg) = match command.split_once(char::is_whitespace) {
+        Some((name, arg)) => (name, arg.trim()),
+        None => (command, ""),
+    };
+
+    match name {
+        "help" => println!("{}", HELP),
+        "vars" => {
+            for (name, value) in engine.vars() {
+                println!("{} = {:?}", name, value);
+            }
+        }
+        "load" if !arg.is_empty() => match std::fs::read_to_string(arg) {
+            Ok(src) => eval(engine, &src),
+            Err(e) => eprintln!("Can't read {}: {}", arg, e),
+        },
+        "load" => eprintln!("usage: :load <file>"),
+        "quit" => return false,
+        _ => eprintln!("Unknown command :{}, see :help", name),
+    }
+
+    true
+}
+
+fn history_path() -> Option<PathBuf> {
+    std::env::var_os("HOME").map(|home| PathBuf::from(home).join(".crabscript_history"))
+}
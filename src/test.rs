@@ -0,0 +1,54 @@
+use crabscript::golden::{check_all, discover};
+use std::path::PathBuf;
+
+/// Checks the scripts in `args` against their expectation files, directories are searched for
+/// scripts and `Tests` is checked without paths. `--bless` updates the expectation files instead.
+/// Returns the exit code.
+pub fn run(args: &[String]) -> i32 {
+    let mut bless = false;
+    let mut paths = Vec::new();
+    for arg in args {
+        match arg.as_str() {
+            "--bless" => bless = true,
+            _ => paths.push(PathBuf::from(arg)),
+        }
+    }
+    if paths.is_empty() {
+        paths.push(PathBuf::from("Tests"));
+    }
+
+    let mut scripts = Vec::new();
+    for path in paths {
+        if !path.is_dir() {
+            scripts.push(path);
+            continue;
+        }
+        match discover(&path) {
+            Ok(found) => scripts.extend(found),
+            Err(e) => {
+                eprintln!("Can't read {}: {}", path.display(), e);
+                return 2;
+            }
+        }
+    }
+
+    let outcomes = check_all(&scripts, bless);
+    for outcome in &outcomes {
+        println!("{}", outcome);
+    }
+    let failed = outcomes.iter().filter(|o| !o.passed()).count();
+    let skipped = outcomes.iter().filter(|o| o.skipped.is_some()).count();
+    println!(
+        "\n{} passed, {} failed, {} skipped{}",
+        outcomes.len() - failed - skipped,
+        failed,
+        skipped,
+        if bless { ", expectations blessed" } else { "" }
+    );
+
+    if failed > 0 {
+        1
+    } else {
+        0
+    }
+}
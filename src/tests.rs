@@ -1,4 +1,27 @@
-use super::run;
+use super::{
+    analysis::{analyze, CompletionKind, Target},
+    base::DayObject,
+    budget::Limits,
+    build_pre_map,
+    capabilities::Capabilities,
+    debug::{Action, Debugger, Pause, Reason},
+    dump::{dump_ast, dump_tokens, Format},
+    engine::Engine,
+    error::CrabError,
+    format::format,
+    golden::{check, discover, expectation_path, Annotations, Failure},
+    manager::RuntimeManager,
+    module::NativeModule,
+    optimizer::OptLevel,
+    parser::Parser,
+    parsing_error::ParsingErrorKind,
+    profile::FunctionKind,
+    run,
+    runtime_error::{RuntimeError, RuntimeErrorKind},
+    stdio::{Capture, Stdio, Tee},
+    tokenizer::{build_lexer, is_incomplete, Token, TokenStream},
+};
+use std::{cell::RefCell, path::Path, rc::Rc, sync::Arc};
 
 #[test]
 fn arithmetics() {
@@ -459,3 +482,1600 @@ pub fn iter_test() {
     }
     ").unwrap();
 }*/
+
+#[test]
+pub fn runtime_error_type() {
+    let err = run("let x = 10
+    let y = add(x, \"ten\")")
+    .unwrap_err();
+
+    match err {
+        CrabError::Runtime(e) => {
+            assert_eq!(e.kind(), RuntimeErrorKind::TypeError);
+            assert_eq!(e.line(), Some(2));
+        }
+        other => panic!("expected a runtime error received {:?}", other),
+    }
+}
+
+#[test]
+pub fn runtime_error_not_callable() {
+    let err = run("let x = 10
+    x()")
+    .unwrap_err();
+
+    match err {
+        CrabError::Runtime(e) => assert_eq!(e.kind(), RuntimeErrorKind::NotCallable),
+        other => panic!("expected a runtime error received {:?}", other),
+    }
+}
+
+#[test]
+pub fn runtime_error_io() {
+    let err = run(r#"cat("/this/file/does/not/exist.crab")"#).unwrap_err();
+
+    match err {
+        CrabError::Runtime(e) => assert_eq!(e.kind(), RuntimeErrorKind::Io),
+        other => panic!("expected a runtime error received {:?}", other),
+    }
+}
+
+#[test]
+pub fn runtime_error_assert() {
+    let err = run("assert(eq(1, 2), \"one is not two\")").unwrap_err();
+
+    match err {
+        CrabError::Runtime(e) => {
+            assert_eq!(e.kind(), RuntimeErrorKind::AssertionFailed);
+            assert_eq!(e.message(), "\"one is not two\"");
+        }
+        other => panic!("expected a runtime error received {:?}", other),
+    }
+}
+
+#[test]
+pub fn runtime_error_in_loop() {
+    let err = run("let i = 0
+    while lt(i, 3) {
+        i = add(i, 1)
+    }
+    for x in range(0, 3) {
+        div(x, 0)
+    }")
+    .unwrap_err();
+
+    match err {
+        CrabError::Runtime(e) => assert_eq!(e.kind(), RuntimeErrorKind::Arithmetic),
+        other => panic!("expected a runtime error received {:?}", other),
+    }
+}
+
+#[test]
+pub fn integer_overflow() {
+    for src in [
+        "let x = 9223372036854775807\nadd(x, 1)",
+        "let x = 9223372036854775807\nx + 1",
+        "let x = 0 - 9223372036854775807\nsub(x, 2)",
+        "let x = 4611686018427387904\nmul(x, 2)",
+        "let x = 0 - 9223372036854775807\nneg(sub(x, 1))",
+    ] {
+        match run(src).unwrap_err() {
+            CrabError::Runtime(e) => {
+                assert_eq!(e.kind(), RuntimeErrorKind::Arithmetic);
+                assert!(e.message().contains("overflows"), "{}", e.message());
+            }
+            other => panic!("expected a runtime error received {:?}", other),
+        }
+    }
+}
+
+#[test]
+pub fn integer_literal_range() {
+    run("assert(eq(-9223372036854775808, sub(0 - 9223372036854775807, 1)))").unwrap();
+    run("assert(eq(9223372036854775807, add(9223372036854775806, 1)))").unwrap();
+    for src in [
+        "let x = 9223372036854775808",
+        "let x = -9223372036854775809",
+        "let x = 99999999999999999999999",
+    ] {
+        match run(src).unwrap_err() {
+            CrabError::Parsing(e) => assert_eq!(*e.kind(), ParsingErrorKind::IntegerOutOfRange),
+            other => panic!("expected a parsing error received {:?}", other),
+        }
+    }
+}
+
+#[test]
+pub fn defaults_use_declaring_scope() {
+    run(r#"
+    fn outer {
+        fn inner(a = len(args)) { ret a }
+        ret inner()
+    }
+    assert(eq(outer(1, 2, 3), 3))
+    "#)
+    .unwrap();
+    match run("fn f(a = args) { ret a }\nf()").unwrap_err() {
+        CrabError::Runtime(e) => assert_eq!(e.kind(), RuntimeErrorKind::ArgsOutsideFunction),
+        other => panic!("expected a runtime error received {:?}", other),
+    }
+}
+
+#[test]
+pub fn redeclared_variables() {
+    run(r#"
+    for i in range(0, 2) {
+        let i = 7
+        let j = 1
+        assert(eq(i, 7))
+    }
+    let x = 1
+    let x = 2
+    let y = 3
+    assert(eq(x, 2))
+    "#)
+    .unwrap();
+    match run("fn f(a, b = 1, a) { ret a }").unwrap_err() {
+        CrabError::Parsing(e) => assert_eq!(
+            *e.kind(),
+            ParsingErrorKind::DuplicateParameter("a".to_string())
+        ),
+        other => panic!("expected a parsing error received {:?}", other),
+    }
+}
+
+#[test]
+pub fn args_outside_function() {
+    for src in ["args[0]", "println(1)\nif true { let a = args }"] {
+        match run(src).unwrap_err() {
+            CrabError::Runtime(e) => assert_eq!(e.kind(), RuntimeErrorKind::ArgsOutsideFunction),
+            other => panic!("expected a runtime error received {:?}", other),
+        }
+    }
+    run("fn f { ret args[0] }\nassert(eq(f(3), 3))").unwrap();
+}
+
+#[test]
+pub fn operators_end_at_line_breaks() {
+    run("let x = 5\nlet y = x\n-1\nassert(eq(y, 5))").unwrap();
+    run("let z = 5 +\n  2\nassert(eq(z, 7))").unwrap();
+    run("assert(eq(add(1,\n -2), -1))").unwrap();
+}
+
+#[test]
+pub fn try_catch_io() {
+    run(r#"
+    let content = "default"
+    try {
+        content = cat("/this/file/does/not/exist.crab")
+    } catch e {
+        assert(is_error(e))
+        assert(eq(error_kind(e), "IoError"))
+    }
+    assert(eq(content, "default"))
+    "#)
+    .unwrap();
+}
+
+#[test]
+pub fn try_catch_raise() {
+    run(r#"
+    let caught = false
+    try {
+        raise(error("custom failure"))
+        panic("raise didn't stop the try block")
+    } catch err {
+        caught = true
+        assert(eq(error_message(err), "custom failure"))
+        assert(eq(error_kind(err), "Error"))
+    }
+    assert(caught)
+    "#)
+    .unwrap();
+}
+
+#[test]
+pub fn try_without_error() {
+    run(r#"
+    let x = 0
+    try {
+        x = 1
+    } catch {
+        x = 2
+    }
+    assert(eq(x, 1))
+    assert(eq(is_error(x), false))
+    "#)
+    .unwrap();
+}
+
+#[test]
+pub fn try_catch_reraise() {
+    let err = run(r#"
+    try {
+        try {
+            div(1, 0)
+        } catch inner {
+            raise(inner)
+        }
+    } catch outer {
+        raise(error_message(outer))
+    }
+    "#)
+    .unwrap_err();
+
+    match err {
+        CrabError::Runtime(e) => assert_eq!(e.kind(), RuntimeErrorKind::Custom),
+        other => panic!("expected a runtime error received {:?}", other),
+    }
+}
+
+#[test]
+pub fn runtime_error_span() {
+    let src = "let i = 0
+for x in range(0, 3) {
+\tdiv(x, i)
+}";
+    let err = run(src).unwrap_err();
+    let span = err.span().unwrap();
+
+    assert_eq!((span.line, span.column), (3, 2));
+    assert_eq!(
+        err.render(src),
+        "ERROR [l. 3, c. 2]:\tArithmeticError: div of 0 by 0 is not defined
+3 | \tdiv(x, i)
+  | \t^^^^^^^^^"
+    );
+}
+
+#[test]
+pub fn traceback() {
+    let src = "fn inner(n) {
+    ret div(n, 0)
+}
+let twice = fn(n) { ret inner(n) }
+fn rec(n) {
+    if eq(n, 0) { ret twice(n) }
+    ret rec(sub(n, 1))
+}
+rec(5)";
+    let err = run(src).unwrap_err();
+    let e = match &err {
+        CrabError::Runtime(e) => e,
+        other => panic!("expected a runtime error received {:?}", other),
+    };
+    let frames: Vec<_> = e
+        .trace()
+        .iter()
+        .map(|frame| (frame.function.as_str(), frame.call_site.unwrap().line))
+        .collect();
+    assert_eq!(frames[..3], [("inner", 4), ("fn", 6), ("rec", 7)]);
+    assert_eq!(frames.len(), 8);
+
+    assert_eq!(
+        err.render(src),
+        "Traceback (most recent call last):
+  line 9, in top level
+    rec(5)
+  line 7, in rec
+    ret rec(sub(n, 1))
+  line 7, in rec
+    ret rec(sub(n, 1))
+  line 7, in rec
+    ret rec(sub(n, 1))
+  [Previous line repeated 2 more times]
+  line 6, in rec
+    if eq(n, 0) { ret twice(n) }
+  line 4, in fn
+    let twice = fn(n) { ret inner(n) }
+  line 2, in inner
+    ret div(n, 0)
+ERROR [l. 2, c. 9]:\tArithmeticError: div of 0 by 0 is not defined
+2 |     ret div(n, 0)
+  |         ^^^^^^^^^"
+    );
+
+    // The arguments are checked before the function starts, the error is at the call site
+    let err = run("fn f(a) { ret a }\nf(1, 2)").unwrap_err();
+    assert!(!err.render("").contains("Traceback"));
+    assert_eq!(err.span().unwrap().line, 2);
+
+    // Caught errors keep the trace
+    let mut engine = Engine::new();
+    engine
+        .eval("fn f() { raise(\"no\") }\nlet e = none\ntry { f() } catch err { e = err }")
+        .unwrap();
+    match engine.get_var("e") {
+        Some(DayObject::Error(e)) => assert_eq!(e.trace()[0].function, "f"),
+        other => panic!("expected an error received {:?}", other),
+    }
+}
+
+#[test]
+pub fn profile() {
+    let mut engine = Engine::new();
+    engine.set_opt_level(OptLevel::None);
+    assert!(engine.take_profile().is_none());
+    engine.start_profiling();
+    engine
+        .eval(
+            "fn rec(n) {
+    if eq(n, 0) { ret 0 }
+    ret rec(sub(n, 1))
+}
+let square = fn(x) { ret mul(x, x) }
+for i in range(0, 3) {
+    rec(4)
+}
+square(2)",
+        )
+        .unwrap();
+    engine.call("square", &[DayObject::Integer(3)]).unwrap();
+    let profile = engine.take_profile().unwrap();
+
+    let rec = profile.function("rec").unwrap();
+    assert_eq!((rec.calls, rec.kind), (15, FunctionKind::Script));
+    assert!(rec.inclusive >= rec.exclusive);
+    assert_eq!(profile.function("fn:5").unwrap().calls, 2);
+    let sub = profile.function("math.sub").unwrap();
+    assert_eq!((sub.calls, sub.kind), (12, FunctionKind::Std));
+    assert!(rec.inclusive >= sub.inclusive);
+    // The `if` and the `ret` in it
+    assert_eq!(profile.line(None, 2).unwrap().hits, 18);
+    assert_eq!(profile.line(None, 7).unwrap().hits, 3);
+    assert!(profile.line(None, 4).is_none());
+    assert!(profile.total() >= rec.inclusive);
+
+    let report = profile.report("main.crab");
+    assert!(report.contains("math.sub (std)"));
+    assert!(report.contains("main.crab:7"));
+    for stack in profile.folded().lines() {
+        let (stack, time) = stack.rsplit_once(' ').unwrap();
+        assert!(stack.starts_with("top level"));
+        assert!(time.parse::<u64>().unwrap() > 0);
+    }
+}
+
+#[test]
+pub fn golden() {
+    let dir = std::env::temp_dir().join("crabscript_golden");
+    let _ = std::fs::remove_dir_all(&dir);
+    std::fs::create_dir_all(dir.join("modules")).unwrap();
+    let script = dir.join("script.crab");
+    std::fs::write(&script, "println(1)\neprintln(2)").unwrap();
+    std::fs::write(dir.join("script.crab.out"), "2\n").unwrap();
+
+    assert_eq!(discover(&dir).unwrap(), std::slice::from_ref(&script));
+    let outcome = check(&script, false);
+    match &outcome.failures[..] {
+        [Failure::Mismatch { received, .. }, Failure::Mismatch { expected, .. }] => {
+            assert_eq!((received.as_str(), expected.as_str()), ("1\n", ""))
+        }
+        other => panic!("expected two mismatches received {:?}", other),
+    }
+
+    assert!(check(&script, true).passed());
+    assert!(check(&script, false).passed());
+    assert_eq!(
+        std::fs::read_to_string(expectation_path(&script, "err")).unwrap(),
+        "2\n"
+    );
+    std::fs::write(&script, "println(1)").unwrap();
+    assert!(check(&script, true).passed());
+    assert!(!expectation_path(&script, "err").exists());
+
+    std::fs::write(&script, "// expect-error: not defined\nprintln(x)").unwrap();
+    assert!(check(&script, true).passed());
+    std::fs::write(&script, "// expect-error: not defined\nprintln(1)").unwrap();
+    assert!(matches!(
+        check(&script, true).failures[..],
+        [Failure::MissingError(_)]
+    ));
+    std::fs::write(
+        &script,
+        "// expect-error: steps\n// max-steps: 5\nwhile true {}",
+    )
+    .unwrap();
+    assert!(check(&script, true).passed());
+    std::fs::write(&script, "println(x)").unwrap();
+    assert!(matches!(
+        check(&script, true).failures[..],
+        [Failure::UnexpectedError(_)]
+    ));
+    std::fs::write(&script, "// skip: not implemented\nprintln(x)").unwrap();
+    let outcome = check(&script, false);
+    assert!(outcome.passed());
+    assert_eq!(outcome.skipped.as_deref(), Some("not implemented"));
+
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+pub fn parsing_error_span() {
+    let src = "// the first line is a comment
+let a = 1
+
+  b = a";
+
+    match run(src).unwrap_err() {
+        CrabError::Parsing(e) => {
+            assert_eq!(
+                e.kind(),
+                &ParsingErrorKind::UndefinedVariable("b".to_string())
+            );
+            assert_eq!((e.span().line, e.span().column), (4, 3));
+        }
+        other => panic!("expected a parsing error received {:?}", other),
+    }
+}
+
+#[test]
+pub fn break_continue() {
+    run("let sum = 0
+    for i in range(0, 10) {
+        if eq(mod(i, 2), 0) {
+            continue
+        }
+        if gt(i, 6) {
+            break
+        }
+        sum = add(sum, i)
+    }
+    assert(eq(sum, 9))
+
+    let i = 0
+    let found = while true {
+        i = add(i, 1)
+        try {
+            if eq(i, 4) {
+                break mul(i, 10)
+            }
+        } catch {}
+    }
+    assert(eq(found, 40))")
+    .unwrap();
+}
+
+#[test]
+pub fn break_nested_loops() {
+    run("let count = 0
+    for i in range(0, 3) {
+        for j in range(0, 3) {
+            if eq(j, 1) {
+                break
+            }
+            count = add(count, 1)
+        }
+    }
+    assert(eq(count, 3))")
+    .unwrap();
+}
+
+#[test]
+pub fn break_outside_loop() {
+    match run("let x = 1
+    if eq(x, 1) {
+        break
+    }")
+    .unwrap_err()
+    {
+        CrabError::Parsing(e) => {
+            assert_eq!(
+                e.kind(),
+                &ParsingErrorKind::OutsideOfLoop("break".to_string())
+            )
+        }
+        other => panic!("expected a parsing error received {:?}", other),
+    }
+}
+
+#[test]
+pub fn operators() {
+    run("let a = 10
+    let b = 3
+    assert(1 + 2 * 3 == 7)
+    assert((1 + 2) * 3 == 9)
+    assert(a - b - 2 == 5)
+    assert(a / b == 3 && a % b == 1)
+    assert(-a + 4 == -6)
+    assert(-(a * 2) == -20)
+    assert(2.5 * 2 == 5.0)
+    assert(a -1 == 9)
+    assert(a > b && b >= 3 && !(a < b) && a != b && b <= 3)
+    assert(a < b || b == 3)
+    let i = 0
+    while i < 5 {
+        i = i + 1
+    }
+    assert(i == 5)")
+    .unwrap();
+}
+
+#[test]
+pub fn operators_short_circuit() {
+    run("let x = 0
+    assert(!(x != 0 && 10 / x > 1))
+    assert(x == 0 || 10 / x > 1)")
+    .unwrap();
+}
+
+#[test]
+pub fn operator_error_span() {
+    let err = run("let x = 0
+    let y = 1 + 10 / x")
+    .unwrap_err();
+
+    match err {
+        CrabError::Runtime(e) => {
+            assert_eq!(e.kind(), RuntimeErrorKind::Arithmetic);
+            assert_eq!(e.span().map(|s| (s.line, s.column)), Some((2, 20)));
+        }
+        other => panic!("expected a runtime error received {:?}", other),
+    }
+}
+
+#[test]
+pub fn not_fn() {
+    run("assert(not(false, 0))
+    assert(eq(not(true), false))
+    assert(eq(not(false, 1), false))")
+    .unwrap();
+}
+
+#[test]
+pub fn maps() {
+    run("let m = {
+        \"a\": 1,
+        \"b\": 2,
+    }
+    assert(m[\"a\"] + m[\"b\"] == 3)
+    m[\"c\"] = 3
+    m[\"a\"] = 10
+    assert(eq(keys(m), array(\"a\", \"b\", \"c\")))
+    assert(eq(values(m), array(10, 2, 3)))
+    assert(has(m, \"c\") && !has(m, \"d\"))
+    assert(len(m) == 3)
+    let m2 = remove(m, \"b\")
+    assert(eq(keys(m2), array(\"a\", \"c\")) && len(m) == 3)
+    assert(eq(m, dict(\"c\", 3, \"b\", 2, \"a\", 10)))
+    assert(eq(entries(m2), array(array(\"a\", 10), array(\"c\", 3))))
+    let nested = {1: {\"x\": {}}}
+    nested[1][\"x\"][true] = \"yes\"
+    assert(nested[1][\"x\"][true] == \"yes\")")
+    .unwrap();
+}
+
+#[test]
+pub fn map_iteration() {
+    run("let m = {\"x\": 1, \"y\": 2, \"z\": 3}
+    let ks = \"\"
+    let sum = 0
+    for k, v in m {
+        ks = string(array(ks, k))
+        sum = sum + v
+    }
+    assert(sum == 6)
+    let count = 0
+    for e in m {
+        count = count + len(e)
+    }
+    assert(count == 6)
+    for k, v in entries(m) {
+        if k == \"y\" {
+            assert(v == 2)
+        }
+    }")
+    .unwrap();
+}
+
+#[test]
+pub fn map_errors() {
+    match run("let m = {\"a\": 1}
+    let x = m[\"b\"]")
+    .unwrap_err()
+    {
+        CrabError::Runtime(e) => {
+            assert_eq!(e.kind(), RuntimeErrorKind::Index);
+            assert_eq!(e.line(), Some(2));
+        }
+        other => panic!("expected a runtime error received {:?}", other),
+    }
+
+    match run("let m = {1.5: 1}").unwrap_err() {
+        CrabError::Runtime(e) => assert_eq!(e.kind(), RuntimeErrorKind::TypeError),
+        other => panic!("expected a runtime error received {:?}", other),
+    }
+}
+
+#[test]
+pub fn indexing() {
+    run("let a = array(array(1, 2, 3), array(4, 5, 6))
+    assert(a[1][2] == 6)
+    assert(a[-1][-3] == 4)
+    a[0][2] = 10
+    a[-1][0] = a[0][2] + 1
+    assert(eq(a, array(array(1, 2, 10), array(11, 5, 6))))
+    assert(array(7, 8)[1] == 8)
+    assert(eq(\"crab\"[1], \"crab\"[-3]))
+    let s = \"crab\"
+    s[0] = s[3]
+    assert(eq(s, \"brab\"))
+    let r = range(0, 10)
+    assert(r[3] == 3 && r[-1] == 9)")
+    .unwrap();
+}
+
+#[test]
+pub fn slices() {
+    run("let a = array(1, 2, 3, 4, 5)
+    assert(eq(a[1:3], array(2, 3)))
+    assert(eq(a[:2], array(1, 2)))
+    assert(eq(a[-2:], array(4, 5)))
+    assert(eq(a[:], a))
+    assert(eq(\"crabscript\"[:4], \"crab\"))
+    let i = 1
+    a[i:i + 3] = array(0)
+    assert(eq(a, array(1, 0, 5)))
+    let s = \"crabscript\"
+    s[4:] = \"s\"
+    assert(eq(s, \"crabs\"))")
+    .unwrap();
+}
+
+#[test]
+pub fn index_out_of_bounds() {
+    for code in [
+        "let a = array(1, 2)\n    println(a[2])",
+        "let a = array(1, 2)\n    a[-3] = 1",
+        "let a = array(1, 2)\n    let b = a[1:3]",
+    ] {
+        match run(code).unwrap_err() {
+            CrabError::Runtime(e) => {
+                assert_eq!(e.kind(), RuntimeErrorKind::Index);
+                assert_eq!(e.line(), Some(2));
+            }
+            other => panic!("expected a runtime error received {:?}", other),
+        }
+    }
+}
+
+#[test]
+pub fn recursion() {
+    run("fn fib {
+        if args[0] < 2 {
+            ret args[0]
+        }
+        ret fib(args[0] - 1) + fib(args[0] - 2)
+    }
+    assert(fib(15) == 610)
+    fn fact {
+        let n = args[0]
+        if n == 0 {
+            ret 1
+        }
+        let rest = fact(n - 1)
+        ret n * rest
+    }
+    assert(fact(10) == 3628800)")
+    .unwrap();
+}
+
+#[test]
+pub fn fn_before_declaration() {
+    run("assert(double(4) == 8)
+    fn double {
+        ret quadruple(args[0]) / 2
+    }
+    fn quadruple {
+        ret args[0] * 4
+    }")
+    .unwrap();
+}
+
+#[test]
+pub fn closure_captures_call_scope() {
+    // The scope of every call is kept by the closures created in it
+    run("fn fun {
+        let a = args[0]
+        ret fn {
+            let old = a
+            a = args[0]
+            ret old
+        }
+    }
+    let f = fun(\"a\")
+    assert(eq(f(\"c\"), \"a\"))
+    let g = fun(\"b\")
+    assert(eq(f(\"d\"), \"c\"))
+    assert(eq(g(\"e\"), \"b\"))
+    let fs = array()
+    for i in range(0, 3) {
+        fs = push(fs, fn { ret i })
+    }
+    let first = fs[0]
+    let last = fs[2]
+    assert(first() == 0 && last() == 2)")
+    .unwrap();
+}
+
+#[test]
+pub fn named_functions_free_their_scope() {
+    let src = "fn outer(n) {
+    fn inner() { ret n }
+    ret inner()
+}
+fn make(x) {
+    fn get() { ret x }
+    ret get
+}
+let g = make(7)
+assert(eq(outer(1), 1) && eq(g(), 7))
+g = none";
+    let lexer = build_lexer().unwrap();
+    let mut parser = Parser::new(build_pre_map());
+    let block = parser
+        .parse_tokens(TokenStream::new(src, lexer.tokens(src)))
+        .unwrap();
+    let scope = Arc::new(RuntimeManager::new());
+    block.execute_in(&scope).unwrap();
+    // Neither the named functions stored in the scope nor the scopes of the calls keep it alive
+    assert_eq!(Arc::strong_count(&scope), 1);
+}
+
+#[test]
+pub fn named_params() {
+    run("fn add3(a, b, c = 0) {
+        ret a + b + c
+    }
+    assert(add3(1, 2) == 3 && add3(1, 2, 3) == 6)
+    let offset = 10
+    fn shift(x, by = offset * 2, ...rest) {
+        assert(len(args) == len(rest) + 1 || len(args) == len(rest) + 2)
+        ret array(x + by, rest)
+    }
+    assert(eq(shift(1), array(21, array())))
+    assert(eq(shift(1, 2, 3, 4), array(3, array(3, 4))))
+    let sum = fn (...nums) {
+        let s = 0
+        for n in nums {
+            s = s + n
+        }
+        ret s
+    }
+    assert(sum() == 0 && sum(1, 2, 3) == 6)
+    fn old {
+        ret args[1]
+    }
+    assert(old(1, 2) == 2)
+    assert(eq(collect(map(iter(array(1, 2)), fn (x) { ret x * 2 })), array(2, 4)))")
+    .unwrap();
+}
+
+#[test]
+pub fn arity_error() {
+    for code in [
+        "fn two(a, b) { ret a }\n    two(1)",
+        "fn two(a, b) { ret a }\n    two(1, 2, 3)",
+        "let f = fn (a, b = 1, ...c) { ret a }\n    f()",
+    ] {
+        match run(code).unwrap_err() {
+            CrabError::Runtime(e) => {
+                assert_eq!(e.kind(), RuntimeErrorKind::InvalidArguments);
+                assert_eq!(e.line(), Some(2));
+            }
+            other => panic!("expected a runtime error received {:?}", other),
+        }
+    }
+
+    match run("fn f(a = 1, b) {}").unwrap_err() {
+        CrabError::Parsing(e) => assert_eq!(
+            e.kind(),
+            &ParsingErrorKind::ExpectedNotFound("default value".to_string())
+        ),
+        other => panic!("expected a parsing error received {:?}", other),
+    }
+}
+
+#[test]
+pub fn engine_keeps_top_level_state() {
+    let mut engine = Engine::new();
+    assert!(engine.eval("let x = 20").unwrap() == DayObject::None);
+    engine.eval("fn double(n) { ret n * 2 }").unwrap();
+    assert!(engine.eval("x = double(x)\nx + 2").unwrap() == DayObject::Integer(42));
+
+    // A failed parse doesn't declare its variables
+    assert!(engine.eval("let y = (").is_err());
+    assert!(engine.eval("let z = 1").is_ok());
+    let names: Vec<&str> = engine.vars().into_iter().map(|(name, _)| name).collect();
+    assert_eq!(names, ["x", "double", "z"]);
+
+    // Neither does a runtime error remove them
+    assert!(engine.eval("let w = 3\nassert(false)\nw = 4").is_err());
+    assert!(engine.eval("w").unwrap() == DayObject::Integer(3));
+}
+
+#[test]
+pub fn incomplete_input() {
+    assert!(is_incomplete("fn f(a) {"));
+    assert!(is_incomplete("let a = array(1,\n2"));
+    assert!(!is_incomplete("let m = {\"a\": [1, 2]}"));
+    assert!(!is_incomplete("}"));
+}
+
+#[test]
+pub fn engine_embedding() {
+    let mut engine = Engine::new();
+    engine.set_var("limit", DayObject::Integer(3));
+    let script = engine
+        .compile(
+            "let count = 0
+    fn bump(by = 1) {
+        count = count + by
+        ret count < limit
+    }",
+        )
+        .unwrap();
+    assert!(engine.get_var("count").unwrap() == DayObject::None);
+    engine.run(&script).unwrap();
+
+    assert!(engine.call("bump", &[]).unwrap() == DayObject::Bool(true));
+    assert!(engine.call("bump", &[DayObject::Integer(2)]).unwrap() == DayObject::Bool(false));
+    assert!(engine.get_var("count").unwrap() == DayObject::Integer(3));
+
+    engine.set_var("count", DayObject::Integer(-5));
+    assert!(engine.eval("bump(3)\ncount").unwrap() == DayObject::Integer(-2));
+    assert!(engine.get_var("missing").is_none());
+
+    match engine.call("missing", &[]).unwrap_err() {
+        CrabError::Runtime(e) => assert_eq!(e.kind(), RuntimeErrorKind::NotCallable),
+        other => panic!("expected a runtime error received {:?}", other),
+    }
+
+    // The engine doesn't borrow the names of the sources it compiled
+    let src = String::from("let temporary = 1");
+    engine.eval(&src).unwrap();
+    drop(src);
+    engine.set_var(&String::from("added"), DayObject::Integer(2));
+    assert!(engine.eval("temporary + added").unwrap() == DayObject::Integer(3));
+
+    match Engine::new().run(&script).unwrap_err() {
+        CrabError::Runtime(e) => assert_eq!(e.kind(), RuntimeErrorKind::ForeignScript),
+        other => panic!("expected a runtime error received {:?}", other),
+    }
+}
+
+#[test]
+pub fn script_args() {
+    let mut engine = Engine::new();
+    assert!(engine.eval("len(argv())").unwrap() == DayObject::Integer(0));
+    engine.set_argv(["script.crab", "first"]);
+    assert!(engine.eval("argv(1)").unwrap() == DayObject::Str("first".to_string()));
+    assert!(engine.eval("len(argv())").unwrap() == DayObject::Integer(2));
+    assert!(engine.eval("argv(2)").is_err());
+    // The args belong to the engine
+    assert!(Engine::new().eval("len(argv())").unwrap() == DayObject::Integer(0));
+}
+
+#[test]
+pub fn native_functions() {
+    use std::sync::atomic::{AtomicI64, Ordering};
+
+    let calls = Arc::new(AtomicI64::new(0));
+    let counted = Arc::clone(&calls);
+    let mut engine = Engine::new();
+    engine.register_fn("count", move |_| {
+        Ok(DayObject::Integer(
+            counted.fetch_add(1, Ordering::Relaxed) + 1,
+        ))
+    });
+    engine.register_module(
+        NativeModule::new("math")
+            .function("double", |args| match args {
+                [DayObject::Integer(i)] => Ok(DayObject::Integer(i * 2)),
+                _ => Err(RuntimeError::invalid_args("math.double", "expected an int")),
+            })
+            .function("answer", |_| Ok(DayObject::Integer(42))),
+    );
+
+    assert!(engine.eval("count()\ncount()").unwrap() == DayObject::Integer(2));
+    assert_eq!(calls.load(Ordering::Relaxed), 2);
+    assert!(engine.eval("math.double(math.answer())").unwrap() == DayObject::Integer(84));
+    assert!(
+        engine
+            .eval("let f = math.double\ncollect(map(iter(array(1, 2)), f))")
+            .unwrap()
+            == DayObject::Array(vec![DayObject::Integer(2), DayObject::Integer(4)])
+    );
+
+    match engine.eval("\nmath.double(true)").unwrap_err() {
+        CrabError::Runtime(e) => {
+            assert_eq!(e.kind(), RuntimeErrorKind::InvalidArguments);
+            assert_eq!(e.line(), Some(2));
+        }
+        other => panic!("expected a runtime error received {:?}", other),
+    }
+    match engine.eval("math.missing()").unwrap_err() {
+        CrabError::Parsing(e) => assert_eq!(
+            e.kind(),
+            &ParsingErrorKind::UndefinedVariable("math.missing".to_string())
+        ),
+        other => panic!("expected a parsing error received {:?}", other),
+    }
+}
+
+#[test]
+pub fn imports() {
+    let mut engine = Engine::new();
+    engine.add_search_path(concat!(env!("CARGO_MANIFEST_DIR"), "/Tests/modules"));
+
+    // Both imports share the module, it is only executed once
+    let res = engine.eval(
+        r#"import "counter.crab"
+    import "uses_counter.crab"
+    counter.next()
+    uses_counter.twice()"#,
+    );
+    assert!(res.unwrap() == DayObject::Integer(13));
+    assert!(engine.eval("counter.START").unwrap() == DayObject::Integer(10));
+    assert!(engine.eval("import math\nmath.mod(7, 4)").unwrap() == DayObject::Integer(3));
+
+    let parsing_error = |engine: &mut Engine, code: &str| match engine.eval(code).unwrap_err() {
+        CrabError::Parsing(e) => e,
+        other => panic!("expected a parsing error received {:?}", other),
+    };
+    assert_eq!(
+        parsing_error(&mut engine, "counter._count").kind(),
+        &ParsingErrorKind::UndefinedVariable("counter._count".to_string())
+    );
+    assert_eq!(
+        parsing_error(&mut engine, "import nope").kind(),
+        &ParsingErrorKind::ModuleNotFound("nope".to_string())
+    );
+    assert_eq!(
+        parsing_error(&mut engine, r#"import "nope.crab""#).kind(),
+        &ParsingErrorKind::ModuleNotFound("nope.crab".to_string())
+    );
+
+    let error = parsing_error(&mut engine, "\nimport \"cycle_a.crab\"");
+    assert_eq!(error.span().line, 2);
+    let mut kind = error.kind();
+    while let ParsingErrorKind::InModule { error, .. } = kind {
+        kind = error.kind();
+    }
+    match kind {
+        ParsingErrorKind::ImportCycle(chain) => {
+            let files: Vec<_> = chain.split(" -> ").collect();
+            assert_eq!(files.len(), 3);
+            assert!(files[0].ends_with("cycle_a.crab") && files[2].ends_with("cycle_a.crab"));
+            assert!(files[1].ends_with("cycle_b.crab"));
+        }
+        other => panic!("expected an import cycle received {:?}", other),
+    }
+
+    match parsing_error(&mut engine, r#"import "broken.crab""#).kind() {
+        ParsingErrorKind::InModule { path, .. } => assert!(path.ends_with("broken.crab")),
+        other => panic!("expected an error in a module received {:?}", other),
+    }
+
+    // The error keeps its span in the module, the import is a frame of the traceback
+    let src = "\n\nimport \"failing.crab\"";
+    let err = engine.eval(src).unwrap_err();
+    match &err {
+        CrabError::Runtime(e) => {
+            assert_eq!(e.kind(), RuntimeErrorKind::AssertionFailed);
+            assert_eq!(e.line(), Some(2));
+            assert!(e.module().unwrap().ends_with("failing.crab"));
+            assert_eq!(e.trace()[0].call_site.unwrap().line, 3);
+        }
+        other => panic!("expected a runtime error received {:?}", other),
+    }
+    let rendered = err.render(src);
+    assert!(rendered.starts_with("Traceback (most recent call last):\n  line 3, in top level\n"));
+    assert!(rendered.contains("failing.crab, line 2, in top level\nERROR [l. 2, c. 1]"));
+}
+
+#[test]
+pub fn sandbox() {
+    let runtime_error = |engine: &mut Engine, code: &str| match engine.eval(code).unwrap_err() {
+        CrabError::Runtime(e) => e,
+        other => panic!("expected a runtime error received {:?}", other),
+    };
+
+    let mut engine = Engine::sandboxed();
+    assert!(engine.eval("len(array(1, 2))").unwrap() == DayObject::Integer(2));
+    for code in [
+        "cat(\"Cargo.toml\")",
+        "fs.rm(\"x\")",
+        "argv()",
+        "import io\nio.readln()",
+    ] {
+        let e = runtime_error(&mut engine, code);
+        assert_eq!(e.kind(), RuntimeErrorKind::PermissionDenied);
+    }
+    // The error can be caught like any other
+    assert!(engine
+        .eval("try { println(1) } catch e { error_kind(e) }")
+        .is_ok());
+    match engine
+        .eval(r#"import "Tests/modules/counter.crab""#)
+        .unwrap_err()
+    {
+        CrabError::Parsing(e) => assert!(matches!(e.kind(), ParsingErrorKind::ModuleNotFound(_))),
+        other => panic!("expected a parsing error received {:?}", other),
+    }
+
+    let root = std::env::temp_dir().join("crabscript_sandbox");
+    std::fs::create_dir_all(&root).unwrap();
+    std::fs::write(root.join("lib.crab"), "fn f() { ret 1 }").unwrap();
+    let mut engine = Engine::with_capabilities(&Capabilities::sandboxed().fs_root(&root));
+    engine
+        .eval("fwrite(\"/note.txt\", \"hi\")\nimport \"lib.crab\"")
+        .unwrap();
+    assert_eq!(
+        std::fs::read_to_string(root.join("note.txt")).unwrap(),
+        "hi"
+    );
+    assert!(engine.eval("fs.cat(\"a/../note.txt\")").unwrap() == DayObject::Str("hi".into()));
+    assert!(engine.eval("lib.f()").unwrap() == DayObject::Integer(1));
+    let e = runtime_error(&mut engine, "cat(\"../note.txt\")");
+    assert_eq!(e.kind(), RuntimeErrorKind::PermissionDenied);
+    assert!(engine.eval("import \"../lib.crab\"").is_err());
+}
+
+#[test]
+pub fn execution_limits() {
+    let kind = |engine: &mut Engine, code: &str| match engine.eval(code).unwrap_err() {
+        CrabError::Runtime(e) => e.kind(),
+        other => panic!("expected a runtime error received {:?}", other),
+    };
+
+    let mut engine = Engine::new();
+    engine.set_limits(Limits {
+        max_steps: Some(100),
+        ..Limits::default()
+    });
+    assert!(
+        engine
+            .eval("let i = 0\nwhile i < 100 { i = i + 1 }\ni")
+            .unwrap()
+            == DayObject::Integer(100)
+    );
+    assert_eq!(
+        kind(&mut engine, "for i in range(0, 101) {}"),
+        RuntimeErrorKind::StepLimit
+    );
+    // Limits can't be caught by the script
+    assert_eq!(
+        kind(&mut engine, "try { while true {} } catch { 1 }"),
+        RuntimeErrorKind::StepLimit
+    );
+
+    engine.set_limits(Limits {
+        max_depth: Some(50),
+        ..Limits::default()
+    });
+    engine
+        .eval("fn down(n) { if n > 0 { down(n - 1) } }")
+        .unwrap();
+    assert!(engine.eval("down(49)").is_ok());
+    assert_eq!(
+        kind(&mut engine, "down(50)"),
+        RuntimeErrorKind::RecursionLimit
+    );
+    assert!(engine.call("down", &[DayObject::Integer(49)]).is_ok());
+
+    engine.set_limits(Limits {
+        timeout: Some(std::time::Duration::from_millis(20)),
+        ..Limits::default()
+    });
+    assert_eq!(
+        kind(&mut engine, "while true {}"),
+        RuntimeErrorKind::Timeout
+    );
+
+    engine.set_limits(Limits::default());
+    let cancel = engine.cancel_handle();
+    let canceller = std::thread::spawn(move || {
+        std::thread::sleep(std::time::Duration::from_millis(20));
+        cancel.cancel();
+    });
+    assert_eq!(
+        kind(&mut engine, "while true {}"),
+        RuntimeErrorKind::Cancelled
+    );
+    canceller.join().unwrap();
+    assert_eq!(kind(&mut engine, "1"), RuntimeErrorKind::Cancelled);
+    engine.cancel_handle().reset();
+    assert!(engine.eval("1").is_ok());
+}
+
+#[test]
+pub fn custom_stdio() {
+    let (output, error, log) = (Capture::new(), Capture::new(), Capture::new());
+    let mut engine = Engine::new();
+    engine.set_stdio(
+        Stdio::inherit()
+            .input(std::io::Cursor::new("Ferris\n!"))
+            .output(Tee(output.clone(), log.clone()))
+            .error(error.clone()),
+    );
+
+    engine
+        .eval(
+            r#"print("Name: ")
+    let name = readln()
+    println("Hello", name)
+    eprint("warning: ", read())
+    eprintln()
+    println()"#,
+        )
+        .unwrap();
+    assert_eq!(output.contents(), "Name: Hello\nFerris\n\n\n");
+    assert_eq!(log.contents(), output.contents());
+    assert_eq!(error.contents(), "warning: !\n");
+
+    // Reading past the end of the input is an error
+    match engine.eval("read()").unwrap_err() {
+        CrabError::Runtime(e) => assert_eq!(e.kind(), RuntimeErrorKind::Io),
+        other => panic!("expected a runtime error received {:?}", other),
+    }
+}
+
+/// Runs `src` with captured output in an engine set up by `configure`
+fn run_captured(
+    src: &str,
+    dir: &std::path::Path,
+    configure: impl FnOnce(&mut Engine),
+) -> (Result<DayObject, CrabError>, String) {
+    let output = Capture::new();
+    let mut engine = Engine::new();
+    engine.add_search_path(dir);
+    engine.set_limits(Limits {
+        max_steps: Some(20_000),
+        ..Limits::default()
+    });
+    engine.set_stdio(
+        Stdio::inherit()
+            .input(std::io::empty())
+            .output(output.clone())
+            .error(output.clone()),
+    );
+    configure(&mut engine);
+
+    let res = engine.eval(src);
+    (res, output.contents())
+}
+
+#[test]
+pub fn scripts() {
+    let dir = std::path::Path::new(env!("CARGO_MANIFEST_DIR")).join("Tests");
+    for path in discover(&dir).unwrap() {
+        let name = path.file_name().unwrap().to_str().unwrap();
+        let src = std::fs::read_to_string(&path).unwrap();
+        let annotations = Annotations::parse(&src);
+        if annotations.skip.is_some() || annotations.slow.is_some() {
+            continue;
+        }
+
+        let (res, output) = run_captured(&src, &dir, |_| ());
+        let (unoptimized_res, unoptimized_output) =
+            run_captured(&src, &dir, |engine| engine.set_opt_level(OptLevel::None));
+        assert_eq!(output, unoptimized_output, "the output of {} differs", name);
+        assert_eq!(
+            format!("{:?}", res),
+            format!("{:?}", unoptimized_res),
+            "the result of {} differs",
+            name
+        );
+        #[cfg(feature = "vm")]
+        {
+            let (tree_res, tree_output) = run_captured(&src, &dir, |engine| {
+                engine.set_backend(crate::vm::Backend::Tree)
+            });
+            assert_eq!(output, tree_output, "the output of {} differs", name);
+            assert_eq!(
+                format!("{:?}", res),
+                format!("{:?}", tree_res),
+                "the result of {} differs",
+                name
+            );
+        }
+
+        assert_eq!(
+            res.is_err(),
+            !annotations.expected_errors.is_empty(),
+            "unexpected result of {}: {:?}",
+            name,
+            res
+        );
+    }
+}
+
+#[cfg(feature = "vm")]
+#[test]
+pub fn vm_matches_tree_walker() {
+    use crate::vm::Backend;
+
+    let src = r#"
+    let f = 0
+    for i in range(0, 3) {
+        if eq(i, 1) {
+            f = fn { ret i }
+        }
+    }
+    println(f())
+
+    let n = 0
+    for i in range(0, 5) {
+        try {
+            if eq(mod(i, 2), 0) {
+                continue
+            }
+            div(1, 0)
+        } catch e {
+            n = add(n, 1)
+        }
+    }
+    println(n)
+
+    let res = while true {
+        for j in range(0, 10) {
+            try {
+                if eq(j, 3) {
+                    break
+                }
+            } catch {}
+        }
+        break "done"
+    }
+    println(res, true && false, false || 1)
+
+    let a = array(array(1, 2), array(3, 4, 5))
+    a[1][0] = 30
+    println(a[1][0:2], a[1][1:])
+    div(1, 0)"#;
+
+    let dir = std::path::Path::new(".");
+    let (res, output) = run_captured(src, dir, |_| ());
+    let (tree_res, tree_output) =
+        run_captured(src, dir, |engine| engine.set_backend(Backend::Tree));
+    assert_eq!(output, tree_output);
+    assert_eq!(output, "1\n2\ndone\nfalse\ntrue\n[30, 4]\n[4, 5]\n");
+    assert_eq!(format!("{:?}", res), format!("{:?}", tree_res));
+    assert_eq!(res.unwrap_err().span().map(|s| s.line), Some(38));
+}
+
+#[test]
+pub fn optimizer() {
+    let parse = |src: &str, level: OptLevel| {
+        let lexer = build_lexer().unwrap();
+        let mut parser = Parser::new(build_pre_map());
+        parser.set_opt_level(level);
+        let block = parser
+            .parse_tokens(TokenStream::new(src, lexer.tokens(src)))
+            .unwrap();
+        // Without whitespace, so the nodes can be searched independent of the indentation
+        format!("{:?}", block)
+            .split_whitespace()
+            .collect::<String>()
+    };
+
+    let folded = parse("const X = add(1, mul(2, 3))\nprintln(X)", OptLevel::Basic);
+    // Only the call of println is left and it prints the folded value
+    assert_eq!(folded.matches("FunctionCallNode").count(), 1);
+    assert_eq!(folded.matches("Data(7,)").count(), 2);
+    assert!(!folded.contains("Identifier"));
+    assert!(parse("println(X)\nconst X = 1", OptLevel::Basic).contains("Identifier"));
+    // Failing calls fail when they are executed
+    assert!(parse("div(1, 0)", OptLevel::Full).contains("FunctionCall"));
+    let overflow = parse(
+        "const X = 9223372036854775807 + 1\nprintln(X)",
+        OptLevel::Full,
+    );
+    assert!(overflow.contains("BinaryOp") && !overflow.contains("Data(-9223372036854775808"));
+    let err = run("const X = 9223372036854775807 + 1").unwrap_err();
+    assert!(err.to_string().contains("overflows"));
+
+    let pruned = parse(
+        "if eq(1, 2) { println(1) } else if true { println(2) } else { println(3) }",
+        OptLevel::Basic,
+    );
+    // Only the block of the second branch is left
+    assert!(!pruned.contains("BranchNode") && pruned.contains("Block("));
+    assert_eq!(pruned.matches("FunctionCallNode").count(), 1);
+    assert!(pruned.contains("Data(2,)") && !pruned.contains("Data(3,)"));
+
+    let counter = "let i = 0\ni = add(i, 1)";
+    assert!(parse(counter, OptLevel::Full).contains("BinaryOp"));
+    assert!(!parse(counter, OptLevel::Basic).contains("BinaryOp"));
+    assert!(!parse(counter, OptLevel::None).contains("BinaryOp"));
+
+    // The const and the variable of the second loop share a slot
+    run("let r = 0
+    for i in range(0, 1) {
+        const A = 5
+        r = add(r, A)
+    }
+    for i in range(0, 1) {
+        let b = 2
+        r = add(r, b)
+    }
+    fn f(x) {
+        const Y = sub(10, 1)
+        ret add(x, Y)
+    }
+    assert(eq(r, 7))
+    assert(eq(f(1), 10))
+    assert(lt(r, f(r)))")
+    .unwrap();
+}
+
+#[test]
+pub fn dump() {
+    let src = "let a = 1\nfor i in range(0, 2) {\n    a = i\n}";
+    let tokens = dump_tokens(src, Format::Text);
+    assert!(tokens.starts_with("1:1      Keyword(Let)\n"));
+    assert!(tokens.contains("2:19     Data(Integer(2))\n"));
+
+    let mut engine = Engine::new();
+    let script = engine.compile(src).unwrap();
+    let ast = dump_ast(script.block(), &engine.scopes(), Format::Text);
+    assert!(ast.contains("    Var name=a id=0 depth=0 const=false\n"));
+    assert!(ast.contains("      Var name=i id=0 depth=1 const=false\n"));
+    assert!(ast.contains("      RustFunction name=iter.range\n"));
+    // The assigned variable is in the top level scope, the loop variable in the body
+    assert!(ast.contains(
+        "      Block purpose=For capacity=1
+        Assignment
+          Identifier id=0 depth=0
+          Identifier id=0 depth=1
+"
+    ));
+
+    let json = dump_ast(script.block(), &engine.scopes(), Format::Json);
+    assert!(json.starts_with("{\"scopes\":{\"kind\":\"Scope\",\"depth\":0,"));
+    assert!(json.contains("{\"kind\":\"Identifier\",\"id\":0,\"depth\":1}"));
+    let json = dump_tokens("\"a\\n\"", Format::Json);
+    assert_eq!(
+        json,
+        "[{\"line\":1,\"column\":1,\"token\":\"Data(Str(\\\"a\\\\n\\\"))\"}]\n"
+    );
+}
+
+#[test]
+pub fn formatter() {
+    // The tokens without the newlines and the comments, formatting mustn't change either
+    let tokens = |src: &str| {
+        let lexer = build_lexer().unwrap();
+        let mut significant = Vec::new();
+        let mut comments = Vec::new();
+        for (token, text) in lexer.tokens(src) {
+            match token {
+                _ if text.starts_with("//") => comments.push(text.trim_end().to_string()),
+                Token::Newline => (),
+                token => significant.push(format!("{:?}", token)),
+            }
+        }
+        (significant, comments)
+    };
+
+    let dir = std::path::Path::new(env!("CARGO_MANIFEST_DIR")).join("Tests");
+    for entry in std::fs::read_dir(&dir).unwrap() {
+        let path = entry.unwrap().path();
+        let src = match std::fs::read_to_string(&path) {
+            Ok(src) => src,
+            Err(_) => continue,
+        };
+        let formatted = match format(&src) {
+            Ok(formatted) => formatted,
+            Err(_) => continue,
+        };
+        assert_eq!(tokens(&src), tokens(&formatted), "{:?}", path);
+        assert_eq!(format(&formatted).unwrap(), formatted, "{:?}", path);
+    }
+
+    let src = "fn f(x,y) { ret add(x,-1) }   //inc
+if f(1) { println(1) }
+else{println( 2 )}
+
+
+let m = {\"a\":1}
+println(m[\"a\"], args[1:], args[:-1])";
+    assert_eq!(
+        format(src).unwrap(),
+        "fn f(x, y) {
+    ret add(x, -1)
+} //inc
+if f(1) {
+    println(1)
+} else {
+    println(2)
+}
+
+let m = {\"a\": 1}
+println(m[\"a\"], args[1:], args[:-1])
+"
+    );
+
+    // Groups too long for a line or with comments get a line for every arg
+    let long = format!(
+        "println({0}, {0}, {0})",
+        "\"0123456789012345678901234567890\""
+    );
+    assert_eq!(
+        format(&long).unwrap(),
+        format!(
+            "println(\n    {0},\n    {0},\n    {0}\n)\n",
+            "\"0123456789012345678901234567890\""
+        )
+    );
+    assert_eq!(
+        format("array(1, //one\n  2)").unwrap(),
+        "array(\n    1, //one\n    2\n)\n"
+    );
+
+    // An operator starting a line starts a new statement, it isn't joined onto the line before
+    assert_eq!(format("let y = 1\n-2").unwrap(), "let y = 1\n-2\n");
+
+    assert!(format("println(1))").is_err());
+    assert!(format("fn f {").is_err());
+}
+
+#[test]
+pub fn analysis() {
+    let src = "let x = 1\nfn f(a) {\n    ret add(a, x)\n}\nprintln(f(2))";
+    let analysis = analyze(src, None);
+    assert!(analysis.error.is_none());
+
+    // `x` in the body of `f` is declared on the first line
+    let use_of_x = src.rfind("x)").unwrap();
+    let definition = analysis.definition_at(use_of_x).unwrap();
+    assert_eq!((definition.offset, definition.len), (4, 1));
+    // A declaration is its own definition, std functions have none
+    assert_eq!(analysis.definition_at(4), Some(definition));
+    let use_of_a = src.find("a, x").unwrap();
+    assert_eq!(analysis.definition_at(use_of_a).unwrap().offset, 15);
+    assert_eq!(analysis.definition_at(src.find("add").unwrap()), None);
+
+    let hover = analysis.hover_at(src.find("println").unwrap()).unwrap();
+    assert!(hover.starts_with("println(values...)"));
+    assert!(hover.contains("`io.println`"));
+    assert_eq!(analysis.hover_at(use_of_x), None);
+    assert!(matches!(
+        &analysis.reference_at(src.find("add").unwrap()).unwrap().target,
+        Target::Std(name) if name == "add"
+    ));
+
+    // The parameter is only visible in the body of the function
+    let labels = |offset| {
+        analysis
+            .completions_at(offset)
+            .into_iter()
+            .map(|c| c.label)
+            .collect::<Vec<_>>()
+    };
+    let in_body = labels(use_of_a);
+    assert_eq!(in_body[..3], ["a", "f", "x"]);
+    assert!(in_body.contains(&"range".to_string()));
+    assert!(!labels(src.len()).contains(&"a".to_string()));
+    let completions = analysis.completions_at(0);
+    let add = completions.iter().find(|c| c.label == "add").unwrap();
+    assert_eq!(add.kind, CompletionKind::Function);
+    assert_eq!(add.detail, Some("add(a, b, ...)"));
+
+    // A broken script still has its scopes and the identifiers before the error
+    let analysis = analyze("const y = 2\nprintln(y +", None);
+    let error = analysis.error.as_ref().unwrap();
+    assert_eq!(*error.kind(), ParsingErrorKind::UnexpectedEndOfInput);
+    assert_eq!(analysis.scopes.as_ref().unwrap().vars[0].name, "y");
+    assert_eq!(
+        analysis.definition_at(20),
+        Some(analysis.definition_at(6).unwrap())
+    );
+    let unknown = analyze("println(z)", None).error.unwrap();
+    assert_eq!((unknown.span().offset, unknown.span().len), (8, 1));
+}
+
+#[test]
+pub fn debugger() {
+    type Stop = (u64, Reason, Vec<String>, Option<DayObject>);
+    struct Recorder {
+        breakpoints: Vec<u64>,
+        actions: Vec<Action>,
+        stops: Rc<RefCell<Vec<Stop>>>,
+    }
+    impl Debugger for Recorder {
+        fn is_breakpoint(&self, file: Option<&Path>, line: u64) -> bool {
+            file.is_none() && self.breakpoints.contains(&line)
+        }
+
+        fn paused(&mut self, pause: &Pause) -> Action {
+            let stop = (
+                pause.span.line,
+                pause.reason,
+                pause.calls.to_vec(),
+                pause.var("n").or_else(|| pause.var("total")),
+            );
+            self.stops.borrow_mut().push(stop);
+            self.actions.pop().unwrap_or(Action::Continue)
+        }
+    }
+
+    let src = "let total = 1
+fn twice(n) {
+    let doubled = add(n, n)
+    ret doubled
+}
+total = twice(total)
+total = twice(total)
+println(total)";
+    let stops = Rc::default();
+    let mut engine = Engine::new();
+    engine.set_stdio(Stdio::inherit().output(Capture::new()));
+    engine.set_debugger(Recorder {
+        breakpoints: vec![6],
+        actions: vec![Action::StepOut, Action::StepOver, Action::StepInto],
+        stops: Rc::clone(&stops),
+    });
+    engine.eval(src).unwrap();
+    let calls = |names: &[&str]| names.iter().map(|n| n.to_string()).collect::<Vec<_>>();
+    assert_eq!(
+        *stops.borrow(),
+        vec![
+            (
+                6,
+                Reason::Breakpoint,
+                calls(&[]),
+                Some(DayObject::Integer(1))
+            ),
+            (
+                3,
+                Reason::Step,
+                calls(&["twice"]),
+                Some(DayObject::Integer(1))
+            ),
+            (
+                4,
+                Reason::Step,
+                calls(&["twice"]),
+                Some(DayObject::Integer(1))
+            ),
+            (7, Reason::Step, calls(&[]), Some(DayObject::Integer(2))),
+        ]
+    );
+    assert_eq!(engine.get_var("total"), Some(DayObject::Integer(4)));
+
+    // The scopes of the function and of the top level with the names of their variables
+    struct Inspector(Rc<RefCell<Vec<String>>>);
+    impl Debugger for Inspector {
+        fn is_breakpoint(&self, _: Option<&Path>, line: u64) -> bool {
+            line == 4
+        }
+
+        fn paused(&mut self, pause: &Pause) -> Action {
+            for scope in pause.scopes() {
+                for (name, value) in scope.vars {
+                    self.0
+                        .borrow_mut()
+                        .push(format!("{}:{}={:?}", scope.depth, name, value));
+                }
+            }
+            Action::Continue
+        }
+    }
+    let seen = Rc::default();
+    let mut engine = Engine::new();
+    engine.set_debugger(Inspector(Rc::clone(&seen)));
+    engine
+        .eval("let a = 1\nfor i in range(5, 7) {\n    let b = i\n    a = b\n}")
+        .unwrap();
+    assert_eq!(
+        *seen.borrow(),
+        ["1:i=5", "1:b=5", "0:a=1", "1:i=6", "1:b=6", "0:a=5"].map(String::from)
+    );
+
+    // Without a debugger nothing pauses
+    engine.remove_debugger();
+    engine.eval("a = 2").unwrap();
+    assert_eq!(seen.borrow().len(), 6);
+}
@@ -0,0 +1,36 @@
+use crabscript::golden::{check_all, discover, Annotations};
+use std::path::{Path, PathBuf};
+
+/// The scripts in `Tests` that are slow or not
+fn scripts(slow: bool) -> Vec<PathBuf> {
+    let dir = Path::new(env!("CARGO_MANIFEST_DIR")).join("Tests");
+    let mut scripts = discover(&dir).unwrap();
+    scripts.retain(|script| Annotations::read(script).unwrap().slow.is_some() == slow);
+    scripts
+}
+
+/// Runs the scripts in `Tests` against their expectation files,
+/// `crabscript test --bless` updates them
+#[test]
+fn golden() {
+    check(scripts(false));
+}
+
+/// The benchmarks and sleeping scripts, run with `cargo test -- --ignored`
+#[test]
+#[ignore]
+fn golden_slow() {
+    check(scripts(true));
+}
+
+fn check(scripts: Vec<PathBuf>) {
+    let outcomes = check_all(&scripts, false);
+    assert!(!outcomes.is_empty());
+
+    let failed: Vec<_> = outcomes
+        .iter()
+        .filter(|outcome| !outcome.passed())
+        .map(ToString::to_string)
+        .collect();
+    assert!(failed.is_empty(), "\n{}", failed.join("\n"));
+}